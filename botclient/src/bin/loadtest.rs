@@ -0,0 +1,361 @@
+// Drives a server with many bot clients to find where it falls over. Bots
+// join at --ramp a second, let the GameManager put them in whatever game it
+// likes, then press keys at --input-rate while reading snapshots, or just sit
+// there with --idle to see what keepalives and idle timeouts do at scale.
+//
+// cargo run --release -p botclient --bin loadtest -- --addr host:42001 --players 500 --metrics host:9100
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use botclient::{BotClient, Join};
+use clap::Parser;
+use encoding::server;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+#[derive(Parser, Debug)]
+#[clap()]
+struct Args {
+    // the game server, host:port
+    #[clap(long = "addr", default_value = "127.0.0.1:42001")]
+    addr: String,
+
+    #[clap(short = 'n', long = "players", default_value_t = 100)]
+    players: usize,
+
+    // new connections a second
+    #[clap(long = "ramp", default_value_t = 20.0)]
+    ramp: f64,
+
+    // key presses a second per bot
+    #[clap(long = "input-rate", default_value_t = 8.0)]
+    input_rate: f64,
+
+    // seconds every bot stays after joining
+    #[clap(long = "hold", default_value_t = 30)]
+    hold: u64,
+
+    // no input at all, for keepalives and idle timeouts
+    #[clap(long = "idle")]
+    idle: bool,
+
+    // the server's --metrics-port, host:port, for tick overruns
+    #[clap(long = "metrics")]
+    metrics: Option<String>,
+
+    // a tick slower than this overran, one of the tick_duration_us buckets
+    #[clap(long = "tick-budget-us", default_value_t = 16_666)]
+    tick_budget_us: u64,
+}
+
+/// how one bot's run went.
+#[derive(Debug, Default)]
+struct BotRun {
+    handshake: Option<Duration>,
+    error: Option<String>,
+    snapshots: usize,
+    // between one snapshot and the next
+    gaps: Vec<Duration>,
+    // the server closed the connection during the hold, after this long
+    dropped_after: Option<Duration>,
+}
+
+// keys a player mashes, mostly moving
+const KEYS: &[u8] = b"hjklhjklhjklwbx";
+
+async fn run_bot(index: usize, args: &Args) -> BotRun {
+    let mut run = BotRun::default();
+    let started = Instant::now();
+    let mut bot = match BotClient::connect(&args.addr, Join::Named(format!("load{}", index))).await {
+        Ok(bot) => bot,
+        Err(e) => {
+            run.error = Some(e.to_string());
+            return run;
+        }
+    };
+    run.handshake = Some(started.elapsed());
+
+    let joined = Instant::now();
+    let hold_until = joined + Duration::from_secs(args.hold);
+    let input_every = match args.idle || args.input_rate <= 0.0 {
+        true => None,
+        false => Some(Duration::from_secs_f64(1.0 / args.input_rate)),
+    };
+    let mut next_input = joined;
+    let mut last_snapshot: Option<Instant> = None;
+    // xorshift, every bot its own stream of keys
+    let mut rng = (index as u32).wrapping_mul(2_654_435_761) | 1;
+
+    loop {
+        let now = Instant::now();
+        if now >= hold_until {
+            break;
+        }
+
+        if let Some(every) = input_every.filter(|_| now >= next_input) {
+            rng ^= rng << 13;
+            rng ^= rng >> 17;
+            rng ^= rng << 5;
+            _ = bot.press(KEYS[rng as usize % KEYS.len()]);
+            next_input += every;
+            continue;
+        }
+
+        let wake = match input_every {
+            Some(_) => next_input.min(hold_until),
+            None => hold_until,
+        };
+        match bot.try_next_message(wake - now).await {
+            Ok(Some(server::Message::Snapshot(_) | server::Message::FineSnapshot(_))) => {
+                let at = Instant::now();
+                if let Some(last) = last_snapshot {
+                    run.gaps.push(at - last);
+                }
+                last_snapshot = Some(at);
+                run.snapshots += 1;
+            }
+            Ok(_) => {}
+            Err(_) => {
+                run.dropped_after = Some(joined.elapsed());
+                return run;
+            }
+        }
+    }
+
+    bot.disconnect().await;
+    return run;
+}
+
+async fn scrape(addr: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: loadtest\r\nConnection: close\r\n\r\n").await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    let (_, body) = response.split_once("\r\n\r\n").ok_or_else(|| anyhow!("no metrics body"))?;
+    return Ok(body.to_string());
+}
+
+// the game_id label of a metric line
+fn game_id(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("game_id=\"")?;
+    return rest.split('"').next();
+}
+
+/// ticks over budget per game id, from the tick duration histogram.
+fn overruns(body: &str, budget_us: u64) -> HashMap<String, u64> {
+    let bucket = format!("le=\"{}\"", budget_us);
+    let mut within: HashMap<String, u64> = HashMap::new();
+    let mut total: HashMap<String, u64> = HashMap::new();
+    for line in body.lines() {
+        let (Some(id), Some(value)) = (game_id(line), line.rsplit(' ').next().and_then(|v| v.parse::<u64>().ok())) else {
+            continue;
+        };
+        if line.starts_with("vim_royale_tick_duration_us_bucket") && line.contains(&bucket) {
+            within.insert(id.to_string(), value);
+        } else if line.starts_with("vim_royale_tick_duration_us_count") {
+            total.insert(id.to_string(), value);
+        }
+    }
+
+    return total
+        .into_iter()
+        .map(|(id, count)| {
+            let over = count.saturating_sub(within.get(&id).copied().unwrap_or(count));
+            (id, over)
+        })
+        .collect();
+}
+
+fn gauge(body: &str, name: &str) -> Option<u64> {
+    return body
+        .lines()
+        .find(|line| line.split(' ').next() == Some(name))
+        .and_then(|line| line.rsplit(' ').next())
+        .and_then(|value| value.parse().ok());
+}
+
+/// what the metrics endpoint said over the run.
+#[derive(Debug, Default)]
+struct ServerSide {
+    // from the first scrape, games that were already running
+    baseline: Option<HashMap<String, u64>>,
+    // the last seen per game, a game's series goes away when it ends
+    overruns: HashMap<String, u64>,
+    peak_games: u64,
+    peak_players: u64,
+}
+
+impl ServerSide {
+    fn add(&mut self, body: &str, budget_us: u64) {
+        let overruns = overruns(body, budget_us);
+        self.baseline.get_or_insert_with(|| overruns.clone());
+        self.overruns.extend(overruns);
+        self.peak_games = self.peak_games.max(gauge(body, "vim_royale_active_games").unwrap_or(0));
+        self.peak_players = self.peak_players.max(gauge(body, "vim_royale_players_connected").unwrap_or(0));
+    }
+
+    fn total_overruns(&self) -> u64 {
+        let baseline = self.baseline.clone().unwrap_or_default();
+        return self
+            .overruns
+            .iter()
+            .map(|(id, over)| over.saturating_sub(baseline.get(id).copied().unwrap_or(0)))
+            .sum();
+    }
+}
+
+async fn watch_metrics(addr: String, budget_us: u64, mut done: watch::Receiver<bool>) -> ServerSide {
+    let mut server = ServerSide::default();
+    let mut every = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = every.tick() => match scrape(&addr).await {
+                Ok(body) => server.add(&body, budget_us),
+                Err(e) => eprintln!("metrics scrape failed: {}", e),
+            },
+            _ = done.changed() => break,
+        }
+    }
+    if let Ok(body) = scrape(&addr).await {
+        server.add(&body, budget_us);
+    }
+
+    return server;
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    return sorted[index];
+}
+
+fn distribution(label: &str, mut values: Vec<Duration>) {
+    values.sort();
+    println!(
+        "{:<22} n={} p50={:?} p90={:?} p99={:?} max={:?}",
+        label,
+        values.len(),
+        percentile(&values, 0.5),
+        percentile(&values, 0.9),
+        percentile(&values, 0.99),
+        values.last().copied().unwrap_or_default(),
+    );
+}
+
+fn report(args: &Args, runs: &[BotRun], server: Option<&ServerSide>) {
+    let joined = runs.iter().filter(|run| run.handshake.is_some()).count();
+    println!(
+        "connections            {}/{} joined ({:.1}%)",
+        joined,
+        runs.len(),
+        100.0 * joined as f64 / runs.len().max(1) as f64
+    );
+
+    let mut errors: HashMap<&str, usize> = HashMap::new();
+    for error in runs.iter().filter_map(|run| run.error.as_deref()) {
+        *errors.entry(error).or_insert(0) += 1;
+    }
+    for (error, count) in errors {
+        println!("  {:>6}x {}", count, error);
+    }
+
+    distribution("handshake", runs.iter().filter_map(|run| run.handshake).collect());
+
+    let gaps: Vec<Duration> = runs.iter().flat_map(|run| run.gaps.iter().copied()).collect();
+    let mean = gaps.iter().sum::<Duration>().as_secs_f64() / gaps.len().max(1) as f64;
+    let variance = gaps.iter().map(|gap| (gap.as_secs_f64() - mean).powi(2)).sum::<f64>() / gaps.len().max(1) as f64;
+    println!(
+        "snapshots              {} received, inter-arrival mean {:?} jitter (stddev) {:?}",
+        runs.iter().map(|run| run.snapshots).sum::<usize>(),
+        Duration::from_secs_f64(mean),
+        Duration::from_secs_f64(variance.sqrt())
+    );
+    distribution("snapshot inter-arrival", gaps);
+
+    let dropped: Vec<Duration> = runs.iter().filter_map(|run| run.dropped_after).collect();
+    println!("dropped during hold    {} of {} ({}s hold{})", dropped.len(), joined, args.hold, if args.idle { ", idle" } else { "" });
+    if !dropped.is_empty() {
+        distribution("dropped after", dropped);
+    }
+
+    if let Some(server) = server {
+        println!(
+            "server                 {} ticks over {}us, peak {} games and {} players",
+            server.total_overruns(),
+            args.tick_budget_us,
+            server.peak_games,
+            server.peak_players
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: &'static Args = Box::leak(Box::new(Args::parse()));
+    if args.ramp <= 0.0 {
+        return Err(anyhow!("--ramp has to be more than 0"));
+    }
+
+    let (done, done_rx) = watch::channel(false);
+    let metrics = args
+        .metrics
+        .clone()
+        .map(|addr| tokio::spawn(watch_metrics(addr, args.tick_budget_us, done_rx)));
+
+    let start = Instant::now();
+    let mut bots = Vec::with_capacity(args.players);
+    for index in 0..args.players {
+        tokio::time::sleep_until(start + Duration::from_secs_f64(index as f64 / args.ramp)).await;
+        bots.push(tokio::spawn(run_bot(index, args)));
+    }
+
+    let mut runs = Vec::with_capacity(bots.len());
+    for bot in bots {
+        runs.push(bot.await?);
+    }
+    _ = done.send(true);
+
+    let server = match metrics {
+        Some(metrics) => Some(metrics.await?),
+        None => None,
+    };
+    println!("{} players against {} in {:?}", args.players, args.addr, start.elapsed());
+    report(args, &runs, server.as_ref());
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use super::{overruns, ServerSide};
+
+    #[test]
+    fn test_overruns_come_from_the_tick_histogram() {
+        let before = "vim_royale_active_games 1\n\
+            vim_royale_tick_duration_us_bucket{game_id=\"1\",le=\"16666\"} 90\n\
+            vim_royale_tick_duration_us_count{game_id=\"1\"} 100\n";
+        let after = "vim_royale_active_games 2\n\
+            vim_royale_players_connected 7\n\
+            vim_royale_tick_duration_us_bucket{game_id=\"1\",le=\"8000\"} 100\n\
+            vim_royale_tick_duration_us_bucket{game_id=\"1\",le=\"16666\"} 180\n\
+            vim_royale_tick_duration_us_count{game_id=\"1\"} 200\n\
+            vim_royale_tick_duration_us_bucket{game_id=\"2\",le=\"16666\"} 50\n\
+            vim_royale_tick_duration_us_count{game_id=\"2\"} 55\n";
+
+        assert_eq!(overruns(after, 16_666).get("1"), Some(&20));
+
+        let mut server = ServerSide::default();
+        server.add(before, 16_666);
+        server.add(after, 16_666);
+        // game 1 already had 10 before the run
+        assert_eq!(server.total_overruns(), 10 + 5);
+        assert_eq!((server.peak_games, server.peak_players), (2, 7));
+    }
+}
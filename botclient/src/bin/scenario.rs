@@ -0,0 +1,48 @@
+// Runs scenario files against a running server, see botclient::scenario for
+// the format. Prints a line per scenario and exits 1 when any failed.
+//
+// cargo run -p botclient --bin scenario -- --addr host:42001 botclient/tests/scenarios/*.scenario
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use botclient::scenario::{run, Scenario};
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap()]
+struct Args {
+    // the game server, host:port
+    #[clap(long = "addr", default_value = "127.0.0.1:42001")]
+    addr: String,
+
+    scenarios: Vec<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut failed = 0;
+    for path in &args.scenarios {
+        let scenario = Scenario::read(path)?;
+        match run(&scenario, &args.addr).await {
+            Ok(report) => {
+                if !report.passed() {
+                    failed += 1;
+                }
+                println!("{}", report);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}: {:#}", scenario.name, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+
+    return Ok(());
+}
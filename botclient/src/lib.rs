@@ -0,0 +1,369 @@
+pub mod model;
+pub mod scenario;
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use encoding::server::{self, NamedWhoami, PlayerName, ServerMessage, WHO_AM_I_CLIENT};
+use futures::{SinkExt, StreamExt};
+use tracing::warn;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite;
+
+pub use model::GameModel;
+
+// how long connect waits for the server to put the bot in a game
+pub const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how a bot asks to be let in, reconnects ask the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Join {
+    Anonymous,
+    Named(String),
+    // a registered tournament player's token
+    Tournament(u64),
+}
+
+impl Join {
+    fn handshake(&self) -> server::Message {
+        return match self {
+            Join::Anonymous => server::Message::Whoami(WHO_AM_I_CLIENT),
+            Join::Named(name) => server::Message::NamedWhoami(NamedWhoami {
+                whoami: WHO_AM_I_CLIENT,
+                name: PlayerName::new(name),
+            }),
+            Join::Tournament(token) => server::Message::JoinTournament(*token),
+        };
+    }
+}
+
+// what the browser client answers clock syncs with, Date.now()
+fn now_millis() -> i64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+}
+
+/// a headless player over a real websocket. clock syncs are answered in the
+/// background like the real client does, everything else the server sends
+/// goes into `model` and waits to be read with next_message or expect.
+#[derive(Debug)]
+pub struct BotClient {
+    addr: String,
+    join: Join,
+    pub model: GameModel,
+    seq_nu: u16,
+    // read while waiting to join, not handed out yet
+    pending: VecDeque<server::Message>,
+    inbox: mpsc::UnboundedReceiver<server::Message>,
+    outbox: Option<mpsc::UnboundedSender<tungstenite::Message>>,
+    reader: JoinHandle<()>,
+    // None once disconnected
+    writer: Option<JoinHandle<()>>,
+}
+
+impl BotClient {
+    /// connects to addr (host:port) and returns once the bot is in a lobby
+    /// or a game. join errors and a full server are errors.
+    pub async fn connect(addr: &str, join: Join) -> Result<Self> {
+        let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await?;
+        let (mut sink, mut stream) = socket.split();
+
+        let (out_tx, mut out_rx) = mpsc::unbounded_channel::<tungstenite::Message>();
+        let (in_tx, inbox) = mpsc::unbounded_channel();
+
+        // the sink closes once every sender is gone
+        let writer = tokio::spawn(async move {
+            while let Some(msg) = out_rx.recv().await {
+                if sink.send(msg).await.is_err() {
+                    return;
+                }
+            }
+            _ = sink.close().await;
+        });
+
+        let clock = out_tx.clone();
+        let reader = tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                let bytes = match msg {
+                    tungstenite::Message::Binary(bytes) => bytes,
+                    _ => continue,
+                };
+                let msg = match ServerMessage::deserialize(&bytes) {
+                    Ok(msg) => msg.msg,
+                    Err(e) => {
+                        warn!(error = ?e, "bad message from the server");
+                        continue;
+                    }
+                };
+
+                if let server::Message::ClockSyncRequest(_) = msg {
+                    if let Ok(resp) = ServerMessage::new(0, server::Message::clock_response(now_millis())).serialize() {
+                        _ = clock.send(tungstenite::Message::Binary(resp));
+                    }
+                    continue;
+                }
+
+                // a tick's events come together, they are read one by one
+                let msgs = match msg {
+                    server::Message::EventBatch(batch) => batch.events.into_iter().map(|e| e.into_message()).collect(),
+                    msg => vec![msg],
+                };
+                for msg in msgs {
+                    if in_tx.send(msg).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let mut bot = BotClient {
+            addr: addr.to_string(),
+            join,
+            model: GameModel::default(),
+            seq_nu: 0,
+            pending: VecDeque::new(),
+            inbox,
+            outbox: Some(out_tx),
+            reader,
+            writer: Some(writer),
+        };
+
+        bot.send(bot.join.handshake())?;
+        bot.wait_for_join().await?;
+
+        return Ok(bot);
+    }
+
+    async fn wait_for_join(&mut self) -> Result<()> {
+        let deadline = Instant::now() + JOIN_TIMEOUT;
+        loop {
+            let msg = self.recv(deadline).await?;
+            let joined = match &msg {
+                server::Message::LobbyState(_) | server::Message::PlayerStart(_) => true,
+                server::Message::JoinError(code) => return Err(anyhow!("join error {}", code)),
+                server::Message::ServerFull(wait) => return Err(anyhow!("server full, try again in {}s", wait)),
+                server::Message::ServerBusy => return Err(anyhow!("server busy, try again later")),
+                _ => false,
+            };
+            self.pending.push_back(msg);
+
+            if joined {
+                return Ok(());
+            }
+        }
+    }
+
+    // the next message off the socket, applied to the model
+    async fn recv(&mut self, deadline: Instant) -> Result<server::Message> {
+        let msg = match tokio::time::timeout_at(deadline, self.inbox.recv()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return Err(anyhow!("connection closed")),
+            Err(_) => return Err(anyhow!("timed out")),
+        };
+        self.model.apply(&msg);
+
+        return Ok(msg);
+    }
+
+    /// sends msg with the bot's next sequence number.
+    pub fn send(&mut self, msg: server::Message) -> Result<()> {
+        let outbox = self.outbox.as_ref().ok_or_else(|| anyhow!("disconnected"))?;
+        self.seq_nu = self.seq_nu.wrapping_add(1);
+        let bytes = ServerMessage::new(self.seq_nu, msg).serialize()?;
+        outbox.send(tungstenite::Message::Binary(bytes)).map_err(|_| anyhow!("disconnected"))?;
+
+        return Ok(());
+    }
+
+    /// frame goes out as is, for seeing how the server takes what no real
+    /// client sends.
+    pub fn send_frame(&mut self, frame: tungstenite::Message) -> Result<()> {
+        let outbox = self.outbox.as_ref().ok_or_else(|| anyhow!("disconnected"))?;
+        outbox.send(frame).map_err(|_| anyhow!("disconnected"))?;
+
+        return Ok(());
+    }
+
+    /// one vim key, h j k l move a tile.
+    pub fn press(&mut self, key: u8) -> Result<()> {
+        return self.send(server::Message::key_press(key, 0));
+    }
+
+    /// the closest the game has to chat, players close by see it.
+    pub fn emote(&mut self, emote_id: u8) -> Result<()> {
+        return self.send(server::Message::Emote(server::Emote { from: 0, emote_id }));
+    }
+
+    /// the next message the server sent, oldest first.
+    pub async fn next_message(&mut self, timeout: Duration) -> Result<server::Message> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+        return self.recv(Instant::now() + timeout).await;
+    }
+
+    /// like next_message, but None when nothing came in time. errors once the
+    /// connection is closed.
+    pub async fn try_next_message(&mut self, timeout: Duration) -> Result<Option<server::Message>> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(Some(msg));
+        }
+        return match tokio::time::timeout(timeout, self.inbox.recv()).await {
+            Ok(Some(msg)) => {
+                self.model.apply(&msg);
+                Ok(Some(msg))
+            }
+            Ok(None) => Err(anyhow!("connection closed")),
+            Err(_) => Ok(None),
+        };
+    }
+
+    /// skips messages until pick takes one, errors when none did in time.
+    pub async fn expect<T>(&mut self, timeout: Duration, mut pick: impl FnMut(&server::Message) -> Option<T>) -> Result<T> {
+        let deadline = Instant::now() + timeout;
+        while let Some(msg) = self.pending.pop_front() {
+            if let Some(picked) = pick(&msg) {
+                return Ok(picked);
+            }
+        }
+
+        loop {
+            let msg = self.recv(deadline).await.map_err(|e| anyhow!("expected message not seen: {}", e))?;
+            if let Some(picked) = pick(&msg) {
+                return Ok(picked);
+            }
+        }
+    }
+
+    /// reads messages until the model passes check, errors when it didn't in time.
+    pub async fn wait_until(&mut self, timeout: Duration, mut check: impl FnMut(&GameModel) -> bool) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        self.pending.clear();
+        while !check(&self.model) {
+            self.recv(deadline).await.map_err(|e| anyhow!("model never got there: {}", e))?;
+        }
+
+        return Ok(());
+    }
+
+    /// closes the socket, the server sees a normal close.
+    pub async fn disconnect(&mut self) {
+        // the reader holds a sender too for the clock syncs
+        self.reader.abort();
+        self.outbox = None;
+        if let Some(writer) = self.writer.take() {
+            _ = writer.await;
+        }
+    }
+
+    /// disconnects and joins again the way the bot first did, with a fresh model.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.disconnect().await;
+        *self = BotClient::connect(&self.addr, self.join.clone()).await?;
+
+        return Ok(());
+    }
+}
+
+impl Drop for BotClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use encoding::server;
+    use game::{game_config::GameConfig, server::LocalServer};
+
+    use super::{BotClient, Join};
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn lobby_count(msg: &server::Message) -> Option<usize> {
+        return match msg {
+            server::Message::LobbyState(lobby) => Some(lobby.players.len()),
+            _ => None,
+        };
+    }
+
+    #[tokio::test]
+    async fn test_lobby_fills_and_starts() -> Result<()> {
+        let server = LocalServer::start(GameConfig {
+            min_players: 3,
+            ..GameConfig::default()
+        })?;
+        let addr = server.address();
+
+        let mut ada = BotClient::connect(&addr, Join::Named("ada".to_string())).await?;
+        let mut bob = BotClient::connect(&addr, Join::Named("bob".to_string())).await?;
+        ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 2)).await?;
+
+        let mut cy = BotClient::connect(&addr, Join::Named("cy".to_string())).await?;
+        for bot in [&mut ada, &mut bob, &mut cy] {
+            bot.wait_until(WAIT, |model| model.entity_id.is_some() && model.names.len() == 3).await?;
+        }
+
+        assert_eq!(ada.model.own_name(), Some("ada"));
+        assert_eq!(cy.model.own_name(), Some("cy"));
+        assert_ne!(ada.model.entity_id, bob.model.entity_id);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_movement_round_trips() -> Result<()> {
+        let server = LocalServer::start(GameConfig::default())?;
+        let addr = server.address();
+        let mut bot = BotClient::connect(&addr, Join::Anonymous).await?;
+        bot.wait_until(WAIT, |model| model.position.is_some()).await?;
+        let start = bot.model.position.unwrap_or_default();
+
+        // some neighbour of the spawn is walkable, keep pressing until the
+        // server's snapshots show the step
+        for (key, (dx, dy)) in [(b'h', (-1, 0)), (b'j', (0, 1)), (b'k', (0, -1)), (b'l', (1, 0))] {
+            let to = ((start.0 as i32 + dx) as u16, (start.1 as i32 + dy) as u16);
+            for _ in 0..10 {
+                bot.press(key)?;
+                let moved = bot.wait_until(Duration::from_millis(100), |model| model.position != Some(start)).await;
+                if moved.is_ok() {
+                    assert_eq!(bot.model.position, Some(to));
+                    return Ok(());
+                }
+            }
+        }
+
+        panic!("never moved off {:?}", start);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_frees_the_lobby_slot() -> Result<()> {
+        let server = LocalServer::start(GameConfig {
+            min_players: 3,
+            ..GameConfig::default()
+        })?;
+        let addr = server.address();
+
+        let mut ada = BotClient::connect(&addr, Join::Named("ada".to_string())).await?;
+        let mut bob = BotClient::connect(&addr, Join::Named("bob".to_string())).await?;
+        ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 2)).await?;
+
+        bob.disconnect().await;
+        ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 1)).await?;
+        assert!(bob.press(b'h').is_err());
+
+        bob.reconnect().await?;
+        ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 2)).await?;
+        assert_eq!(ada.model.lobby.len(), 2);
+
+        return Ok(());
+    }
+}
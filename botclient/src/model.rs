@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use encoding::server::{self, Zone};
+use encoding::tick::widen_tick;
+
+/// what a bot knows about its game, built only from what the server sent it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GameModel {
+    // own entity, None until PlayerStart
+    pub entity_id: Option<usize>,
+    pub position: Option<(u16, u16)>,
+    pub seed: Option<u32>,
+    pub zone: Option<Zone>,
+    // entity id to display name, from the lobby and PlayerJoined
+    pub names: HashMap<usize, String>,
+    // who is waiting in the lobby, empty once the game started
+    pub lobby: Vec<server::LobbyPlayer>,
+    // every entity of the last snapshot, own one included
+    pub entities: HashMap<usize, (u16, u16)>,
+    // widened from the wire's u32 against the last one
+    pub server_tick: Option<u128>,
+    // last countdown, 0 means the match is live
+    pub countdown: Option<u8>,
+    pub join_error: Option<u8>,
+    // the player a spectating bot follows
+    pub following: Option<usize>,
+}
+
+fn name(name: &server::PlayerName) -> String {
+    return String::from_utf8_lossy(&name.name).to_string();
+}
+
+impl GameModel {
+    pub fn apply(&mut self, msg: &server::Message) {
+        match msg {
+            server::Message::PlayerStart(start) => {
+                self.entity_id = Some(start.entity_id);
+                self.position = Some(start.position);
+                self.seed = Some(start.seed);
+                self.see_tick(start.server_tick);
+                self.entities.insert(start.entity_id, start.position);
+                self.lobby.clear();
+            }
+
+            server::Message::LobbyState(lobby) => {
+                for player in &lobby.players {
+                    self.names.insert(player.entity_id, name(&player.name));
+                }
+                self.lobby = lobby.players.clone();
+            }
+
+            server::Message::PlayerJoined(joined) => {
+                self.names.insert(joined.entity_id, name(&joined.name));
+            }
+
+            server::Message::Snapshot(snapshot) | server::Message::SpectatorSync(snapshot) => {
+                self.apply_snapshot(snapshot);
+            }
+
+            // the model is tile based, same as collision
+            server::Message::FineSnapshot(snapshot) => self.apply_snapshot(&snapshot.to_tiles()),
+
+            server::Message::Roster(roster) => {
+                self.entities = roster.entities.iter().map(|e| (e.entity_id, e.position)).collect();
+            }
+
+            server::Message::PlayerPositionUpdate(update) => {
+                self.entities.insert(update.entity_id, update.position);
+                if Some(update.entity_id) == self.entity_id {
+                    self.position = Some(update.position);
+                }
+            }
+
+            server::Message::ZoneUpdate(zone) => self.zone = Some(zone.clone()),
+            server::Message::Countdown(seconds) => self.countdown = Some(*seconds),
+            server::Message::JoinError(code) => self.join_error = Some(*code),
+            server::Message::SpectatorStart(start) => {
+                self.seed = Some(start.seed);
+                self.zone = Some(start.zone.clone());
+            }
+            server::Message::Following(following) => self.following = Some(following.entity_id),
+            server::Message::FollowChanged(changed) => self.following = changed.new_target,
+            server::Message::EventBatch(batch) => {
+                for event in &batch.events {
+                    self.apply(&event.clone().into_message());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_snapshot(&mut self, snapshot: &server::Snapshot) {
+        self.entities = snapshot.entities.iter().map(|e| (e.entity_id, e.position)).collect();
+        if let Some(own) = self.entity_id.and_then(|id| self.entities.get(&id)) {
+            self.position = Some(*own);
+        }
+        self.see_tick(snapshot.server_tick);
+    }
+
+    fn see_tick(&mut self, wire: Option<u32>) {
+        if let Some(wire) = wire {
+            self.server_tick = Some(widen_tick(wire, self.server_tick.unwrap_or(wire as u128)));
+        }
+    }
+
+    /// the display name of the bot's own entity, once both are known.
+    pub fn own_name(&self) -> Option<&str> {
+        return self.entity_id.and_then(|id| self.names.get(&id)).map(|n| n.as_str());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use encoding::server::{self, LobbyPlayer, PlayerName, PlayerPositionUpdate, PlayerStart, Snapshot};
+
+    use super::GameModel;
+
+    #[test]
+    fn test_model_follows_the_lobby_into_the_game() {
+        let mut model = GameModel::default();
+        let ada = LobbyPlayer {
+            entity_id: 0,
+            name: PlayerName::new("ada"),
+            ready: true,
+        };
+        model.apply(&server::Message::LobbyState(server::LobbyState::new(vec![ada])));
+        assert_eq!(model.lobby.len(), 1);
+
+        model.apply(&server::Message::PlayerStart(PlayerStart {
+            entity_id: 0,
+            range: 500,
+            position: (10, 10),
+            seed: 7,
+            view_distance: None,
+            server_tick: Some(3),
+            region: None,
+        }));
+        assert!(model.lobby.is_empty());
+        assert_eq!(model.own_name(), Some("ada"));
+
+        let moved = PlayerPositionUpdate {
+            entity_id: 0,
+            position: (11, 10),
+        };
+        let other = PlayerPositionUpdate {
+            entity_id: 500,
+            position: (12, 10),
+        };
+        model.apply(&server::Message::Snapshot(Snapshot::new(4, vec![moved, other])));
+        assert_eq!(model.position, Some((11, 10)));
+        assert_eq!(model.entities.len(), 2);
+        assert_eq!(model.server_tick, Some(4));
+    }
+}
@@ -0,0 +1,853 @@
+// Gameplay scenarios as text files, so a scenario doesn't need Rust written
+// for it. Each player of a scenario is a BotClient and every line is a step:
+//
+//     # ada walks into the rock west of spawn, bob watches
+//     players ada bob
+//     0 ada press h
+//     1 ada press h
+//     2 ada press h
+//     4 ada check position=126,128
+//     4 bob check sees.ada=126,128
+//     10 ada emote 1
+//     10 bob expect emote from=ada emote_id=1
+//     12 ada never emote emote_id=2 within=30
+//     40 bob reconnect
+//
+// The number is the tick the step runs at, counted from the tick every
+// player was in the game. Steps run one at a time in file order. expect,
+// never and check hold up the steps after them until they pass or run out of
+// ticks, within=N or DEFAULT_WITHIN.
+//
+// expect waits for a message, named like MESSAGE_TAG_NAMES, whose fields
+// match. never fails if such a message shows up. check waits for the bot's
+// model to match, see model_view for its fields. Fields are paths into the
+// message's json, `players.0.ready=true`. Values are numbers, `x,y`,
+// true/false, "text", self or a player's name for their entity id.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use encoding::server::{self, MESSAGE_TAG_NAMES};
+use serde_json::{json, Value};
+use tokio::time::Instant;
+
+use crate::{BotClient, GameModel, Join};
+
+// ticks an expect, never or check gets without within=
+pub const DEFAULT_WITHIN: u128 = 20;
+
+// the scenario fails when the server stops ticking for this long, a game
+// that ended or a server that died never gets to the next tick
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// how many unread messages a player keeps for expect, oldest go first
+const INBOX_LIMIT: usize = 1024;
+
+const POLL: Duration = Duration::from_millis(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub players: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    // 1 based, for the report
+    pub line: usize,
+    pub text: String,
+    pub at: u128,
+    pub player: String,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    // one key press each, in order
+    Press(Vec<u8>),
+    Emote(u8),
+    Disconnect,
+    // joins again the way the player first did, see BotClient::reconnect
+    Reconnect,
+    Expect(Expectation),
+    Never(Expectation),
+    Check(Vec<Field>, u128),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectation {
+    pub message: String,
+    pub fields: Vec<Field>,
+    pub within: u128,
+}
+
+/// path=value, the path goes through objects by key and arrays by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub path: Vec<String>,
+    pub value: Expected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    Number(i64),
+    Pair(i64, i64),
+    Bool(bool),
+    // matches a string or bytes that decode to it, names and announcements
+    // go over the wire as bytes
+    Text(String),
+    // the entity id of the player running the step
+    Own,
+    // the entity id of another player of the scenario
+    Player(String),
+}
+
+// whitespace separated, "quoted text" stays one token quotes and all. a #
+// outside quotes starts a comment
+fn tokens(line: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            '#' if !quoted => break,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if quoted {
+        bail!("unterminated quote");
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    return Ok(tokens);
+}
+
+fn parse_number(value: &str) -> Result<i64> {
+    return value.parse().map_err(|_| anyhow!("{} is not a number", value));
+}
+
+fn parse_expected(value: &str, players: &[String]) -> Result<Expected> {
+    if let Some(text) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return Ok(Expected::Text(text.to_string()));
+    }
+    if let Some((x, y)) = value.split_once(',') {
+        return Ok(Expected::Pair(parse_number(x)?, parse_number(y)?));
+    }
+
+    return match value {
+        "true" => Ok(Expected::Bool(true)),
+        "false" => Ok(Expected::Bool(false)),
+        "self" => Ok(Expected::Own),
+        name if players.iter().any(|p| p == name) => Ok(Expected::Player(name.to_string())),
+        number => {
+            let number = parse_number(number).context("expected a number, x,y, true, false, \"text\", self or a player")?;
+            Ok(Expected::Number(number))
+        }
+    };
+}
+
+// the fields and within= of an expect, never or check
+fn parse_fields(args: &[String], players: &[String]) -> Result<(Vec<Field>, u128)> {
+    let mut fields = vec![];
+    let mut within = DEFAULT_WITHIN;
+
+    for arg in args {
+        let Some((path, value)) = arg.split_once('=') else {
+            bail!("{} is not path=value", arg);
+        };
+        if path == "within" {
+            within = value.parse().map_err(|_| anyhow!("within={} is not a tick count", value))?;
+            continue;
+        }
+        if path.is_empty() || path.split('.').any(|part| part.is_empty()) {
+            bail!("{} is not a path", path);
+        }
+
+        fields.push(Field {
+            path: path.split('.').map(String::from).collect(),
+            value: parse_expected(value, players)?,
+        });
+    }
+
+    return Ok((fields, within));
+}
+
+fn parse_expectation(args: &[String], players: &[String]) -> Result<Expectation> {
+    let Some((message, args)) = args.split_first() else {
+        bail!("which message?");
+    };
+    if message == "unused" || !MESSAGE_TAG_NAMES.contains(&message.as_str()) {
+        bail!("there is no message called {}", message);
+    }
+
+    let (fields, within) = parse_fields(args, players)?;
+    return Ok(Expectation {
+        message: message.clone(),
+        fields,
+        within,
+    });
+}
+
+fn parse_action(action: &str, args: &[String], players: &[String]) -> Result<Action> {
+    let no_args = |action: Action| {
+        if !args.is_empty() {
+            bail!("unexpected {}", args.join(" "));
+        }
+        return Ok(action);
+    };
+
+    return match action {
+        "press" => match args {
+            [keys] => Ok(Action::Press(keys.as_bytes().to_vec())),
+            _ => bail!("press takes the keys, press hjk"),
+        },
+        "emote" => match args {
+            [id] => Ok(Action::Emote(id.parse().map_err(|_| anyhow!("{} is not an emote id", id))?)),
+            _ => bail!("emote takes an emote id"),
+        },
+        "disconnect" => no_args(Action::Disconnect),
+        "reconnect" => no_args(Action::Reconnect),
+        "expect" => Ok(Action::Expect(parse_expectation(args, players)?)),
+        "never" => Ok(Action::Never(parse_expectation(args, players)?)),
+        "check" => {
+            let (fields, within) = parse_fields(args, players)?;
+            if fields.is_empty() {
+                bail!("check what?");
+            }
+            Ok(Action::Check(fields, within))
+        }
+        action => bail!("unknown step {}", action),
+    };
+}
+
+impl Scenario {
+    pub fn parse(name: &str, text: &str) -> Result<Scenario> {
+        let mut scenario = Scenario {
+            name: name.to_string(),
+            players: vec![],
+            steps: vec![],
+        };
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let parsed = tokens(line).and_then(|tokens| {
+                return scenario.parse_line(&tokens).map(|step| {
+                    return step.map(|(at, player, action)| Step {
+                        line: line_number,
+                        text: tokens.join(" "),
+                        at,
+                        player,
+                        action,
+                    });
+                });
+            });
+
+            if let Some(step) = parsed.with_context(|| format!("{} line {}", name, line_number))? {
+                scenario.steps.push(step);
+            }
+        }
+
+        if scenario.players.is_empty() {
+            bail!("{} has no players line", name);
+        }
+
+        return Ok(scenario);
+    }
+
+    /// a scenario file, named after the file without its extension.
+    pub fn read(path: &Path) -> Result<Scenario> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+        return Scenario::parse(&name, &text);
+    }
+
+    // None for blank lines and the players line
+    fn parse_line(&mut self, tokens: &[String]) -> Result<Option<(u128, String, Action)>> {
+        let Some((first, rest)) = tokens.split_first() else {
+            return Ok(None);
+        };
+
+        if first == "players" {
+            if !self.players.is_empty() {
+                bail!("players is already set");
+            }
+            if rest.is_empty() {
+                bail!("players needs at least one name");
+            }
+            if let Some(taken) = rest.iter().find(|name| name.as_str() == "self") {
+                bail!("{} can't be a player's name", taken);
+            }
+            self.players = rest.to_vec();
+            return Ok(None);
+        }
+
+        if self.players.is_empty() {
+            bail!("steps before the players line");
+        }
+
+        let at: u128 = first.parse().map_err(|_| anyhow!("{} is not a tick", first))?;
+        if let Some(last) = self.steps.last() {
+            if at < last.at {
+                bail!("tick {} comes after tick {}", at, last.at);
+            }
+        }
+
+        let [player, action, args @ ..] = rest else {
+            bail!("expected <tick> <player> <step>");
+        };
+        if !self.players.contains(player) {
+            bail!("{} isn't in the players line", player);
+        }
+
+        return Ok(Some((at, player.clone(), parse_action(action, args, &self.players)?)));
+    }
+}
+
+// what a message looks like to the field paths, the json without the
+// message name around it
+pub fn message_view(msg: &server::Message) -> Value {
+    return match serde_json::to_value(msg) {
+        Ok(Value::Object(mut fields)) if fields.len() == 1 => {
+            let name = fields.keys().next().cloned().unwrap_or_default();
+            fields.remove(&name).unwrap_or(Value::Null)
+        }
+        // messages without fields
+        _ => Value::Null,
+    };
+}
+
+/// what check looks at: position, entity_id, in_game, entities (how many
+/// the last snapshot had), lobby (how many are waiting), countdown, and
+/// sees.<player> for where that player's entity is.
+pub fn model_view(model: &GameModel, players: &[(String, Option<usize>)]) -> Value {
+    let sees: serde_json::Map<String, Value> = players
+        .iter()
+        .filter_map(|(name, id)| {
+            let (x, y) = model.entities.get(&(*id)?)?;
+            return Some((name.clone(), json!([x, y])));
+        })
+        .collect();
+
+    return json!({
+        "entity_id": model.entity_id,
+        "position": model.position.map(|(x, y)| json!([x, y])),
+        "in_game": model.position.is_some(),
+        "entities": model.entities.len(),
+        "lobby": model.lobby.len(),
+        "countdown": model.countdown,
+        "sees": sees,
+    });
+}
+
+fn lookup<'a>(view: &'a Value, path: &[String]) -> Option<&'a Value> {
+    let mut value = view;
+    for part in path {
+        value = match value {
+            Value::Object(fields) => fields.get(part)?,
+            Value::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    return Some(value);
+}
+
+// bytes as the wire sends text, padding and all
+fn as_text(value: &Value) -> Option<String> {
+    return match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(items) => {
+            let bytes: Option<Vec<u8>> = items.iter().map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok())).collect();
+            Some(String::from_utf8_lossy(&bytes?).trim_end_matches('\0').to_string())
+        }
+        _ => None,
+    };
+}
+
+/// player names resolved to entity ids, own is the player running the step.
+pub struct Names<'a> {
+    pub own: Option<usize>,
+    pub players: &'a [(String, Option<usize>)],
+}
+
+impl Names<'_> {
+    fn id(&self, name: &str) -> Option<usize> {
+        return self.players.iter().find(|(n, _)| n == name).and_then(|(_, id)| *id);
+    }
+}
+
+/// whether the value at field's path is what it expects. players that
+/// aren't in a game have no entity id and match nothing.
+pub fn field_matches(view: &Value, field: &Field, names: &Names) -> bool {
+    let Some(actual) = lookup(view, &field.path) else {
+        return false;
+    };
+
+    return match &field.value {
+        Expected::Number(n) => actual.as_i64() == Some(*n),
+        Expected::Pair(x, y) => {
+            actual.as_array().is_some_and(|pair| pair.len() == 2 && pair[0].as_i64() == Some(*x) && pair[1].as_i64() == Some(*y))
+        }
+        Expected::Bool(b) => actual.as_bool() == Some(*b),
+        Expected::Text(text) => as_text(actual).as_deref() == Some(text.as_str()),
+        Expected::Own => names.own.is_some_and(|id| actual.as_u64() == Some(id as u64)),
+        Expected::Player(name) => names.id(name).is_some_and(|id| actual.as_u64() == Some(id as u64)),
+    };
+}
+
+fn expectation_matches(msg: &server::Message, expectation: &Expectation, names: &Names) -> bool {
+    if MESSAGE_TAG_NAMES[msg.tag()] != expectation.message {
+        return false;
+    }
+
+    let view = message_view(msg);
+    return expectation.fields.iter().all(|field| field_matches(&view, field, names));
+}
+
+/// how a scenario went, failure is the first step that didn't pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub name: String,
+    // steps that passed
+    pub passed: usize,
+    pub failure: Option<Failure>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub line: usize,
+    pub step: String,
+    pub reason: String,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        return self.failure.is_none();
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match &self.failure {
+            None => write!(f, "PASS {} ({} steps)", self.name, self.passed),
+            Some(failure) => write!(
+                f,
+                "FAIL {} line {}: `{}` {} ({} steps passed)",
+                self.name, failure.line, failure.step, failure.reason, self.passed
+            ),
+        };
+    }
+}
+
+struct Player {
+    name: String,
+    bot: BotClient,
+    // read but not matched by an expect yet
+    inbox: VecDeque<server::Message>,
+    connected: bool,
+}
+
+struct Runner {
+    addr: String,
+    players: Vec<Player>,
+    // the highest server tick any player has seen
+    clock: u128,
+    // when clock last moved
+    ticked: Instant,
+}
+
+impl Runner {
+    fn player(&mut self, name: &str) -> &mut Player {
+        let index = self.players.iter().position(|p| p.name == name).expect("parse checked the player names");
+        return &mut self.players[index];
+    }
+
+    fn ids(&self) -> Vec<(String, Option<usize>)> {
+        return self.players.iter().map(|p| (p.name.clone(), p.bot.model.entity_id)).collect();
+    }
+
+    // reads whatever came in without waiting on it
+    async fn pump(&mut self) {
+        for player in self.players.iter_mut().filter(|p| p.connected) {
+            loop {
+                match player.bot.try_next_message(Duration::ZERO).await {
+                    Ok(Some(msg)) => {
+                        if player.inbox.len() == INBOX_LIMIT {
+                            player.inbox.pop_front();
+                        }
+                        player.inbox.push_back(msg);
+                    }
+                    Ok(None) => break,
+                    Err(_) => {
+                        player.connected = false;
+                        break;
+                    }
+                }
+            }
+
+            if let Some(tick) = player.bot.model.server_tick.filter(|&tick| tick > self.clock) {
+                self.clock = tick;
+                self.ticked = Instant::now();
+            }
+        }
+    }
+
+    // Err once the server stopped ticking
+    async fn wait(&mut self) -> Result<()> {
+        tokio::time::sleep(POLL).await;
+        self.pump().await;
+        if self.ticked.elapsed() > STALL_TIMEOUT {
+            bail!("the server stopped ticking at tick {}", self.clock);
+        }
+
+        return Ok(());
+    }
+
+    async fn wait_for_tick(&mut self, tick: u128) -> Result<()> {
+        while self.clock < tick {
+            self.wait().await?;
+        }
+
+        return Ok(());
+    }
+
+    // Ok(Err(reason)) when the step failed, Err when running it did
+    async fn step(&mut self, step: &Step) -> Result<std::result::Result<(), String>> {
+        let ids = self.ids();
+        match &step.action {
+            Action::Press(keys) => {
+                let bot = &mut self.player(&step.player).bot;
+                for key in keys {
+                    bot.press(*key)?;
+                }
+            }
+
+            Action::Emote(id) => self.player(&step.player).bot.emote(*id)?,
+
+            Action::Disconnect => {
+                let player = self.player(&step.player);
+                player.bot.disconnect().await;
+                player.connected = false;
+            }
+
+            Action::Reconnect => {
+                let player = self.player(&step.player);
+                player.bot.reconnect().await?;
+                player.inbox.clear();
+                player.connected = true;
+            }
+
+            Action::Expect(expectation) => {
+                let deadline = self.clock + expectation.within;
+                loop {
+                    let player = self.player(&step.player);
+                    let names = Names {
+                        own: player.bot.model.entity_id,
+                        players: &ids,
+                    };
+                    if let Some(found) = player.inbox.iter().position(|msg| expectation_matches(msg, expectation, &names)) {
+                        player.inbox.drain(..=found);
+                        return Ok(Ok(()));
+                    }
+                    if self.clock > deadline {
+                        return Ok(Err(format!("not seen within {} ticks", expectation.within)));
+                    }
+                    self.wait().await?;
+                }
+            }
+
+            Action::Never(expectation) => {
+                let deadline = self.clock + expectation.within;
+                while self.clock <= deadline {
+                    let clock = self.clock;
+                    let player = self.player(&step.player);
+                    let names = Names {
+                        own: player.bot.model.entity_id,
+                        players: &ids,
+                    };
+                    if let Some(seen) = player.inbox.iter().find(|msg| expectation_matches(msg, expectation, &names)) {
+                        return Ok(Err(format!("saw {:?} by tick {}", seen, clock)));
+                    }
+                    self.wait().await?;
+                }
+            }
+
+            Action::Check(fields, within) => {
+                let deadline = self.clock + within;
+                loop {
+                    let player = self.player(&step.player);
+                    let names = Names {
+                        own: player.bot.model.entity_id,
+                        players: &ids,
+                    };
+                    let view = model_view(&player.bot.model, &ids);
+                    let failed: Vec<String> = fields
+                        .iter()
+                        .filter(|field| !field_matches(&view, field, &names))
+                        .map(|field| format!("{} was {}", field.path.join("."), lookup(&view, &field.path).unwrap_or(&Value::Null)))
+                        .collect();
+
+                    if failed.is_empty() {
+                        return Ok(Ok(()));
+                    }
+                    if self.clock > deadline {
+                        return Ok(Err(format!("still {} after {} ticks", failed.join(", "), within)));
+                    }
+                    self.wait().await?;
+                }
+            }
+        }
+
+        return Ok(Ok(()));
+    }
+}
+
+/// connects every player of scenario to the server at addr as a named bot,
+/// waits until all of them are in the game and runs the steps. Err when
+/// the players couldn't get into a game, a step that fails is in the report.
+pub async fn run(scenario: &Scenario, addr: &str) -> Result<Report> {
+    let mut players = vec![];
+    for name in &scenario.players {
+        let bot = BotClient::connect(addr, Join::Named(name.clone()))
+            .await
+            .with_context(|| format!("{} couldn't join", name))?;
+        players.push(Player {
+            name: name.clone(),
+            bot,
+            inbox: VecDeque::new(),
+            connected: true,
+        });
+    }
+
+    let mut runner = Runner {
+        addr: addr.to_string(),
+        players,
+        clock: 0,
+        ticked: Instant::now(),
+    };
+    while !runner.players.iter().all(|p| p.bot.model.position.is_some()) {
+        runner
+            .wait()
+            .await
+            .with_context(|| format!("not every player of {} got into a game at {}", scenario.name, runner.addr))?;
+    }
+
+    let start = runner.clock;
+    let mut report = Report {
+        name: scenario.name.clone(),
+        passed: 0,
+        failure: None,
+    };
+
+    for step in &scenario.steps {
+        let ran = match runner.wait_for_tick(start + step.at).await {
+            Ok(()) => runner.step(step).await,
+            Err(e) => Err(e),
+        };
+        let reason = match ran {
+            Ok(Ok(())) => {
+                report.passed += 1;
+                continue;
+            }
+            Ok(Err(reason)) => reason,
+            Err(e) => e.to_string(),
+        };
+
+        report.failure = Some(Failure {
+            line: step.line,
+            step: step.text.clone(),
+            reason,
+        });
+        break;
+    }
+
+    for player in runner.players.iter_mut() {
+        player.bot.disconnect().await;
+    }
+
+    return Ok(report);
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use encoding::server::{self, Announcement, Emote, ANNOUNCEMENT_WARNING};
+    use serde_json::json;
+
+    use super::{field_matches, message_view, model_view, Action, Expected, Expectation, Field, Names, Scenario};
+    use crate::GameModel;
+
+    fn field(path: &str, value: Expected) -> Field {
+        return Field {
+            path: path.split('.').map(String::from).collect(),
+            value,
+        };
+    }
+
+    fn players() -> Vec<(String, Option<usize>)> {
+        return vec![("ada".to_string(), Some(0)), ("bob".to_string(), Some(500)), ("cy".to_string(), None)];
+    }
+
+    #[test]
+    fn test_parse_steps() -> Result<()> {
+        let text = "
+            # a comment
+            players ada bob
+
+            0 ada press hjk   # pressed in order
+            3 bob emote 1
+            3 bob expect emote from=ada emote_id=1 within=5
+            4 ada never announcement text=\"zone closing\"
+            9 ada check position=126,128 sees.bob=1,2 in_game=true
+            12 bob reconnect
+        ";
+        let scenario = Scenario::parse("walk", text)?;
+        assert_eq!(scenario.players, vec!["ada", "bob"]);
+
+        let actions: Vec<_> = scenario.steps.iter().map(|step| (step.line, step.at, step.action.clone())).collect();
+        assert_eq!(
+            actions,
+            vec![
+                (5, 0, Action::Press(b"hjk".to_vec())),
+                (6, 3, Action::Emote(1)),
+                (
+                    7,
+                    3,
+                    Action::Expect(Expectation {
+                        message: "emote".to_string(),
+                        fields: vec![field("from", Expected::Player("ada".to_string())), field("emote_id", Expected::Number(1))],
+                        within: 5,
+                    })
+                ),
+                (
+                    8,
+                    4,
+                    Action::Never(Expectation {
+                        message: "announcement".to_string(),
+                        fields: vec![field("text", Expected::Text("zone closing".to_string()))],
+                        within: super::DEFAULT_WITHIN,
+                    })
+                ),
+                (
+                    9,
+                    9,
+                    Action::Check(
+                        vec![
+                            field("position", Expected::Pair(126, 128)),
+                            field("sees.bob", Expected::Pair(1, 2)),
+                            field("in_game", Expected::Bool(true)),
+                        ],
+                        super::DEFAULT_WITHIN
+                    )
+                ),
+                (10, 12, Action::Reconnect),
+            ]
+        );
+        assert_eq!(scenario.steps[0].text, "0 ada press hjk");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_line() {
+        let cases = [
+            ("0 ada press h", "line 1"),
+            ("players ada\n0 bob press h", "bob isn't in the players line"),
+            ("players ada\n5 ada press h\n2 ada press h", "tick 2 comes after tick 5"),
+            ("players ada\n0 ada jump", "unknown step jump"),
+            ("players ada\n0 ada expect volleyball", "no message called volleyball"),
+            ("players ada\n0 ada expect unused", "no message called unused"),
+            ("players ada\n0 ada check position", "position is not path=value"),
+            ("players ada\n0 ada check position=up", "expected a number"),
+            ("players ada\n0 ada expect emote within=soon", "within=soon"),
+            ("players ada\n0 ada never announcement text=\"open", "unterminated quote"),
+            ("players ada\n0 ada disconnect now", "unexpected now"),
+            ("players self", "self can't be a player's name"),
+            ("# nobody", "no players line"),
+        ];
+
+        for (text, expected) in cases {
+            let error = format!("{:#}", Scenario::parse("broken", text).expect_err(text));
+            assert!(error.contains(expected), "{:?}: {}", text, error);
+        }
+    }
+
+    #[test]
+    fn test_fields_match_message_json() {
+        let players = players();
+        let names = Names {
+            own: Some(500),
+            players: &players,
+        };
+
+        let emote = message_view(&server::Message::Emote(Emote { from: 0, emote_id: 3 }));
+        assert!(field_matches(&emote, &field("from", Expected::Player("ada".to_string())), &names));
+        assert!(!field_matches(&emote, &field("from", Expected::Own), &names));
+        assert!(field_matches(&emote, &field("emote_id", Expected::Number(3)), &names));
+        assert!(!field_matches(&emote, &field("emote_id", Expected::Text("3".to_string())), &names));
+        // not in a game, no entity id to match
+        assert!(!field_matches(&emote, &field("from", Expected::Player("cy".to_string())), &names));
+        assert!(!field_matches(&emote, &field("missing", Expected::Number(0)), &names));
+
+        let announcement = message_view(&server::Message::Announcement(Announcement::new(ANNOUNCEMENT_WARNING, "zone closing")));
+        assert!(field_matches(&announcement, &field("text", Expected::Text("zone closing".to_string())), &names));
+        assert!(!field_matches(&announcement, &field("text", Expected::Text("zone".to_string())), &names));
+
+        let snapshot = message_view(&server::Message::Snapshot(server::Snapshot::new(
+            7,
+            vec![server::PlayerPositionUpdate {
+                entity_id: 500,
+                position: (126, 128),
+            }],
+        )));
+        assert!(field_matches(&snapshot, &field("entities.0.entity_id", Expected::Own), &names));
+        assert!(field_matches(&snapshot, &field("entities.0.position", Expected::Pair(126, 128)), &names));
+        assert!(!field_matches(&snapshot, &field("entities.1.position", Expected::Pair(126, 128)), &names));
+        assert!(!field_matches(&snapshot, &field("entities.x.position", Expected::Pair(126, 128)), &names));
+
+        assert_eq!(message_view(&server::Message::ServerBusy), json!(null));
+    }
+
+    #[test]
+    fn test_model_view() {
+        let players = players();
+        let names = Names {
+            own: Some(0),
+            players: &players,
+        };
+
+        let mut model = GameModel::default();
+        let view = model_view(&model, &players);
+        assert!(field_matches(&view, &field("in_game", Expected::Bool(false)), &names));
+        assert!(field_matches(&view, &field("lobby", Expected::Number(0)), &names));
+
+        model.entity_id = Some(0);
+        model.position = Some((126, 128));
+        model.entities.insert(0, (126, 128));
+        model.entities.insert(500, (130, 128));
+        let view = model_view(&model, &players);
+        assert!(field_matches(&view, &field("entity_id", Expected::Own), &names));
+        assert!(field_matches(&view, &field("position", Expected::Pair(126, 128)), &names));
+        assert!(field_matches(&view, &field("sees.bob", Expected::Pair(130, 128)), &names));
+        assert!(field_matches(&view, &field("entities", Expected::Number(2)), &names));
+        assert!(!field_matches(&view, &field("sees.cy", Expected::Pair(130, 128)), &names));
+    }
+}
@@ -0,0 +1,141 @@
+// Bots against a whole server, listener to game loop, see
+// game::server::LocalServer. Every test gets its own server on its own port,
+// games tick fast so a whole match fits in a second.
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use botclient::{BotClient, Join};
+use encoding::server;
+use game::{
+    accept::MAX_MESSAGE_SIZE, game_config::GameConfig, game_manager::SHUTDOWN_NOTICE, server::LocalServer,
+    tick_rate::TickRate,
+};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+fn fast_game(min_players: usize) -> Result<GameConfig> {
+    return Ok(GameConfig {
+        tick_rate: TickRate::from_hz(200)?,
+        min_players,
+        ..GameConfig::default()
+    });
+}
+
+async fn named(server: &LocalServer, name: &str) -> Result<BotClient> {
+    return BotClient::connect(&server.address(), Join::Named(name.to_string())).await;
+}
+
+fn lobby_count(msg: &server::Message) -> Option<usize> {
+    return match msg {
+        server::Message::LobbyState(lobby) => Some(lobby.players.len()),
+        _ => None,
+    };
+}
+
+// reads until the server closes the connection
+async fn until_closed(bot: &mut BotClient) -> Result<()> {
+    let deadline = Instant::now() + WAIT;
+    while Instant::now() < deadline {
+        if bot.try_next_message(Duration::from_millis(100)).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    bail!("the server never closed the connection");
+}
+
+#[tokio::test]
+async fn test_two_players_play_until_time_is_up() -> Result<()> {
+    let server = LocalServer::start(GameConfig {
+        max_ticks: Some(100),
+        ..fast_game(2)?
+    })?;
+
+    let mut ada = named(&server, "ada").await?;
+    let mut bob = named(&server, "bob").await?;
+    for bot in [&mut ada, &mut bob] {
+        bot.wait_until(WAIT, |model| model.position.is_some()).await?;
+    }
+    ada.press(b'h')?;
+    bob.press(b'l')?;
+
+    for bot in [&mut ada, &mut bob] {
+        until_closed(bot).await?;
+        assert!(bot.model.server_tick.is_some_and(|tick| tick > 50), "{:?}", bot.model.server_tick);
+        assert_eq!(bot.model.entities.len(), 2);
+    }
+
+    // the next game starts fresh
+    let cy = named(&server, "cy").await?;
+    assert_eq!(cy.model.lobby.len(), 1);
+
+    return Ok(());
+}
+
+#[tokio::test]
+async fn test_reconnect_mid_game_lands_in_the_next_lobby() -> Result<()> {
+    let server = LocalServer::start(fast_game(2)?)?;
+
+    let mut ada = named(&server, "ada").await?;
+    let mut bob = named(&server, "bob").await?;
+    for bot in [&mut ada, &mut bob] {
+        bot.wait_until(WAIT, |model| model.position.is_some()).await?;
+    }
+
+    // there are no reconnect tokens yet, the running game lets bob go and
+    // bob starts over in a lobby
+    bob.reconnect().await?;
+    assert_eq!(bob.model.lobby.len(), 1);
+    assert_eq!(bob.model.entity_id, None);
+    ada.wait_until(WAIT, |model| model.entities.len() == 1).await?;
+
+    return Ok(());
+}
+
+#[tokio::test]
+async fn test_oversized_message_disconnects() -> Result<()> {
+    let server = LocalServer::start(fast_game(3)?)?;
+
+    let mut ada = named(&server, "ada").await?;
+    let mut bob = named(&server, "bob").await?;
+    ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 2)).await?;
+
+    bob.send_frame(tungstenite::Message::Binary(vec![0; MAX_MESSAGE_SIZE + 1]))?;
+    until_closed(&mut bob).await?;
+    ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 1)).await?;
+
+    // just under the limit is only a bad message
+    ada.send_frame(tungstenite::Message::Binary(vec![0; MAX_MESSAGE_SIZE / 2]))?;
+    assert!(ada.try_next_message(Duration::from_millis(200)).await.is_ok());
+
+    return Ok(());
+}
+
+#[tokio::test]
+async fn test_shutdown_tells_everyone() -> Result<()> {
+    let server = LocalServer::start(fast_game(3)?)?;
+
+    let mut ada = named(&server, "ada").await?;
+    let mut bob = named(&server, "bob").await?;
+    ada.expect(WAIT, |msg| lobby_count(msg).filter(|&n| n == 2)).await?;
+
+    let start = Instant::now();
+    server.shutdown().await;
+    assert!(start.elapsed() < WAIT);
+
+    for bot in [&mut ada, &mut bob] {
+        let notice = bot
+            .expect(WAIT, |msg| match msg {
+                server::Message::Announcement(announcement) => Some(announcement.clone()),
+                _ => None,
+            })
+            .await?;
+        assert_eq!(notice, server::Announcement::new(server::ANNOUNCEMENT_WARNING, SHUTDOWN_NOTICE));
+        until_closed(bot).await?;
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,51 @@
+// The scenario files in tests/scenarios, see botclient::scenario, each
+// against a server of its own.
+
+use std::path::Path;
+
+use anyhow::Result;
+use botclient::scenario::{run, Scenario};
+use game::{
+    game_config::{GameConfig, ManagerConfig},
+    seed::SeedMode,
+    server::LocalServer,
+    tick_rate::TickRate,
+};
+
+// the scenarios walk around on this map: spawn is open ground and the rock
+// is three tiles west of it
+const SEED: u32 = 26;
+
+async fn run_file(name: &str) -> Result<()> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios").join(format!("{}.scenario", name));
+    let scenario = Scenario::read(&path)?;
+    let server = LocalServer::start_with(ManagerConfig {
+        game: GameConfig {
+            tick_rate: TickRate::from_hz(100)?,
+            min_players: scenario.players.len(),
+            ..GameConfig::default()
+        },
+        seeds: SeedMode::Fixed(SEED),
+        ..ManagerConfig::default()
+    })?;
+
+    let report = run(&scenario, &server.address()).await?;
+    assert!(report.passed(), "{}", report);
+
+    return Ok(());
+}
+
+#[tokio::test]
+async fn test_movement() -> Result<()> {
+    return run_file("movement").await;
+}
+
+#[tokio::test]
+async fn test_emotes() -> Result<()> {
+    return run_file("emotes").await;
+}
+
+#[tokio::test]
+async fn test_reconnect() -> Result<()> {
+    return run_file("reconnect").await;
+}
@@ -0,0 +1,122 @@
+// Encoding costs: every message of fixtures::canonical_messages both ways in
+// both formats, a 100 player snapshot, and an EventBatch next to the same
+// events sent one message each.
+//
+// cargo bench -p encoding --bench serialization
+//
+// baseline, 1 core xeon vm, release (every message is in the report, these
+// are the smallest and the ones that go out every tick):
+//   deku_serialize/whoami               ~176 ns
+//   deku_deserialize/whoami             ~82 ns
+//   json_serialize/whoami               ~77 ns
+//   json_deserialize/whoami             ~0.96 µs
+//   deku_serialize/snapshot             ~0.6 µs
+//   deku_deserialize/snapshot           ~2.7 µs
+//   json_serialize/snapshot             ~0.26 µs
+//   json_deserialize/snapshot           ~6.9 µs
+//   snapshot_100/deku_build_serialize   ~16 µs
+//   snapshot_100/json_build_serialize   ~4.9 µs
+//   snapshot_100/deku_deserialize       ~138 µs
+//   event_batch/16_in_one_batch         ~2.0 µs
+//   event_batch/16_messages             ~3.6 µs
+
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use encoding::{
+    fixtures::canonical_messages,
+    server::{Emote, EventBatch, GameEvent, Message, PlayerPositionUpdate, ServerMessage, Snapshot},
+};
+
+fn deku(msg: &Message) -> Vec<u8> {
+    return ServerMessage::new(1, msg.clone()).serialize().expect("serializes");
+}
+
+fn json(msg: &Message) -> Vec<u8> {
+    return serde_json::to_vec(&ServerMessage::new(1, msg.clone())).expect("serializes");
+}
+
+fn messages(c: &mut Criterion) {
+    let messages = canonical_messages();
+
+    let mut group = c.benchmark_group("deku_serialize");
+    for (name, msg) in &messages {
+        group.bench_with_input(BenchmarkId::from_parameter(name), msg, |b, msg| b.iter(|| deku(msg)));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("deku_deserialize");
+    for (name, msg) in &messages {
+        let bytes = deku(msg);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+            b.iter(|| ServerMessage::deserialize(bytes).expect("deserializes"))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("json_serialize");
+    for (name, msg) in &messages {
+        group.bench_with_input(BenchmarkId::from_parameter(name), msg, |b, msg| b.iter(|| json(msg)));
+    }
+    group.finish();
+
+    // the way the server reads json, checked against the binary encoding
+    let mut group = c.benchmark_group("json_deserialize");
+    for (name, msg) in &messages {
+        let bytes = json(msg);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+            b.iter(|| ServerMessage::from_json(bytes).expect("deserializes"))
+        });
+    }
+    group.finish();
+}
+
+fn snapshot_100() -> Message {
+    let entities = (0..100)
+        .map(|i| PlayerPositionUpdate {
+            entity_id: i * 500,
+            position: (i as u16, 255 - i as u16),
+        })
+        .collect();
+
+    return Message::Snapshot(Snapshot::new(1000, entities));
+}
+
+fn snapshots(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot_100");
+    group.bench_function("deku_build_serialize", |b| b.iter(|| deku(&snapshot_100())));
+    group.bench_function("json_build_serialize", |b| b.iter(|| json(&snapshot_100())));
+
+    let bytes = deku(&snapshot_100());
+    group.bench_function("deku_deserialize", |b| b.iter(|| ServerMessage::deserialize(&bytes).expect("deserializes")));
+    group.finish();
+}
+
+fn event_batch(c: &mut Criterion) {
+    let events: Vec<GameEvent> = (0..16)
+        .map(|i| {
+            return match i % 2 {
+                0 => GameEvent::Countdown(i as u8),
+                _ => GameEvent::Emote(Emote { from: i * 500, emote_id: 1 }),
+            };
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("event_batch");
+    group.bench_function("16_in_one_batch", |b| b.iter(|| deku(&Message::EventBatch(EventBatch::new(events.clone())))));
+    group.bench_function("16_messages", |b| {
+        b.iter(|| events.iter().map(|event| deku(&event.clone().into_message())).collect::<Vec<_>>())
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .warm_up_time(Duration::from_millis(200))
+        .measurement_time(Duration::from_millis(500))
+        .sample_size(20);
+    targets = messages, snapshots, event_batch
+}
+criterion_main!(benches);
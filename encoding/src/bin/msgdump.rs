@@ -0,0 +1,67 @@
+// Prints the messages in a binary or json dump as pretty json, see
+// encoding::msgdump.
+//
+// cargo run -p encoding --bin msgdump -- [options] [FILE | - | TEXT]
+//
+// Without FILE it reads stdin. TEXT is hex or base64 given right on the
+// command line, "00 01 01 13 03" or AAEBEwM=.
+
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail, Result};
+use encoding::msgdump::{input_bytes, render, Format, Input, Options};
+
+const USAGE: &str = "usage: msgdump [--binary | --json] [--framed] [--version N] [--hex | --base64 | --raw] [FILE | - | TEXT]";
+
+fn parse_args() -> Result<(Options, Option<String>)> {
+    let mut options = Options::default();
+    let mut source = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--binary" => options.format = Some(Format::Binary),
+            "--json" => options.format = Some(Format::Json),
+            "--framed" => options.framed = true,
+            "--hex" => options.input = Some(Input::Hex),
+            "--base64" => options.input = Some(Input::Base64),
+            "--raw" => options.input = Some(Input::Raw),
+            "--version" => {
+                let version = args.next().ok_or_else(|| anyhow!("--version needs a number"))?;
+                options.version = Some(version.parse()?);
+            }
+            "-h" | "--help" => bail!(USAGE),
+            _ if source.is_none() => source = Some(arg),
+            _ => bail!("one input at a time\n{}", USAGE),
+        }
+    }
+
+    return Ok((options, source));
+}
+
+fn read_input(source: Option<&str>) -> Result<Vec<u8>> {
+    return match source {
+        None | Some("-") => {
+            let mut data = vec![];
+            std::io::stdin().read_to_end(&mut data)?;
+            Ok(data)
+        }
+        Some(path) if Path::new(path).is_file() => Ok(std::fs::read(path)?),
+        Some(text) => Ok(text.as_bytes().to_vec()),
+    };
+}
+
+fn main() -> Result<ExitCode> {
+    let (options, source) = parse_args()?;
+    let data = input_bytes(&read_input(source.as_deref())?, options.input)?;
+    if data.is_empty() {
+        bail!("nothing to decode");
+    }
+
+    let (out, ok) = render(&data, &options)?;
+    print!("{}", out);
+
+    return Ok(if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+}
@@ -0,0 +1,63 @@
+// Writes the golden files for the current protocol version, see fixtures.
+//
+// cargo run -p encoding --bin regen-fixtures
+//
+// New messages just get their files. A fixture that already exists and
+// would change is a wire format change, it only goes into a new version's
+// directory, so VERSION has to be bumped in the same commit.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use encoding::{
+    fixtures::{canonical_messages, encode, version_dir, FIXTURE_DIR},
+    version::VERSION,
+};
+
+fn main() -> Result<()> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_DIR).join(version_dir(VERSION));
+    std::fs::create_dir_all(&dir)?;
+
+    let mut files = vec![];
+    for (name, msg) in canonical_messages() {
+        let (binary, json) = encode(&msg)?;
+        files.push((format!("{}.bin", name), binary));
+        files.push((format!("{}.json", name), json));
+    }
+
+    let mut changed = vec![];
+    let mut added = vec![];
+    for (file, bytes) in files.iter() {
+        match std::fs::read(dir.join(file)) {
+            Ok(old) if &old == bytes => {}
+            Ok(_) => changed.push(file.clone()),
+            Err(_) => added.push(file.clone()),
+        }
+    }
+
+    // a message that went away is as much a format change
+    let wanted: HashSet<&str> = files.iter().map(|(file, _)| file.as_str()).collect();
+    for entry in std::fs::read_dir(&dir)? {
+        let file = entry?.file_name().to_string_lossy().to_string();
+        if !wanted.contains(file.as_str()) {
+            changed.push(file);
+        }
+    }
+
+    if !changed.is_empty() {
+        changed.sort();
+        bail!(
+            "the wire format of {} changed under version {}, bump VERSION in encoding/src/version.rs and run this again",
+            changed.join(", "),
+            VERSION
+        );
+    }
+
+    for (file, bytes) in files.iter().filter(|(file, _)| added.contains(file)) {
+        std::fs::write(dir.join(file), bytes)?;
+    }
+    println!("{} new fixtures in {}", added.len(), dir.display());
+
+    return Ok(());
+}
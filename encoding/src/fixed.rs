@@ -0,0 +1,85 @@
+// Sub tile positions as 16.16 fixed point: the high 16 bits are the tile, the
+// low 16 how far into it. Integer math only, so every peer rounds the same
+// way, and the tile part is what collision keeps working on.
+
+/// a 16.16 fixed point coordinate.
+pub type Fixed = i32;
+
+pub const FRACTION_BITS: u32 = 16;
+pub const ONE: Fixed = 1 << FRACTION_BITS;
+pub const HALF: Fixed = ONE / 2;
+// the sign takes a bit, maps are far smaller than this
+pub const MAX_TILE: u16 = i16::MAX as u16;
+
+/// the top left corner of a tile, tiles past MAX_TILE clamp to it.
+pub fn from_tile(tile: u16) -> Fixed {
+    return (tile.min(MAX_TILE) as Fixed) << FRACTION_BITS;
+}
+
+/// the tile a coordinate is in, None off either edge of the map.
+pub fn to_tile(fixed: Fixed) -> Option<u16> {
+    return u16::try_from(fixed >> FRACTION_BITS).ok();
+}
+
+/// nearest representable value, saturating at the i32 range.
+pub fn from_f32(value: f32) -> Fixed {
+    return (value * ONE as f32).round() as Fixed;
+}
+
+pub fn to_f32(fixed: Fixed) -> f32 {
+    return fixed as f32 / ONE as f32;
+}
+
+/// drops the fraction bits below `bits`, for clients that don't need the full
+/// precision and want snapshots to compress better.
+pub fn quantize(fixed: Fixed, bits: u32) -> Fixed {
+    let dropped = FRACTION_BITS - bits.min(FRACTION_BITS);
+    return (fixed >> dropped) << dropped;
+}
+
+pub fn from_tiles(position: (u16, u16)) -> (Fixed, Fixed) {
+    return (from_tile(position.0), from_tile(position.1));
+}
+
+pub fn to_tiles(position: (Fixed, Fixed)) -> Option<(u16, u16)> {
+    return Some((to_tile(position.0)?, to_tile(position.1)?));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_f32, from_tile, from_tiles, quantize, to_f32, to_tile, to_tiles, HALF, MAX_TILE, ONE};
+
+    #[test]
+    fn test_tiles_round_trip() {
+        for tile in [0, 1, 255, 256, 1000, MAX_TILE] {
+            assert_eq!(to_tile(from_tile(tile)), Some(tile));
+            // anywhere inside the tile is still that tile
+            assert_eq!(to_tile(from_tile(tile) + (ONE - 1)), Some(tile));
+        }
+
+        assert_eq!(to_tile(-1), None);
+        assert_eq!(from_tile(u16::MAX), from_tile(MAX_TILE));
+        assert_eq!(to_tiles(from_tiles((12, 34))), Some((12, 34)));
+    }
+
+    #[test]
+    fn test_floats_round_trip_within_a_step() {
+        for value in [0.0, 0.5, 1.25, 10.999, 123.456, 255.75] {
+            let fixed = from_f32(value);
+            assert!((to_f32(fixed) - value).abs() <= 1.0 / ONE as f32, "{}", value);
+        }
+
+        assert_eq!(from_f32(2.5), from_tile(2) + HALF);
+        assert_eq!(to_tile(from_f32(2.99)), Some(2));
+    }
+
+    #[test]
+    fn test_quantize_keeps_the_tile() {
+        let fixed = from_f32(7.7);
+
+        assert_eq!(quantize(fixed, 16), fixed);
+        assert_eq!(quantize(fixed, 0), from_tile(7));
+        assert_eq!(quantize(fixed, 1), from_tile(7) + HALF);
+        assert_eq!(to_tile(quantize(fixed, 4)), Some(7));
+    }
+}
@@ -0,0 +1,246 @@
+// Canonical values for every message variant, what the golden files in
+// tests/wire/ hold the bytes of. Round trip tests can't tell when a layout
+// changes on both ends at once, the golden files can. Changing a value here
+// or a message's layout means new fixtures, and new fixtures mean a new
+// VERSION, see the regen-fixtures binary.
+
+use anyhow::Result;
+
+use crate::{
+    fixed,
+    server::{
+        region, AdminMessage, Announcement, ClockSyncRequest, ClockSyncResponse, DebugTelemetry, Emote, EventBatch,
+        EventQuery, FinePosition, FineSnapshot, FollowChanged, Following, GameEvent, GameList, GameListing, HitConfirm,
+        InspectChunk, InspectGame, KeyPress, LobbyPlayer, LobbyState, MapInfo, Message, NamedWhoami, PlayerJoined,
+        PlayerName, PlayerPositionUpdate, PlayerStart, PrivateGameCode, ServerMessage, Snapshot, SpectatorStart,
+        VolkmiresObject, Zone, ANNOUNCEMENT_WARNING, EVENT_KIND_JOIN, GAME_LISTING_LOBBY, JOIN_ERROR_BAD_NAME,
+    },
+};
+
+/// under the encoding crate, one directory per protocol version in it.
+pub const FIXTURE_DIR: &str = "tests/wire";
+
+pub fn version_dir(version: u8) -> String {
+    return format!("v{}", version);
+}
+
+fn positions() -> Vec<PlayerPositionUpdate> {
+    return vec![
+        PlayerPositionUpdate {
+            entity_id: 0,
+            position: (12, 34),
+        },
+        PlayerPositionUpdate {
+            entity_id: 500,
+            position: (256, 1),
+        },
+    ];
+}
+
+/// (file name without extension, message) for every variant, a variant with
+/// optional fields has one per shape it goes out in.
+pub fn canonical_messages() -> Vec<(&'static str, Message)> {
+    let zone = Zone {
+        center: (128, 64),
+        radius: 100,
+    };
+    let code = PrivateGameCode { code: *b"VIMVIM" };
+
+    return vec![
+        ("whoami", Message::Whoami(1)),
+        (
+            "player_start",
+            Message::PlayerStart(PlayerStart {
+                entity_id: 500,
+                range: 500,
+                position: (3, 4),
+                seed: 69,
+                view_distance: Some(40),
+                server_tick: Some(7),
+                region: Some(region("eu-west")),
+            }),
+        ),
+        (
+            "player_start_without_optionals",
+            Message::PlayerStart(PlayerStart {
+                entity_id: 500,
+                range: 500,
+                position: (3, 4),
+                seed: 69,
+                view_distance: None,
+                server_tick: None,
+                region: None,
+            }),
+        ),
+        (
+            "player_position_update",
+            Message::PlayerPositionUpdate(PlayerPositionUpdate {
+                entity_id: 1000,
+                position: (300, 301),
+            }),
+        ),
+        ("clock_sync_request", Message::ClockSyncRequest(ClockSyncRequest {})),
+        ("clock_sync_response", Message::ClockSyncResponse(ClockSyncResponse::new(1_700_000_000_000))),
+        (
+            "volkmires_object",
+            Message::VolkmiresObject(VolkmiresObject {
+                width: 80,
+                height: 24,
+                cps: 1.5,
+            }),
+        ),
+        ("key_press_event", Message::KeyPressEvent(KeyPress { key: b'j', state: 1 })),
+        ("player_count", Message::PlayerCount(42)),
+        ("player_queue_count", Message::PlayerQueueCount),
+        ("game_count", Message::GameCount),
+        ("player_queue_count_result", Message::PlayerQueueCountResult(7)),
+        ("game_count_result", Message::GameCountResult(300)),
+        (
+            "spectator_start",
+            Message::SpectatorStart(SpectatorStart {
+                seed: 1337,
+                map_info: MapInfo {
+                    width: 256,
+                    height: 256,
+                },
+                zone: zone.clone(),
+            }),
+        ),
+        ("create_private_game", Message::CreatePrivateGame),
+        ("join_private_game", Message::JoinPrivateGame(code.clone())),
+        ("private_game_created", Message::PrivateGameCreated(code)),
+        ("join_error", Message::JoinError(JOIN_ERROR_BAD_NAME)),
+        ("snapshot", Message::Snapshot(Snapshot::new(9, positions()))),
+        (
+            "snapshot_without_tick",
+            Message::Snapshot(Snapshot {
+                server_tick: None,
+                ..Snapshot::new(0, positions())
+            }),
+        ),
+        ("countdown", Message::Countdown(3)),
+        ("spectator_sync", Message::SpectatorSync(Snapshot::new(10, positions()))),
+        ("server_full", Message::ServerFull(90)),
+        ("zone_update", Message::ZoneUpdate(zone)),
+        ("join_tournament", Message::JoinTournament(0x0123_4567_89ab_cdef)),
+        ("roster", Message::Roster(Snapshot::new(11, positions()))),
+        ("list_games", Message::ListGames),
+        (
+            "game_list",
+            Message::GameList(GameList::new(
+                vec![GameListing {
+                    game_id: 3,
+                    state: GAME_LISTING_LOBBY,
+                    players: 1,
+                    capacity: 100,
+                }],
+                region("us-east-2"),
+            )),
+        ),
+        ("join_game", Message::JoinGame(4)),
+        (
+            "named_whoami",
+            Message::NamedWhoami(NamedWhoami {
+                whoami: 1,
+                name: PlayerName::new("vimmer"),
+            }),
+        ),
+        (
+            "player_joined",
+            Message::PlayerJoined(PlayerJoined {
+                entity_id: 500,
+                name: PlayerName::new("emacser"),
+            }),
+        ),
+        ("announcement", Message::Announcement(Announcement::new(ANNOUNCEMENT_WARNING, "restart in 5 minutes"))),
+        ("emote", Message::Emote(Emote { from: 500, emote_id: 1 })),
+        ("following", Message::Following(Following { entity_id: 1000 })),
+        (
+            "inspect_game",
+            Message::InspectGame(InspectGame {
+                game_id: 1,
+                interval_secs: 5,
+            }),
+        ),
+        (
+            "inspect_chunk",
+            Message::InspectChunk(InspectChunk {
+                game_id: 1,
+                index: 0,
+                count: 1,
+                len: 2,
+                data: b"{}".to_vec(),
+            }),
+        ),
+        ("dump_game", Message::DumpGame(9)),
+        (
+            "query_events",
+            Message::QueryEvents(EventQuery {
+                game_id: 1,
+                kind: EVENT_KIND_JOIN,
+                since_tick: 5,
+            }),
+        ),
+        (
+            "hit_confirm",
+            Message::HitConfirm(HitConfirm {
+                target: 1000,
+                damage: 25,
+                killed: true,
+            }),
+        ),
+        ("admin_message", Message::AdminMessage(AdminMessage::new(true, "behave"))),
+        ("admin_error", Message::AdminError(1)),
+        ("debug_opt_in", Message::DebugOptIn(2)),
+        (
+            "debug_telemetry",
+            Message::DebugTelemetry(DebugTelemetry {
+                position: (10, 20),
+                last_input_seq: 300,
+                rtt_us: 40_000,
+                clock_diff: -1200,
+                move_budget: 150,
+                emote_cooldown: 12,
+                queue_depth: 3,
+            }),
+        ),
+        (
+            "lobby_state",
+            Message::LobbyState(LobbyState::new(vec![LobbyPlayer {
+                entity_id: 0,
+                name: PlayerName::new("vimmer"),
+                ready: true,
+            }])),
+        ),
+        ("follow_changed", Message::FollowChanged(FollowChanged { new_target: Some(1000) })),
+        ("follow_changed_overhead", Message::FollowChanged(FollowChanged { new_target: None })),
+        (
+            "fine_snapshot",
+            Message::FineSnapshot(FineSnapshot::new(
+                12,
+                vec![FinePosition {
+                    entity_id: 500,
+                    position: (fixed::from_f32(10.5), fixed::from_f32(-0.25)),
+                }],
+            )),
+        ),
+        ("server_busy", Message::ServerBusy),
+        (
+            "event_batch",
+            Message::EventBatch(EventBatch::new(vec![
+                GameEvent::Countdown(0),
+                GameEvent::Emote(Emote { from: 500, emote_id: 1 }),
+                GameEvent::Announcement(Announcement::new(ANNOUNCEMENT_WARNING, "zone closing")),
+            ])),
+        ),
+    ];
+}
+
+/// what the fixture files for msg hold, (.bin, .json).
+pub fn encode(msg: &Message) -> Result<(Vec<u8>, Vec<u8>)> {
+    let msg = ServerMessage::new(1, msg.clone());
+    let mut json = serde_json::to_vec_pretty(&msg)?;
+    json.push(b'\n');
+
+    return Ok((msg.serialize()?, json));
+}
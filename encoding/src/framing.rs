@@ -0,0 +1,189 @@
+// Websockets hand over whole messages, a plain byte stream (tcp, a quic
+// stream) hands over whatever arrived. Over one of those every message goes
+// out as a frame: a 4 byte big endian payload length, then the payload.
+// FrameDecoder takes the bytes in as they come and hands out whole payloads.
+
+use anyhow::Result;
+
+use crate::server::ServerMessage;
+
+pub const FRAME_HEADER_LENGTH: usize = 4;
+
+/// the longest payload a decoder accepts unless told otherwise, the same as
+/// the largest websocket message the server takes.
+pub const MAX_FRAME_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameError {
+    // the header announces more than the decoder's max_length, the stream
+    // is garbage or hostile and can't be read any further
+    TooLong { length: usize, max_length: usize },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            FrameError::TooLong { length, max_length } => {
+                write!(f, "frame of {} bytes is longer than {}", length, max_length)
+            }
+        };
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// appends payload to out as one frame.
+pub fn write_frame(out: &mut Vec<u8>, payload: &[u8]) {
+    out.reserve(FRAME_HEADER_LENGTH + payload.len());
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// payload as one frame.
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    write_frame(&mut out, payload);
+    return out;
+}
+
+/// msg in the binary encoding as one frame.
+pub fn encode_message(msg: ServerMessage) -> Result<Vec<u8>> {
+    return Ok(encode_frame(&msg.serialize()?));
+}
+
+/// reassembles frames from a byte stream read in pieces of any size.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    max_length: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        return FrameDecoder::new(MAX_FRAME_LENGTH);
+    }
+}
+
+impl FrameDecoder {
+    pub fn new(max_length: usize) -> Self {
+        return FrameDecoder { buf: vec![], max_length };
+    }
+
+    /// bytes read off the stream, in order.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// the next whole payload, None until all of it has been pushed. once it
+    /// returned an error the stream is out of step and has to be dropped.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameError> {
+        let Some(header) = self.buf.get(..FRAME_HEADER_LENGTH) else {
+            return Ok(None);
+        };
+
+        let length = u32::from_be_bytes(header.try_into().expect("4 byte header")) as usize;
+        if length > self.max_length {
+            return Err(FrameError::TooLong {
+                length,
+                max_length: self.max_length,
+            });
+        }
+
+        if self.buf.len() < FRAME_HEADER_LENGTH + length {
+            return Ok(None);
+        }
+
+        let payload = self.buf[FRAME_HEADER_LENGTH..FRAME_HEADER_LENGTH + length].to_vec();
+        self.buf.drain(..FRAME_HEADER_LENGTH + length);
+
+        return Ok(Some(payload));
+    }
+
+    /// next_frame decoded as a ServerMessage.
+    pub fn next_message(&mut self) -> Result<Option<ServerMessage>> {
+        return match self.next_frame()? {
+            Some(payload) => Ok(Some(ServerMessage::deserialize(&payload)?)),
+            None => Ok(None),
+        };
+    }
+
+    /// bytes pushed that aren't part of a returned frame yet.
+    pub fn buffered(&self) -> usize {
+        return self.buf.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use super::{encode_frame, encode_message, FrameDecoder, FrameError, FRAME_HEADER_LENGTH};
+    use crate::fixtures::canonical_messages;
+    use crate::server::ServerMessage;
+
+    #[test]
+    fn test_every_message_round_trips_through_a_frame() -> Result<()> {
+        let mut stream = vec![];
+        let messages: Vec<ServerMessage> = canonical_messages()
+            .into_iter()
+            .enumerate()
+            .map(|(seq, (_, msg))| ServerMessage::new(seq as u16, msg))
+            .collect();
+        for msg in &messages {
+            stream.extend(encode_message(msg.clone())?);
+        }
+
+        let mut decoder = FrameDecoder::default();
+        decoder.push(&stream);
+        for msg in &messages {
+            assert_eq!(decoder.next_message()?.as_ref(), Some(msg));
+        }
+        assert_eq!(decoder.next_frame(), Ok(None));
+        assert_eq!(decoder.buffered(), 0);
+
+        // an empty payload is still a frame
+        decoder.push(&encode_frame(&[]));
+        assert_eq!(decoder.next_frame(), Ok(Some(vec![])));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_frames_reassemble_from_partial_reads() {
+        let payloads: Vec<Vec<u8>> = vec![vec![1, 2, 3], vec![], (0..=255).collect(), vec![9; 1000]];
+        let stream: Vec<u8> = payloads.iter().flat_map(|payload| encode_frame(payload)).collect();
+
+        // one byte at a time, then in reads that split headers and payloads
+        for read in [1, 3, 7, 100] {
+            let mut decoder = FrameDecoder::default();
+            let mut out = vec![];
+            for chunk in stream.chunks(read) {
+                decoder.push(chunk);
+                while let Some(payload) = decoder.next_frame().expect("valid stream") {
+                    out.push(payload);
+                }
+            }
+
+            assert_eq!(out, payloads, "reads of {}", read);
+            assert_eq!(decoder.buffered(), 0);
+        }
+
+        // half a header isn't anything yet
+        let mut decoder = FrameDecoder::default();
+        decoder.push(&stream[..FRAME_HEADER_LENGTH - 1]);
+        assert_eq!(decoder.next_frame(), Ok(None));
+    }
+
+    #[test]
+    fn test_oversized_frame_is_rejected_before_its_payload() {
+        let mut decoder = FrameDecoder::new(16);
+        decoder.push(&17u32.to_be_bytes());
+        assert_eq!(
+            decoder.next_frame(),
+            Err(FrameError::TooLong {
+                length: 17,
+                max_length: 16
+            })
+        );
+    }
+}
@@ -0,0 +1,76 @@
+// What the fuzz targets in encoding/fuzz check, kept here so the inputs they
+// turned up can run as plain tests too. Both panic on a broken property, that
+// is what the fuzzer reports as a crash.
+
+use crate::server::ServerMessage;
+
+/// bytes off a socket either fail to parse or parse into a message that
+/// encodes, and that encoding parses and encodes to the same bytes again.
+/// compared as bytes since a message can carry a NaN.
+pub fn check_binary(data: &[u8]) {
+    let Ok(msg) = ServerMessage::deserialize(data) else {
+        return;
+    };
+
+    let bytes = msg.serialize().expect("a parsed message encodes");
+    let again = ServerMessage::deserialize(&bytes).expect("an encoded message parses");
+    assert_eq!(again.serialize().expect("a parsed message encodes"), bytes);
+}
+
+/// the same for json clients, and whatever they get through from_json has to
+/// reach a binary client the same.
+pub fn check_json(data: &[u8]) {
+    let Ok(msg) = ServerMessage::from_json(data) else {
+        return;
+    };
+
+    let json = serde_json::to_vec(&msg).expect("a parsed message encodes");
+    let again = ServerMessage::from_json(&json).expect("an encoded message parses");
+    assert_eq!(serde_json::to_vec(&again).expect("a parsed message encodes"), json);
+
+    let bytes = msg.serialize().expect("from_json only takes messages that encode");
+    check_binary(&bytes);
+    let from_binary = ServerMessage::deserialize(&bytes).expect("an encoded message parses");
+    assert_eq!(serde_json::to_vec(&from_binary).expect("a parsed message encodes"), json);
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{check_binary, check_json};
+    use crate::server::ServerMessage;
+
+    fn seeds(target: &str) -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/seeds").join(target);
+        return std::fs::read_dir(dir)
+            .expect("seeds are checked in")
+            .map(|entry| std::fs::read(entry.expect("seed").path()).expect("seed"))
+            .collect();
+    }
+
+    #[test]
+    fn test_seeds_and_regressions_hold() {
+        for seed in seeds("binary_message") {
+            check_binary(&seed);
+        }
+        for seed in seeds("json_message") {
+            check_json(&seed);
+        }
+    }
+
+    #[test]
+    fn test_json_that_wont_encode_the_same_is_rejected() {
+        // a count that doesn't match its list
+        let count = br#"{"seq_nu":1,"version":1,"msg":{"AdminMessage":{"private":true,"len":5,"text":[98,101,104,97,118,101]}}}"#;
+        assert!(serde_json::from_slice::<ServerMessage>(count).is_ok());
+        assert!(ServerMessage::from_json(count).is_err());
+
+        // server_tick without the view_distance before it would decode as one
+        let gap = br#"{"seq_nu":1,"version":1,"msg":{"PlayerStart":{"entity_id":500,"range":500,"position":[3,4],"seed":69,"server_tick":7}}}"#;
+        assert!(ServerMessage::from_json(gap).is_err());
+
+        let ok = br#"{"seq_nu":1,"version":1,"msg":{"AdminMessage":{"private":true,"len":6,"text":[98,101,104,97,118,101]}}}"#;
+        assert!(ServerMessage::from_json(ok).is_ok());
+    }
+}
@@ -1,2 +1,8 @@
+pub mod fixed;
+pub mod fixtures;
+pub mod framing;
+pub mod fuzzing;
+pub mod msgdump;
+pub mod tick;
 pub mod version;
 pub mod server;
@@ -0,0 +1,338 @@
+// What the msgdump binary does, kept here so the golden tests in
+// tests/msgdump/ can pin its output. Input is whatever support has at hand:
+// the raw bytes, a hex dump out of a client log or base64, holding one or
+// more messages back to back, binary or json, optionally length prefixed
+// frames, see framing.
+
+use anyhow::{anyhow, bail, Result};
+use deku::prelude::*;
+
+use crate::{
+    framing::{FrameDecoder, FrameError, FRAME_HEADER_LENGTH},
+    server::{ServerMessage, MESSAGE_TAG_NAMES},
+    version::VERSION,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    Json,
+}
+
+/// how the input is written down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Raw,
+    Hex,
+    Base64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    // None guesses from the input
+    pub input: Option<Input>,
+    pub format: Option<Format>,
+    // the messages are length prefixed frames
+    pub framed: bool,
+    // the protocol version the input was written with, None takes every
+    // message's own version byte
+    pub version: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoded {
+    // where the message (or its frame) starts in the input
+    pub offset: usize,
+    pub length: usize,
+    pub msg: ServerMessage,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    // which message in the input, from 0
+    pub index: usize,
+    // the byte decoding stopped at
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "message {} at byte {}: {}", self.index, self.offset, self.reason);
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// hex digits, optionally 0x prefixed and split up by whitespace, commas or
+/// colons. None for anything else.
+pub fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .map(|word| word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(word))
+        .flat_map(|word| word.bytes())
+        .collect();
+
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    return digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect();
+}
+
+/// standard or url safe base64, padding optional. None for anything else.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut bits: u32 = 0;
+    let mut count = 0;
+
+    for c in text.trim_end_matches(|c: char| c == '=' || c.is_whitespace()).bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+
+    if out.is_empty() {
+        return None;
+    }
+
+    return Some(out);
+}
+
+fn looks_like_json(data: &[u8]) -> bool {
+    return data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+}
+
+/// the bytes data stands for. text that isn't json is tried as hex and then
+/// as base64, anything else is taken as it is.
+pub fn input_bytes(data: &[u8], input: Option<Input>) -> Result<Vec<u8>> {
+    let text = std::str::from_utf8(data).ok();
+    return match (input, text) {
+        (Some(Input::Raw), _) => Ok(data.to_vec()),
+        (Some(Input::Hex), Some(text)) => decode_hex(text).ok_or_else(|| anyhow!("input isn't hex")),
+        (Some(Input::Base64), Some(text)) => decode_base64(text).ok_or_else(|| anyhow!("input isn't base64")),
+        (Some(_), None) => bail!("input isn't text"),
+        (None, Some(text)) if !looks_like_json(data) => {
+            Ok(decode_hex(text).or_else(|| decode_base64(text)).unwrap_or_else(|| data.to_vec()))
+        }
+        (None, _) => Ok(data.to_vec()),
+    };
+}
+
+pub fn detect_format(bytes: &[u8]) -> Format {
+    if looks_like_json(bytes) {
+        return Format::Json;
+    }
+
+    return Format::Binary;
+}
+
+// the tag name of the message at the start of bytes, for errors
+fn describe(bytes: &[u8]) -> String {
+    return match bytes.get(3).map(|&tag| (tag, MESSAGE_TAG_NAMES.get(tag as usize))) {
+        Some((_, Some(&name))) if name != "unused" => format!("a {} message", name),
+        Some((tag, _)) => format!("a message with unknown tag {}", tag),
+        None => "a message header".to_string(),
+    };
+}
+
+// one binary message at the start of bytes, and how long it was. an error
+// carries the offset into bytes it happened at
+fn decode_binary(bytes: &[u8]) -> Result<(ServerMessage, usize), (usize, String)> {
+    return match ServerMessage::from_bytes((bytes, 0)) {
+        Ok(((rest, _), msg)) => Ok((msg, bytes.len() - rest.len())),
+        Err(DekuError::Incomplete(need)) => Err((
+            bytes.len(),
+            format!("input ends in {}, at least {} more bytes needed", describe(bytes), need.byte_size()),
+        )),
+        Err(e) => Err((0, format!("{} can't be decoded: {}", describe(bytes), e))),
+    };
+}
+
+fn decode_binary_stream(bytes: &[u8]) -> (Vec<Decoded>, Option<DecodeError>) {
+    let mut decoded = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        match decode_binary(&bytes[offset..]) {
+            Ok((msg, length)) => {
+                decoded.push(Decoded { offset, length, msg });
+                offset += length;
+            }
+            Err((at, reason)) => {
+                let error = DecodeError {
+                    index: decoded.len(),
+                    offset: offset + at,
+                    reason,
+                };
+                return (decoded, Some(error));
+            }
+        }
+    }
+
+    return (decoded, None);
+}
+
+fn decode_frames(bytes: &[u8]) -> (Vec<Decoded>, Option<DecodeError>) {
+    let mut decoder = FrameDecoder::default();
+    decoder.push(bytes);
+
+    let mut decoded = vec![];
+    let mut offset = 0;
+    loop {
+        let index = decoded.len();
+        let error = |reason: String, at: usize| DecodeError {
+            index,
+            offset: at,
+            reason,
+        };
+
+        let payload = match decoder.next_frame() {
+            Ok(Some(payload)) => payload,
+            Ok(None) if decoder.buffered() == 0 => return (decoded, None),
+            Ok(None) => {
+                let reason = format!("input ends {} bytes into a frame", decoder.buffered());
+                return (decoded, Some(error(reason, bytes.len())));
+            }
+            Err(e @ FrameError::TooLong { .. }) => return (decoded, Some(error(e.to_string(), offset))),
+        };
+
+        let start = offset + FRAME_HEADER_LENGTH;
+        match decode_binary(&payload) {
+            Ok((msg, length)) if length == payload.len() => {
+                decoded.push(Decoded {
+                    offset,
+                    length: FRAME_HEADER_LENGTH + length,
+                    msg,
+                });
+            }
+            Ok((_, length)) => {
+                let reason = format!("{} bytes left over in the frame", payload.len() - length);
+                return (decoded, Some(error(reason, start + length)));
+            }
+            Err((at, reason)) => return (decoded, Some(error(reason, start + at))),
+        }
+        offset = start + payload.len();
+    }
+}
+
+fn decode_json_stream(bytes: &[u8]) -> (Vec<Decoded>, Option<DecodeError>) {
+    let mut decoded = vec![];
+    let mut stream = serde_json::Deserializer::from_slice(bytes).into_iter::<serde_json::Value>();
+
+    loop {
+        let end = stream.byte_offset();
+        let offset = end + bytes[end..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+        let index = decoded.len();
+        let error = |reason: String, at: usize| DecodeError {
+            index,
+            offset: at,
+            reason,
+        };
+
+        let value = match stream.next() {
+            None => return (decoded, None),
+            Some(Ok(value)) => value,
+            Some(Err(e)) => {
+                // serde_json counts lines and columns, the offset is where it stopped
+                return (decoded, Some(error(e.to_string(), stream.byte_offset())));
+            }
+        };
+
+        // the same check the server does on json from a client
+        let checked = serde_json::to_vec(&value).map_err(anyhow::Error::from).and_then(|json| ServerMessage::from_json(&json));
+        match checked {
+            Ok(msg) => decoded.push(Decoded {
+                offset,
+                length: stream.byte_offset() - offset,
+                msg,
+            }),
+            Err(e) => return (decoded, Some(error(e.to_string(), offset))),
+        }
+    }
+}
+
+/// every message in bytes up to the first that can't be decoded.
+pub fn decode(bytes: &[u8], options: &Options) -> (Vec<Decoded>, Option<DecodeError>) {
+    if let Some(version) = options.version.filter(|version| *version != VERSION) {
+        let error = DecodeError {
+            index: 0,
+            offset: 0,
+            reason: format!("protocol version {} is unknown, this build decodes version {}", version, VERSION),
+        };
+        return (vec![], Some(error));
+    }
+
+    return match (options.format.unwrap_or_else(|| detect_format(bytes)), options.framed) {
+        (Format::Json, _) => decode_json_stream(bytes),
+        (Format::Binary, true) => decode_frames(bytes),
+        (Format::Binary, false) => decode_binary_stream(bytes),
+    };
+}
+
+/// decode as text: every message with where it was in the input, then the
+/// error if one stopped decoding. true when everything decoded.
+pub fn render(bytes: &[u8], options: &Options) -> Result<(String, bool)> {
+    let (decoded, error) = decode(bytes, options);
+
+    let mut out = String::new();
+    for (index, decoded) in decoded.iter().enumerate() {
+        let tag = decoded.msg.msg.tag();
+        out += &format!(
+            "message {} at byte {}, {} bytes, {} seq {} version {}",
+            index, decoded.offset, decoded.length, MESSAGE_TAG_NAMES[tag], decoded.msg.seq_nu, decoded.msg.version
+        );
+        if decoded.msg.version != VERSION {
+            out += &format!(" (decoded as version {})", VERSION);
+        }
+        out += "\n";
+        out += &serde_json::to_string_pretty(&decoded.msg)?;
+        out += "\n";
+    }
+
+    if let Some(error) = &error {
+        out += &format!("error: {}\n", error);
+    }
+
+    return Ok((out, error.is_none()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_base64, decode_hex, input_bytes, Input};
+
+    #[test]
+    fn test_hex_and_base64_inputs() {
+        assert_eq!(decode_hex("00 01 01 13 03"), Some(vec![0, 1, 1, 0x13, 3]));
+        assert_eq!(decode_hex("0x00, 0x01,0x1F\n0x03"), Some(vec![0, 1, 0x1f, 3]));
+        assert_eq!(decode_hex("000101"), Some(vec![0, 1, 1]));
+        assert_eq!(decode_hex("0001 0"), None);
+        assert_eq!(decode_hex("+1"), None);
+
+        assert_eq!(decode_base64("AAEBEwM="), Some(vec![0, 1, 1, 0x13, 3]));
+        assert_eq!(decode_base64("AAEBEwM"), Some(vec![0, 1, 1, 0x13, 3]));
+        assert_eq!(decode_base64("AA$B"), None);
+
+        // hex first, it's base64 too
+        assert_eq!(input_bytes(b"0001", None).ok(), Some(vec![0, 1]));
+        assert_eq!(input_bytes(b"0001", Some(Input::Base64)).ok(), Some(vec![211, 77, 53]));
+        assert_eq!(input_bytes(b"{\"a\": 1}", None).ok(), Some(b"{\"a\": 1}".to_vec()));
+        assert_eq!(input_bytes(&[0, 1, 1, 0x13, 3], None).ok(), Some(vec![0, 1, 1, 0x13, 3]));
+    }
+}
@@ -1,13 +1,134 @@
 use anyhow::Result;
+use deku::bitvec::{BitVec, Msb0};
 use deku::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 
+use crate::fixed::{self, Fixed};
 use crate::version::VERSION;
 
+// Protocol evolution is additive only:
+// * existing fields never change type, order or meaning
+// * new fields are only appended to the end of a message and are `Option`s
+//   read with `#[deku(cond = "!deku::rest.is_empty()")]` and `#[serde(default)]`,
+//   so older peers that don't send them decode as `None`
+// * an optional field can only be Some when every optional field before it is
+// * a message is the last thing in a frame, so older decoders just leave the
+//   new trailing bytes unread
+// Anything that can't follow these rules needs a VERSION bump.
+
+// entity ids go over the wire in 24 bits. writing a message with a bigger one
+// fails instead of cutting it down into somebody else's id
+pub const ENTITY_ID_BITS: usize = 24;
+pub const ENTITY_ID_SPACE: usize = 1 << ENTITY_ID_BITS;
+
 pub const WHO_AM_I_SERVER: u8 = 0;
 pub const WHO_AM_I_CLIENT: u8 = 1;
 pub const WHO_AM_I_UNKNOWN: u8 = 2;
+pub const WHO_AM_I_SPECTATOR: u8 = 3;
+pub const WHO_AM_I_ADMIN: u8 = 4;
+
+pub const JOIN_ERROR_FULL: u8 = 0;
+pub const JOIN_ERROR_STARTED: u8 = 1;
+pub const JOIN_ERROR_NOT_FOUND: u8 = 2;
+pub const JOIN_ERROR_NOT_REGISTERED: u8 = 3;
+pub const JOIN_ERROR_BAD_NAME: u8 = 4;
+// the match is about to end and takes no more spectators
+pub const JOIN_ERROR_ENDING: u8 = 5;
+
+pub const GAME_LISTING_LOBBY: u8 = 0;
+pub const GAME_LISTING_RUNNING: u8 = 1;
+
+pub const PRIVATE_CODE_LENGTH: usize = 6;
+
+pub const REGION_LENGTH: usize = 16;
+
+// label of the server process a game runs on, ascii padded with zeros
+pub type Region = [u8; REGION_LENGTH];
+
+pub const ANNOUNCEMENT_INFO: u8 = 0;
+pub const ANNOUNCEMENT_WARNING: u8 = 1;
+pub const ANNOUNCEMENT_CRITICAL: u8 = 2;
+// bytes of text
+pub const ANNOUNCEMENT_MAX_LENGTH: usize = 200;
+
+// bytes of inspection document per InspectChunk
+pub const INSPECT_CHUNK_SIZE: usize = 8 * 1024;
+
+// EventQuery::kind, which recent game events to send
+pub const EVENT_KIND_ALL: u8 = 0;
+pub const EVENT_KIND_JOIN: u8 = 1;
+pub const EVENT_KIND_LEAVE: u8 = 2;
+pub const EVENT_KIND_KICK: u8 = 3;
+pub const EVENT_KIND_STATE: u8 = 4;
+pub const EVENT_KIND_ERROR: u8 = 5;
+
+// why an admin command was turned down, see Message::AdminError
+pub const ADMIN_ERROR_BAD_COMMAND: u8 = 0;
+// empty, too long or has control characters
+pub const ADMIN_ERROR_BAD_TEXT: u8 = 1;
+// the session has to InspectGame a game before talking to it
+pub const ADMIN_ERROR_NOT_ATTACHED: u8 = 2;
+pub const ADMIN_ERROR_NO_SUCH_PLAYER: u8 = 3;
+pub const ADMIN_ERROR_GAME_GONE: u8 = 4;
+// the audit log couldn't record it, nothing was done
+pub const ADMIN_ERROR_AUDIT: u8 = 5;
+// no :login with a configured admin key first, the connection is closed after it
+pub const ADMIN_ERROR_UNAUTHORIZED: u8 = 6;
+
+// one past the highest Message id, per message type counters are arrays this long
+pub const MESSAGE_TAGS: usize = 47;
+
+// indexed by Message::tag, ids that were never given out are "unused"
+pub const MESSAGE_TAG_NAMES: [&str; MESSAGE_TAGS] = [
+    "whoami",
+    "player_start",
+    "player_position_update",
+    "clock_sync_request",
+    "clock_sync_response",
+    "volkmires_object",
+    "key_press_event",
+    "unused",
+    "player_count",
+    "player_queue_count",
+    "game_count",
+    "player_queue_count_result",
+    "game_count_result",
+    "spectator_start",
+    "create_private_game",
+    "join_private_game",
+    "private_game_created",
+    "join_error",
+    "snapshot",
+    "countdown",
+    "spectator_sync",
+    "server_full",
+    "zone_update",
+    "join_tournament",
+    "roster",
+    "list_games",
+    "game_list",
+    "join_game",
+    "named_whoami",
+    "player_joined",
+    "announcement",
+    "emote",
+    "following",
+    "inspect_game",
+    "inspect_chunk",
+    "dump_game",
+    "query_events",
+    "hit_confirm",
+    "admin_message",
+    "admin_error",
+    "debug_opt_in",
+    "debug_telemetry",
+    "lobby_state",
+    "follow_changed",
+    "fine_snapshot",
+    "server_busy",
+    "event_batch",
+];
 
 #[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
 #[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
@@ -39,21 +160,452 @@ pub struct VolkmiresObject {
 #[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
 #[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
 pub struct PlayerStart {
-    #[deku(bits = 24)]
+    #[deku(bits = 24, assert = "*entity_id < ENTITY_ID_SPACE")]
     pub entity_id: usize,
     pub range: u16,
     pub position: (u16, u16),
     pub seed: u32,
+
+    // optional, see the protocol evolution rules at the top
+    #[deku(cond = "!deku::rest.is_empty()")]
+    #[serde(default)]
+    pub view_distance: Option<u16>,
+
+    // tick the game was on when this was sent, wraps, see tick::widen_tick
+    #[deku(cond = "!deku::rest.is_empty()")]
+    #[serde(default)]
+    pub server_tick: Option<u32>,
+
+    #[deku(cond = "!deku::rest.is_empty()")]
+    #[serde(default)]
+    pub region: Option<Region>,
+}
+
+/// label cut to REGION_LENGTH bytes.
+pub fn region(label: &str) -> Region {
+    let mut region = [0; REGION_LENGTH];
+    for (to, from) in region.iter_mut().zip(label.bytes()) {
+        *to = from;
+    }
+
+    return region;
+}
+
+/// the label without its padding, empty when it isn't valid utf8.
+pub fn region_label(region: &Region) -> &str {
+    let len = region.iter().position(|&b| b == 0).unwrap_or(REGION_LENGTH);
+    return std::str::from_utf8(&region[..len]).unwrap_or("");
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct MapInfo {
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct Zone {
+    pub center: (u16, u16),
+    pub radius: u16,
+}
+
+// Same as PlayerStart minus the owned entity, spectators only need enough to
+// regenerate and render the world.
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct SpectatorStart {
+    pub seed: u32,
+    pub map_info: MapInfo,
+    pub zone: Zone,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct PrivateGameCode {
+    pub code: [u8; PRIVATE_CODE_LENGTH],
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct GameListing {
+    pub game_id: u32,
+    // GAME_LISTING_*
+    pub state: u8,
+    pub players: u8,
+    pub capacity: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct GameList {
+    #[deku(update = "self.games.len()")]
+    pub count: u8,
+    #[deku(count = "count")]
+    pub games: Vec<GameListing>,
+
+    // every listed game runs on this server, so they all share one region
+    #[deku(cond = "!deku::rest.is_empty()")]
+    #[serde(default)]
+    pub region: Option<Region>,
+}
+
+impl GameList {
+    pub fn new(games: Vec<GameListing>, region: Region) -> Self {
+        return GameList {
+            count: games.len() as u8,
+            games,
+            region: Some(region),
+        };
+    }
+}
+
+// utf8 display name, what is allowed in it is up to the server
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct PlayerName {
+    #[deku(update = "self.name.len()")]
+    pub len: u8,
+    #[deku(count = "len")]
+    pub name: Vec<u8>,
+}
+
+impl PlayerName {
+    // anything past 255 bytes is cut off
+    pub fn new(name: &str) -> Self {
+        let name: Vec<u8> = name.bytes().take(u8::MAX as usize).collect();
+        return PlayerName {
+            len: name.len() as u8,
+            name,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct NamedWhoami {
+    pub whoami: u8,
+    pub name: PlayerName,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct PlayerJoined {
+    #[deku(bits = 24, assert = "*entity_id < ENTITY_ID_SPACE")]
+    pub entity_id: usize,
+    pub name: PlayerName,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct LobbyPlayer {
+    #[deku(bits = 24, assert = "*entity_id < ENTITY_ID_SPACE")]
+    pub entity_id: usize,
+    pub name: PlayerName,
+    // done joining, players still syncing their clock aren't
+    pub ready: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct LobbyState {
+    #[deku(update = "self.players.len()")]
+    pub count: u8,
+    #[deku(count = "count")]
+    pub players: Vec<LobbyPlayer>,
+}
+
+impl LobbyState {
+    pub fn new(players: Vec<LobbyPlayer>) -> Self {
+        return LobbyState {
+            count: players.len() as u8,
+            players,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct Emote {
+    // entity id of the sender, filled in by the server
+    #[deku(bits = 24, assert = "*from < ENTITY_ID_SPACE")]
+    pub from: usize,
+    // index into the server's emote table
+    pub emote_id: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct Following {
+    // the player the spectator's snapshots are centered on
+    #[deku(bits = 24, assert = "*entity_id < ENTITY_ID_SPACE")]
+    pub entity_id: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct FollowChanged {
+    // who the spectator follows now, None is the overhead view of the whole game
+    #[deku(
+        bits = 24,
+        cond = "!deku::rest.is_empty()",
+        assert = "new_target.is_none_or(|id| id < ENTITY_ID_SPACE)"
+    )]
+    #[serde(default)]
+    pub new_target: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct HitConfirm {
+    // entity id of whoever was hit
+    #[deku(bits = 24, assert = "*target < ENTITY_ID_SPACE")]
+    pub target: usize,
+    pub damage: u16,
+    pub killed: bool,
+}
+
+// most events a batch carries, its count is a u8
+pub const EVENT_BATCH_MAX: usize = u8::MAX as usize;
+
+/// something that happened in a tick, goes out in the tick's EventBatch.
+/// each carries what the Message of the same name would.
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(
+    type = "u8",
+    endian = "parent_endian",
+    ctx = "parent_endian: deku::ctx::Endian"
+)]
+pub enum GameEvent {
+    #[deku(id = "0")]
+    PlayerJoined(PlayerJoined),
+
+    #[deku(id = "1")]
+    Countdown(u8),
+
+    #[deku(id = "2")]
+    ZoneUpdate(Zone),
+
+    #[deku(id = "3")]
+    Announcement(Announcement),
+
+    #[deku(id = "4")]
+    Emote(Emote),
+
+    #[deku(id = "5")]
+    HitConfirm(HitConfirm),
+}
+
+impl GameEvent {
+    /// the message it would have been on its own, clients handle a batch by
+    /// handling these in order.
+    pub fn into_message(self) -> Message {
+        return match self {
+            GameEvent::PlayerJoined(joined) => Message::PlayerJoined(joined),
+            GameEvent::Countdown(seconds) => Message::Countdown(seconds),
+            GameEvent::ZoneUpdate(zone) => Message::ZoneUpdate(zone),
+            GameEvent::Announcement(announcement) => Message::Announcement(announcement),
+            GameEvent::Emote(emote) => Message::Emote(emote),
+            GameEvent::HitConfirm(hit) => Message::HitConfirm(hit),
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct EventBatch {
+    #[deku(update = "self.events.len()")]
+    pub count: u8,
+    // in the order they happened
+    #[deku(count = "count")]
+    pub events: Vec<GameEvent>,
+}
+
+impl EventBatch {
+    // anything past EVENT_BATCH_MAX is cut off, the sender splits before that
+    pub fn new(mut events: Vec<GameEvent>) -> Self {
+        events.truncate(EVENT_BATCH_MAX);
+        return EventBatch {
+            count: events.len() as u8,
+            events,
+        };
+    }
+}
+
+// the server's view of one player, for client side debug overlays
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct DebugTelemetry {
+    pub position: (u16, u16),
+    // seq_nu of the last key press the game processed, moves it rejected too
+    pub last_input_seq: u16,
+    // round trip of the last clock resync
+    pub rtt_us: u32,
+    pub clock_diff: i64,
+    pub move_budget: u32,
+    // ticks until they can emote again
+    pub emote_cooldown: u16,
+    // player messages waiting for the game loop, every player shares the queue
+    pub queue_depth: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct InspectGame {
+    pub game_id: u32,
+    // 0 answers once, anything else keeps sending a document every that many seconds
+    pub interval_secs: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct EventQuery {
+    pub game_id: u32,
+    // one of the EVENT_KIND_ codes
+    pub kind: u8,
+    // only events from this tick on, 0 for everything the game still has.
+    // the game widens it against its own tick
+    pub since_tick: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct InspectChunk {
+    pub game_id: u32,
+    pub index: u8,
+    // chunks of this document, the json is the data of all of them in order
+    pub count: u8,
+    #[deku(update = "self.data.len()")]
+    pub len: u16,
+    #[deku(count = "len")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct Announcement {
+    // ANNOUNCEMENT_*
+    pub severity: u8,
+    #[deku(update = "self.text.len()")]
+    pub len: u8,
+    #[deku(count = "len")]
+    pub text: Vec<u8>,
+}
+
+impl Announcement {
+    // anything past ANNOUNCEMENT_MAX_LENGTH bytes is cut off
+    pub fn new(severity: u8, text: &str) -> Self {
+        let text: Vec<u8> = text.bytes().take(ANNOUNCEMENT_MAX_LENGTH).collect();
+        return Announcement {
+            severity,
+            len: text.len() as u8,
+            text,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct AdminMessage {
+    // sent to this player only, otherwise to everyone in the game
+    pub private: bool,
+    #[deku(update = "self.text.len()")]
+    pub len: u8,
+    #[deku(count = "len")]
+    pub text: Vec<u8>,
+}
+
+impl AdminMessage {
+    // same limit as announcements
+    pub fn new(private: bool, text: &str) -> Self {
+        let text: Vec<u8> = text.bytes().take(ANNOUNCEMENT_MAX_LENGTH).collect();
+        return AdminMessage {
+            private,
+            len: text.len() as u8,
+            text,
+        };
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
 #[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
 pub struct PlayerPositionUpdate {
-    #[deku(bits = 24)]
+    #[deku(bits = 24, assert = "*entity_id < ENTITY_ID_SPACE")]
     pub entity_id: usize,
     pub position: (u16, u16),
 }
 
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct Snapshot {
+    #[deku(update = "self.entities.len()")]
+    pub count: u8,
+    #[deku(count = "count")]
+    pub entities: Vec<PlayerPositionUpdate>,
+
+    // tick the snapshot was taken on, optional, see the protocol evolution rules.
+    // wraps, see tick::widen_tick
+    #[deku(cond = "!deku::rest.is_empty()")]
+    #[serde(default)]
+    pub server_tick: Option<u32>,
+}
+
+impl Snapshot {
+    pub fn new(server_tick: u32, entities: Vec<PlayerPositionUpdate>) -> Self {
+        return Snapshot {
+            count: entities.len() as u8,
+            entities,
+            server_tick: Some(server_tick),
+        };
+    }
+}
+
+// PlayerPositionUpdate with sub tile precision, see fixed
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct FinePosition {
+    #[deku(bits = 24, assert = "*entity_id < ENTITY_ID_SPACE")]
+    pub entity_id: usize,
+    pub position: (Fixed, Fixed),
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+#[deku(endian = "parent_endian", ctx = "parent_endian: deku::ctx::Endian")]
+pub struct FineSnapshot {
+    // wraps, see tick::widen_tick
+    pub server_tick: u32,
+    #[deku(update = "self.entities.len()")]
+    pub count: u8,
+    #[deku(count = "count")]
+    pub entities: Vec<FinePosition>,
+}
+
+impl FineSnapshot {
+    pub fn new(server_tick: u32, entities: Vec<FinePosition>) -> Self {
+        return FineSnapshot {
+            server_tick,
+            count: entities.len() as u8,
+            entities,
+        };
+    }
+
+    /// the same snapshot in whole tiles, for clients that only take those.
+    pub fn to_tiles(&self) -> Snapshot {
+        let entities = self
+            .entities
+            .iter()
+            .filter_map(|e| {
+                return fixed::to_tiles(e.position).map(|position| PlayerPositionUpdate {
+                    entity_id: e.entity_id,
+                    position,
+                });
+            })
+            .collect();
+        return Snapshot::new(self.server_tick, entities);
+    }
+}
+
 const KEY_PRESS_STATE_DOWN: u8= 0;
 const KEY_PRESS_STATE_UP: u8 = 0;
 
@@ -108,6 +660,139 @@ pub enum Message {
 
     #[deku(id = "12")]
     GameCountResult(u16),
+
+    #[deku(id = "13")]
+    SpectatorStart(SpectatorStart),
+
+    // sent instead of Whoami to open a private game
+    #[deku(id = "14")]
+    CreatePrivateGame,
+
+    // sent instead of Whoami to join a private game
+    #[deku(id = "15")]
+    JoinPrivateGame(PrivateGameCode),
+
+    #[deku(id = "16")]
+    PrivateGameCreated(PrivateGameCode),
+
+    #[deku(id = "17")]
+    JoinError(u8),
+
+    #[deku(id = "18")]
+    Snapshot(Snapshot),
+
+    // seconds until the match goes live, 0 means it just did
+    #[deku(id = "19")]
+    Countdown(u8),
+
+    // everything a spectator joining a running game missed, follows SpectatorStart
+    #[deku(id = "20")]
+    SpectatorSync(Snapshot),
+
+    // every game slot is taken, estimated seconds until one frees up
+    #[deku(id = "21")]
+    ServerFull(u16),
+
+    // current safe zone, players get one right after PlayerStart
+    #[deku(id = "22")]
+    ZoneUpdate(Zone),
+
+    // sent instead of Whoami by a registered tournament player, carries their token
+    #[deku(id = "23")]
+    JoinTournament(u64),
+
+    // who is still in the game, sent when players drop out during start
+    #[deku(id = "24")]
+    Roster(Snapshot),
+
+    // sent instead of Whoami to browse the public games, answered with GameList
+    #[deku(id = "25")]
+    ListGames,
+
+    #[deku(id = "26")]
+    GameList(GameList),
+
+    // follows a GameList, picks the game to join
+    #[deku(id = "27")]
+    JoinGame(u32),
+
+    // sent instead of Whoami to pick a display name
+    #[deku(id = "28")]
+    NamedWhoami(NamedWhoami),
+
+    // a player and their display name, everyone gets one per player after the start
+    #[deku(id = "29")]
+    PlayerJoined(PlayerJoined),
+
+    // operator message, the message of the day goes out right after the whoami
+    #[deku(id = "30")]
+    Announcement(Announcement),
+
+    // clients send it to emote, the server passes it on to everyone close by
+    #[deku(id = "31")]
+    Emote(Emote),
+
+    // answers a spectator asking to follow someone
+    #[deku(id = "32")]
+    Following(Following),
+
+    // admin connections only, asks for a game's state document
+    #[deku(id = "33")]
+    InspectGame(InspectGame),
+
+    // one piece of the json state document an InspectGame asked for
+    #[deku(id = "34")]
+    InspectChunk(InspectChunk),
+
+    // admin connections only, the game writes its state to the server's dump dir
+    #[deku(id = "35")]
+    DumpGame(u32),
+
+    // admin connections only, the game's recent events come back as InspectChunks
+    #[deku(id = "36")]
+    QueryEvents(EventQuery),
+
+    // only to the attacker, for hit markers
+    #[deku(id = "37")]
+    HitConfirm(HitConfirm),
+
+    // from an admin to the players of one game, not chat
+    #[deku(id = "38")]
+    AdminMessage(AdminMessage),
+
+    // admin connections only, one of the ADMIN_ERROR_ codes
+    #[deku(id = "39")]
+    AdminError(u8),
+
+    // debug servers only, asks for DebugTelemetry this many times a second.
+    // the server caps the rate, 0 stops it
+    #[deku(id = "40")]
+    DebugOptIn(u8),
+
+    // only to the player that opted in, never in ranked games
+    #[deku(id = "41")]
+    DebugTelemetry(DebugTelemetry),
+
+    // everyone in the lobby, sent to the lobby whenever it changes until the start
+    #[deku(id = "42")]
+    LobbyState(LobbyState),
+
+    // spectators only, the player they followed left and the server moved them on
+    #[deku(id = "43")]
+    FollowChanged(FollowChanged),
+
+    // Snapshot with 16.16 fixed point positions, games configured for them send it instead
+    #[deku(id = "44")]
+    FineSnapshot(FineSnapshot),
+
+    // the server is taking connections in faster than it can hand them to games,
+    // try again later or somewhere else. the connection closes after it
+    #[deku(id = "45")]
+    ServerBusy,
+
+    // every discrete event of a tick in one message, before the tick's snapshot
+    #[deku(id = "46")]
+    EventBatch(EventBatch),
 }
 
 impl Message {
@@ -125,6 +810,66 @@ impl Message {
             state,
         });
     }
+
+    /// the deku id, keep it in step with the #[deku(id)] above.
+    pub fn tag(&self) -> usize {
+        return match self {
+            Message::Whoami(_) => 0,
+            Message::PlayerStart(_) => 1,
+            Message::PlayerPositionUpdate(_) => 2,
+            Message::ClockSyncRequest(_) => 3,
+            Message::ClockSyncResponse(_) => 4,
+            Message::VolkmiresObject(_) => 5,
+            Message::KeyPressEvent(_) => 6,
+            Message::PlayerCount(_) => 8,
+            Message::PlayerQueueCount => 9,
+            Message::GameCount => 10,
+            Message::PlayerQueueCountResult(_) => 11,
+            Message::GameCountResult(_) => 12,
+            Message::SpectatorStart(_) => 13,
+            Message::CreatePrivateGame => 14,
+            Message::JoinPrivateGame(_) => 15,
+            Message::PrivateGameCreated(_) => 16,
+            Message::JoinError(_) => 17,
+            Message::Snapshot(_) => 18,
+            Message::Countdown(_) => 19,
+            Message::SpectatorSync(_) => 20,
+            Message::ServerFull(_) => 21,
+            Message::ZoneUpdate(_) => 22,
+            Message::JoinTournament(_) => 23,
+            Message::Roster(_) => 24,
+            Message::ListGames => 25,
+            Message::GameList(_) => 26,
+            Message::JoinGame(_) => 27,
+            Message::NamedWhoami(_) => 28,
+            Message::PlayerJoined(_) => 29,
+            Message::Announcement(_) => 30,
+            Message::Emote(_) => 31,
+            Message::Following(_) => 32,
+            Message::InspectGame(_) => 33,
+            Message::InspectChunk(_) => 34,
+            Message::DumpGame(_) => 35,
+            Message::QueryEvents(_) => 36,
+            Message::HitConfirm(_) => 37,
+            Message::AdminMessage(_) => 38,
+            Message::AdminError(_) => 39,
+            Message::DebugOptIn(_) => 40,
+            Message::DebugTelemetry(_) => 41,
+            Message::LobbyState(_) => 42,
+            Message::FollowChanged(_) => 43,
+            Message::FineSnapshot(_) => 44,
+            Message::ServerBusy => 45,
+            Message::EventBatch(_) => 46,
+        };
+    }
+}
+
+/// where serialize_into and json_into write, it keeps its capacity from one
+/// message to the next.
+#[derive(Debug, Default)]
+pub struct EncodeBuffer {
+    bits: BitVec<Msb0, u8>,
+    json: Vec<u8>,
 }
 
 #[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
@@ -153,6 +898,36 @@ impl ServerMessage {
         return Ok(self.try_into()?);
     }
 
+    /// serialize without allocating, once buffer has grown to fit the
+    /// largest message it's given. the bytes are good until the next use of
+    /// buffer.
+    pub fn serialize_into<'a>(&self, buffer: &'a mut EncodeBuffer) -> Result<&'a [u8]> {
+        buffer.bits.clear();
+        self.write(&mut buffer.bits, ())?;
+        return Ok(buffer.bits.as_raw_slice());
+    }
+
+    /// serialize_into for json clients.
+    pub fn json_into<'a>(&self, buffer: &'a mut EncodeBuffer) -> Result<&'a [u8]> {
+        buffer.json.clear();
+        serde_json::to_writer(&mut buffer.json, self)?;
+        return Ok(&buffer.json);
+    }
+
+    /// json has nothing tying a count to the list after it or an optional
+    /// field to the ones before it, so only a message that comes back the same
+    /// through the binary encoding is taken. it can go to any client then.
+    pub fn from_json(bytes: &[u8]) -> Result<ServerMessage> {
+        let msg: ServerMessage = serde_json::from_slice(bytes)?;
+        let binary = ServerMessage::deserialize(&msg.clone().serialize()?)?;
+        // as json values, a NaN is never equal to itself
+        if serde_json::to_value(&binary)? != serde_json::to_value(&msg)? {
+            return Err(anyhow::anyhow!("json message changes through the binary encoding"));
+        }
+
+        return Ok(msg);
+    }
+
     pub const CLIENT_WHO_AM_I: Self = ServerMessage {
         seq_nu: 0,
         version: VERSION,
@@ -169,9 +944,287 @@ impl ServerMessage {
 #[cfg(test)]
 mod test {
     use anyhow::Result;
+    use deku::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    use super::{
+        fixed, region, region_label, AdminMessage, DebugTelemetry, Emote, EncodeBuffer, EventBatch, EventQuery,
+        FinePosition, FineSnapshot, FollowChanged, GameEvent, GameList, GameListing, HitConfirm, InspectGame,
+        LobbyPlayer, LobbyState, Message, PlayerName, PlayerPositionUpdate, PlayerStart, ServerMessage, Snapshot, Zone,
+        ADMIN_ERROR_NO_SUCH_PLAYER, ENTITY_ID_SPACE, EVENT_KIND_ALL, MESSAGE_TAG_NAMES,
+    };
+    use crate::fixtures::canonical_messages;
+
+    // PlayerStart as it was before view_distance existed
+    #[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite, Serialize, Deserialize)]
+    #[deku(endian = "big")]
+    struct OldPlayerStartMessage {
+        seq_nu: u16,
+        version: u8,
+        id: u8,
+        #[deku(bits = 24)]
+        entity_id: usize,
+        range: u16,
+        position: (u16, u16),
+        seed: u32,
+    }
+
+    fn old_player_start() -> OldPlayerStartMessage {
+        return OldPlayerStartMessage {
+            seq_nu: 1,
+            version: crate::version::VERSION,
+            id: 1,
+            entity_id: 500,
+            range: 500,
+            position: (3, 4),
+            seed: 69,
+        };
+    }
+
+    fn new_player_start(view_distance: Option<u16>) -> ServerMessage {
+        return ServerMessage::new(
+            1,
+            Message::PlayerStart(PlayerStart {
+                entity_id: 500,
+                range: 500,
+                position: (3, 4),
+                seed: 69,
+                view_distance,
+                server_tick: view_distance.map(|_| 7),
+                region: view_distance.map(|_| region("eu-west")),
+            }),
+        );
+    }
 
     #[test]
     fn test_serialization() -> Result<()> {
+        let msg = new_player_start(Some(40));
+        let bytes = msg.clone().serialize()?;
+        assert_eq!(ServerMessage::deserialize(&bytes)?, msg);
+
+        let json = serde_json::to_vec(&msg)?;
+        assert_eq!(serde_json::from_slice::<ServerMessage>(&json)?, msg);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_encode_buffer_matches_the_allocating_encodings() -> Result<()> {
+        let mut buffer = EncodeBuffer::default();
+        for (name, msg) in canonical_messages() {
+            let msg = ServerMessage::new(300, msg);
+            assert_eq!(msg.serialize_into(&mut buffer)?, msg.clone().serialize()?, "{}", name);
+            assert_eq!(msg.json_into(&mut buffer)?, serde_json::to_vec(&msg)?, "{}", name);
+        }
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_entity_id_past_the_field_width_fails_to_encode() -> Result<()> {
+        let snapshot = |entity_id| {
+            let entities = vec![PlayerPositionUpdate { entity_id, position: (1, 2) }];
+            return ServerMessage::new(1, Message::Snapshot(Snapshot::new(7, entities)));
+        };
+        let fine = |entity_id| {
+            let entities = vec![FinePosition { entity_id, position: (0, 0) }];
+            return ServerMessage::new(1, Message::FineSnapshot(FineSnapshot::new(7, entities)));
+        };
+
+        // the last id that fits comes back the same
+        for msg in [snapshot(ENTITY_ID_SPACE - 1), fine(ENTITY_ID_SPACE - 1)] {
+            assert_eq!(ServerMessage::deserialize(&msg.clone().serialize()?)?, msg);
+        }
+
+        // the next one would alias id 0
+        for msg in [snapshot(ENTITY_ID_SPACE), fine(ENTITY_ID_SPACE)] {
+            let error = msg.serialize().expect_err("doesn't fit 24 bits").to_string();
+            assert!(error.contains("entity_id"), "{}", error);
+        }
+
+        let follow = FollowChanged { new_target: Some(ENTITY_ID_SPACE) };
+        assert!(ServerMessage::new(1, Message::FollowChanged(follow)).serialize().is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_old_schema_decodes_with_defaults() -> Result<()> {
+        let old: Vec<u8> = old_player_start().try_into()?;
+        assert_eq!(ServerMessage::deserialize(&old)?, new_player_start(None));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_new_schema_decodes_with_old_decoder() -> Result<()> {
+        let new = new_player_start(Some(40)).serialize()?;
+        let (_, old) = OldPlayerStartMessage::from_bytes((&new, 0))?;
+        assert_eq!(old, old_player_start());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_old_json_decodes_with_defaults() -> Result<()> {
+        let old = serde_json::json!({
+            "seq_nu": 1,
+            "version": crate::version::VERSION,
+            "msg": {"PlayerStart": {"entity_id": 500, "range": 500, "position": [3, 4], "seed": 69}},
+        });
+
+        let msg: ServerMessage = serde_json::from_value(old)?;
+        assert_eq!(msg, new_player_start(None));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_game_list_region() -> Result<()> {
+        let listing = GameListing {
+            game_id: 3,
+            state: 0,
+            players: 1,
+            capacity: 100,
+        };
+        let msg = ServerMessage::new(1, Message::GameList(GameList::new(vec![listing.clone()], region("us-east-2"))));
+        let bytes = msg.clone().serialize()?;
+        assert_eq!(ServerMessage::deserialize(&bytes)?, msg);
+
+        // a list from before regions existed
+        let mut old = GameList::new(vec![listing], region(""));
+        old.region = None;
+        let old = ServerMessage::new(1, Message::GameList(old));
+        let bytes = old.clone().serialize()?;
+        assert_eq!(ServerMessage::deserialize(&bytes)?, old);
+
+        assert_eq!(region_label(&region("us-east-2")), "us-east-2");
+        assert_eq!(region_label(&region("a-very-long-region-label")), "a-very-long-regi");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_tag_is_the_wire_id() -> Result<()> {
+        let msgs = vec![
+            Message::Whoami(1),
+            Message::clock_request(),
+            Message::clock_response(3),
+            Message::key_press(b'j', 0),
+            Message::PlayerCount(2),
+            Message::PlayerQueueCount,
+            Message::GameCount,
+            Message::CreatePrivateGame,
+            Message::JoinError(1),
+            Message::Countdown(3),
+            Message::ZoneUpdate(Zone {
+                center: (1, 2),
+                radius: 3,
+            }),
+            Message::ListGames,
+            Message::JoinGame(4),
+            Message::Emote(Emote { from: 500, emote_id: 1 }),
+            Message::InspectGame(InspectGame {
+                game_id: 1,
+                interval_secs: 0,
+            }),
+            Message::DumpGame(9),
+            Message::QueryEvents(EventQuery {
+                game_id: 1,
+                kind: EVENT_KIND_ALL,
+                since_tick: 5,
+            }),
+            Message::HitConfirm(HitConfirm {
+                target: 1000,
+                damage: 25,
+                killed: true,
+            }),
+            Message::AdminMessage(AdminMessage::new(true, "behave")),
+            Message::AdminError(ADMIN_ERROR_NO_SUCH_PLAYER),
+            Message::DebugOptIn(2),
+            Message::DebugTelemetry(DebugTelemetry {
+                position: (10, 20),
+                last_input_seq: 300,
+                rtt_us: 40_000,
+                clock_diff: -1200,
+                move_budget: 150,
+                emote_cooldown: 12,
+                queue_depth: 3,
+            }),
+            Message::LobbyState(LobbyState::new(vec![
+                LobbyPlayer {
+                    entity_id: 0,
+                    name: PlayerName::new("vimmer"),
+                    ready: true,
+                },
+                LobbyPlayer {
+                    entity_id: 500,
+                    name: PlayerName::new("emacser"),
+                    ready: false,
+                },
+            ])),
+            Message::FollowChanged(FollowChanged { new_target: Some(1000) }),
+            Message::FollowChanged(FollowChanged { new_target: None }),
+            Message::FineSnapshot(FineSnapshot::new(
+                9,
+                vec![FinePosition {
+                    entity_id: 500,
+                    position: (fixed::from_f32(10.5), fixed::from_f32(3.25)),
+                }],
+            )),
+            Message::ServerBusy,
+            Message::EventBatch(EventBatch::new(vec![
+                GameEvent::Countdown(2),
+                GameEvent::HitConfirm(HitConfirm {
+                    target: 1000,
+                    damage: 25,
+                    killed: false,
+                }),
+            ])),
+        ];
+
+        for msg in msgs {
+            let tag = msg.tag();
+            // seq_nu and version come first
+            let bytes = ServerMessage::new(1, msg.clone()).serialize()?;
+            assert_eq!(bytes[3] as usize, tag);
+            assert_eq!(ServerMessage::deserialize(&bytes)?.msg, msg);
+            assert_ne!(MESSAGE_TAG_NAMES[tag], "unused");
+        }
+        assert_eq!(MESSAGE_TAG_NAMES[Message::key_press(b'j', 0).tag()], "key_press_event");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_fine_snapshot_round_trips() -> Result<()> {
+        let entities = vec![
+            FinePosition {
+                entity_id: 0,
+                position: (fixed::from_f32(12.75), fixed::from_tile(40)),
+            },
+            // off the map, a client can't place it on a tile
+            FinePosition {
+                entity_id: 500,
+                position: (fixed::from_f32(-0.5), fixed::from_tile(1)),
+            },
+        ];
+        let msg = Message::FineSnapshot(FineSnapshot::new(7, entities.clone()));
+
+        let bytes = ServerMessage::new(1, msg.clone()).serialize()?;
+        let decoded = ServerMessage::deserialize(&bytes)?.msg;
+        assert_eq!(decoded, msg);
+
+        let json = serde_json::to_string(&msg)?;
+        assert_eq!(serde_json::from_str::<Message>(&json)?, msg);
+
+        let tiles = FineSnapshot::new(7, entities).to_tiles();
+        assert_eq!(tiles.server_tick, Some(7));
+        assert_eq!(tiles.entities, vec![PlayerPositionUpdate {
+            entity_id: 0,
+            position: (12, 40),
+        }]);
+
         return Ok(());
     }
 }
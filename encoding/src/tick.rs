@@ -0,0 +1,43 @@
+// Games count ticks in a u128, messages only carry the low 32 bits. At 60
+// ticks a second that wraps after about two years, at the fastest tick rate
+// after a few weeks, so anything reading ticks off the wire widens them
+// against a tick it already knows instead of trusting the u32.
+
+/// the low 32 bits of tick, what goes in a message.
+pub fn wire_tick(tick: u128) -> u32 {
+    return tick as u32;
+}
+
+/// the full tick closest to reference that goes out as wire. reference is
+/// any recent tick, a snapshot's or the receiver's own, and has to be within
+/// 2^31 ticks of the real one.
+pub fn widen_tick(wire: u32, reference: u128) -> u128 {
+    let delta = wire.wrapping_sub(wire_tick(reference)) as i32;
+    return match delta >= 0 {
+        true => reference.saturating_add(delta as u128),
+        false => reference.saturating_sub(delta.unsigned_abs() as u128),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::{widen_tick, wire_tick};
+
+    #[test]
+    fn test_ticks_past_u32_widen_back() {
+        let wrap = u32::MAX as u128 + 1;
+
+        for tick in [0, 1, 1000, wrap - 1, wrap, wrap + 5, 3 * wrap + 17] {
+            let wire = wire_tick(tick);
+            for reference in [tick, tick.saturating_sub(100), tick + 100] {
+                assert_eq!(widen_tick(wire, reference), tick, "{} from {}", tick, reference);
+            }
+        }
+
+        assert_eq!(wire_tick(wrap + 5), 5);
+        // right after the wrap, against a tick from before it
+        assert_eq!(widen_tick(2, wrap - 3), wrap + 2);
+        // near zero it can't go below it
+        assert_eq!(widen_tick(u32::MAX, 1), 0);
+    }
+}
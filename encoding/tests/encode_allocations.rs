@@ -0,0 +1,85 @@
+// serialize_into and json_into with a warm EncodeBuffer must not touch the
+// heap for messages that are the same size every time, that is the whole
+// point of them. A counting global allocator counts this thread only, the
+// test harness allocates on its own threads while tests run.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use anyhow::Result;
+use encoding::server::{EncodeBuffer, Message, PlayerPositionUpdate, ServerMessage, Snapshot};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        return System.alloc(layout);
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        return System.realloc(ptr, layout, new_size);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocations() -> usize {
+    return ALLOCATIONS.with(|count| count.get());
+}
+
+// the messages that go out every tick, built before counting
+fn messages() -> Vec<ServerMessage> {
+    let entities = (0..100)
+        .map(|i| PlayerPositionUpdate {
+            entity_id: i * 3,
+            position: (i as u16, 2 * i as u16),
+        })
+        .collect();
+
+    return vec![
+        ServerMessage::new(1, Message::Snapshot(Snapshot::new(77, entities))),
+        ServerMessage::new(2, Message::key_press(b'j', 3)),
+        ServerMessage::new(3, Message::clock_response(1234)),
+    ];
+}
+
+#[test]
+fn test_warm_buffer_encodes_without_allocating() -> Result<()> {
+    let messages = messages();
+    let mut buffer = EncodeBuffer::default();
+    // the first round grows the buffer
+    for msg in messages.iter() {
+        msg.serialize_into(&mut buffer)?;
+        msg.json_into(&mut buffer)?;
+    }
+
+    for msg in messages.iter() {
+        let before = allocations();
+        let length = msg.serialize_into(&mut buffer)?.len();
+        assert_eq!(allocations() - before, 0, "binary {} allocated", msg.msg.tag());
+        assert!(length > 0);
+
+        let before = allocations();
+        let length = msg.json_into(&mut buffer)?.len();
+        assert_eq!(allocations() - before, 0, "json {} allocated", msg.msg.tag());
+        assert!(length > 0);
+    }
+
+    // the allocating one does, or this test counts nothing
+    let before = allocations();
+    messages[0].clone().serialize()?;
+    assert!(allocations() > before);
+
+    return Ok(());
+}
@@ -0,0 +1,59 @@
+// Pins what msgdump prints for the inputs in tests/msgdump/, every input
+// against the <name>.out next to it. When the output changes on purpose
+// write them again with
+// UPDATE_MSGDUMP=1 cargo test -p encoding --test msgdump
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use encoding::msgdump::{input_bytes, render, Options};
+
+const UPDATE_ENV: &str = "UPDATE_MSGDUMP";
+
+fn inputs() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/msgdump");
+}
+
+// (input file, options beyond what msgdump guesses, decodes completely)
+fn cases() -> Vec<(&'static str, Options, bool)> {
+    let framed = Options {
+        framed: true,
+        ..Options::default()
+    };
+
+    return vec![
+        ("event_batch.hex", Options::default(), true),
+        ("back_to_back.b64", Options::default(), true),
+        ("json.json", Options::default(), true),
+        ("framed_truncated.bin", framed, false),
+        ("unknown_tag.hex", Options::default(), false),
+    ];
+}
+
+#[test]
+fn test_output_matches_golden_files() -> Result<()> {
+    let update = std::env::var_os(UPDATE_ENV).is_some();
+
+    for (name, options, complete) in cases() {
+        let data = input_bytes(&std::fs::read(inputs().join(name))?, options.input)?;
+        let (out, ok) = render(&data, &options)?;
+        assert_eq!(ok, complete, "{}", name);
+
+        let golden = inputs().join(name).with_extension("out");
+        if update {
+            std::fs::write(&golden, &out)?;
+            continue;
+        }
+
+        let expected = std::fs::read_to_string(&golden).unwrap_or_else(|_| panic!("no {}, run with {}=1", golden.display(), UPDATE_ENV));
+        assert!(
+            expected == out,
+            "msgdump prints {} differently, rerun with {}=1 if that was the point:\n{}",
+            name,
+            UPDATE_ENV,
+            out
+        );
+    }
+
+    return Ok(());
+}
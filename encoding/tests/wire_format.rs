@@ -0,0 +1,80 @@
+// The bytes of every message pinned against the golden files in tests/wire/,
+// see encoding::fixtures. A failure here means the wire format changed: if
+// that was the point, bump VERSION and run
+// cargo run -p encoding --bin regen-fixtures
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use encoding::{
+    fixtures::{canonical_messages, encode, version_dir, FIXTURE_DIR},
+    server::{ServerMessage, MESSAGE_TAG_NAMES},
+    version::VERSION,
+};
+
+fn fixtures() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_DIR);
+}
+
+#[test]
+fn test_fixtures_are_for_the_current_version() -> Result<()> {
+    assert!(
+        fixtures().join(version_dir(VERSION)).is_dir(),
+        "VERSION is {} but there are no fixtures for it, run regen-fixtures",
+        VERSION
+    );
+
+    for entry in std::fs::read_dir(fixtures())? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let version: u8 = name
+            .strip_prefix('v')
+            .and_then(|version| version.parse().ok())
+            .unwrap_or_else(|| panic!("{} isn't a version directory", name));
+        assert!(version <= VERSION, "fixtures for version {} but VERSION is {}", version, VERSION);
+    }
+
+    return Ok(());
+}
+
+#[test]
+fn test_messages_match_their_fixtures_byte_for_byte() -> Result<()> {
+    let dir = fixtures().join(version_dir(VERSION));
+    let mut expected = HashSet::new();
+
+    for (name, msg) in canonical_messages() {
+        let (binary, json) = encode(&msg)?;
+        for (ext, bytes) in [("bin", &binary), ("json", &json)] {
+            let file = format!("{}.{}", name, ext);
+            let fixture = std::fs::read(dir.join(&file)).unwrap_or_else(|_| panic!("no fixture {}, run regen-fixtures", file));
+            assert!(
+                &fixture == bytes,
+                "{} no longer encodes like {}, that is a wire format change",
+                name,
+                file
+            );
+            expected.insert(file);
+        }
+
+        // and the fixtures still decode to the value
+        assert_eq!(ServerMessage::deserialize(&binary)?.msg, msg, "{}.bin", name);
+        assert_eq!(ServerMessage::from_json(&json)?.msg, msg, "{}.json", name);
+    }
+
+    for entry in std::fs::read_dir(&dir)? {
+        let file = entry?.file_name().to_string_lossy().to_string();
+        assert!(expected.contains(&file), "{} has no canonical message anymore", file);
+    }
+
+    return Ok(());
+}
+
+#[test]
+fn test_every_variant_has_a_fixture() {
+    let covered: HashSet<usize> = canonical_messages().iter().map(|(_, msg)| msg.tag()).collect();
+    for (tag, name) in MESSAGE_TAG_NAMES.iter().enumerate() {
+        if *name != "unused" {
+            assert!(covered.contains(&tag), "{} has no canonical message", name);
+        }
+    }
+}
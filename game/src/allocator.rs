@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use tracing::error;
+
+use crate::seed::{SeedSource, TimeSeeds};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameAllocation {
+    pub game_id: u32,
+    // what the game plays with, see SeedSource::game_seed
+    pub seed: u32,
+    // what the seed source drew, kept to reproduce seed from
+    pub base_seed: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AllocError {
+    // every u32 id has been handed out, ids are never recycled
+    Exhausted,
+    // couldn't persist the high water mark, the id could repeat after a restart
+    Persist,
+}
+
+struct AllocatorState {
+    next: u32,
+    seeds: Box<dyn SeedSource>,
+}
+
+/// hands out game ids that are unique for the life of the process, and across
+/// restarts when there is a state file to keep the high water mark in.
+pub struct GameIdAllocator {
+    // held across the state file write, so marks are written in order
+    state: tokio::sync::Mutex<AllocatorState>,
+    // game_id -> its seeds while the game is around, for results and replays
+    allocations: Mutex<HashMap<u32, GameAllocation>>,
+    path: Option<PathBuf>,
+}
+
+fn read_high_water(path: &PathBuf) -> Result<u32> {
+    if !path.exists() {
+        return Ok(1);
+    }
+
+    let contents = std::fs::read_to_string(path).context("reading game id state")?;
+    return contents.trim().parse().context("parsing game id state");
+}
+
+fn write_high_water(path: &PathBuf, next: u32) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, next.to_string())?;
+    std::fs::rename(&tmp, path)?;
+    return Ok(());
+}
+
+impl GameIdAllocator {
+    pub fn new(path: Option<PathBuf>) -> Result<Self> {
+        return Self::with_seeds(path, Box::new(TimeSeeds::new()));
+    }
+
+    pub fn with_seeds(path: Option<PathBuf>, seeds: Box<dyn SeedSource>) -> Result<Self> {
+        let next = match &path {
+            Some(path) => read_high_water(path)?,
+            None => 1,
+        };
+
+        return Ok(GameIdAllocator {
+            state: tokio::sync::Mutex::new(AllocatorState { next, seeds }),
+            allocations: Mutex::new(HashMap::new()),
+            path,
+        });
+    }
+
+    pub async fn allocate(&self) -> Result<GameAllocation, AllocError> {
+        let mut state = self.state.lock().await;
+        let game_id = state.next;
+        let next = game_id.checked_add(1).ok_or(AllocError::Exhausted)?;
+
+        // persist first, an id that might repeat after a restart is never handed out.
+        // the write goes to the blocking pool, not the runtime's threads
+        if let Some(path) = self.path.clone() {
+            let written = tokio::task::spawn_blocking(move || write_high_water(&path, next))
+                .await
+                .unwrap_or_else(|e| Err(e.into()));
+            if let Err(e) = written {
+                error!(error = ?e, "failed to persist game id high water mark");
+                return Err(AllocError::Persist);
+            }
+        }
+
+        state.next = next;
+        let base_seed = state.seeds.next_seed();
+        let allocation = GameAllocation {
+            game_id,
+            seed: state.seeds.game_seed(base_seed, game_id),
+            base_seed,
+        };
+        self.allocations.lock().unwrap_or_else(|e| e.into_inner()).insert(game_id, allocation);
+
+        return Ok(allocation);
+    }
+
+    /// forgets a game's seeds once it closed.
+    pub fn release(&self, game_id: u32) {
+        self.allocations.lock().unwrap_or_else(|e| e.into_inner()).remove(&game_id);
+    }
+
+    pub fn seed_for(&self, game_id: u32) -> Option<u32> {
+        return self.allocation(game_id).map(|allocation| allocation.seed);
+    }
+
+    pub fn allocation(&self, game_id: u32) -> Option<GameAllocation> {
+        let allocations = self.allocations.lock().unwrap_or_else(|e| e.into_inner());
+        return allocations.get(&game_id).cloned();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::{read_high_water, write_high_water, AllocError, GameIdAllocator};
+    use crate::seed::FixedSeed;
+
+    fn state_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vim-royale-{}-{}", name, std::process::id()));
+        _ = std::fs::remove_file(&path);
+        return path;
+    }
+
+    #[tokio::test]
+    async fn test_restart_continues_after_high_water() -> anyhow::Result<()> {
+        let path = state_path("restart");
+
+        let first = GameIdAllocator::new(Some(path.clone()))?;
+        let mut ids = vec![];
+        for _ in 0..3 {
+            ids.push(first.allocate().await.map(|a| a.game_id));
+        }
+        assert_eq!(ids, vec![Ok(1), Ok(2), Ok(3)]);
+        drop(first);
+
+        let restarted = GameIdAllocator::new(Some(path.clone()))?;
+        assert_eq!(restarted.allocate().await.map(|a| a.game_id), Ok(4));
+
+        std::fs::remove_file(&path)?;
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_wraparound_is_refused() -> anyhow::Result<()> {
+        let path = state_path("wraparound");
+        write_high_water(&path, u32::MAX - 1)?;
+
+        let allocator = GameIdAllocator::new(Some(path.clone()))?;
+        assert_eq!(allocator.allocate().await.map(|a| a.game_id), Ok(u32::MAX - 1));
+        assert_eq!(allocator.allocate().await, Err(AllocError::Exhausted));
+        assert_eq!(allocator.allocate().await, Err(AllocError::Exhausted));
+
+        std::fs::remove_file(&path)?;
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_colliding_base_seeds_play_different_seeds() -> anyhow::Result<()> {
+        // what two time seeded games drawing the same seed looks like
+        struct Collide;
+        impl crate::seed::SeedSource for Collide {
+            fn next_seed(&mut self) -> u32 {
+                return 1234;
+            }
+        }
+
+        let allocator = GameIdAllocator::with_seeds(None, Box::new(Collide))?;
+        let first = allocator.allocate().await.expect("ids left");
+        let second = allocator.allocate().await.expect("ids left");
+        assert_eq!((first.base_seed, second.base_seed), (1234, 1234));
+        assert_ne!(first.seed, second.seed);
+        assert_eq!(allocator.allocation(second.game_id), Some(second));
+
+        // gone with the game
+        allocator.release(second.game_id);
+        assert_eq!(allocator.allocation(second.game_id), None);
+        assert_eq!(allocator.allocation(first.game_id), Some(first));
+
+        // a fixed seed is on purpose, every game keeps it
+        let fixed = GameIdAllocator::with_seeds(None, Box::new(FixedSeed(9)))?;
+        assert_eq!(fixed.allocate().await.map(|a| a.seed), Ok(9));
+        assert_eq!(fixed.allocate().await.map(|a| a.seed), Ok(9));
+
+        return Ok(());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_allocations_are_unique() -> anyhow::Result<()> {
+        let path = state_path("concurrent");
+        let allocator = Arc::new(GameIdAllocator::new(Some(path.clone()))?);
+
+        let mut tasks = vec![];
+        for _ in 0..8 {
+            let allocator = allocator.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut allocations = vec![];
+                for _ in 0..100 {
+                    allocations.push(allocator.allocate().await.expect("ids left"));
+                }
+                return allocations;
+            }));
+        }
+
+        let mut ids = HashSet::new();
+        for task in tasks {
+            for allocation in task.await? {
+                assert!(ids.insert(allocation.game_id));
+                assert_eq!(allocator.seed_for(allocation.game_id), Some(allocation.seed));
+            }
+        }
+        assert_eq!(ids.len(), 800);
+        // the last write is the highest mark
+        assert_eq!(read_high_water(&path)?, 801);
+
+        std::fs::remove_file(&path)?;
+
+        return Ok(());
+    }
+}
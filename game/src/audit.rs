@@ -0,0 +1,231 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::recovery::now_millis;
+
+// what the first record of a file chains onto
+pub const GENESIS: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug)]
+pub enum AuditError {
+    Io(std::io::Error),
+    // line numbers start at 1
+    Corrupt(usize),
+    BrokenChain(usize),
+}
+
+impl std::fmt::Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            AuditError::Io(e) => write!(f, "audit log io error: {}", e),
+            AuditError::Corrupt(line) => write!(f, "audit log line {} is not a record", line),
+            AuditError::BrokenChain(line) => write!(f, "audit log line {} doesn't chain onto the line before", line),
+        };
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<std::io::Error> for AuditError {
+    fn from(e: std::io::Error) -> Self {
+        return AuditError::Io(e);
+    }
+}
+
+/// one admin action. admins that ask for one get a record before it runs
+/// and one with how it went after.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AuditEntry {
+    pub at_millis: u64,
+    pub admin: String,
+    // where the admin connected from, records from before it was kept have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<SocketAddr>,
+    pub game_id: Option<u32>,
+    pub action: String,
+    pub params: serde_json::Value,
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        admin: &str,
+        peer: Option<SocketAddr>,
+        game_id: Option<u32>,
+        action: &str,
+        params: serde_json::Value,
+        outcome: &str,
+    ) -> Self {
+        return Self {
+            at_millis: now_millis(),
+            admin: admin.to_string(),
+            peer,
+            game_id,
+            action: action.to_string(),
+            params,
+            outcome: outcome.to_string(),
+        };
+    }
+}
+
+/// a line of the audit file. hash covers prev and the entry, so editing or
+/// dropping a line breaks every hash after it.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AuditRecord {
+    pub entry: AuditEntry,
+    pub prev: String,
+    pub hash: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+}
+
+fn chain_hash(prev: &str, entry: &AuditEntry) -> Result<String, AuditError> {
+    let entry = serde_json::to_vec(entry).map_err(std::io::Error::from)?;
+    let mut hasher = Sha1::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(&entry);
+
+    return Ok(hex(&hasher.finalize()));
+}
+
+/// walks the whole chain, Ok is (records, hash of the last one). a file that
+/// lost lines off its end still verifies, compare the head with the one the
+/// server logged to catch that.
+pub fn verify(path: &Path) -> Result<(usize, String), AuditError> {
+    let mut head = GENESIS.to_string();
+    let mut records = 0;
+
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let record: AuditRecord = serde_json::from_str(&line?).map_err(|_| AuditError::Corrupt(i + 1))?;
+        if record.prev != head || chain_hash(&record.prev, &record.entry)? != record.hash {
+            return Err(AuditError::BrokenChain(i + 1));
+        }
+
+        head = record.hash;
+        records += 1;
+    }
+
+    return Ok((records, head));
+}
+
+/// the append only audit file every admin action goes into.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    head: String,
+}
+
+impl AuditLog {
+    /// picks up where the file left off, a file that doesn't verify is
+    /// never appended to.
+    pub fn open(path: &Path) -> Result<Self, AuditError> {
+        let head = match path.exists() {
+            true => verify(path)?.1,
+            false => GENESIS.to_string(),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        return Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            head,
+        });
+    }
+
+    /// only returns once the record is on disk.
+    pub fn append(&mut self, entry: AuditEntry) -> Result<(), AuditError> {
+        let hash = chain_hash(&self.head, &entry)?;
+        let record = AuditRecord {
+            entry,
+            prev: self.head.clone(),
+            hash,
+        };
+
+        let mut line = serde_json::to_vec(&record).map_err(std::io::Error::from)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.head = record.hash;
+
+        return Ok(());
+    }
+
+    // every append fails, for tests of what happens then
+    #[cfg(test)]
+    pub fn unwritable() -> Self {
+        let path = PathBuf::from("/dev/full");
+        return Self {
+            file: OpenOptions::new().append(true).open(&path).expect("/dev/full exists"),
+            path,
+            head: GENESIS.to_string(),
+        };
+    }
+
+    pub fn head(&self) -> &str {
+        return &self.head;
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{verify, AuditEntry, AuditError, AuditLog, GENESIS};
+
+    fn entry(action: &str) -> AuditEntry {
+        return AuditEntry::new("admin-1", None, Some(3), action, json!({ "player_id": 2 }), "ok");
+    }
+
+    #[test]
+    fn test_tampered_file_fails_verification() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("vim-royale-audit-{}.jsonl", std::process::id()));
+        _ = std::fs::remove_file(&path);
+
+        let mut log = AuditLog::open(&path)?;
+        assert_eq!(log.head(), GENESIS);
+        log.append(entry("mute"))?;
+        log.append(entry("unmute"))?;
+        drop(log);
+
+        // picks the chain back up
+        let mut log = AuditLog::open(&path)?;
+        log.append(entry("slow"))?;
+        let (records, head) = verify(&path)?;
+        assert_eq!(records, 3);
+        assert_eq!(head, log.head());
+
+        let intact = std::fs::read_to_string(&path)?;
+        let lines: Vec<&str> = intact.lines().collect();
+
+        let edited = intact.replacen("\"unmute\"", "\"mute\"", 1);
+        std::fs::write(&path, edited)?;
+        assert!(matches!(verify(&path), Err(AuditError::BrokenChain(2))));
+        assert!(AuditLog::open(&path).is_err());
+
+        let dropped = format!("{}\n{}\n", lines[0], lines[2]);
+        std::fs::write(&path, dropped)?;
+        assert!(matches!(verify(&path), Err(AuditError::BrokenChain(2))));
+
+        let cut = &intact[..intact.len() - 10];
+        std::fs::write(&path, cut)?;
+        assert!(matches!(verify(&path), Err(AuditError::Corrupt(3))));
+
+        // whole lines off the end only show against the head
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[1]))?;
+        assert_ne!(verify(&path)?.1, head);
+
+        _ = std::fs::remove_file(&path);
+        return Ok(());
+    }
+}
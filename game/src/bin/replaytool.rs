@@ -0,0 +1,61 @@
+// Looks at the capture files a server writes with --capture-dir, see
+// game::capture. print shows every frame decoded, replay plays the client's
+// side into a fresh game with the same seed and prints what that game sent
+// back, to see whether the server does the same thing twice.
+//
+// cargo run -p game --bin replaytool -- print captures/game-3-0-player-2.capture
+// cargo run -p game --bin replaytool -- replay captures/game-3-0-player-2.capture
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use game::{
+    capture::{replay, Capture, Direction},
+    game_config::GameConfig,
+};
+
+#[derive(Parser, Debug)]
+#[clap()]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Print {
+        capture: PathBuf,
+    },
+
+    Replay {
+        capture: PathBuf,
+
+        // how long to keep listening after the capture's last frame
+        #[clap(long = "settle-ms", default_value_t = 500)]
+        settle_ms: u64,
+    },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    match Args::parse().command {
+        Command::Print { capture } => print!("{}", Capture::read(&capture)?.pretty()),
+
+        Command::Replay { capture, settle_ms } => {
+            let capture = Capture::read(&capture)?;
+            let sent = replay(&capture, GameConfig::default(), Duration::from_millis(settle_ms)).await?;
+            let captured = capture.frames.iter().filter(|frame| frame.direction == Direction::Out).count();
+
+            let replayed = Capture {
+                header: capture.header.clone(),
+                frames: sent,
+            };
+            print!("{}", replayed.pretty());
+            println!("{} frames sent in the capture, {} in the replay", captured, replayed.frames.len());
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,139 @@
+use encoding::server::{PlayerPositionUpdate, Zone};
+use map::rand::mulberry32;
+
+use crate::interest::{in_range, VIEW_DISTANCE};
+
+// ticks a bot keeps walking in the same direction while wandering
+const WANDER_TICKS: u8 = 30;
+const WANDER_KEYS: [u8; 4] = [b'h', b'j', b'k', b'l'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behavior {
+    Wander,
+    Chase((u16, u16)),
+    Flee,
+}
+
+/// server side stand in for a player. it sits in a regular player slot and
+/// only ever acts through the same key presses a client would send.
+/// bots don't count for ranked results, everything reporting on a game should
+/// check Game::is_bot.
+pub struct Bot {
+    pub id: u8,
+    rand: Box<dyn FnMut() -> u32 + Send>,
+    wander_key: u8,
+    wander_ticks: u8,
+}
+
+impl Bot {
+    /// the brain only draws from a rand seeded by the game seed and its id,
+    /// so a replay of the same inputs makes the same decisions.
+    pub fn new(id: u8, seed: u32) -> Self {
+        return Bot {
+            id,
+            rand: Box::new(mulberry32(seed ^ id as u32)),
+            wander_key: b'h',
+            wander_ticks: 0,
+        };
+    }
+
+    pub fn behavior(&self, me: (u16, u16), others: &[PlayerPositionUpdate], zone: &Zone) -> Behavior {
+        if !in_range(me, zone.center, zone.radius) {
+            return Behavior::Flee;
+        }
+
+        let nearest = others
+            .iter()
+            .map(|other| other.position)
+            .filter(|&pos| pos != me && in_range(me, pos, VIEW_DISTANCE))
+            .min_by_key(|pos| me.0.abs_diff(pos.0) as u32 + me.1.abs_diff(pos.1) as u32);
+
+        return match nearest {
+            Some(pos) => Behavior::Chase(pos),
+            None => Behavior::Wander,
+        };
+    }
+
+    /// the key this bot presses this tick, others should not include the bot itself.
+    pub fn think(&mut self, me: (u16, u16), others: &[PlayerPositionUpdate], zone: &Zone) -> Option<u8> {
+        return match self.behavior(me, others, zone) {
+            Behavior::Flee => step_towards(me, zone.center),
+            Behavior::Chase(target) => step_towards(me, target),
+            Behavior::Wander => Some(self.wander()),
+        };
+    }
+
+    fn wander(&mut self) -> u8 {
+        if self.wander_ticks == 0 {
+            self.wander_key = WANDER_KEYS[(self.rand)() as usize % WANDER_KEYS.len()];
+            self.wander_ticks = WANDER_TICKS;
+        }
+
+        self.wander_ticks -= 1;
+        return self.wander_key;
+    }
+}
+
+// walks the longer axis first, None once it's there
+fn step_towards(from: (u16, u16), to: (u16, u16)) -> Option<u8> {
+    let dx = to.0 as i32 - from.0 as i32;
+    let dy = to.1 as i32 - from.1 as i32;
+
+    if dx == 0 && dy == 0 {
+        return None;
+    }
+
+    if dx.abs() >= dy.abs() {
+        return Some(if dx > 0 { b'l' } else { b'h' });
+    }
+
+    return Some(if dy > 0 { b'j' } else { b'k' });
+}
+
+#[cfg(test)]
+mod test {
+    use encoding::server::{PlayerPositionUpdate, Zone};
+
+    use super::{Behavior, Bot};
+
+    const ZONE: Zone = Zone {
+        center: (100, 100),
+        radius: 50,
+    };
+
+    fn other(position: (u16, u16)) -> PlayerPositionUpdate {
+        return PlayerPositionUpdate {
+            entity_id: 0,
+            position,
+        };
+    }
+
+    #[test]
+    fn test_same_seed_same_decisions() {
+        let mut a = Bot::new(3, 1337);
+        let mut b = Bot::new(3, 1337);
+
+        let a_keys: Vec<_> = (0..200).map(|_| a.think((100, 100), &[], &ZONE)).collect();
+        let b_keys: Vec<_> = (0..200).map(|_| b.think((100, 100), &[], &ZONE)).collect();
+
+        assert_eq!(a_keys, b_keys);
+    }
+
+    #[test]
+    fn test_behaviors() {
+        let mut bot = Bot::new(0, 0);
+
+        // outside the zone beats everything else
+        let others = [other((10, 10))];
+        assert_eq!(bot.behavior((10, 12), &others, &ZONE), Behavior::Flee);
+        assert_eq!(bot.think((10, 12), &others, &ZONE), Some(b'l'));
+
+        let others = [other((100, 130)), other((100, 110))];
+        assert_eq!(bot.behavior((100, 100), &others, &ZONE), Behavior::Chase((100, 110)));
+        assert_eq!(bot.think((100, 100), &others, &ZONE), Some(b'j'));
+
+        // standing on top of someone or nobody in view
+        assert_eq!(bot.behavior((100, 100), &[other((100, 100))], &ZONE), Behavior::Wander);
+        assert_eq!(bot.behavior((100, 100), &[other((145, 100))], &ZONE), Behavior::Wander);
+    }
+}
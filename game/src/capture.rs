@@ -0,0 +1,596 @@
+// Every frame of one connection, for when a client reports a desync and
+// their exact byte stream is needed. With a capture dir set every player
+// gets a capture file once they take their slot, inbound and outbound frames
+// with the time they went by. The replaytool binary prints them and can play
+// the inbound side into a fresh game on the Memory transport.
+//
+// The file is a header and then records, all integers little endian:
+//   header  b"VRCAP" version:u8 inbound:u8 outbound:u8 seed:u32 game_id:u32
+//           epoch:u32 player_id:u8 started_ms:u64
+//   record  direction:u8 micros:u64 len:u32 bytes
+// micros count from the capture's start. A server that died mid write
+// leaves a cut off last record, reading stops before it.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{atomic::AtomicU8, Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use encoding::server::{self, ServerMessage, WHO_AM_I_CLIENT};
+use futures::{SinkExt, Stream, StreamExt};
+use tracing::warn;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use crate::{
+    connection::SerializationType,
+    game::game_run,
+    game_comms::{GameComms, GameKey, GameMessage, IncomingConnection},
+    game_config::GameConfig,
+    player::deserialize,
+    transport::{memory_pair, Memory},
+};
+
+pub const CAPTURE_MAGIC: &[u8; 5] = b"VRCAP";
+pub const CAPTURE_VERSION: u8 = 1;
+
+const HEADER_LENGTH: usize = 5 + 1 + 1 + 1 + 4 + 4 + 4 + 1 + 8;
+const RECORD_HEADER_LENGTH: usize = 1 + 8 + 4;
+
+// frames in flight between the replay and its game
+const REPLAY_BUFFER: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    // client to server
+    In,
+    Out,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureHeader {
+    pub game: GameKey,
+    pub player_id: u8,
+    pub seed: u32,
+    // what the client sends and what the server answers in
+    pub inbound: SerializationType,
+    pub outbound: SerializationType,
+    // unix millis
+    pub started_ms: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub direction: Direction,
+    // since the capture started
+    pub at: Duration,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capture {
+    pub header: CaptureHeader,
+    pub frames: Vec<CapturedFrame>,
+}
+
+fn ser_type_byte(ser_type: SerializationType) -> u8 {
+    return ser_type as u8;
+}
+
+fn ser_type_from(byte: u8) -> Result<SerializationType> {
+    return match byte {
+        0 => Ok(SerializationType::JSON),
+        1 => Ok(SerializationType::Deku),
+        _ => Err(anyhow!("unknown serialization {}", byte)),
+    };
+}
+
+impl CaptureHeader {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH);
+        bytes.extend_from_slice(CAPTURE_MAGIC);
+        bytes.push(CAPTURE_VERSION);
+        bytes.push(ser_type_byte(self.inbound));
+        bytes.push(ser_type_byte(self.outbound));
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.game.id.to_le_bytes());
+        bytes.extend_from_slice(&self.game.epoch.to_le_bytes());
+        bytes.push(self.player_id);
+        bytes.extend_from_slice(&self.started_ms.to_le_bytes());
+
+        return bytes;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<CaptureHeader> {
+        if bytes.len() < HEADER_LENGTH || &bytes[..5] != CAPTURE_MAGIC {
+            bail!("not a capture file");
+        }
+        if bytes[5] != CAPTURE_VERSION {
+            bail!("capture version {}, this reads {}", bytes[5], CAPTURE_VERSION);
+        }
+
+        let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        let mut started_ms = [0; 8];
+        started_ms.copy_from_slice(&bytes[21..29]);
+
+        return Ok(CaptureHeader {
+            inbound: ser_type_from(bytes[6])?,
+            outbound: ser_type_from(bytes[7])?,
+            seed: u32_at(8),
+            game: GameKey {
+                id: u32_at(12),
+                epoch: u32_at(16),
+            },
+            player_id: bytes[20],
+            started_ms: u64::from_le_bytes(started_ms),
+        });
+    }
+}
+
+impl CapturedFrame {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self.direction {
+            Direction::In => 0,
+            Direction::Out => 1,
+        });
+        out.extend_from_slice(&(self.at.as_micros() as u64).to_le_bytes());
+        out.extend_from_slice(&(self.bytes.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.bytes);
+    }
+}
+
+impl Capture {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.header.encode();
+        for frame in self.frames.iter() {
+            frame.encode(&mut bytes);
+        }
+
+        return bytes;
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Capture> {
+        let header = CaptureHeader::decode(bytes)?;
+        let mut frames = vec![];
+        let mut rest = &bytes[HEADER_LENGTH..];
+
+        while rest.len() >= RECORD_HEADER_LENGTH {
+            let direction = match rest[0] {
+                0 => Direction::In,
+                1 => Direction::Out,
+                other => bail!("bad direction {} in frame {}", other, frames.len()),
+            };
+            let mut micros = [0; 8];
+            micros.copy_from_slice(&rest[1..9]);
+            let len = u32::from_le_bytes([rest[9], rest[10], rest[11], rest[12]]) as usize;
+
+            // cut off mid write
+            let Some(frame) = rest.get(RECORD_HEADER_LENGTH..RECORD_HEADER_LENGTH + len) else {
+                break;
+            };
+            frames.push(CapturedFrame {
+                direction,
+                at: Duration::from_micros(u64::from_le_bytes(micros)),
+                bytes: frame.to_vec(),
+            });
+            rest = &rest[RECORD_HEADER_LENGTH + len..];
+        }
+
+        return Ok(Capture { header, frames });
+    }
+
+    pub fn read(path: &Path) -> Result<Capture> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        return Capture::decode(&bytes);
+    }
+
+    /// one line per frame, decoded with the encoding crate where it decodes.
+    pub fn pretty(&self) -> String {
+        let header = &self.header;
+        let mut out = format!(
+            "capture v{} game {}/{} player {} seed {} in {:?} out {:?} started {}\n",
+            CAPTURE_VERSION,
+            header.game.id,
+            header.game.epoch,
+            header.player_id,
+            header.seed,
+            header.inbound,
+            header.outbound,
+            header.started_ms
+        );
+
+        for frame in self.frames.iter() {
+            let (arrow, ser_type) = match frame.direction {
+                Direction::In => ("->", header.inbound),
+                Direction::Out => ("<-", header.outbound),
+            };
+            let msg = match deserialize(frame.bytes.clone(), &ser_type) {
+                Ok(msg) => format!("#{} {:?}", msg.seq_nu, msg.msg),
+                Err(_) => format!("{} undecodable bytes", frame.bytes.len()),
+            };
+            out.push_str(&format!("{:>12.6}s {} {}\n", frame.at.as_secs_f64(), arrow, msg));
+        }
+
+        return out;
+    }
+}
+
+/// tournament tokens are as good as a password, they never reach a file.
+pub fn redact(bytes: &[u8], ser_type: SerializationType) -> Vec<u8> {
+    let Ok(mut msg) = deserialize(bytes.to_vec(), &ser_type) else {
+        return bytes.to_vec();
+    };
+    let server::Message::JoinTournament(token) = &mut msg.msg else {
+        return bytes.to_vec();
+    };
+    *token = 0;
+
+    let redacted = match ser_type {
+        SerializationType::JSON => serde_json::to_vec(&msg).map_err(anyhow::Error::from),
+        SerializationType::Deku => msg.serialize(),
+    };
+    // a token that can't be taken out doesn't go in either
+    return redacted.unwrap_or_default();
+}
+
+/// tees one connection into its capture file as frames go by. writes are
+/// blocking and flushed per frame, it is for debugging only.
+#[derive(Debug)]
+pub struct CaptureWriter {
+    started: Instant,
+    inbound: SerializationType,
+    out: Mutex<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path, header: &CaptureHeader) -> Result<CaptureWriter> {
+        let mut out = BufWriter::new(File::create(path).with_context(|| format!("creating {}", path.display()))?);
+        out.write_all(&header.encode())?;
+        out.flush()?;
+
+        return Ok(CaptureWriter {
+            started: Instant::now(),
+            inbound: header.inbound,
+            out: Mutex::new(out),
+        });
+    }
+
+    pub fn record(&self, direction: Direction, bytes: &[u8]) {
+        let bytes = match direction {
+            Direction::In => redact(bytes, self.inbound),
+            Direction::Out => bytes.to_vec(),
+        };
+        let frame = CapturedFrame {
+            direction,
+            at: self.started.elapsed(),
+            bytes,
+        };
+        let mut record = vec![];
+        frame.encode(&mut record);
+
+        let Ok(mut out) = self.out.lock() else {
+            return;
+        };
+        if let Err(e) = out.write_all(&record).and_then(|_| out.flush()) {
+            warn!(error = ?e, "capture write failed");
+        }
+    }
+}
+
+/// one capture file per player, named after their game and slot.
+#[derive(Debug, Clone)]
+pub struct CaptureDir {
+    dir: PathBuf,
+}
+
+impl CaptureDir {
+    pub fn new(dir: &Path) -> Self {
+        return CaptureDir { dir: dir.to_path_buf() };
+    }
+
+    pub fn path(&self, game: GameKey, player_id: u8) -> PathBuf {
+        return self
+            .dir
+            .join(format!("game-{}-{}-player-{}.capture", game.id, game.epoch, player_id));
+    }
+
+    pub fn open(&self, header: &CaptureHeader) -> Result<Arc<CaptureWriter>> {
+        std::fs::create_dir_all(&self.dir).context("creating capture dir")?;
+        return Ok(Arc::new(CaptureWriter::create(&self.path(header.game, header.player_id), header)?));
+    }
+}
+
+/// a connection's inbound frames, recorded on their way to the game.
+#[derive(Debug)]
+pub struct CaptureStream<S> {
+    inner: S,
+    capture: Arc<CaptureWriter>,
+}
+
+impl<S> CaptureStream<S> {
+    pub fn new(inner: S, capture: Arc<CaptureWriter>) -> Self {
+        return CaptureStream { inner, capture };
+    }
+}
+
+impl<S> Stream for CaptureStream<S>
+where
+    S: Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    type Item = Result<Message, tungstenite::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let next = self.inner.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(Message::Binary(bytes)))) = &next {
+            self.capture.record(Direction::In, bytes);
+        }
+
+        return next;
+    }
+}
+
+fn encode_for(msg: server::Message, ser_type: SerializationType) -> Result<Vec<u8>> {
+    let msg = ServerMessage::new(0, msg);
+    return match ser_type {
+        SerializationType::JSON => Ok(serde_json::to_vec(&msg)?),
+        SerializationType::Deku => msg.serialize(),
+    };
+}
+
+/// plays the inbound side of capture into a fresh game with its seed, on the
+/// Memory transport and on the capture's timeline, and returns what the
+/// server sent back. the clock sync before the player got their slot isn't
+/// in a capture, it is answered here. settle is how long to keep listening
+/// after the capture's last frame.
+pub async fn replay(capture: &Capture, config: GameConfig, settle: Duration) -> Result<Vec<CapturedFrame>> {
+    let header = &capture.header;
+    let config = GameConfig {
+        ser_type: header.inbound,
+        min_players: 1,
+        ..config
+    };
+    config.validate(crate::game::PLAYER_COUNT)?;
+
+    let (manager_tx, _manager_rx) = mpsc::channel(16);
+    let (comms, sender): (GameComms<Memory>, _) = GameComms::with_sender(manager_tx);
+    let game = tokio::spawn(game_run(header.seed, Arc::new(AtomicU8::new(0)), header.game, comms, config));
+
+    let (server, mut client) = memory_pair(REPLAY_BUFFER);
+    let (sink, stream) = server.split();
+    sender
+        .send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)))
+        .await
+        .map_err(|_| anyhow!("the game didn't take the connection"))?;
+
+    let last = capture.frames.last().map(|frame| frame.at).unwrap_or_default();
+    let mut inbound = capture.frames.iter().filter(|frame| frame.direction == Direction::In).peekable();
+    let mut started: Option<tokio::time::Instant> = None;
+    let mut sent = vec![];
+
+    loop {
+        // nothing is sent until the handshake is over
+        let next = match (started, inbound.peek()) {
+            (Some(started), Some(frame)) => started + frame.at,
+            (Some(started), None) => started + last + settle,
+            (None, _) => tokio::time::Instant::now() + config.handshake_timeout,
+        };
+
+        tokio::select! {
+            frame = client.next() => {
+                let bytes = match frame {
+                    Some(Ok(Message::Binary(bytes))) => bytes,
+                    Some(_) => continue,
+                    None => break,
+                };
+
+                let Some(started) = started else {
+                    match deserialize(bytes.clone(), &header.outbound)?.msg {
+                        server::Message::ClockSyncRequest(_) => {
+                            let now = crate::player::now_micros() / 1000;
+                            let resp = encode_for(server::Message::clock_response(now), header.inbound)?;
+                            client.send(Message::Binary(resp)).await?;
+                        }
+                        _ => {
+                            started = Some(tokio::time::Instant::now());
+                            sent.push(CapturedFrame { direction: Direction::Out, at: Duration::ZERO, bytes });
+                        }
+                    }
+                    continue;
+                };
+
+                sent.push(CapturedFrame { direction: Direction::Out, at: started.elapsed(), bytes });
+            }
+
+            _ = tokio::time::sleep_until(next) => {
+                if started.is_none() {
+                    bail!("the game never finished the handshake");
+                }
+                match inbound.next() {
+                    Some(frame) => client.send(Message::Binary(frame.bytes.clone())).await?,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    drop(client);
+    game.abort();
+
+    return Ok(sent);
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use std::sync::{atomic::AtomicU8, Arc};
+
+    use anyhow::Result;
+    use encoding::server::{self, ServerMessage, WHO_AM_I_CLIENT};
+    use futures::{SinkExt, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{replay, Capture, CaptureDir, CaptureHeader, CapturedFrame, Direction, CAPTURE_VERSION};
+    use crate::{
+        connection::SerializationType,
+        game::game_run,
+        game_comms::{GameComms, GameKey, GameMessage, IncomingConnection},
+        game_config::GameConfig,
+        test_utils::complete_handshake,
+        transport::{memory_pair, Memory},
+    };
+
+    fn header() -> CaptureHeader {
+        return CaptureHeader {
+            game: GameKey { id: 3, epoch: 1 },
+            player_id: 2,
+            seed: 69,
+            inbound: SerializationType::Deku,
+            outbound: SerializationType::Deku,
+            started_ms: 1_700_000_000_000,
+        };
+    }
+
+    fn frame(direction: Direction, millis: u64, msg: server::Message) -> CapturedFrame {
+        return CapturedFrame {
+            direction,
+            at: Duration::from_millis(millis),
+            bytes: ServerMessage::new(millis as u16, msg).serialize().expect("serializes"),
+        };
+    }
+
+    fn sample() -> Capture {
+        return Capture {
+            header: header(),
+            frames: vec![
+                frame(Direction::Out, 0, server::Message::Countdown(0)),
+                frame(Direction::In, 12, server::Message::key_press(b'j', 0)),
+                CapturedFrame {
+                    direction: Direction::In,
+                    at: Duration::from_micros(20_500),
+                    bytes: vec![0xff, 0x00, 0x13],
+                },
+                frame(Direction::Out, 40, server::Message::PlayerPositionUpdate(server::PlayerPositionUpdate {
+                    entity_id: 1000,
+                    position: (128, 129),
+                })),
+            ],
+        };
+    }
+
+    #[test]
+    fn test_capture_round_trips() -> Result<()> {
+        let capture = sample();
+        let bytes = capture.encode();
+        assert_eq!(Capture::decode(&bytes)?, capture);
+
+        // a server that died mid write leaves a cut off last frame
+        let cut = Capture::decode(&bytes[..bytes.len() - 3])?;
+        assert_eq!(cut.frames, capture.frames[..3]);
+
+        let mut old = bytes.clone();
+        old[5] = CAPTURE_VERSION + 1;
+        assert!(Capture::decode(&old).is_err());
+        assert!(Capture::decode(b"VRCA").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_writer_captures_and_redacts_tokens() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vim-royale-captures-{}", std::process::id()));
+        let captures = CaptureDir::new(&dir);
+        let writer = captures.open(&header())?;
+
+        let join = ServerMessage::new(1, server::Message::JoinTournament(0x0123_4567_89ab_cdef)).serialize()?;
+        writer.record(Direction::In, &join);
+        writer.record(Direction::Out, &ServerMessage::new(1, server::Message::Countdown(3)).serialize()?);
+        drop(writer);
+
+        let capture = Capture::read(&captures.path(header().game, header().player_id))?;
+        assert_eq!(capture.header, header());
+        let msgs: Vec<(Direction, server::Message)> = capture
+            .frames
+            .iter()
+            .map(|frame| (frame.direction, ServerMessage::deserialize(&frame.bytes).expect("decodes").msg))
+            .collect();
+        assert_eq!(
+            msgs,
+            vec![
+                (Direction::In, server::Message::JoinTournament(0)),
+                (Direction::Out, server::Message::Countdown(3)),
+            ]
+        );
+        assert!(capture.frames[0].at <= capture.frames[1].at);
+
+        std::fs::remove_dir_all(dir)?;
+        return Ok(());
+    }
+
+    #[test]
+    fn test_pretty_print_matches_golden() {
+        let golden = include_str!("../tests/capture/pretty.txt");
+        assert_eq!(sample().pretty(), golden, "pretty print changed, update tests/capture/pretty.txt if that was the point");
+    }
+
+    #[tokio::test]
+    async fn test_games_capture_their_players() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vim-royale-game-captures-{}", std::process::id()));
+        let (manager_tx, _manager_rx) = mpsc::channel(10);
+        let (mut comms, sender): (GameComms<Memory>, _) = GameComms::with_sender(manager_tx);
+        comms.capture = Some(CaptureDir::new(&dir));
+        let key = GameKey { id: 3, epoch: 1 };
+        let config = GameConfig {
+            max_ticks: Some(10),
+            ..GameConfig::default()
+        };
+        let game = tokio::spawn(game_run(69, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server, mut client) = memory_pair(256);
+        let (sink, stream) = server.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        complete_handshake(&mut client).await?;
+        client.send(Message::Binary(ServerMessage::new(1, server::Message::key_press(b'h', 0)).serialize()?)).await?;
+        tokio::time::timeout(Duration::from_secs(5), game).await??;
+
+        let capture = Capture::read(&CaptureDir::new(&dir).path(key, 0))?;
+        assert_eq!((capture.header.game, capture.header.seed), (key, 69));
+        let msgs: Vec<(Direction, server::Message)> = capture
+            .frames
+            .iter()
+            .map(|frame| (frame.direction, ServerMessage::deserialize(&frame.bytes).expect("decodes").msg))
+            .collect();
+        assert!(msgs.iter().any(|(direction, msg)| *direction == Direction::Out && matches!(msg, server::Message::PlayerStart(_))));
+        assert!(msgs.contains(&(Direction::In, server::Message::key_press(b'h', 0))));
+
+        std::fs::remove_dir_all(dir)?;
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_replay_drives_a_fresh_game() -> Result<()> {
+        let capture = Capture {
+            header: header(),
+            frames: vec![
+                frame(Direction::In, 10, server::Message::key_press(b'h', 0)),
+                frame(Direction::In, 30, server::Message::key_press(b'j', 0)),
+            ],
+        };
+
+        let sent = replay(&capture, GameConfig::default(), Duration::from_millis(100)).await?;
+        let msgs: Vec<server::Message> = sent
+            .iter()
+            .map(|frame| ServerMessage::deserialize(&frame.bytes).expect("decodes").msg)
+            .collect();
+        assert!(msgs.iter().any(|msg| matches!(msg, server::Message::PlayerStart(start) if start.seed == 69)));
+        assert!(msgs.iter().any(|msg| matches!(msg, server::Message::Snapshot(_))));
+        assert!(sent.last().is_some_and(|frame| frame.at >= Duration::from_millis(30)));
+
+        return Ok(());
+    }
+}
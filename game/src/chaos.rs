@@ -0,0 +1,341 @@
+// Connections that misbehave on purpose. Most connection bugs only show
+// under latency spikes, lost frames and connections dying mid message,
+// ChaosSocket wraps one end of a connection and does all of that to what is
+// read from it. Everything is drawn from a seeded rand so a failing run can
+// be replayed. Tests run games on the Chaos transport, see chaos_pair.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use encoding::server::{Message as ServerMsg, ServerMessage};
+use futures::{
+    ready,
+    stream::{SplitSink, SplitStream},
+    Sink, SinkExt, Stream, StreamExt,
+};
+use tracing::info;
+use map::rand::mulberry32;
+use tokio::time::Sleep;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use crate::transport::{memory_pair, MemorySocket, Transport};
+
+/// what a ChaosSocket does to the frames read from it, chances are 0 to 1.
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    pub seed: u32,
+    // every frame waits latency plus up to jitter before it is read
+    pub latency: Duration,
+    pub jitter: Duration,
+    // chance an unreliable frame never arrives, see unreliable
+    pub drop: f32,
+    // chance a frame arrives twice
+    pub duplicate: f32,
+    // one binary frame among the first this many arrives cut in half
+    pub truncate_within: Option<u32>,
+    // the connection dies at a random frame among the first this many
+    pub kill_within: Option<u32>,
+}
+
+/// messages the game sends again soon anyway, losing one is what a client
+/// has to live with.
+pub fn unreliable(frame: &Message) -> bool {
+    let Message::Binary(bytes) = frame else {
+        return false;
+    };
+
+    return matches!(
+        ServerMessage::deserialize(bytes).map(|msg| msg.msg),
+        Ok(ServerMsg::Snapshot(_)
+            | ServerMsg::SpectatorSync(_)
+            | ServerMsg::FineSnapshot(_)
+            | ServerMsg::PlayerPositionUpdate(_)
+            | ServerMsg::DebugTelemetry(_))
+    );
+}
+
+pub struct ChaosSocket<S> {
+    // None once killed, dropping it ends the connection for the other end too
+    inner: Option<S>,
+    config: ChaosConfig,
+    rand: Box<dyn FnMut() -> u32 + Send>,
+    frames: u32,
+    truncate_at: Option<u32>,
+    kill_at: Option<u32>,
+    // the frame waiting out its latency
+    held: Option<(Message, Pin<Box<Sleep>>)>,
+    duplicate: Option<Message>,
+}
+
+impl<S> ChaosSocket<S> {
+    pub fn new(inner: S, config: ChaosConfig) -> Self {
+        let mut rand = mulberry32(config.seed);
+        let truncate_at = config.truncate_within.map(|n| rand() % n.max(1) + 1);
+        let kill_at = config.kill_within.map(|n| rand() % n.max(1) + 1);
+
+        return ChaosSocket {
+            inner: Some(inner),
+            config,
+            rand: Box::new(rand),
+            frames: 0,
+            truncate_at,
+            kill_at,
+            held: None,
+            duplicate: None,
+        };
+    }
+
+    pub fn is_killed(&self) -> bool {
+        return self.inner.is_none();
+    }
+
+    fn chance(&mut self, chance: f32) -> bool {
+        return chance > 0.0 && ((self.rand)() as f64 / u32::MAX as f64) < chance as f64;
+    }
+
+    fn delay(&mut self) -> Duration {
+        let jitter = self.config.jitter.as_micros() as u32;
+        let jitter = match jitter {
+            0 => 0,
+            _ => (self.rand)() % jitter,
+        };
+
+        return self.config.latency + Duration::from_micros(jitter as u64);
+    }
+}
+
+impl<S> Debug for ChaosSocket<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f
+            .debug_struct("ChaosSocket")
+            .field("config", &self.config)
+            .field("frames", &self.frames)
+            .field("killed", &self.is_killed())
+            .finish_non_exhaustive();
+    }
+}
+
+// the connection is gone, same as a socket the peer closed
+fn killed() -> tungstenite::Error {
+    return tungstenite::Error::ConnectionClosed;
+}
+
+impl<S> Stream for ChaosSocket<S>
+where
+    S: Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    type Item = Result<Message, tungstenite::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            if let Some((_, sleep)) = this.held.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                if let Some((frame, _)) = this.held.take() {
+                    return Poll::Ready(Some(Ok(frame)));
+                }
+            }
+
+            if let Some(frame) = this.duplicate.take() {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+
+            let Some(inner) = this.inner.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            let mut frame = match ready!(inner.poll_next_unpin(cx)) {
+                Some(Ok(frame)) => frame,
+                other => return Poll::Ready(other),
+            };
+            this.frames += 1;
+
+            if this.kill_at == Some(this.frames) {
+                info!(frame = this.frames, "chaos killing the connection");
+                this.inner = None;
+                return Poll::Ready(None);
+            }
+
+            if this.chance(this.config.drop) && unreliable(&frame) {
+                continue;
+            }
+
+            if this.truncate_at.is_some_and(|at| at <= this.frames) {
+                if let Message::Binary(bytes) = &mut frame {
+                    info!(frame = this.frames, "chaos truncating a frame");
+                    bytes.truncate(bytes.len() / 2);
+                    this.truncate_at = None;
+                }
+            }
+
+            if this.chance(this.config.duplicate) {
+                this.duplicate = Some(frame.clone());
+            }
+
+            let delay = this.delay();
+            if delay.is_zero() {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+            this.held = Some((frame, Box::pin(tokio::time::sleep(delay))));
+        }
+    }
+}
+
+impl<S> Sink<Message> for ChaosSocket<S>
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    type Error = tungstenite::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return match self.inner.as_mut() {
+            Some(inner) => inner.poll_ready_unpin(cx),
+            None => Poll::Ready(Err(killed())),
+        };
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, msg: Message) -> Result<(), Self::Error> {
+        return match self.inner.as_mut() {
+            Some(inner) => inner.start_send_unpin(msg),
+            None => Err(killed()),
+        };
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return match self.inner.as_mut() {
+            Some(inner) => inner.poll_flush_unpin(cx),
+            None => Poll::Ready(Err(killed())),
+        };
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return match self.inner.as_mut() {
+            Some(inner) => inner.poll_close_unpin(cx),
+            None => Poll::Ready(Ok(())),
+        };
+    }
+}
+
+/// (server end, client end) of an in memory connection with config applied
+/// both ways, the client end draws from the next seed.
+pub fn chaos_pair(buffer: usize, config: ChaosConfig) -> (ChaosSocket<MemorySocket>, ChaosSocket<MemorySocket>) {
+    let (server, client) = memory_pair(buffer);
+    let client_config = ChaosConfig {
+        seed: config.seed.wrapping_add(1),
+        ..config.clone()
+    };
+
+    return (ChaosSocket::new(server, config), ChaosSocket::new(client, client_config));
+}
+
+/// Memory with chaos, see chaos_pair.
+#[derive(Debug)]
+pub struct Chaos;
+
+impl Transport for Chaos {
+    type Stream = SplitStream<ChaosSocket<MemorySocket>>;
+    type Sink = SplitSink<ChaosSocket<MemorySocket>, Message>;
+
+    fn close(stream: Self::Stream, sink: Self::Sink) {
+        drop(stream);
+        drop(sink);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use encoding::server::{self, ServerMessage, Snapshot};
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{chaos_pair, unreliable, ChaosConfig};
+    use crate::transport::memory_pair;
+
+    fn frame(msg: server::Message) -> Message {
+        return Message::Binary(ServerMessage::new(0, msg).serialize().expect("serializes"));
+    }
+
+    // what the server end reads of count snapshots followed by a countdown
+    async fn read_through(config: ChaosConfig, count: usize) -> Result<Vec<Message>> {
+        let (mut server, mut client) = memory_pair(count + 1);
+        for tick in 0..count {
+            client.send(frame(server::Message::Snapshot(Snapshot::new(tick as u32, vec![])))).await?;
+        }
+        client.send(frame(server::Message::Countdown(1))).await?;
+        drop(client);
+
+        let mut chaos = super::ChaosSocket::new(&mut server, config);
+        let mut read = vec![];
+        while let Some(frame) = chaos.next().await {
+            read.push(frame?);
+        }
+
+        return Ok(read);
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_same_chaos() -> Result<()> {
+        let config = ChaosConfig {
+            seed: 7,
+            drop: 0.3,
+            duplicate: 0.3,
+            truncate_within: Some(10),
+            ..ChaosConfig::default()
+        };
+
+        let first = read_through(config.clone(), 50).await?;
+        assert_eq!(first, read_through(config.clone(), 50).await?);
+        assert_ne!(first, read_through(ChaosConfig { seed: 8, ..config }, 50).await?);
+
+        assert_ne!(first, read_through(ChaosConfig::default(), 50).await?);
+        // only snapshots are lost, the countdown always makes it
+        assert!(first.contains(&frame(server::Message::Countdown(1))));
+        assert!(first.iter().filter(|frame| unreliable(frame)).count() < 50);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_latency_holds_frames_back() -> Result<()> {
+        let config = ChaosConfig {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            ..ChaosConfig::default()
+        };
+
+        let start = std::time::Instant::now();
+        assert_eq!(read_through(config, 3).await?.len(), 4);
+        assert!(start.elapsed() >= Duration::from_millis(80));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_kill_ends_both_ends() -> Result<()> {
+        let (mut server, mut client) = chaos_pair(
+            8,
+            ChaosConfig {
+                kill_within: Some(1),
+                ..ChaosConfig::default()
+            },
+        );
+
+        client.send(frame(server::Message::Countdown(1))).await?;
+        assert!(server.next().await.is_none());
+        assert!(server.is_killed());
+        assert!(server.send(frame(server::Message::Countdown(2))).await.is_err());
+
+        // the client end sees a closed connection, not chaos
+        assert!(client.next().await.is_none());
+        assert!(client.send(frame(server::Message::Countdown(3))).await.is_err());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,97 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// where a game gets the time from, for its tick schedule and its lobby
+/// timer. log sampling and send timings stay on the real clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep_until(&self, deadline: Instant) -> Sleep;
+}
+
+/// the real time, what every server game runs on.
+#[derive(Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        return Instant::now();
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        return Box::pin(tokio::time::sleep_until(deadline.into()));
+    }
+}
+
+/// time that only moves when advance is called, sleeps finish once it has
+/// been moved past their deadline.
+#[derive(Debug)]
+pub struct MockClock {
+    now: watch::Sender<Instant>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        return Self {
+            now: watch::channel(Instant::now()).0,
+        };
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.send_modify(|now| *now += by);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        return *self.now.borrow();
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        let mut now = self.now.subscribe();
+        return Box::pin(async move {
+            while *now.borrow_and_update() < deadline {
+                // the clock is gone, its time never moves again
+                if now.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+
+    use super::{Clock, MockClock};
+
+    #[tokio::test]
+    async fn test_mock_sleeps_wait_for_advance() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sleep = clock.sleep_until(start + Duration::from_secs(10));
+
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_secs(9));
+        assert!((&mut sleep).now_or_never().is_none());
+        assert_eq!(clock.now(), start + Duration::from_secs(9));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(sleep.now_or_never().is_some());
+        // a deadline already behind the clock doesn't wait
+        assert!(clock.sleep_until(start).now_or_never().is_some());
+    }
+}
@@ -1,8 +1,8 @@
-use anyhow::Result;
-use encoding::server::ServerMessage;
+use anyhow::{anyhow, Result};
+use encoding::server::{self, ServerMessage, PRIVATE_CODE_LENGTH, WHO_AM_I_UNKNOWN};
 use tokio_tungstenite::tungstenite;
 
-#[derive(clap::ValueEnum, Clone, Debug, Copy)]
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
 pub enum SerializationType {
     JSON = 0,
     Deku = 1,
@@ -15,10 +15,54 @@ pub enum ConnectionError {
     WebSocketError(tungstenite::Error),
 }
 
+use crate::player_slab::PlayerKey;
+
 #[derive(Debug)]
 pub enum ConnectionMessage {
-    Close(u8),
+    // keys, not ids: a stream can still be sending after its slot went to
+    // someone else
+    Close(PlayerKey),
     ControlMessage,
-    Msg((u8, Result<ServerMessage, anyhow::Error>)),
-    Error((u8, ConnectionError)),
+    Msg((PlayerKey, Result<ServerMessage, anyhow::Error>)),
+    Error((PlayerKey, ConnectionError)),
+    // a spectator's connection went away, by spectator id
+    SpectatorClose(u8),
+}
+
+/// The first message of every connection, decides where the GameManager routes it.
+#[derive(Debug, PartialEq)]
+pub enum Handshake {
+    Whoami(u8),
+    // whoami plus the display name the client asked for, not validated yet
+    Named(u8, Vec<u8>),
+    CreatePrivate,
+    JoinPrivate([u8; PRIVATE_CODE_LENGTH]),
+    JoinTournament(u64),
+    ListGames,
+}
+
+pub fn handshake<T>(msg: Option<Result<tungstenite::Message, T>>) -> Result<Handshake> {
+    match msg {
+        Some(Ok(tungstenite::Message::Binary(msg))) => {
+            let msg = ServerMessage::deserialize(&msg)?;
+            match msg.msg {
+                server::Message::Whoami(whoami) => return Ok(Handshake::Whoami(whoami)),
+                server::Message::NamedWhoami(named) => {
+                    return Ok(Handshake::Named(named.whoami, named.name.name));
+                }
+                server::Message::CreatePrivateGame => return Ok(Handshake::CreatePrivate),
+                server::Message::JoinPrivateGame(join) => {
+                    return Ok(Handshake::JoinPrivate(join.code));
+                }
+                server::Message::JoinTournament(token) => {
+                    return Ok(Handshake::JoinTournament(token));
+                }
+                server::Message::ListGames => return Ok(Handshake::ListGames),
+                _ => {
+                    return Err(anyhow!("expected whoami or private game message"));
+                }
+            }
+        }
+        _ => return Ok(Handshake::Whoami(WHO_AM_I_UNKNOWN)),
+    }
 }
@@ -0,0 +1,144 @@
+use crate::tick_rate::TickRate;
+
+// after this many windows of growing drift snapshots go out at half rate
+const DEGRADE_AFTER_WINDOWS: u32 = 4;
+
+/// Tracks how far the game loop is behind where it should be (tick * tick length vs
+/// the wall clock). A single slow tick is fine, the loop catches up by not
+/// sleeping, drift that keeps growing every window means it never will.
+pub struct DriftMonitor {
+    // drift is checked once a second, a tick is the tolerance
+    window_ticks: u128,
+    tolerance_us: u128,
+    window_drift: u128,
+    behind_windows: u32,
+}
+
+impl DriftMonitor {
+    pub fn new(tick_rate: TickRate) -> Self {
+        return DriftMonitor {
+            window_ticks: tick_rate.hz(),
+            tolerance_us: tick_rate.micros(),
+            window_drift: 0,
+            behind_windows: 0,
+        };
+    }
+
+    /// returns how many windows in a row the drift has grown when it is
+    /// time to warn about it (1, 2, 4, 8...) so the warnings back off.
+    pub fn record(&mut self, tick: u128, expected_us: u128, elapsed_us: u128) -> Option<u32> {
+        if !tick.is_multiple_of(self.window_ticks) {
+            return None;
+        }
+
+        let drift = elapsed_us.saturating_sub(expected_us);
+        let grew = drift > self.window_drift + self.tolerance_us;
+        self.window_drift = drift;
+
+        if !grew {
+            self.behind_windows = 0;
+            return None;
+        }
+
+        self.behind_windows += 1;
+        if self.behind_windows.is_power_of_two() {
+            return Some(self.behind_windows);
+        }
+
+        return None;
+    }
+
+    pub fn drift_us(&self) -> u128 {
+        return self.window_drift;
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        return self.behind_windows >= DEGRADE_AFTER_WINDOWS;
+    }
+
+    pub fn snapshot_interval(&self) -> u128 {
+        if self.is_degraded() {
+            return 2;
+        }
+
+        return 1;
+    }
+}
+
+/// how long the work part of a tick takes (everything but the sleep)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickTiming {
+    pub last_us: u128,
+    pub max_us: u128,
+    pub average_us: u128,
+}
+
+impl TickTiming {
+    pub fn record(&mut self, tick_us: u128, tick_rate: TickRate) {
+        self.last_us = tick_us;
+        self.max_us = self.max_us.max(tick_us);
+        // ~1 second worth of smoothing
+        let window = tick_rate.hz();
+        self.average_us = (self.average_us * (window - 1) + tick_us) / window;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tick_rate::TickRate;
+
+    use super::{DriftMonitor, TickTiming};
+
+    fn run(monitor: &mut DriftMonitor, tick_rate: TickRate, seconds: u128, slowdown: f64) -> Vec<u32> {
+        let mut warnings = vec![];
+        for tick in 1..=tick_rate.ticks(seconds) {
+            let expected = tick * tick_rate.micros();
+            let elapsed = (expected as f64 * slowdown) as u128;
+            if let Some(level) = monitor.record(tick, expected, elapsed) {
+                warnings.push(level);
+            }
+        }
+
+        return warnings;
+    }
+
+    #[test]
+    fn test_on_time_loop_never_warns() {
+        for hz in [20, 60, 128] {
+            let tick_rate = TickRate::from_hz(hz).unwrap();
+            let mut monitor = DriftMonitor::new(tick_rate);
+            assert!(run(&mut monitor, tick_rate, 20, 1.0).is_empty());
+            assert!(!monitor.is_degraded());
+        }
+    }
+
+    #[test]
+    fn test_slow_clock_escalates_and_degrades() {
+        // windows are a second long whatever the tick rate
+        for hz in [20, 60, 128] {
+            let tick_rate = TickRate::from_hz(hz).unwrap();
+            let mut monitor = DriftMonitor::new(tick_rate);
+            let warnings = run(&mut monitor, tick_rate, 10, 1.5);
+
+            assert_eq!(warnings, vec![1, 2, 4, 8]);
+            assert!(monitor.is_degraded());
+            assert_eq!(monitor.snapshot_interval(), 2);
+            assert!(monitor.drift_us() > 0);
+        }
+    }
+
+    #[test]
+    fn test_tick_average_smooths_over_a_second() {
+        for hz in [1, 20, 60, 128] {
+            let tick_rate = TickRate::from_hz(hz).unwrap();
+            let mut timing = TickTiming {
+                average_us: 1_000,
+                ..TickTiming::default()
+            };
+            timing.record(1_000 + tick_rate.hz() * 100, tick_rate);
+
+            // one slow tick moves the average by its share of a second
+            assert_eq!(timing.average_us, 1_100, "{} ticks a second", hz);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use encoding::server;
+
+use crate::game_state::GameState;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct DumpedPlayer {
+    pub player_id: u8,
+    pub entity_id: usize,
+    pub name: String,
+    pub position: (u16, u16),
+    pub bot: bool,
+    pub clock_diff: i64,
+    pub clock_sync_pending: bool,
+    pub move_budget: u32,
+    // tick of the last emote and the first tick the next one is allowed
+    pub last_emote: Option<u128>,
+    pub emote_ready_at: Option<u128>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct DumpedSpectator {
+    pub id: u8,
+    pub following: Option<u8>,
+}
+
+/// a copy of everything a game knows, taken in one go on the game loop and
+/// written out somewhere else. fields are listed out one by one instead of
+/// serializing the game's own types, anything secret added to those later
+/// stays out of the dump until someone puts it here.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct GameDump {
+    pub game_id: u32,
+    pub seed: u32,
+    pub state: GameState,
+    pub tick: u128,
+    pub zone: server::Zone,
+    pub players: Vec<DumpedPlayer>,
+    pub spectators: Vec<DumpedSpectator>,
+    pub pending_handshakes: usize,
+    // player messages waiting for the game loop
+    pub queued_messages: usize,
+    pub short_handed: bool,
+}
+
+/// the dump as pretty json with every object's keys sorted and players and
+/// spectators by id, the same game state always comes out byte for byte the
+/// same. what state snapshot tests compare.
+pub fn canonical_json(dump: &GameDump) -> Result<String> {
+    let mut dump = dump.clone();
+    dump.players.sort_by_key(|player| player.player_id);
+    dump.spectators.sort_by_key(|spectator| spectator.id);
+
+    // serde_json's Map is a BTreeMap, going through a Value sorts the keys.
+    // to_value can't take the u128 ticks, read back from text they fit a u64
+    let value: serde_json::Value = serde_json::from_str(&serde_json::to_string(&dump)?)?;
+    return Ok(serde_json::to_string_pretty(&value)? + "\n");
+}
+
+pub fn dump_path(dir: &Path, game_id: u32, at: SystemTime) -> PathBuf {
+    let millis = at.duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    return dir.join(format!("game-{}-{}.json", game_id, millis));
+}
+
+/// writes the dump to a new file in dir, returns where it went. blocking,
+/// keep it off the game loop.
+pub fn write_dump(dir: &Path, dump: &GameDump) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).context("creating dump dir")?;
+
+    let path = dump_path(dir, dump.game_id, SystemTime::now());
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(dump)?).context("writing dump")?;
+    std::fs::rename(&tmp, &path).context("moving dump into place")?;
+
+    return Ok(path);
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use encoding::server::Zone;
+
+    use crate::game_state::GameState;
+
+    use super::{dump_path, write_dump, GameDump};
+
+    #[test]
+    fn test_dump_written_with_timestamped_name() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("vim-royale-dumps-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+
+        let at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!(dump_path(&dir, 4, at), dir.join("game-4-1700000000123.json"));
+
+        let dump = GameDump {
+            game_id: 4,
+            seed: 9,
+            state: GameState::Live,
+            tick: 12,
+            zone: Zone {
+                center: (1, 1),
+                radius: 1,
+            },
+            players: vec![],
+            spectators: vec![],
+            pending_handshakes: 0,
+            queued_messages: 0,
+            short_handed: false,
+        };
+        let path = write_dump(&dir, &dump)?;
+
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        assert!(name.starts_with("game-4-") && name.ends_with(".json"));
+        let written: serde_json::Value = serde_json::from_slice(&std::fs::read(&path)?)?;
+        assert_eq!(written["tick"], 12);
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        std::fs::remove_dir_all(&dir)?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,45 @@
+// what every emote_id stands for, clients only ever send the index so there
+// is no free text to moderate
+pub const EMOTES: [&str; 8] = [
+    "hello",
+    "gg",
+    "thanks",
+    "oops",
+    "nice",
+    "help",
+    "run",
+    "follow me",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EmoteError {
+    Unknown,
+    TooSoon,
+}
+
+/// an emote on tick from a player that last emoted on last_emote.
+pub fn check_emote(emote_id: u8, tick: u128, last_emote: Option<u128>, cooldown: u128) -> Result<(), EmoteError> {
+    if emote_id as usize >= EMOTES.len() {
+        return Err(EmoteError::Unknown);
+    }
+
+    if last_emote.is_some_and(|last| tick < last + cooldown) {
+        return Err(EmoteError::TooSoon);
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_emote, EmoteError, EMOTES};
+
+    #[test]
+    fn test_check_emote() {
+        assert_eq!(check_emote(1, 10, None, 60), Ok(()));
+        assert_eq!(check_emote(EMOTES.len() as u8, 10, None, 60), Err(EmoteError::Unknown));
+
+        assert_eq!(check_emote(1, 69, Some(10), 60), Err(EmoteError::TooSoon));
+        assert_eq!(check_emote(1, 70, Some(10), 60), Ok(()));
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+use encoding::server;
+use tracing::warn;
+
+use crate::game_config::GameConfig;
+
+// entity ids go over the wire in 24 bits
+pub const ENTITY_ID_SPACE: usize = server::ENTITY_ID_SPACE;
+
+pub const ENTITY_KINDS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityKind {
+    Player = 0,
+    Projectile = 1,
+    Item = 2,
+}
+
+impl EntityKind {
+    pub fn label(&self) -> &'static str {
+        return match self {
+            EntityKind::Player => "player",
+            EntityKind::Projectile => "projectile",
+            EntityKind::Item => "item",
+        };
+    }
+}
+
+/// splits the entity id space into one range per kind, back to back in
+/// EntityKind order. a full range stays full, it never spills into the next
+/// kind's ids.
+#[derive(Clone, Debug)]
+pub struct EntityIdAllocator {
+    ranges: [Range<usize>; ENTITY_KINDS],
+    next: [usize; ENTITY_KINDS],
+    free: [Vec<usize>; ENTITY_KINDS],
+    // so a full range is only warned about once until an id comes back
+    warned: [bool; ENTITY_KINDS],
+}
+
+impl EntityIdAllocator {
+    /// sizes per kind, None when they don't fit into ENTITY_ID_SPACE.
+    pub fn new(sizes: [usize; ENTITY_KINDS]) -> Option<Self> {
+        let mut start = 0usize;
+        let mut ranges: [Range<usize>; ENTITY_KINDS] = [0..0, 0..0, 0..0];
+        for (range, size) in ranges.iter_mut().zip(sizes) {
+            let end = start.checked_add(size)?;
+            *range = start..end;
+            start = end;
+        }
+
+        if start > ENTITY_ID_SPACE {
+            return None;
+        }
+
+        return Some(Self {
+            next: [ranges[0].start, ranges[1].start, ranges[2].start],
+            ranges,
+            free: [vec![], vec![], vec![]],
+            warned: [false; ENTITY_KINDS],
+        });
+    }
+
+    /// every player owns entity_range ids starting at player id * entity_range,
+    /// projectiles and items come after all of them.
+    pub fn for_config(config: &GameConfig) -> Option<Self> {
+        let players = config.max_players.checked_mul(config.entity_range as usize)?;
+        return Self::new([players, config.projectile_ids, config.item_ids]);
+    }
+
+    pub fn range(&self, kind: EntityKind) -> Range<usize> {
+        return self.ranges[kind as usize].clone();
+    }
+
+    /// None once every id of the kind is in use, the caller skips the spawn.
+    /// players don't allocate from here, they get their slice of the player
+    /// range by player id.
+    pub fn try_alloc(&mut self, kind: EntityKind) -> Option<usize> {
+        let k = kind as usize;
+        if let Some(id) = self.free[k].pop() {
+            return Some(id);
+        }
+
+        if self.next[k] < self.ranges[k].end {
+            let id = self.next[k];
+            self.next[k] += 1;
+            return Some(id);
+        }
+
+        if !self.warned[k] {
+            self.warned[k] = true;
+            warn!(kind = kind.label(), in_use = self.ranges[k].len(), "out of entity ids, not spawning any more");
+        }
+
+        return None;
+    }
+
+    /// hands an id back, ids outside every range or never handed out are ignored.
+    pub fn release(&mut self, id: usize) {
+        let Some(k) = self.ranges.iter().position(|range| range.contains(&id)) else {
+            return;
+        };
+
+        if id >= self.next[k] || self.free[k].contains(&id) {
+            return;
+        }
+
+        self.free[k].push(id);
+        self.warned[k] = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game_config::GameConfig;
+
+    use super::{EntityIdAllocator, EntityKind, ENTITY_ID_SPACE};
+
+    #[test]
+    fn test_exhausted_projectile_range_returns_none() {
+        let config = GameConfig {
+            max_players: 4,
+            entity_range: 10,
+            projectile_ids: 3,
+            item_ids: 2,
+            ..GameConfig::default()
+        };
+        let mut ids = EntityIdAllocator::for_config(&config).expect("fits the id space");
+        let players = ids.range(EntityKind::Player);
+        assert_eq!(players, 0..40);
+
+        let projectiles: Vec<usize> = (0..3).filter_map(|_| ids.try_alloc(EntityKind::Projectile)).collect();
+        assert_eq!(projectiles, vec![40, 41, 42]);
+        assert!(projectiles.iter().all(|id| !players.contains(id)));
+        assert_eq!(ids.try_alloc(EntityKind::Projectile), None);
+        assert_eq!(ids.try_alloc(EntityKind::Projectile), None);
+
+        // other kinds are unaffected
+        assert_eq!(ids.try_alloc(EntityKind::Item), Some(43));
+
+        ids.release(41);
+        ids.release(41);
+        assert_eq!(ids.try_alloc(EntityKind::Projectile), Some(41));
+        assert_eq!(ids.try_alloc(EntityKind::Projectile), None);
+    }
+
+    #[test]
+    fn test_layout_past_the_wire_limit_is_rejected() {
+        assert!(EntityIdAllocator::new([ENTITY_ID_SPACE, 0, 0]).is_some());
+        assert!(EntityIdAllocator::new([ENTITY_ID_SPACE, 1, 0]).is_none());
+        assert!(EntityIdAllocator::new([usize::MAX, 1, 0]).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+
+use encoding::server::{
+    EventQuery, EVENT_KIND_ALL, EVENT_KIND_ERROR, EVENT_KIND_JOIN, EVENT_KIND_KICK, EVENT_KIND_LEAVE,
+    EVENT_KIND_STATE,
+};
+use encoding::tick::{widen_tick, wire_tick};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum EventKind {
+    Join,
+    Leave,
+    Kick,
+    // the game moved on to another GameState
+    State,
+    Error,
+}
+
+impl EventKind {
+    /// None for EVENT_KIND_ALL and codes that don't exist.
+    pub fn from_code(code: u8) -> Option<EventKind> {
+        return match code {
+            EVENT_KIND_JOIN => Some(EventKind::Join),
+            EVENT_KIND_LEAVE => Some(EventKind::Leave),
+            EVENT_KIND_KICK => Some(EventKind::Kick),
+            EVENT_KIND_STATE => Some(EventKind::State),
+            EVENT_KIND_ERROR => Some(EventKind::Error),
+            _ => None,
+        };
+    }
+}
+
+/// something worth knowing about after the fact. copy only, recording one
+/// never allocates outside of the log's own buffer.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+pub struct GameEvent {
+    pub tick: u128,
+    pub kind: EventKind,
+    pub player_id: Option<u8>,
+    pub detail: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EventFilter {
+    pub kind: Option<EventKind>,
+    pub since_tick: Option<u128>,
+}
+
+impl EventFilter {
+    /// None when the query asks for a kind that doesn't exist.
+    pub fn from_query(query: &EventQuery) -> Option<EventFilter> {
+        let kind = match query.kind {
+            EVENT_KIND_ALL => None,
+            code => Some(EventKind::from_code(code)?),
+        };
+
+        return Some(EventFilter {
+            kind,
+            since_tick: (query.since_tick > 0).then_some(query.since_tick as u128),
+        });
+    }
+
+    /// queries only carry the low bits of since_tick, now is the game's tick.
+    pub fn widened(self, now: u128) -> EventFilter {
+        return EventFilter {
+            since_tick: self.since_tick.map(|since| widen_tick(wire_tick(since), now)),
+            ..self
+        };
+    }
+
+    fn matches(&self, event: &GameEvent) -> bool {
+        return self.kind.is_none_or(|kind| kind == event.kind)
+            && self.since_tick.is_none_or(|since| event.tick >= since);
+    }
+}
+
+/// the last capacity events of a game, oldest first. a full log drops its
+/// oldest event for every new one.
+#[derive(Clone, Debug)]
+pub struct EventLog {
+    events: VecDeque<GameEvent>,
+    capacity: usize,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        return Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        };
+    }
+
+    pub fn record(&mut self, event: GameEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn query(&self, filter: &EventFilter) -> Vec<GameEvent> {
+        return self.events.iter().filter(|event| filter.matches(event)).copied().collect();
+    }
+
+    pub fn all(&self) -> Vec<GameEvent> {
+        return self.events.iter().copied().collect();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.events.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.events.is_empty();
+    }
+}
+
+/// what an admin gets back for a QueryEvents, sent as json.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct EventsDocument {
+    pub game_id: u32,
+    pub events: Vec<GameEvent>,
+}
+
+#[cfg(test)]
+mod test {
+    use encoding::server::{EventQuery, EVENT_KIND_ALL, EVENT_KIND_LEAVE};
+
+    use super::{EventFilter, EventKind, EventLog, GameEvent};
+
+    fn event(tick: u128, kind: EventKind) -> GameEvent {
+        return GameEvent {
+            tick,
+            kind,
+            player_id: Some(1),
+            detail: "test",
+        };
+    }
+
+    #[test]
+    fn test_full_log_overwrites_the_oldest() {
+        let mut log = EventLog::new(3);
+        for tick in 1..=5 {
+            log.record(event(tick, EventKind::Join));
+        }
+
+        let ticks: Vec<u128> = log.all().iter().map(|e| e.tick).collect();
+        assert_eq!(ticks, vec![3, 4, 5]);
+        assert_eq!(log.len(), 3);
+
+        let mut off = EventLog::new(0);
+        off.record(event(1, EventKind::Join));
+        assert!(off.is_empty());
+    }
+
+    #[test]
+    fn test_since_tick_and_kind_filters() {
+        let mut log = EventLog::new(10);
+        log.record(event(1, EventKind::Join));
+        log.record(event(4, EventKind::Leave));
+        log.record(event(6, EventKind::Join));
+        log.record(event(9, EventKind::Leave));
+
+        let since = EventFilter {
+            since_tick: Some(4),
+            ..EventFilter::default()
+        };
+        let ticks: Vec<u128> = log.query(&since).iter().map(|e| e.tick).collect();
+        assert_eq!(ticks, vec![4, 6, 9]);
+
+        let query = EventQuery {
+            game_id: 1,
+            kind: EVENT_KIND_LEAVE,
+            since_tick: 5,
+        };
+        let filter = EventFilter::from_query(&query).expect("leave is a kind");
+        assert_eq!(log.query(&filter), vec![event(9, EventKind::Leave)]);
+
+        let everything = EventQuery {
+            kind: EVENT_KIND_ALL,
+            since_tick: 0,
+            ..query
+        };
+        assert_eq!(EventFilter::from_query(&everything), Some(EventFilter::default()));
+        assert_eq!(EventFilter::from_query(&EventQuery { kind: 200, ..query }), None);
+
+        // past u32::MAX ticks the query only has the low bits
+        let wrap = u32::MAX as u128 + 1;
+        let mut long = EventLog::new(10);
+        long.record(event(wrap - 2, EventKind::Join));
+        long.record(event(wrap + 3, EventKind::Leave));
+        let filter = EventFilter::from_query(&EventQuery { since_tick: 1, ..everything }).expect("all is a kind");
+        let ticks: Vec<u128> = long.query(&filter.widened(wrap + 10)).iter().map(|e| e.tick).collect();
+        assert_eq!(ticks, vec![wrap + 3]);
+    }
+}
@@ -0,0 +1,121 @@
+// What a game needs from the async runtime under it. Everything else it uses
+// doesn't care which runtime polls it: tokio's mpsc channels and semaphores
+// work on any executor and the tick schedule runs on a Clock. The server runs
+// every game on tokio, game_run_on takes another executor for embedding.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{select, Either};
+
+use crate::clock::Sleep;
+
+pub type Task = std::pin::Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// runs a game's connection tasks and file writes, and times the sends and
+/// handshakes that can't wait forever. its timer is real time, a game's
+/// schedule goes by its Clock.
+pub trait Executor: Send + Sync {
+    fn spawn(&self, task: Task);
+    // for work that blocks, writing dumps and recovery images
+    fn spawn_blocking(&self, job: Box<dyn FnOnce() + Send>);
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// the server's runtime, what games run on unless told otherwise.
+#[derive(Debug, Default)]
+pub struct TokioExecutor;
+
+impl Executor for TokioExecutor {
+    fn spawn(&self, task: Task) {
+        tokio::spawn(task);
+    }
+
+    fn spawn_blocking(&self, job: Box<dyn FnOnce() + Send>) {
+        tokio::task::spawn_blocking(job);
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        return Box::pin(tokio::time::sleep(duration));
+    }
+}
+
+pub fn tokio_executor() -> Arc<dyn Executor> {
+    return Arc::new(TokioExecutor);
+}
+
+/// what future finished with, None when it took longer than duration.
+pub async fn timeout<F: Future>(executor: &dyn Executor, duration: Duration, future: F) -> Option<F::Output> {
+    let future = std::pin::pin!(future);
+    return match select(future, executor.sleep(duration)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use encoding::server;
+    use futures::channel::oneshot;
+
+    use super::{timeout, Executor, Task};
+    use crate::{clock::Sleep, game::sim::SimHarness, game_config::GameConfig};
+
+    // no runtime at all: a thread per task, each blocking on its future
+    struct ThreadExecutor;
+
+    impl Executor for ThreadExecutor {
+        fn spawn(&self, task: Task) {
+            std::thread::spawn(move || futures::executor::block_on(task));
+        }
+
+        fn spawn_blocking(&self, job: Box<dyn FnOnce() + Send>) {
+            std::thread::spawn(job);
+        }
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            let (done, slept) = oneshot::channel();
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                _ = done.send(());
+            });
+            return Box::pin(async move {
+                _ = slept.await;
+            });
+        }
+    }
+
+    #[test]
+    fn test_timeouts_go_by_the_executor() {
+        futures::executor::block_on(async {
+            let executor = ThreadExecutor;
+            assert_eq!(timeout(&executor, Duration::from_secs(5), async { 3 }).await, Some(3));
+
+            let never = futures::future::pending::<()>();
+            assert_eq!(timeout(&executor, Duration::from_millis(10), never).await, None);
+        });
+    }
+
+    #[test]
+    fn test_game_runs_without_tokio() {
+        let mut sim = SimHarness::with_executor(5, GameConfig::default(), Arc::new(ThreadExecutor));
+
+        let moves = futures::executor::block_on(async {
+            let id = sim.join("embedded").await.expect("joins");
+            sim.start().await.expect("starts");
+            sim.take_outbound(id);
+
+            sim.at(1, id, server::Message::key_press(b'l', 0));
+            sim.run_ticks(5).await;
+
+            return sim.take_outbound(id);
+        });
+
+        let snapshots = moves.iter().filter(|msg| matches!(msg, server::Message::Snapshot(_))).count();
+        assert!(snapshots > 0, "no snapshots in {:?}", moves);
+    }
+}
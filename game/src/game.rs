@@ -1,306 +1,4679 @@
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
 use std::sync::{
     atomic::{AtomicU8, Ordering},
     Arc,
 };
 
 use crate::{
+    bot::Bot,
+    capture::{CaptureDir, CaptureHeader, CaptureStream, CaptureWriter},
+    clock::{Clock, TokioClock},
     connection::{ConnectionMessage, SerializationType},
-    game_comms::{GameComms, GameMessage},
-    player::{spawn_player_stream, Player, PlayerSink, PlayerWebSink, PlayerWebStream},
+    drift::{DriftMonitor, TickTiming},
+    dump::{write_dump, DumpedPlayer, DumpedSpectator, GameDump},
+    events::{EventKind, EventLog, GameEvent},
+    executor::{tokio_executor, Executor},
+    game_comms::{
+        CrashReport, GameComms, GameKey, GameInspection, GameMessage, GameResult, GameStatus, IncomingConnection,
+        InspectedPlayer, PlayerToken,
+    },
+    game_config::{GameConfig, OnDeadline, PositionFormat},
+    game_state::{GameState, GameStateMachine, StateEvent},
+    emote::{check_emote, EMOTES},
+    health::HealthReport,
+    interest::{distance, snapshot_message, VIEW_DISTANCE},
+    log_sampler::LogSampler,
+    logging::panic_message,
+    metrics::{metrics, GameMetrics},
+    moderation::{EmoteFilter, Moderation},
+    movement::{apply_step, key_to_step, max_move_per_tick, validate_move},
+    names::{bot_name, default_name, unique_name},
+    recovery::{now_millis, write_image, RecoveredPlayer, RecoveryImage},
+    player_slab::{PlayerKey, PlayerSlab},
+    player::{
+        reject_connection_on, spawn_handshake, spawn_player_stream, Handshake, Player, PlayerSink, SyncedPlayer,
+    },
+    shared_message::SharedMessage,
+    slots::PlayerSlots,
+    spatial_grid::SpatialGrid,
+    spectator::{spawn_spectator_stream, Spectator},
+    standings::{OutReason, Standings},
+    telemetry::telemetry_interval,
+    traffic::{InboundTraffic, Traffic},
+    transport::{FrameSink, Transport, WebSocket},
 };
-use anyhow::{Result, anyhow};
-use encoding::server::{self, ServerMessage, WHO_AM_I_CLIENT, WHO_AM_I_UNKNOWN};
+use anyhow::Result;
+use futures::FutureExt;
+use encoding::server::{
+    self, ServerMessage, ANNOUNCEMENT_WARNING, JOIN_ERROR_ENDING, JOIN_ERROR_FULL, JOIN_ERROR_STARTED, MESSAGE_TAGS,
+    WHO_AM_I_CLIENT, WHO_AM_I_SPECTATOR,
+};
+use encoding::tick::wire_tick;
 
-use futures::StreamExt;
-use log::{error, info, warn};
-use map::map::Map;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info, info_span, warn, Instrument, Span};
+use map::map::{Map, MAP_SIZE_SIDE};
+use tokio::sync::{
+    mpsc::{error::TrySendError, Receiver, Sender},
+    Semaphore,
+};
 
-const PLAYER_COUNT: usize = 100;
-const FPS: u128 = 16_666;
-const ENTITY_RANGE: u16 = 500;
+// upper bound on players, the actual capacity is GameConfig::max_players
+pub const PLAYER_COUNT: usize = 100;
+// last seconds of warm up that get a Countdown broadcast
+const COUNTDOWN_SECONDS: u128 = 3;
+const SPAWN_POSITION: (u16, u16) = (MAP_SIZE_SIDE as u16 / 2, MAP_SIZE_SIDE as u16 / 2);
+// what a lobby cancelled by OnDeadline::Cancel tells its players
+pub const LOBBY_CANCELLED: &str = "not enough players, lobby closed";
+// how often the lobby looks at its timer and drains player messages
+const LOBBY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
 
-struct Game<const P: usize> {
+pub(crate) struct Game<const P: usize, T: Transport = WebSocket> {
     seed: u32,
-    _map: Map,
-    players: [Option<Player>; P],
+    map: Map,
+    // generational, see player_slab
+    players: PlayerSlab<Player<T::Sink>>,
+    // where they are, changes with every position in players
+    grid: SpatialGrid,
+    // bots sit in players like everyone else, this is their brains
+    bots: Vec<Bot>,
+    spectators: Vec<Spectator<T::Sink>>,
+    next_spectator_id: u8,
+    zone: server::Zone,
+    state: GameStateMachine,
+    drift: DriftMonitor,
+    tick: u128,
+    timing: TickTiming,
+    // this game's /metrics series, registered by game_run
+    series: Arc<GameMetrics>,
+    // the tick schedule and the lobby timer go by it, tests swap in a MockClock
+    clock: Arc<dyn Clock>,
+    // connection tasks, file writes and send timeouts, see game_run_on
+    executor: Arc<dyn Executor>,
+    created: std::time::Instant,
+    // last time round the loop, see Game::health
+    last_tick: std::time::Instant,
+    // when the first player of the current lobby showed up
+    lobby_since: Option<std::time::Instant>,
+    short_handed: bool,
     player_count: Arc<AtomicU8>,
-    ser_type: SerializationType,
+    // which player ids are in use, player_count is theirs to change
+    slots: PlayerSlots,
+    config: GameConfig,
     game_id: u32,
     rx: Receiver<ConnectionMessage>,
     tx: Sender<ConnectionMessage>,
+    // players whose clock sync is still running and the name they asked for,
+    // their slot is already taken
+    handshaking: HashMap<u8, String>,
+    handshake_permits: Arc<Semaphore>,
+    synced_rx: Receiver<Handshake<T>>,
+    synced_tx: Sender<Handshake<T>>,
+    // (where it was sent from, the emote, the only player to get it) waiting
+    // for send_emotes
+    emotes: Vec<((u16, u16), server::Emote, Option<u8>)>,
+    // names of muted players, rejoining under the same name doesn't unmute
+    muted: HashSet<String>,
+    // ticks admins put on top of emote_cooldown_ticks
+    slow_mode: u128,
+    emote_filter: Arc<dyn EmoteFilter>,
+    // counted by the stream tasks, folded into traffic every tick
+    inbound: Arc<InboundTraffic>,
+    traffic: Traffic,
+    // joins, leaves, errors, ... for admins and crash reports
+    events: EventLog,
+    // who went out of the running mid-game and in which place
+    standings: Standings,
+    // tournament players by player id, their placements go out by token
+    tokens: HashMap<u8, PlayerToken>,
+    // tournament players that dropped mid-game, the tick their slot is held until
+    held: HashMap<PlayerToken, u128>,
+    // the per message and per player logs, see log_summaries
+    hot_logs: LogSampler,
+    // the manager dropped its receiver, the game finishes on its own and
+    // keeps its result in the outcome sink
+    manager_gone: bool,
+    // players get a capture file under here, see capture
+    capture: Option<(GameKey, CaptureDir)>,
+    // spent encoding broadcasts once for every sink, see SharedMessage
+    serialize_time: std::time::Duration,
+}
+
+// the snapshot of visible, encoded the first time someone sees exactly these
+// entities. keyed by entity ids, every position in a tick comes from the same list
+fn shared_snapshot<'a>(
+    encoded: &'a mut HashMap<Vec<usize>, SharedMessage>,
+    types: &[SerializationType],
+    format: PositionFormat,
+    tick: u32,
+    visible: Vec<server::PlayerPositionUpdate>,
+) -> Result<&'a SharedMessage> {
+    let key = visible.iter().map(|e| e.entity_id).collect();
+    return match encoded.entry(key) {
+        std::collections::hash_map::Entry::Occupied(entry) => Ok(entry.into_mut()),
+        std::collections::hash_map::Entry::Vacant(entry) => {
+            Ok(entry.insert(SharedMessage::encode(snapshot_message(format, tick, visible), types)?))
+        }
+    };
+}
+
+// what a player at center sees, everyone without an interest range. the grid
+// finds who is in range, in id order like entities
+fn visible_entities(
+    grid: &SpatialGrid,
+    entities: &[server::PlayerPositionUpdate],
+    entity_range: u16,
+    center: (u16, u16),
+    range: Option<u16>,
+) -> Vec<server::PlayerPositionUpdate> {
+    let Some(range) = range else {
+        return entities.to_vec();
+    };
+
+    return grid
+        .players_within_sorted(center, range)
+        .into_iter()
+        .map(|(id, position)| server::PlayerPositionUpdate {
+            entity_id: entity_id(id, entity_range),
+            position,
+        })
+        .collect();
 }
 
-fn create_player_start_msg(player: &Player, seed: u32) -> server::Message {
+fn entity_id(player_id: u8, range: u16) -> usize {
+    return player_id as usize * range as usize;
+}
+
+fn create_player_start_msg<S: FrameSink>(
+    player: &Player<S>,
+    seed: u32,
+    range: u16,
+    server_tick: u32,
+    region: server::Region,
+) -> server::Message {
     return server::Message::PlayerStart(server::PlayerStart {
-        entity_id: player.id as usize * ENTITY_RANGE as usize,
+        entity_id: entity_id(player.id, range),
         position: player.position,
-        range: ENTITY_RANGE,
+        range,
+        seed,
+        view_distance: Some(VIEW_DISTANCE),
+        server_tick: Some(server_tick),
+        region: Some(region),
+    });
+}
+
+fn create_spectator_start_msg(seed: u32, zone: &server::Zone) -> server::Message {
+    return server::Message::SpectatorStart(server::SpectatorStart {
         seed,
+        map_info: server::MapInfo {
+            width: MAP_SIZE_SIDE as u16,
+            height: MAP_SIZE_SIDE as u16,
+        },
+        zone: zone.clone(),
     });
 }
 
-impl<const P: usize> Game<P> {
+impl<const P: usize, T: Transport> Game<P, T> {
     pub fn new(
         seed: u32,
         game_id: u32,
         player_count: Arc<AtomicU8>,
-        ser_type: SerializationType,
-    ) -> Self {
-        let players = std::array::from_fn(|_| None);
+        mut config: GameConfig,
+        emote_filter: Arc<dyn EmoteFilter>,
+    ) -> Result<Self> {
+        let players = PlayerSlab::new(P);
         let (tx, rx) = tokio::sync::mpsc::channel(100);
+        let (synced_tx, synced_rx) = tokio::sync::mpsc::channel(P.max(1));
+        config.max_players = config.max_players.min(P);
+
+        // clients build the map from the seed they're sent, the map that had
+        // enough spawns may be from a perturbed one. without one there is no
+        // game, pick_spawn would run out
+        let map = Map::with_spawns(seed, config.max_players)
+            .map_err(|e| anyhow::anyhow!("no map with enough spawns from seed {}: {:?}", seed, e))?;
+        let seed = map.seed;
+        let clock: Arc<dyn Clock> = Arc::new(TokioClock);
 
-        return Game {
-            _map: Map::new(seed),
+        return Ok(Game {
+            map,
+            slots: PlayerSlots::new(player_count.clone(), P),
             player_count,
             players,
+            grid: SpatialGrid::new(),
+            bots: vec![],
+            spectators: vec![],
+            next_spectator_id: 0,
+            zone: server::Zone {
+                center: (MAP_SIZE_SIDE as u16 / 2, MAP_SIZE_SIDE as u16 / 2),
+                radius: MAP_SIZE_SIDE as u16 / 2,
+            },
+            state: GameStateMachine::new(config.warmup_ticks),
+            drift: DriftMonitor::new(config.tick_rate),
+            tick: 0,
+            timing: TickTiming::default(),
+            series: Arc::new(GameMetrics::new(config.tick_rate)),
+            created: clock.now(),
+            last_tick: clock.now(),
+            clock,
+            executor: tokio_executor(),
+            lobby_since: None,
+            short_handed: false,
             game_id,
             seed,
-            ser_type,
+            config,
             rx,
             tx,
-        };
+            handshaking: HashMap::new(),
+            handshake_permits: Arc::new(Semaphore::new(config.max_concurrent_handshakes.max(1))),
+            synced_rx,
+            synced_tx,
+            emotes: vec![],
+            muted: HashSet::new(),
+            slow_mode: 0,
+            emote_filter,
+            inbound: Arc::new(InboundTraffic::new()),
+            traffic: Traffic::default(),
+            events: EventLog::new(config.event_log_capacity),
+            standings: Standings::default(),
+            tokens: HashMap::new(),
+            held: HashMap::new(),
+            hot_logs: LogSampler::default(),
+            manager_gone: false,
+            capture: None,
+            serialize_time: std::time::Duration::ZERO,
+        });
     }
 
     fn process_message(&mut self, msg: ConnectionMessage) {
         match msg {
-            ConnectionMessage::Msg(msg) => info!("[GAME]: ServerMessage {:?}", msg),
+            // in flight from a stream whose player left, maybe with someone
+            // else in the slot now
+            ConnectionMessage::Msg((key, _)) | ConnectionMessage::Error((key, _)) if !self.players.is_current(key) => {
+                if let Some(suppressed) = self.hot_logs.sample("stale input dropped", self.clock.now()) {
+                    info!(player_id = key.id, suppressed, "stale input dropped");
+                }
+            }
+
+            ConnectionMessage::Msg((PlayerKey { id, .. }, Ok(ServerMessage {
+                msg: server::Message::ClockSyncResponse(resp),
+                ..
+            }))) => {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.on_clock_sync_response(resp.client_time);
+                }
+            }
+
+            ConnectionMessage::Msg((PlayerKey { id, .. }, Ok(ServerMessage {
+                seq_nu,
+                msg: server::Message::KeyPressEvent(press),
+                ..
+            }))) => {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.last_input_seq = seq_nu;
+                }
+                self.move_player(id, press.key);
+            }
+
+            ConnectionMessage::Msg((PlayerKey { id, .. }, Ok(ServerMessage {
+                msg: server::Message::Emote(emote),
+                ..
+            }))) => self.queue_emote(id, emote.emote_id),
+
+            ConnectionMessage::Msg((PlayerKey { id, .. }, Ok(ServerMessage {
+                msg: server::Message::DebugOptIn(hz),
+                ..
+            }))) => self.opt_in_telemetry(id, hz),
+
+            ConnectionMessage::Msg(msg) => {
+                if let Some(suppressed) = self.hot_logs.sample("unhandled server message", self.clock.now()) {
+                    info!(msg = ?msg, suppressed, "unhandled server message");
+                }
+            }
+
+            // the slot may already be gone if a send to it failed first, or
+            // be someone else's by now
+            ConnectionMessage::Close(key) if !self.players.is_current(key) => {
+                info!(player_id = key.id, "stale connection closed");
+            }
+
+            ConnectionMessage::Close(PlayerKey { id, .. }) => {
+                info!(player_id = id, "connection closed");
+                if self.hold_slot(id) {
+                    return;
+                }
 
-            ConnectionMessage::Close(id) => {
-                info!("[GAME]: ConnectionClosed {:?}", id);
-                self.players[id as usize] = None;
-                self.player_count.fetch_sub(1, Ordering::Relaxed);
+                self.player_out(id, OutReason::Disconnected);
+                if let Some(player) = self.remove_player(id) {
+                    self.traffic.add_outbound(&player.sink.sent);
+                    self.slots.leave(id);
+                    self.record_event(EventKind::Leave, Some(id), "connection closed");
+                }
             },
 
-            x => info!("[GAME]: ConnectionMessage {:?}", x),
+            ConnectionMessage::SpectatorClose(id) => {
+                info!(spectator_id = id, "spectator connection closed");
+                self.spectators.retain(|s| s.id != id);
+            }
+
+            x => {
+                if let Some(suppressed) = self.hot_logs.sample("unhandled connection message", self.clock.now()) {
+                    info!(msg = ?x, suppressed, "unhandled connection message");
+                }
+            }
         }
     }
 
-    fn get_messages(&mut self) -> Vec<ConnectionMessage> {
-        let mut msgs = vec![];
-        while let Ok(msg) = self.rx.try_recv() {
-            msgs.push(msg);
+    fn move_player(&mut self, id: u8, key: u8) {
+        let (Some(step), Some(player)) = (key_to_step(key), self.players.get_mut(id)) else {
+            return;
+        };
+
+        let Some(to) = apply_step(player.position, step) else {
+            return;
+        };
+
+        match validate_move(&self.map, player.position, to, player.move_budget) {
+            Ok(cost) => {
+                player.position = to;
+                player.move_budget -= cost;
+                self.grid.place(id, to);
+            }
+            Err(e) => {
+                if let Some(suppressed) = self.hot_logs.sample("move rejected", self.clock.now()) {
+                    info!(player_id = id, error = ?e, suppressed, "move rejected");
+                }
+            }
         }
+    }
 
-        return msgs;
+    fn queue_emote(&mut self, id: u8, emote_id: u8) {
+        let Some(player) = self.players.get_mut(id) else {
+            return;
+        };
+
+        let cooldown = self.config.emote_cooldown_ticks + self.slow_mode;
+        if let Err(e) = check_emote(emote_id, self.tick, player.last_emote, cooldown) {
+            if let Some(suppressed) = self.hot_logs.sample("emote rejected", self.clock.now()) {
+                info!(player_id = id, emote_id, error = ?e, suppressed, "emote rejected");
+            }
+            return;
+        }
+
+        player.last_emote = Some(self.tick);
+        if !self.emote_filter.allow(id, EMOTES[emote_id as usize]) {
+            if let Some(suppressed) = self.hot_logs.sample("emote filtered", self.clock.now()) {
+                info!(player_id = id, emote_id, suppressed, "emote filtered");
+            }
+            return;
+        }
+
+        let emote = server::Emote {
+            from: entity_id(id, self.config.entity_range),
+            emote_id,
+        };
+        // a muted player still sees their own emote, nobody else does
+        let only = self.muted.contains(&player.name).then_some(id);
+        self.emotes.push((player.position, emote, only));
     }
 
-    async fn run(&mut self) -> Result<()> {
-        error!("[GAME]: game run game_id={}, seed={}", self.game_id, self.seed);
-        let start = std::time::Instant::now();
-        let mut tick = 0;
+    // only servers started for debugging hand it out, and never in ranked games
+    fn opt_in_telemetry(&mut self, id: u8, hz: u8) {
+        if !self.config.debug_telemetry || self.config.ranked {
+            info!(player_id = id, hz, ranked = self.config.ranked, "debug telemetry not allowed");
+            return;
+        }
 
-        loop {
-            tick += 1;
-
-            // 1. get every message sent to the sink
-            // 2. process and update game state
-            // 3. respond to any players with msgs
-            // 4. sleep some amount of time
-
-            // 1.
-            let msgs = self.get_messages();
-            if !msgs.is_empty() {
-                for msg in msgs {
-                    self.process_message(msg);
+        if let Some(player) = self.players.get_mut(id) {
+            player.telemetry = telemetry_interval(hz, self.config.tick_rate.hz());
+            info!(player_id = id, hz, every_ticks = ?player.telemetry, "debug telemetry");
+        }
+    }
+
+    // unreliable like snapshots, a dropped one is replaced by the next
+    async fn send_telemetry(&mut self) {
+        let queue_depth = (self.tx.max_capacity() - self.tx.capacity()).min(u16::MAX as usize) as u16;
+        let cooldown = self.config.emote_cooldown_ticks + self.slow_mode;
+
+        for player in self.players.iter_mut() {
+            if !player.telemetry.is_some_and(|every| self.tick.is_multiple_of(every)) {
+                continue;
+            }
+
+            let emote_cooldown = player
+                .last_emote
+                .map(|last| (last + cooldown).saturating_sub(self.tick))
+                .unwrap_or(0);
+            let telemetry = server::DebugTelemetry {
+                position: player.position,
+                last_input_seq: player.last_input_seq,
+                rtt_us: player.rtt.clamp(0, u32::MAX as i64) as u32,
+                clock_diff: player.clock_diff,
+                move_budget: player.move_budget,
+                emote_cooldown: emote_cooldown.min(u16::MAX as u128) as u16,
+                queue_depth,
+            };
+            _ = player.sink.send(server::Message::DebugTelemetry(telemetry)).await;
+        }
+    }
+
+    /// true when it applied, mutes need the player to be in the game.
+    fn moderate(&mut self, moderation: Moderation) -> bool {
+        match moderation {
+            Moderation::Mute(id) | Moderation::Unmute(id) => {
+                let Some(name) = self.players.get(id).map(|p| p.name.clone()) else {
+                    warn!(player_id = id, ?moderation, "moderation of unknown player");
+                    return false;
+                };
+
+                if moderation == Moderation::Mute(id) {
+                    self.muted.insert(name);
+                } else {
+                    self.muted.remove(&name);
                 }
             }
+            Moderation::SlowMode(seconds) => self.slow_mode = self.config.ticks(seconds as u128),
+        }
 
-            let current = start.elapsed().as_micros();
-            let next_frame = tick * FPS;
+        warn!(?moderation, "moderation applied");
+        return true;
+    }
 
-            if current < next_frame {
-                let duration = (next_frame - current) as u64;
-                let duration = std::time::Duration::from_micros(duration);
-                tokio::time::sleep(duration).await;
+    // same view distance as snapshots, spectators see everything
+    fn send_emotes(&mut self) {
+        for (from, emote, only) in std::mem::take(&mut self.emotes) {
+            if let Some(id) = only {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.sink.queue_event(server::GameEvent::Emote(emote.clone()));
+                }
+                continue;
             }
 
-            // check leave conditions.
-            if self.player_count.load(Ordering::Relaxed) == 0 {
-                break;
+            for (id, _) in self.grid.players_within(from, VIEW_DISTANCE) {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.sink.queue_event(server::GameEvent::Emote(emote.clone()));
+                }
+            }
+
+            for spectator in self.spectators.iter_mut() {
+                spectator.sink.queue_event(server::GameEvent::Emote(emote.clone()));
             }
         }
+    }
 
-        self.error("Game Completed");
-        return Ok(());
+    // budget is capped at a single tick on open ground so it can't be banked
+    fn accrue_move_budgets(&mut self) {
+        let speed = self.config.move_speed;
+        for player in self.players.iter_mut() {
+            let (x, y) = player.position;
+            let terrain = self.map.terrain_at(x as usize, y as usize);
+            player.move_budget = (player.move_budget + max_move_per_tick(speed, terrain)).min(speed);
+        }
     }
 
-    fn is_ready(&self) -> bool {
-        let id = self.player_count.load(Ordering::Relaxed);
-        info!("[GAME] Ready check {} == {}", id, 1);
-        return id == 1;
+    // bots go through the same input path as everyone else
+    fn bot_inputs(&mut self) -> Vec<ConnectionMessage> {
+        let range = self.config.entity_range;
+        // the only one a bot goes after is its nearest threat
+        let threats: Vec<Vec<server::PlayerPositionUpdate>> = self
+            .bots
+            .iter()
+            .map(|bot| {
+                return self
+                    .nearest_threat(bot.id)
+                    .and_then(|id| self.players.get(id))
+                    .map(|threat| server::PlayerPositionUpdate {
+                        entity_id: entity_id(threat.id, range),
+                        position: threat.position,
+                    })
+                    .into_iter()
+                    .collect();
+            })
+            .collect();
+        let mut msgs = vec![];
+
+        for (bot, others) in self.bots.iter_mut().zip(threats) {
+            let Some(player) = self.players.get(bot.id) else {
+                continue;
+            };
+
+            let Some(key) = self.players.key(bot.id) else {
+                continue;
+            };
+
+            if let Some(press) = bot.think(player.position, &others, &self.zone) {
+                let msg = ServerMessage::new(0, server::Message::key_press(press, 0));
+                msgs.push(ConnectionMessage::Msg((key, Ok(msg))));
+            }
+        }
+
+        return msgs;
     }
 
-    async fn add_player(
-        &mut self,
-        mut stream: PlayerWebStream,
-        mut sink: PlayerWebSink,
-    ) -> Result<()> {
-        let player_id = self.player_count.fetch_add(1, Ordering::Relaxed);
+    // what the connections sent, straight off the channel into
+    // process_message. at most max_messages_per_tick of it, the rest waits in
+    // the channel for the next tick so a flood can't stretch a tick out.
+    // returns how many went through
+    fn process_inbound(&mut self) -> usize {
+        let mut processed = 0;
+        while processed < self.config.max_messages_per_tick {
+            let Ok(msg) = self.rx.try_recv() else {
+                return processed;
+            };
+            self.process_message(msg);
+            processed += 1;
+        }
 
-        let clock_diff = Player::sync_clock(10, &mut stream, &mut sink).await.unwrap_or(0);
-        self.error(&format!("creating player({}): synced clock with offset {}", player_id, clock_diff));
+        let deferred = self.tx.max_capacity() - self.tx.capacity();
+        if deferred > 0 {
+            if let Some(suppressed) = self.hot_logs.sample("inbound messages deferred", self.clock.now()) {
+                warn!(tick = self.tick, deferred, suppressed, "inbound messages deferred");
+            }
+        }
 
-        let player = Player {
-            position: (256, 256),
-            id: player_id,
-            sink: PlayerSink::new(player_id, sink),
-            clock_diff,
-        };
+        return processed;
+    }
+
+    // whatever the connections sent while the last tick ran. closes still
+    // count towards the final numbers, inputs are too late to matter
+    fn drain_late_messages(&mut self) {
+        while let Ok(msg) = self.rx.try_recv() {
+            match msg {
+                ConnectionMessage::Close(_) => self.process_message(msg),
+                msg => {
+                    if let Some(suppressed) = self.hot_logs.sample("dropping message after game end", self.clock.now()) {
+                        info!(msg = ?msg, suppressed, "dropping message after game end");
+                    }
+                }
+            }
+        }
+    }
 
-        spawn_player_stream(player_id, stream, self.ser_type, self.tx.clone());
+    // players and the grid only change together
+    fn insert_player(&mut self, player: Player<T::Sink>) -> PlayerKey {
+        self.grid.place(player.id, player.position);
+        return self.players.insert(player.id, player);
+    }
 
-        self.players[player_id as usize] = Some(player);
+    fn remove_player(&mut self, id: u8) -> Option<Player<T::Sink>> {
+        self.grid.remove(id);
+        return self.players.remove(id);
+    }
 
-        return Ok(());
+    fn entities(&self) -> Vec<server::PlayerPositionUpdate> {
+        return self
+            .players
+            .iter()
+            .map(|player| server::PlayerPositionUpdate {
+                entity_id: entity_id(player.id, self.config.entity_range),
+                position: player.position,
+            })
+            .collect();
     }
 
-    // TODO: this probably has to be more robust to not cause a panic
-    async fn start_game(&mut self) -> Result<()> {
-        let mut handles = vec![];
+    // small games have everyone on screen anyways, skip the filtering
+    fn interest_range(&self) -> Option<u16> {
+        if self.players.len() <= self.config.full_snapshot_players {
+            return None;
+        }
+
+        return Some(VIEW_DISTANCE);
+    }
+
+    // the tick as messages carry it, it wraps and clients widen it again
+    fn server_tick(&self) -> u32 {
+        return wire_tick(self.tick);
+    }
+
+    // places a player that is about to leave the game, before it started or
+    // after it ended there is nothing to place
+    fn player_out(&mut self, id: u8, reason: OutReason) {
+        if !matches!(self.state.state(), GameState::WarmUp | GameState::Live) {
+            return;
+        }
+
+        let alive = self.players.len();
+        let Some(player) = self.players.get(id) else {
+            return;
+        };
+
+        let place = self.standings.out(id, &player.name, alive, self.tick, reason);
+        info!(player_id = id, place, reason = ?reason, "player out");
+    }
+
+    fn record_event(&mut self, kind: EventKind, player_id: Option<u8>, detail: &'static str) {
+        self.events.record(GameEvent {
+            tick: self.tick,
+            kind,
+            player_id,
+            detail,
+        });
+    }
+
+    async fn broadcast_snapshots(&mut self) {
+        let entities = self.entities();
+        let range = self.interest_range();
+        let tick = self.server_tick();
+        let format = self.config.positions;
+        let types = self.serialization_types();
+        // everyone seeing the same entities gets the same snapshot, it's
+        // encoded once for all of them. without interest ranges that is everyone
+        let mut encoded = HashMap::new();
 
-        self.warn("starting game");
         for player in self.players.iter_mut() {
-            if let Some(player) = player {
-                let msg = create_player_start_msg(player, self.seed);
-                handles.push(player.sink.send(msg));
+            let visible = visible_entities(&self.grid, &entities, self.config.entity_range, player.position, range);
+            let sent = match shared_snapshot(&mut encoded, &types, format, tick, visible) {
+                Ok(snapshot) => player.sink.send_raw(snapshot).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                if let Some(suppressed) = self.hot_logs.sample("snapshot failed", self.clock.now()) {
+                    warn!(player_id = player.id, error = ?e, suppressed, "snapshot failed");
+                }
             }
         }
 
-        let _ = futures::future::join_all(handles).await;
-
-        // TODO: Close any connections that errored and get rid of them.
+        // spectators see everything, unless they follow someone
+        self.retarget_followers().await;
+        let mut dropped = vec![];
+        for spectator in self.spectators.iter_mut() {
+            let visible = match spectator.following.and_then(|id| self.players.get(id)) {
+                Some(target) => {
+                    spectator.center = target.position;
+                    visible_entities(&self.grid, &entities, self.config.entity_range, target.position, range)
+                }
+                None => entities.clone(),
+            };
+            let sent = match shared_snapshot(&mut encoded, &types, format, tick, visible) {
+                Ok(snapshot) => spectator.sink.send_raw(snapshot).await,
+                Err(e) => Err(e),
+            };
+            if sent.is_err() {
+                dropped.push(spectator.id);
+            }
+        }
+        self.serialize_time += encoded.values().map(|snapshot| snapshot.serialize_time).sum::<std::time::Duration>();
 
-        return Ok(());
+        if !dropped.is_empty() {
+            self.spectators.retain(|s| !dropped.contains(&s.id));
+        }
     }
 
-    fn error(&self, msg: &str) {
-        error!(
-            "[GAME]: msg={} id={} player_count={} seed={}",
-            msg,
-            self.game_id,
-            self.player_count.load(Ordering::Relaxed),
-            self.seed
-        );
+    // how long this tick spent encoding messages, most of it is the broadcasts.
+    // if it eats most of the tick budget snapshots need a cheaper encoding.
+    fn record_serialize_time(&mut self) -> std::time::Duration {
+        let mut total = std::mem::take(&mut self.serialize_time);
+        for player in self.players.iter_mut() {
+            total += std::mem::take(&mut player.sink.serialize_time);
+        }
+        for spectator in self.spectators.iter_mut() {
+            total += std::mem::take(&mut spectator.sink.serialize_time);
+        }
+
+        self.series.serialize_time(total);
+        return total;
     }
 
-    fn warn(&self, msg: &str) {
-        warn!(
-            "[GAME]: msg={} id={} player_count={} seed={}",
-            msg,
-            self.game_id,
-            self.player_count.load(Ordering::Relaxed),
-            self.seed
-        );
+    // folds what the streams and sinks counted since the last tick into the
+    // game's totals
+    fn record_traffic(&mut self) {
+        self.traffic.add_inbound(&self.inbound.take());
+        for player in self.players.iter_mut() {
+            self.traffic.add_outbound(&std::mem::replace(&mut player.sink.sent, [0; MESSAGE_TAGS]));
+        }
+        for spectator in self.spectators.iter_mut() {
+            self.traffic.add_outbound(&std::mem::replace(&mut spectator.sink.sent, [0; MESSAGE_TAGS]));
+        }
+
+        self.series.traffic(&self.traffic);
     }
 
-    fn info_string(&self) -> String {
-        return format!(
-            "id={} player_count={} seed={}",
-            self.game_id,
-            self.player_count.load(Ordering::Relaxed),
-            self.seed
-        );
+    // the encodings the game's connections take, bots don't need any
+    fn serialization_types(&self) -> Vec<SerializationType> {
+        let sinks = self.players.iter().map(|p| &p.sink).chain(self.spectators.iter().map(|s| &s.sink));
+
+        let mut types = vec![];
+        for sink in sinks.filter(|sink| sink.sink.is_some()) {
+            if !types.contains(&sink.ser_type) {
+                types.push(sink.ser_type);
+            }
+        }
+
+        return types;
     }
-}
 
-fn whoami<T>(msg: Option<Result<Message, T>>) -> Result<u8> {
-    match msg {
-        Some(Ok(Message::Binary(msg))) => {
-            let msg = ServerMessage::deserialize(&msg)?;
-            match msg.msg {
-                server::Message::Whoami(whoami) => {
-                    return Ok(whoami);
-                }
-                _ => {
-                    return Err(anyhow!("expected whoami message"));
+    async fn broadcast(&mut self, msg: server::Message) {
+        let tag = msg.tag();
+        let shared = match SharedMessage::encode(msg, &self.serialization_types()) {
+            Ok(shared) => shared,
+            Err(e) => {
+                error!(tag, error = ?e, "broadcast couldn't be encoded");
+                self.events.record(GameEvent {
+                    tick: self.tick,
+                    kind: EventKind::Error,
+                    player_id: None,
+                    detail: "broadcast failed",
+                });
+                return;
+            }
+        };
+        self.serialize_time += shared.serialize_time;
+
+        for player in self.players.iter_mut() {
+            if let Err(e) = player.sink.send_raw(&shared).await {
+                if let Some(suppressed) = self.hot_logs.sample("broadcast failed", self.clock.now()) {
+                    warn!(player_id = player.id, error = ?e, suppressed, "broadcast failed");
                 }
+                self.events.record(GameEvent {
+                    tick: self.tick,
+                    kind: EventKind::Error,
+                    player_id: Some(player.id),
+                    detail: "broadcast failed",
+                });
             }
         }
-        _ => return Ok(WHO_AM_I_UNKNOWN),
+
+        for spectator in self.spectators.iter_mut() {
+            _ = spectator.sink.send_raw(&shared).await;
+        }
     }
-}
 
-pub async fn game_run(
-    seed: u32,
-    player_count: Arc<AtomicU8>,
-    game_id: u32,
-    mut comms: GameComms,
-    ser_type: SerializationType,
-) {
-    let mut game = Game::<PLAYER_COUNT>::new(seed, game_id, player_count, ser_type);
-    error!("[GAME-RUNNER]: New game started game_id={}, seed={}", game_id, seed);
+    // goes out with the rest of the tick's events, see flush_events
+    fn broadcast_event(&mut self, event: server::GameEvent) {
+        for player in self.players.iter_mut() {
+            player.sink.queue_event(event.clone());
+        }
 
-    loop {
-        match comms.receiver.recv().await {
-            Some(GameMessage::Connection(mut stream, sink)) => {
-                info!(
-                    "[GAME-RUNNER] new player connection for game {}",
-                    game.info_string()
-                );
-
-                let msg = whoami(stream.next().await);
-
-                if let Ok(WHO_AM_I_CLIENT) = msg {
-                    _ = game.add_player(stream, sink).await;
-                    if game.is_ready() {
-                        break;
-                    }
-                } else {
-                    _ = sink.reunite(stream).map(|mut x| {
-                        _ = x.close(None)
-                    });
-                    continue;
+        for spectator in self.spectators.iter_mut() {
+            spectator.sink.queue_event(event.clone());
+        }
+    }
+
+    // one EventBatch each for whatever the tick queued, ahead of the snapshot
+    async fn flush_events(&mut self) {
+        for player in self.players.iter_mut() {
+            if let Err(e) = player.sink.flush_events().await {
+                if let Some(suppressed) = self.hot_logs.sample("event batch failed", self.clock.now()) {
+                    warn!(player_id = player.id, error = ?e, suppressed, "event batch failed");
                 }
+                self.events.record(GameEvent {
+                    tick: self.tick,
+                    kind: EventKind::Error,
+                    player_id: Some(player.id),
+                    detail: "event batch failed",
+                });
             }
+        }
 
-            Some(msg) => {
-                game.error(&format!(
-                    "Game comms channel gave a non connection message {:?}.",
-                    msg
-                ));
-                unreachable!("this should never happen");
+        for spectator in self.spectators.iter_mut() {
+            _ = spectator.sink.flush_events().await;
+        }
+    }
+
+    // what the hot logs dropped, once their window closes without another
+    // line to carry the count
+    fn log_summaries(&mut self) {
+        for (event, suppressed) in self.hot_logs.expired(self.clock.now()) {
+            warn!(event, suppressed, "log events suppressed");
+        }
+    }
+
+    fn update_state(&mut self, tick: u128) {
+        if let Some(remaining) = self.state.warmup_remaining(tick) {
+            let ticks_per_second = self.config.tick_rate.hz();
+            let seconds = remaining / ticks_per_second;
+            if remaining > 0 && remaining.is_multiple_of(ticks_per_second) && seconds <= COUNTDOWN_SECONDS {
+                self.broadcast_event(server::GameEvent::Countdown(seconds as u8));
+            }
+        }
+
+        if let Some(GameState::Live) = self.state.handle(StateEvent::Tick(tick)) {
+            self.go_live();
+        }
+    }
+
+    // warm up is over, everyone goes back to spawn for the real match
+    fn go_live(&mut self) {
+        warn!("warm up over, going live");
+        self.record_event(EventKind::State, None, "live");
+        for player in self.players.iter_mut() {
+            player.position = SPAWN_POSITION;
+            self.grid.place(player.id, SPAWN_POSITION);
+        }
+
+        self.broadcast_event(server::GameEvent::Countdown(0));
+    }
+
+    async fn resync_clocks(&mut self) {
+        for player in self.players.iter_mut() {
+            if let Err(e) = player.request_clock_resync().await {
+                warn!(player_id = player.id, error = ?e, "clock resync failed");
+                self.events.record(GameEvent {
+                    tick: self.tick,
+                    kind: EventKind::Error,
+                    player_id: Some(player.id),
+                    detail: "clock resync failed",
+                });
+            }
+        }
+    }
+
+    // until the game starts, anyone showing up still gets a slot. the lobby
+    // and the late joins both go through here.
+    async fn handle_lobby_message(&mut self, msg: GameMessage<T>) {
+        match msg {
+            GameMessage::Connection(conn) => {
+                let IncomingConnection { stream, sink, whoami, name, reservation, token } = conn;
+                info!(whoami, player_count = self.player_count.load(Ordering::Relaxed), "new player connection");
+
+                _ = self.add_connection(stream, sink, whoami, name, token).await;
+                // player_count has them now, or they were turned away
+                drop(reservation);
+            }
+
+            GameMessage::QueryStatus(tx) => _ = tx.send(self.status()),
+
+            GameMessage::Inspect(tx) => _ = tx.send(self.inspect()),
+
+            GameMessage::Events(filter, tx) => _ = tx.send(self.events.query(&filter.widened(self.tick))),
+
+            GameMessage::HealthCheck(tx) => _ = tx.send(self.health()),
+
+            GameMessage::Dump(dir) => self.write_dump(dir),
+
+            // nothing in a lobby is worth bringing back
+            GameMessage::Snapshot(_) => {}
+
+            GameMessage::AdminMove(id, position) => self.admin_move(id, position).await,
+
+            GameMessage::Announce(announcement) => {
+                self.broadcast(server::Message::Announcement(announcement)).await;
             }
 
-            None => {
-                game.error("Game comms channel closed");
+            GameMessage::AdminSay(to, msg, tx) => _ = tx.send(self.admin_say(to, msg).await),
+
+            GameMessage::Moderate(moderation, tx) => _ = tx.send(self.moderate(moderation)),
+
+            GameMessage::Follow(spectator_id, entity) => self.follow(spectator_id, entity).await,
+
+            msg => {
+                error!(msg = ?msg, "game comms channel gave a non connection message");
                 unreachable!("this should never happen");
             }
         }
     }
 
-    /*
-    match comms.sender.send(GameMessage::Start).await {
-        Ok(_) => {
-            game.warn("Game sent start");
+    // whatever queued up while the lobby got ready, not just the connections:
+    // a status query behind a late join still waits on its answer
+    async fn drain_lobby_messages(&mut self, comms: &mut GameComms<T>) {
+        while let Ok(msg) = comms.receiver.try_recv() {
+            self.handle_lobby_message(msg).await;
         }
-        Err(_) => {
-            game.error("Game failed to send start");
-            unreachable!("this should never happen in production.");
+    }
+
+    // the lobby is over, anyone showing up now can only watch.
+    async fn handle_game_message(&mut self, msg: GameMessage<T>) {
+        match msg {
+            GameMessage::Connection(conn) => {
+                // the reservation is dropped with the rest once this is handled
+                let IncomingConnection { stream, sink, whoami, reservation: _reservation, token, .. } = conn;
+                if whoami != WHO_AM_I_CLIENT && whoami != WHO_AM_I_SPECTATOR {
+                    T::close(stream, sink);
+                    return;
+                }
+
+                // tournament players only come back into the slot that was
+                // held for them, everyone else late watches
+                let late = match token {
+                    Some(token) if self.held.contains_key(&token) => self.reconnect(stream, sink, token).await,
+                    Some(token) => {
+                        info!(token, "tournament player too late, nothing held for them");
+                        reject_connection_on(self.executor.clone(), sink, JOIN_ERROR_STARTED).await;
+                        Ok(())
+                    }
+                    None => self.add_spectator(stream, sink).await,
+                };
+                if let Err(e) = late {
+                    warn!(error = ?e, "late connection failed");
+                    self.record_event(EventKind::Error, None, "late connection failed");
+                }
+            }
+
+            GameMessage::QueryStatus(tx) => _ = tx.send(self.status()),
+
+            GameMessage::Inspect(tx) => _ = tx.send(self.inspect()),
+
+            GameMessage::Events(filter, tx) => _ = tx.send(self.events.query(&filter.widened(self.tick))),
+
+            GameMessage::HealthCheck(tx) => _ = tx.send(self.health()),
+
+            GameMessage::Dump(dir) => self.write_dump(dir),
+            GameMessage::Snapshot(dir) => self.write_recovery_image(dir),
+
+            GameMessage::AdminMove(id, position) => self.admin_move(id, position).await,
+
+            GameMessage::Announce(announcement) => {
+                self.broadcast_event(server::GameEvent::Announcement(announcement));
+            }
+
+            GameMessage::AdminSay(to, msg, tx) => _ = tx.send(self.admin_say(to, msg).await),
+
+            GameMessage::Moderate(moderation, tx) => _ = tx.send(self.moderate(moderation)),
+
+            GameMessage::Follow(spectator_id, entity) => self.follow(spectator_id, entity).await,
+
+            msg => error!(msg = ?msg, "unexpected game message while running"),
         }
     }
-    */
 
-    match game.start_game().await {
-        Ok(_) => {
-            game.warn("started");
+    /// puts a player somewhere without the movement checks, the spot still
+    /// has to be walkable. everyone hears about it right away.
+    async fn admin_move(&mut self, id: u8, position: (u16, u16)) {
+        if !self.config.admin_commands {
+            warn!("admin move ignored, admin commands are off");
+            return;
         }
-        Err(e) => {
-            game.error(&format!("faled to start: {:?}", e));
+
+        if !self.map.is_walkable(position.0 as usize, position.1 as usize) {
+            warn!(player_id = id, ?position, "admin move to a spot that is not walkable");
+            return;
         }
+
+        let Some(player) = self.players.get_mut(id) else {
+            warn!(player_id = id, "admin move of unknown player");
+            return;
+        };
+
+        player.position = position;
+        self.grid.place(id, position);
+        warn!(player_id = id, ?position, "admin moved player");
+
+        let update = server::PlayerPositionUpdate {
+            entity_id: entity_id(id, self.config.entity_range),
+            position,
+        };
+        self.broadcast(server::Message::PlayerPositionUpdate(update)).await;
     }
 
-    match game.run().await {
-        Ok(_) => {
-            game.warn("finished successfully");
-        }
-        Err(e) => {
-            game.warn(&format!("finished with error {}", e));
+    /// everyone in the game gets it without a player id. false when the
+    /// player isn't in the game, is a bot or the send failed.
+    async fn admin_say(&mut self, to: Option<u8>, msg: server::AdminMessage) -> bool {
+        let text = String::from_utf8_lossy(&msg.text).into_owned();
+        let Some(id) = to else {
+            warn!(text, "admin message to everyone");
+            self.broadcast(server::Message::AdminMessage(msg)).await;
+            return true;
+        };
+
+        let is_bot = self.is_bot(id);
+        let Some(player) = self.players.get_mut(id).filter(|_| !is_bot) else {
+            warn!(player_id = id, text, "admin message to unknown player");
+            return false;
+        };
+
+        if let Err(e) = player.sink.send(server::Message::AdminMessage(msg)).await {
+            warn!(player_id = id, text, error = ?e, "admin message failed");
+            return false;
         }
+
+        warn!(player_id = id, text, "admin message");
+        return true;
+    }
+
+    fn health(&self) -> HealthReport {
+        let interval = match self.state.state() {
+            GameState::Lobby => LOBBY_CHECK_INTERVAL,
+            _ => std::time::Duration::from_micros(self.config.tick_micros() as u64),
+        };
+
+        return HealthReport {
+            game_id: self.game_id,
+            tick: self.tick,
+            last_tick: self.last_tick,
+            interval,
+            state: self.state.state(),
+            lag: std::time::Duration::from_micros(self.drift.drift_us() as u64),
+        };
+    }
+
+    fn dump(&self) -> GameDump {
+        let range = self.config.entity_range;
+        let cooldown = self.config.emote_cooldown_ticks + self.slow_mode;
+        return GameDump {
+            game_id: self.game_id,
+            seed: self.seed,
+            state: self.state.state(),
+            tick: self.tick,
+            zone: self.zone.clone(),
+            players: self
+                .players
+                .iter()
+                .map(|player| DumpedPlayer {
+                    player_id: player.id,
+                    entity_id: entity_id(player.id, range),
+                    name: player.name.clone(),
+                    position: player.position,
+                    bot: self.is_bot(player.id),
+                    clock_diff: player.clock_diff,
+                    clock_sync_pending: player.pending_clock_sync.is_some(),
+                    move_budget: player.move_budget,
+                    last_emote: player.last_emote,
+                    emote_ready_at: player.last_emote.map(|tick| tick + cooldown),
+                })
+                .collect(),
+            spectators: self
+                .spectators
+                .iter()
+                .map(|spectator| DumpedSpectator {
+                    id: spectator.id,
+                    following: spectator.following,
+                })
+                .collect(),
+            pending_handshakes: self.handshaking.len(),
+            queued_messages: self.tx.max_capacity() - self.tx.capacity(),
+            short_handed: self.short_handed,
+        };
     }
 
-    /*
-    _ = comms.sender.send(GameMessage::Close(game.game_id as usize)).await;
-    */
+    // the copy is all the game loop pays for, the file is written elsewhere
+    fn write_dump(&self, dir: PathBuf) {
+        let dump = self.dump();
+        let span = Span::current();
+        self.executor.spawn_blocking(Box::new(move || {
+            let _span = span.enter();
+            match write_dump(&dir, &dump) {
+                Ok(path) => warn!(path = %path.display(), "state dumped"),
+                Err(e) => error!(error = ?e, "state dump failed"),
+            }
+        }));
+    }
+
+    fn recovery_image(&self) -> RecoveryImage {
+        return RecoveryImage {
+            game_id: self.game_id,
+            seed: self.seed,
+            map_checksum: self.map.checksum(),
+            state: self.state.state(),
+            warmup_end: self.state.warmup_end(),
+            tick: self.tick,
+            zone: self.zone.clone(),
+            players: self
+                .players
+                .iter()
+                .map(|player| RecoveredPlayer {
+                    player_id: player.id,
+                    name: player.name.clone(),
+                    position: player.position,
+                    bot: self.is_bot(player.id),
+                    move_budget: player.move_budget,
+                    last_emote: player.last_emote,
+                })
+                .collect(),
+            short_handed: self.short_handed,
+            saved_at: now_millis(),
+        };
+    }
+
+    // same as write_dump, only the copy happens on the game loop
+    fn write_recovery_image(&self, dir: PathBuf) {
+        let image = self.recovery_image();
+        let span = Span::current();
+        self.executor.spawn_blocking(Box::new(move || {
+            let _span = span.enter();
+            if let Err(e) = write_image(&dir, &image) {
+                error!(error = ?e, "recovery image failed");
+            }
+        }));
+    }
+
+    /// a game as it was when the image was taken. the players come back
+    /// without connections, their sinks go nowhere until they are back.
+    pub fn restore(
+        image: &RecoveryImage,
+        player_count: Arc<AtomicU8>,
+        config: GameConfig,
+        emote_filter: Arc<dyn EmoteFilter>,
+    ) -> Result<Self> {
+        let mut game = Game::new(image.seed, image.game_id, player_count, config, emote_filter)?;
+        if game.seed != image.seed || game.map.checksum() != image.map_checksum {
+            return Err(anyhow::anyhow!("map of game {} doesn't match its image", image.game_id));
+        }
+
+        game.tick = image.tick;
+        game.zone = image.zone.clone();
+        game.state = GameStateMachine::restored(image.state, game.config.warmup_ticks, image.warmup_end);
+        game.short_handed = image.short_handed;
+
+        for recovered in image.players.iter() {
+            if recovered.player_id as usize >= game.players.capacity() {
+                return Err(anyhow::anyhow!("player {} doesn't fit the game", recovered.player_id));
+            }
+            if !game.slots.take(recovered.player_id) {
+                return Err(anyhow::anyhow!("player {} is in the image twice", recovered.player_id));
+            }
+
+            game.insert_player(Player {
+                id: recovered.player_id,
+                name: recovered.name.clone(),
+                position: recovered.position,
+                sink: PlayerSink::detached(recovered.player_id),
+                clock_diff: 0,
+                pending_clock_sync: None,
+                move_budget: recovered.move_budget,
+                last_emote: recovered.last_emote,
+                last_input_seq: 0,
+                rtt: 0,
+                telemetry: None,
+            });
+            if recovered.bot {
+                game.bots.push(Bot::new(recovered.player_id, game.seed));
+            }
+        }
+
+        return Ok(game);
+    }
+
+    fn inspect(&self) -> GameInspection {
+        let range = self.config.entity_range;
+        return GameInspection {
+            game_id: self.game_id,
+            state: self.state.state(),
+            tick: self.tick,
+            zone: self.zone.clone(),
+            roster: self
+                .players
+                .iter()
+                .map(|player| InspectedPlayer {
+                    player_id: player.id,
+                    entity_id: entity_id(player.id, range),
+                    name: player.name.clone(),
+                    position: player.position,
+                    bot: self.is_bot(player.id),
+                    clock_diff: player.clock_diff,
+                    move_budget: player.move_budget,
+                    send: player.sink.stats,
+                    muted: self.muted.contains(&player.name),
+                })
+                .collect(),
+            spectators: self.spectators.len(),
+            queued_messages: self.tx.max_capacity() - self.tx.capacity(),
+            slow_mode_ticks: self.slow_mode,
+        };
+    }
+
+    fn status(&self) -> GameStatus {
+        return GameStatus {
+            game_id: self.game_id,
+            state: self.state.state(),
+            tick: self.tick,
+            player_count: self.players.len(),
+            bot_count: self.bots.len(),
+            spectator_count: self.spectators.len(),
+            seed: self.seed,
+            uptime: self.clock.now().duration_since(self.created),
+            timing: self.timing,
+            required_players: self.required_players(self.clock.now()),
+            short_handed: self.short_handed,
+            names: self.players.iter().map(|p| p.name.clone()).collect(),
+            region: self.config.region,
+        };
+    }
+
+    async fn run(&mut self, comms: &mut GameComms<T>) -> Result<()> {
+        error!(player_count = self.player_count.load(Ordering::Relaxed), "game run");
+        let start = self.clock.now();
+        // a bots only game has nobody to leave, it runs until max_ticks
+        let had_humans = self.human_count() > 0;
+
+        loop {
+            let tick_start = self.clock.now();
+            self.last_tick = tick_start;
+            self.step().await;
+            let tick = self.tick;
+
+            // 4. sleep, but keep taking connections from the manager
+            let tick_us = self.clock.now().duration_since(tick_start).as_micros();
+            self.timing.record(tick_us, self.config.tick_rate);
+            self.series.tick(tick_us);
+            self.record_serialize_time();
+            self.record_traffic();
+            self.series.population(self.players.len(), self.spectators.len());
+            let current = self.clock.now().duration_since(start).as_micros();
+            let next_frame = tick * self.config.tick_micros();
+
+            if let Some(windows) = self.drift.record(tick, next_frame, current) {
+                let drift_us = self.drift.drift_us();
+                let degraded = self.config.degrade_on_drift && self.drift.is_degraded();
+
+                if windows >= 8 {
+                    error!(drift_us, behind_for_s = windows, degraded, "loop falling behind real time");
+                } else {
+                    warn!(drift_us, behind_for_s = windows, degraded, "loop falling behind real time");
+                }
+            }
+            let sleep = self.clock.sleep_until(start + std::time::Duration::from_micros(next_frame as u64));
+            tokio::pin!(sleep);
+
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    Some(msg) = comms.receiver.recv() => self.handle_game_message(msg).await,
+                }
+            }
+
+            self.check_manager(comms);
+            if self.finished(had_humans) {
+                break;
+            }
+        }
+
+        self.drain_late_messages();
+        for (event, suppressed) in self.hot_logs.drain() {
+            warn!(event, suppressed, "log events suppressed");
+        }
+        self.record_traffic();
+        error!(
+            short_handed = self.short_handed,
+            bots = self.bots.len(),
+            traffic = %self.traffic,
+            events = self.events.len(),
+            "game completed"
+        );
+        return Ok(());
+    }
+
+    // one tick of the match, everything but the wait for the next one
+    async fn step(&mut self) {
+        self.tick += 1;
+        let tick = self.tick;
+
+        // 1. get every message sent to the sink
+        // 2. process and update game state
+        // 3. respond to any players with msgs
+        // 4. sleep some amount of time, see run
+
+        // 1.
+        self.accrue_move_budgets();
+        for msg in self.bot_inputs() {
+            self.process_message(msg);
+        }
+        self.process_inbound();
+
+        // 2.
+        self.update_state(tick);
+
+        // 3.
+        self.send_emotes();
+        self.flush_events().await;
+
+        if !self.config.degrade_on_drift || tick.is_multiple_of(self.drift.snapshot_interval()) {
+            self.broadcast_snapshots().await;
+        }
+        self.send_telemetry().await;
+
+        if tick.is_multiple_of(self.config.ticks(self.config.clock_resync_seconds)) {
+            self.resync_clocks().await;
+        }
+        self.drop_stalled_players().await;
+        self.expire_held_slots(tick);
+        self.log_summaries();
+    }
+
+    // a manager that went away can't hear from the game anymore, it carries
+    // on to the end without it. logs once
+    fn check_manager(&mut self, comms: &GameComms<T>) {
+        if self.manager_gone || !comms.sender.is_closed() {
+            return;
+        }
+
+        self.manager_gone = true;
+        self.record_event(EventKind::Error, None, "manager gone");
+        error!(tick = self.tick, "manager went away, finishing the game without it");
+    }
+
+    // try_send, the manager only drains these when it handles a connection
+    // and a running game should never wait on it. false when it didn't go out
+    fn notify_manager(&mut self, comms: &GameComms<T>, msg: GameMessage<T>) -> bool {
+        if self.manager_gone {
+            return false;
+        }
+
+        return match comms.sender.try_send(msg) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => false,
+            Err(TrySendError::Closed(_)) => {
+                self.check_manager(comms);
+                false
+            }
+        };
+    }
+
+    // for what the manager can't do without, a lost Close leaves the game's
+    // stub and private code behind for good. waits for room in the channel,
+    // the game is over by then anyway. false when the manager is gone
+    async fn tell_manager(&mut self, comms: &GameComms<T>, msg: GameMessage<T>) -> bool {
+        if self.manager_gone {
+            return false;
+        }
+
+        if comms.sender.send(msg).await.is_err() {
+            self.check_manager(comms);
+            return false;
+        }
+
+        return true;
+    }
+
+    fn result(&self) -> GameResult {
+        let survivors: Vec<(u8, String)> = self
+            .players
+            .iter()
+            .map(|player| (player.id, player.name.clone()))
+            .collect();
+
+        let leaderboard = self.standings.leaderboard(&survivors, self.tick);
+        let placements = leaderboard
+            .iter()
+            .filter_map(|placement| self.tokens.get(&placement.player_id))
+            .copied()
+            .collect();
+
+        return GameResult {
+            placements,
+            leaderboard,
+            region: self.config.region,
+            events: self.events.all(),
+        };
+    }
+
+    // the manager hears how a game ended from its Result, with the manager
+    // gone the result goes to the outcome sink instead of getting lost
+    fn persist_outcome(&self, key: GameKey, comms: &GameComms<T>) {
+        let Some(sink) = comms.outcomes.as_ref() else {
+            warn!("manager gone and no outcome sink, the result is lost");
+            return;
+        };
+
+        match sink.persist(key, &self.result()) {
+            Ok(_) => warn!("outcome persisted without the manager"),
+            Err(e) => error!(error = ?e, "persisting the outcome failed"),
+        }
+    }
+
+    // check leave conditions.
+    fn finished(&mut self, had_humans: bool) -> bool {
+        if self.player_count.load(Ordering::Relaxed) == 0 || (had_humans && self.human_count() == 0) {
+            self.state.handle(StateEvent::Empty);
+            self.record_event(EventKind::State, None, "ended, nobody left");
+            return true;
+        }
+
+        if self.config.max_ticks.is_some_and(|max| self.tick >= max) {
+            // nobody is coming back anymore, they place as out
+            self.expire_held_slots(u128::MAX);
+            self.state.handle(StateEvent::TimeUp);
+            self.record_event(EventKind::State, None, "ended, time up");
+            return true;
+        }
+
+        return false;
+    }
+
+    fn is_ready(&self) -> bool {
+        let count = self.player_count.load(Ordering::Relaxed) as usize;
+        let required = self.required_players(self.clock.now());
+        info!(player_count = count, required, pending = self.handshaking.len(), "ready check");
+        return count >= required && self.handshaking.is_empty();
+    }
+
+    // the timer only starts over once everyone has left the lobby
+    fn update_lobby_timer(&mut self, now: std::time::Instant) {
+        if self.player_count.load(Ordering::Relaxed) == 0 {
+            self.lobby_since = None;
+        } else if self.lobby_since.is_none() {
+            self.lobby_since = Some(now);
+        }
+    }
+
+    // the first player in the lobby has waited max_lobby_wait
+    fn waited_out(&self, now: std::time::Instant) -> bool {
+        return match (self.config.max_lobby_wait, self.lobby_since) {
+            (Some(max_wait), Some(since)) => now.duration_since(since) >= max_wait,
+            _ => false,
+        };
+    }
+
+    fn required_players(&self, now: std::time::Instant) -> usize {
+        if !self.waited_out(now) {
+            return self.config.min_players;
+        }
+
+        return match self.config.on_deadline {
+            OnDeadline::StartAnyway { floor } => {
+                let floor = if self.config.bot_fill { 1 } else { floor };
+                self.config.min_players.min(floor)
+            }
+            OnDeadline::Cancel => self.config.min_players,
+        };
+    }
+
+    fn is_cancelled(&self, now: std::time::Instant) -> bool {
+        let count = self.player_count.load(Ordering::Relaxed) as usize;
+        return self.config.on_deadline == OnDeadline::Cancel && self.waited_out(now) && count < self.config.min_players;
+    }
+
+    fn has_capacity(&self) -> bool {
+        return (self.player_count.load(Ordering::Relaxed) as usize) < self.config.max_players;
+    }
+
+    async fn add_connection(
+        &mut self,
+        stream: T::Stream,
+        sink: T::Sink,
+        whoami: u8,
+        name: Option<String>,
+        token: Option<PlayerToken>,
+    ) -> Result<()> {
+        if whoami == WHO_AM_I_CLIENT {
+            if !self.has_capacity() {
+                warn!("lobby full, rejecting connection");
+                reject_connection_on(self.executor.clone(), sink, JOIN_ERROR_FULL).await;
+                return Ok(());
+            }
+
+            return self.add_player(stream, sink, name, token).await;
+        } else if whoami == WHO_AM_I_SPECTATOR {
+            return self.add_spectator(stream, sink).await;
+        }
+
+        T::close(stream, sink);
+
+        return Ok(());
+    }
+
+    async fn add_player(
+        &mut self,
+        stream: T::Stream,
+        sink: T::Sink,
+        name: Option<String>,
+        token: Option<PlayerToken>,
+    ) -> Result<()> {
+        let Some(player_id) = self.slots.join() else {
+            warn!("no free slot, rejecting connection");
+            reject_connection_on(self.executor.clone(), sink, JOIN_ERROR_FULL).await;
+            return Ok(());
+        };
+        // whoever had the slot before is placed by now if they ever will be
+        match token {
+            Some(token) => self.tokens.insert(player_id, token),
+            None => self.tokens.remove(&player_id),
+        };
+        let asked_for = name.clone().unwrap_or_else(|| default_name(player_id));
+        self.handshaking.insert(player_id, asked_for);
+
+        spawn_handshake(
+            &self.executor,
+            player_id,
+            name,
+            &self.config,
+            stream,
+            sink,
+            self.handshake_permits.clone(),
+            self.synced_tx.clone(),
+        );
+
+        return Ok(());
+    }
+
+    fn finish_handshake(&mut self, handshake: Handshake<T>) {
+        match handshake {
+            Handshake::Synced(synced) => self.finish_player(synced),
+            Handshake::Abandoned(id) => {
+                warn!(player_id = id, "clock sync never finished, dropping the player");
+                self.handshaking.remove(&id);
+                self.slots.leave(id);
+                self.record_event(EventKind::Leave, Some(id), "clock sync never finished");
+            }
+        }
+    }
+
+    fn finish_player(&mut self, synced: SyncedPlayer<T>) {
+        let SyncedPlayer { id, name, stream, sink, clock_diff } = synced;
+        self.handshaking.remove(&id);
+        error!(player_id = id, clock_diff, "player synced, creating player");
+
+        let name = self.unique_name(name.unwrap_or_else(|| default_name(id)));
+        let mut sink = PlayerSink::with_executor(id, sink, self.executor.clone());
+        let capture = self.open_capture(id, &sink);
+        sink.capture = capture.clone();
+        let player = Player {
+            position: SPAWN_POSITION,
+            id,
+            name,
+            sink,
+            clock_diff,
+            pending_clock_sync: None,
+            move_budget: 0,
+            last_emote: None,
+            last_input_seq: 0,
+            rtt: 0,
+            telemetry: None,
+        };
+
+        let key = self.insert_player(player);
+        self.spawn_stream(key, stream, capture);
+        self.record_event(EventKind::Join, Some(id), "player");
+    }
+
+    // the stream holds the key, its close can't take out a later occupant
+    fn spawn_stream(&self, key: PlayerKey, stream: T::Stream, capture: Option<Arc<CaptureWriter>>) {
+        match capture {
+            Some(capture) => spawn_player_stream(
+                &*self.executor,
+                key,
+                CaptureStream::new(stream, capture),
+                self.config.ser_type,
+                self.tx.clone(),
+                self.inbound.clone(),
+            ),
+            None => spawn_player_stream(
+                &*self.executor,
+                key,
+                stream,
+                self.config.ser_type,
+                self.tx.clone(),
+                self.inbound.clone(),
+            ),
+        }
+    }
+
+    // a player's capture file when captures are on, a failure to open one
+    // only costs the capture
+    fn open_capture(&self, id: u8, sink: &PlayerSink<T::Sink>) -> Option<Arc<CaptureWriter>> {
+        let (key, dir) = self.capture.as_ref()?;
+        let header = CaptureHeader {
+            game: *key,
+            player_id: id,
+            seed: self.seed,
+            inbound: self.config.ser_type,
+            outbound: sink.ser_type,
+            started_ms: now_millis(),
+        };
+
+        return match dir.open(&header) {
+            Ok(capture) => Some(capture),
+            Err(e) => {
+                warn!(player_id = id, error = ?e, "couldn't open a capture");
+                None
+            }
+        };
+    }
+
+    async fn finish_handshakes(&mut self) {
+        while !self.handshaking.is_empty() {
+            match self.synced_rx.recv().await {
+                Some(handshake) => self.finish_handshake(handshake),
+                None => break,
+            }
+        }
+    }
+
+    // dupes of a name already in the game get a suffix, first come keeps it
+    fn unique_name(&self, name: String) -> String {
+        let taken: Vec<&str> = self.players.iter().map(|p| p.name.as_str()).collect();
+        return unique_name(&name, &taken, self.config.max_name_length);
+    }
+
+    // everyone holding a slot, by entity id
+    fn lobby_state(&self) -> server::LobbyState {
+        let range = self.config.entity_range;
+        let ready = self.players.iter().map(|player| (player.id, player.name.as_str(), true));
+        let joining = self.handshaking.iter().map(|(id, name)| (*id, name.as_str(), false));
+
+        let mut players: Vec<server::LobbyPlayer> = ready
+            .chain(joining)
+            .map(|(id, name, ready)| server::LobbyPlayer {
+                entity_id: entity_id(id, range),
+                name: server::PlayerName::new(name),
+                ready,
+            })
+            .collect();
+        players.sort_by_key(|player| player.entity_id);
+
+        return server::LobbyState::new(players);
+    }
+
+    fn is_bot(&self, id: u8) -> bool {
+        return self.bots.iter().any(|bot| bot.id == id);
+    }
+
+    fn human_count(&self) -> usize {
+        return self
+            .players
+            .iter()
+            .filter(|player| !self.is_bot(player.id))
+            .count();
+    }
+
+    fn add_bot(&mut self) {
+        let Some(id) = self.slots.join() else {
+            return;
+        };
+        let name = self.unique_name(bot_name(id));
+        self.insert_player(Player {
+            position: SPAWN_POSITION,
+            id,
+            name,
+            sink: PlayerSink::detached(id),
+            clock_diff: 0,
+            pending_clock_sync: None,
+            move_budget: 0,
+            last_emote: None,
+            last_input_seq: 0,
+            rtt: 0,
+            telemetry: None,
+        });
+        self.bots.push(Bot::new(id, self.seed));
+        self.record_event(EventKind::Join, Some(id), "bot");
+    }
+
+    fn fill_with_bots(&mut self) {
+        while (self.player_count.load(Ordering::Relaxed) as usize) < self.config.min_players
+            && self.has_capacity()
+        {
+            self.add_bot();
+        }
+        warn!(bots = self.bots.len(), "filled lobby with bots");
+    }
+
+    // the last spectate_cutoff of a live match with max_ticks
+    fn in_final_stretch(&self) -> bool {
+        let (Some(max), Some(cutoff)) = (self.config.max_ticks, self.config.spectate_cutoff) else {
+            return false;
+        };
+
+        let cutoff_ticks = cutoff.as_micros() / self.config.tick_micros();
+        return self.state.state() == GameState::Live && self.tick + cutoff_ticks >= max;
+    }
+
+    async fn add_spectator(&mut self, stream: T::Stream, sink: T::Sink) -> Result<()> {
+        if self.in_final_stretch() {
+            warn!(tick = self.tick, "match ending, rejecting spectator");
+            reject_connection_on(self.executor.clone(), sink, JOIN_ERROR_ENDING).await;
+            return Ok(());
+        }
+
+        // ids wrap around, the ones still attached are skipped
+        let Some(id) = (0..=u8::MAX)
+            .map(|offset| self.next_spectator_id.wrapping_add(offset))
+            .find(|id| self.spectators.iter().all(|s| s.id != *id))
+        else {
+            warn!(spectators = self.spectators.len(), "out of spectator ids, rejecting spectator");
+            reject_connection_on(self.executor.clone(), sink, JOIN_ERROR_FULL).await;
+            return Ok(());
+        };
+        self.next_spectator_id = id.wrapping_add(1);
+        let mut sink = PlayerSink::with_executor(id, sink, self.executor.clone());
+
+        sink.send(create_spectator_start_msg(self.seed, &self.zone)).await?;
+        if self.state.state() != GameState::Lobby {
+            let snapshot = server::Snapshot::new(self.server_tick(), self.entities());
+            sink.send(server::Message::SpectatorSync(snapshot)).await?;
+        }
+        warn!(spectator_id = id, "spectator attached");
+
+        self.spectators.push(Spectator {
+            id,
+            sink,
+            following: None,
+            center: SPAWN_POSITION,
+        });
+        spawn_spectator_stream(&*self.executor, id, stream, self.tx.clone());
+
+        return Ok(());
+    }
+
+    /// the spectator only gets snapshots around the player with that entity id
+    /// from now on, the same view that player has.
+    async fn follow(&mut self, spectator_id: u8, entity: usize) {
+        let range = self.config.entity_range;
+        let Some((target, position)) = self
+            .players
+            .iter()
+            .find(|player| entity_id(player.id, range) == entity)
+            .map(|player| (player.id, player.position))
+        else {
+            warn!(spectator_id, entity_id = entity, "follow of unknown entity");
+            return;
+        };
+
+        let Some(spectator) = self.spectators.iter_mut().find(|s| s.id == spectator_id) else {
+            warn!(spectator_id, "follow by unknown spectator");
+            return;
+        };
+
+        spectator.following = Some(target);
+        spectator.center = position;
+        let following = server::Following { entity_id: entity };
+        _ = spectator.sink.send(server::Message::Following(following)).await;
+    }
+
+    /// the closest player bot_id can see, ties go to the lower id. nobody has
+    /// health or a team yet, so everyone else still in the game is a live
+    /// enemy. None for anyone who isn't a bot or with nobody in view.
+    pub fn nearest_threat(&self, bot_id: u8) -> Option<u8> {
+        if !self.is_bot(bot_id) {
+            return None;
+        }
+        let me = self.players.get(bot_id)?.position;
+
+        return self
+            .grid
+            .players_within(me, VIEW_DISTANCE)
+            .filter(|&(id, _)| id != bot_id)
+            .min_by_key(|&(id, position)| (distance(me, position), id))
+            .map(|(id, _)| id);
+    }
+
+    // who a spectator whose target left moves on to, humans before bots and
+    // the closest to where they were watching first
+    fn migration_target(&self, center: (u16, u16)) -> Option<u8> {
+        return self
+            .players
+            .iter()
+            .min_by_key(|player| (self.is_bot(player.id), distance(center, player.position), player.id))
+            .map(|player| player.id);
+    }
+
+    // spectators following someone that left or was dropped move on to the
+    // nearest player still in, with nobody left they get the overhead view.
+    // either way they hear where they ended up
+    async fn retarget_followers(&mut self) {
+        let mut moved = vec![];
+        for (i, spectator) in self.spectators.iter().enumerate() {
+            let Some(id) = spectator.following else {
+                continue;
+            };
+            if !self.players.contains(id) {
+                moved.push((i, self.migration_target(spectator.center)));
+            }
+        }
+
+        let range = self.config.entity_range;
+        for (i, next) in moved {
+            let spectator = &mut self.spectators[i];
+            spectator.following = next;
+            let changed = server::FollowChanged {
+                new_target: next.map(|id| entity_id(id, range)),
+            };
+            _ = spectator.sink.send(server::Message::FollowChanged(changed)).await;
+        }
+    }
+
+    // the zone goes right behind PlayerStart so the client never plays without one
+    async fn send_player_start(
+        player: &mut Player<T::Sink>,
+        seed: u32,
+        range: u16,
+        tick: u32,
+        zone: &server::Zone,
+        region: server::Region,
+    ) -> Result<()> {
+        player.sink.send(create_player_start_msg(player, seed, range, tick, region)).await?;
+        return player.sink.send(server::Message::ZoneUpdate(zone.clone())).await;
+    }
+
+    async fn drop_player(&mut self, id: u8) {
+        self.player_out(id, OutReason::Disconnected);
+        if let Some(mut player) = self.remove_player(id) {
+            self.traffic.add_outbound(&player.sink.sent);
+            player.sink.close().await;
+            self.slots.kick(id);
+            self.record_event(EventKind::Leave, Some(id), "dropped");
+        }
+    }
+
+    // a tournament player that drops mid-game keeps their slot for
+    // reconnect_grace_seconds, they stay where they were with nobody
+    // listening. false when they are out right away
+    fn hold_slot(&mut self, id: u8) -> bool {
+        let playing = matches!(self.state.state(), GameState::WarmUp | GameState::Live);
+        if !playing || self.config.reconnect_grace_seconds == 0 {
+            return false;
+        }
+
+        let (Some(&token), Some(player)) = (self.tokens.get(&id), self.players.get_mut(id)) else {
+            return false;
+        };
+        if !self.slots.disconnect(id, token) {
+            return false;
+        }
+
+        let sink = std::mem::replace(&mut player.sink, PlayerSink::detached(id));
+        self.traffic.add_outbound(&sink.sent);
+        self.held.insert(token, self.tick + self.config.ticks(self.config.reconnect_grace_seconds));
+        self.record_event(EventKind::Leave, Some(id), "connection closed, slot held");
+        info!(player_id = id, token, "holding the slot for a reconnect");
+
+        return true;
+    }
+
+    // a tournament player back within their grace, into the slot they left
+    // and where they were. there's no clock sync, the next resync catches up
+    // with a client that restarted
+    async fn reconnect(&mut self, stream: T::Stream, sink: T::Sink, token: PlayerToken) -> Result<()> {
+        self.held.remove(&token);
+        let Some(id) = self.slots.reconnect(token) else {
+            return Err(anyhow::anyhow!("no slot held for token {}", token));
+        };
+        let Some(mut player) = self.remove_player(id) else {
+            self.slots.kick(id);
+            return Err(anyhow::anyhow!("held slot {} has no player", id));
+        };
+
+        let mut sink = PlayerSink::with_executor(id, sink, self.executor.clone());
+        let capture = self.open_capture(id, &sink);
+        sink.capture = capture.clone();
+        player.sink = sink;
+        let (tick, range, region) = (self.server_tick(), self.config.entity_range, self.config.region);
+        if let Err(e) = Self::send_player_start(&mut player, self.seed, range, tick, &self.zone, region).await {
+            // the stream's close holds the slot again
+            warn!(player_id = id, error = ?e, "reconnected player missed their start");
+        }
+
+        let key = self.insert_player(player);
+        self.spawn_stream(key, stream, capture);
+        self.record_event(EventKind::Join, Some(id), "reconnected");
+        info!(player_id = id, token, "player reconnected");
+
+        return Ok(());
+    }
+
+    // held slots whose grace is over by tick, their players are out
+    fn expire_held_slots(&mut self, tick: u128) {
+        let expired: Vec<PlayerToken> = self
+            .held
+            .iter()
+            .filter(|(_, until)| **until <= tick)
+            .map(|(token, _)| *token)
+            .collect();
+
+        for token in expired {
+            self.held.remove(&token);
+            let Some(id) = self.slots.expire(token) else {
+                continue;
+            };
+
+            self.player_out(id, OutReason::Disconnected);
+            self.remove_player(id);
+            self.record_event(EventKind::Leave, Some(id), "reconnect grace over");
+            info!(player_id = id, token, "reconnect grace over");
+        }
+    }
+
+    // players that stopped taking control messages, see PlayerSink::stalled.
+    // spectators go with their next failed snapshot
+    async fn drop_stalled_players(&mut self) {
+        let stalled: Vec<u8> = self
+            .players
+            .iter()
+            .filter(|player| player.sink.stalled)
+            .map(|player| player.id)
+            .collect();
+
+        for id in stalled {
+            warn!(player_id = id, "stalled, disconnecting");
+            metrics().kick("stalled");
+            self.record_event(EventKind::Kick, Some(id), "stalled");
+            self.drop_player(id).await;
+        }
+    }
+
+    /// sends everyone their start, anyone that can't be reached is dropped.
+    /// returns how many players actually got it.
+    async fn start_game(&mut self) -> Result<usize> {
+        let mut handles = vec![];
+
+        warn!(player_count = self.player_count.load(Ordering::Relaxed), "starting game");
+        let tick = self.server_tick();
+        let range = self.config.entity_range;
+        for player in self.players.iter_mut() {
+            let id = player.id;
+            let send = Self::send_player_start(player, self.seed, range, tick, &self.zone, self.config.region);
+            handles.push(async move { (id, send.await) });
+        }
+
+        let results = futures::future::join_all(handles).await;
+        let expected = results.len();
+        let failed: Vec<u8> = results
+            .into_iter()
+            .filter_map(|(id, result)| result.is_err().then_some(id))
+            .collect();
+
+        for id in failed.iter() {
+            warn!(player_id = id, "missed their start, dropping them");
+            metrics().kick("missed_start");
+            self.record_event(EventKind::Kick, Some(*id), "missed start");
+            self.drop_player(*id).await;
+        }
+
+        self.state.handle(StateEvent::Started(0));
+        self.record_event(EventKind::State, None, "started");
+
+        let started = expected - failed.len();
+        if started > 0 && !failed.is_empty() {
+            let roster = server::Snapshot::new(self.server_tick(), self.entities());
+            self.broadcast(server::Message::Roster(roster)).await;
+        }
+
+        for joined in self.player_joined_msgs() {
+            self.broadcast(joined).await;
+        }
+
+        return Ok(started);
+    }
+
+    fn player_joined_msgs(&self) -> Vec<server::Message> {
+        return self
+            .players
+            .iter()
+            .map(|player| {
+                server::Message::PlayerJoined(server::PlayerJoined {
+                    entity_id: entity_id(player.id, self.config.entity_range),
+                    name: server::PlayerName::new(&player.name),
+                })
+            })
+            .collect();
+    }
+
+    // a normal close for everyone still connected, their clients see the
+    // game is over instead of a connection that goes quiet
+    async fn close_connections(&mut self) {
+        for player in self.players.iter_mut() {
+            player.sink.close().await;
+        }
+        for spectator in self.spectators.iter_mut() {
+            spectator.sink.close().await;
+        }
+    }
+
+    // the deadline passed short of players, see OnDeadline::Cancel
+    async fn cancel(&mut self) {
+        warn!(
+            player_count = self.player_count.load(Ordering::Relaxed),
+            min_players = self.config.min_players,
+            "max lobby wait hit, cancelling the lobby"
+        );
+        let notice = server::Announcement::new(ANNOUNCEMENT_WARNING, LOBBY_CANCELLED);
+        self.broadcast(server::Message::Announcement(notice)).await;
+        self.state.handle(StateEvent::Empty);
+        self.close_connections().await;
+        self.record_event(EventKind::State, None, "cancelled, not enough players");
+    }
+
+    // nobody is left to play, close whatever is still connected
+    async fn abort(&mut self) {
+        self.state.handle(StateEvent::Empty);
+        self.close_connections().await;
+        self.record_event(EventKind::State, None, "aborted");
+        error!("aborted, no player received their start");
+    }
+
+    // the game panicked, whatever state it is in the connections still work
+    async fn crashed(&mut self, message: &str) {
+        self.state.handle(StateEvent::Empty);
+        for player in self.players.iter_mut() {
+            player.sink.close_with_error().await;
+        }
+        for spectator in self.spectators.iter_mut() {
+            spectator.sink.close_with_error().await;
+        }
+        metrics().game_crashed();
+        self.record_event(EventKind::Error, None, "crashed");
+        error!(panic = message, "game crashed");
+    }
+}
+
+/// runs a game start to finish, everything it logs sits in a span carrying
+/// its game_id, epoch and seed. a panic anywhere in the game is caught here,
+/// the players are told the server failed and the manager gets a
+/// GameMessage::Crashed before the usual Close.
+pub async fn game_run<T: Transport>(
+    seed: u32,
+    player_count: Arc<AtomicU8>,
+    key: GameKey,
+    comms: GameComms<T>,
+    config: GameConfig,
+) {
+    game_run_on(tokio_executor(), Arc::new(TokioClock), seed, player_count, key, comms, config).await;
+}
+
+/// game_run for a game embedded in something that isn't running tokio. the
+/// game's tasks, file writes and timeouts go to executor and its schedule
+/// follows clock, nothing it does needs a tokio runtime then.
+pub async fn game_run_on<T: Transport>(
+    executor: Arc<dyn Executor>,
+    clock: Arc<dyn Clock>,
+    seed: u32,
+    player_count: Arc<AtomicU8>,
+    key: GameKey,
+    mut comms: GameComms<T>,
+    config: GameConfig,
+) {
+    let span = info_span!("game", game_id = key.id, epoch = key.epoch, seed);
+    async move {
+        if let Err(e) = config.validate(PLAYER_COUNT) {
+            error!(error = %e, "refusing to run game");
+            _ = comms.sender.send(GameMessage::Close(key)).await;
+            return;
+        }
+
+        let emote_filter = comms.emote_filter.clone();
+        let mut game = match Game::<PLAYER_COUNT, T>::new(seed, key.id, player_count, config, emote_filter) {
+            Ok(game) => game,
+            Err(e) => {
+                error!(error = %e, "refusing to run game");
+                _ = comms.sender.send(GameMessage::Close(key)).await;
+                return;
+            }
+        };
+        game.executor = executor;
+        game.clock = clock;
+        game.created = game.clock.now();
+        game.last_tick = game.clock.now();
+        // the map may come from a perturbed seed
+        Span::current().record("seed", game.seed);
+        game.capture = comms.capture.clone().map(|dir| (key, dir));
+        error!("new game started");
+        game.series = metrics().game_started(key.id, game.config.tick_rate);
+
+        if let Err(panic) = AssertUnwindSafe(run_game(&mut game, key, &mut comms)).catch_unwind().await {
+            let message = panic_message(panic.as_ref());
+            game.crashed(&message).await;
+            let report = CrashReport {
+                message,
+                events: game.events.all(),
+            };
+            if !game.tell_manager(&comms, GameMessage::Crashed(key, report)).await {
+                error!("game failed to send crashed");
+            }
+        }
+
+        metrics().game_ended(key.id);
+        // a manager that's gone was logged when it went
+        _ = game.tell_manager(&comms, GameMessage::Close(key)).await;
+    }
+    .instrument(span)
+    .await;
+}
+
+async fn run_game<T: Transport>(game: &mut Game<PLAYER_COUNT, T>, key: GameKey, comms: &mut GameComms<T>) {
+    let mut next_lobby_check = game.clock.now();
+    let mut last_lobby: Option<server::LobbyState> = None;
+    loop {
+        tokio::select! {
+            msg = comms.receiver.recv() => match msg {
+                Some(msg) => game.handle_lobby_message(msg).await,
+
+                // nobody can join anymore, let the lobby go
+                None => {
+                    game.check_manager(comms);
+                    error!("game comms channel closed, closing the lobby");
+                    game.abort().await;
+                    return;
+                }
+            },
+
+            Some(handshake) = game.synced_rx.recv() => game.finish_handshake(handshake),
+
+            // catches players leaving the lobby
+            _ = game.clock.sleep_until(next_lobby_check) => {
+                game.last_tick = game.clock.now();
+                next_lobby_check = game.last_tick + LOBBY_CHECK_INTERVAL;
+                game.process_inbound();
+                game.series.population(game.players.iter().count(), game.spectators.len());
+            }
+        }
+
+        game.update_lobby_timer(game.clock.now());
+
+        // whatever happened above, the lobby hears about it if the roster changed
+        let lobby = game.lobby_state();
+        if last_lobby.as_ref() != Some(&lobby) {
+            game.broadcast(server::Message::LobbyState(lobby.clone())).await;
+            last_lobby = Some(lobby);
+        }
+
+        if game.is_cancelled(game.clock.now()) {
+            game.cancel().await;
+            return;
+        }
+
+        if game.is_ready() {
+            break;
+        }
+    }
+
+    let count = game.player_count.load(Ordering::Relaxed) as usize;
+    if count < game.config.min_players {
+        game.short_handed = true;
+        warn!(player_count = count, min_players = game.config.min_players, "max lobby wait hit, starting short handed");
+
+        if game.config.bot_fill {
+            game.fill_with_bots();
+        }
+    }
+
+    if game.config.allow_late_join {
+        game.drain_lobby_messages(comms).await;
+    }
+
+    game.finish_handshakes().await;
+
+    game.check_manager(comms);
+    match game.notify_manager(comms, GameMessage::Start(key)) {
+        true => warn!("game sent start"),
+        false => error!("game failed to send start"),
+    }
+
+    let started = match game.start_game().await {
+        Ok(started) => {
+            warn!(started, "started with players");
+            started
+        }
+        Err(e) => {
+            error!(error = ?e, "failed to start");
+            0
+        }
+    };
+
+    // a Close without a GameMessage::Result is how the manager learns it aborted
+    if started == 0 {
+        game.abort().await;
+    } else {
+        match game.run(comms).await {
+            Ok(_) => {
+                warn!("finished successfully");
+            }
+            Err(e) => {
+                warn!(error = %e, "finished with error");
+            }
+        }
+        game.close_connections().await;
+
+        // ahead of the Close, the manager takes a Close alone for an abort
+        let result = game.result();
+        if !game.tell_manager(comms, GameMessage::Result(key, result)).await {
+            game.persist_outcome(key, comms);
+        }
+    }
+}
+
+#[cfg(any(test, feature = "bench"))]
+pub mod sim;
+
+#[cfg(test)]
+mod state_snapshots;
+
+#[cfg(test)]
+mod test {
+    use std::sync::{atomic::AtomicU8, Arc};
+
+    use anyhow::Result;
+    use encoding::server;
+    use futures::{SinkExt, StreamExt};
+
+    use encoding::server::{
+        ServerMessage, ANNOUNCEMENT_WARNING, JOIN_ERROR_ENDING, JOIN_ERROR_FULL, JOIN_ERROR_STARTED, WHO_AM_I_CLIENT,
+    };
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::tungstenite;
+
+    use crate::{
+        chaos::{chaos_pair, Chaos, ChaosConfig, ChaosSocket},
+        connection::{ConnectionMessage, SerializationType},
+        emote::EMOTES,
+        events::EventKind,
+        executor::TokioExecutor,
+        game_comms::{GameComms, GameKey, GameMessage, IncomingConnection},
+        game_config::{GameConfig, OnDeadline, OnSyncTimeout},
+        logging::{Filter, Logger},
+        moderation::{EmoteFilter, Moderation, WordList},
+        names::default_name,
+        player::spawn_player_stream,
+        player_slab::PlayerKey,
+        recovery::RecoveryImage,
+        slots::SlotState,
+        standings::OutReason,
+        telemetry::MAX_TELEMETRY_HZ,
+        test_utils::{complete_handshake, complete_slow_handshake, next_message, test_player, ws_pair, TestSocket},
+        transport::{memory_pair, Memory, MemorySocket},
+    };
+
+    use super::{game_run, metrics, Game, GameState, GameStatus, Player, PlayerSink, StateEvent, PLAYER_COUNT};
+
+    fn no_filter() -> Arc<dyn EmoteFilter> {
+        return Arc::new(WordList::default());
+    }
+
+    #[tokio::test]
+    async fn test_spectator_start_has_seed() -> Result<()> {
+        let mut game = Game::<4>::new(1337, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+
+        game.add_spectator(stream, sink).await?;
+
+        match next_message(&mut client).await?.msg {
+            server::Message::SpectatorStart(start) => assert_eq!(start.seed, 1337),
+            msg => panic!("expected SpectatorStart, got {:?}", msg),
+        }
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_spectator_that_disconnects_is_dropped() -> Result<()> {
+        let mut game = Game::<4>::new(1337, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        for _ in 0..2 {
+            let (server_socket, client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            game.add_spectator(stream, sink).await?;
+            drop(client);
+        }
+        let (server_socket, _staying) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        game.add_spectator(stream, sink).await?;
+
+        // without snapshots going out only the stream notices
+        for _ in 0..2 {
+            let msg = game.rx.recv().await.expect("game holds a sender");
+            game.process_message(msg);
+        }
+        let ids: Vec<u8> = game.spectators.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![2]);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_spectator_ids_skip_the_ones_in_use() -> Result<()> {
+        let mut game = Game::<4, Memory>::new(1337, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        let mut clients = vec![];
+        for _ in 0..=u8::MAX {
+            let (server_socket, client) = memory_pair(4);
+            let (sink, stream) = server_socket.split();
+            game.add_spectator(stream, sink).await?;
+            clients.push(client);
+        }
+        game.spectators.retain(|s| s.id != 5);
+
+        // wrapped around to 0, the first free id is 5
+        let (server_socket, _client) = memory_pair(4);
+        let (sink, stream) = server_socket.split();
+        game.add_spectator(stream, sink).await?;
+        assert_eq!(game.spectators.iter().filter(|s| s.id == 5).count(), 1);
+
+        let (server_socket, mut client) = memory_pair(4);
+        let (sink, stream) = server_socket.split();
+        game.add_spectator(stream, sink).await?;
+        match next_message(&mut client).await?.msg {
+            server::Message::JoinError(reason) => assert_eq!(reason, JOIN_ERROR_FULL),
+            msg => panic!("expected JoinError, got {:?}", msg),
+        }
+        assert_eq!(game.spectators.len(), 256);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_spectator_after_cutoff_is_rejected() -> Result<()> {
+        let config = GameConfig {
+            max_ticks: Some(600),
+            spectate_cutoff: Some(std::time::Duration::from_secs(2)),
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(1337, 0, Arc::new(AtomicU8::new(0)), config, no_filter())?;
+        game.state.handle(StateEvent::Started(0));
+
+        // 2s are 120 ticks, 481 leaves 119
+        for (tick, allowed) in [(100, true), (479, true), (481, false), (599, false)] {
+            game.tick = tick;
+            let (server_socket, mut client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            game.add_spectator(stream, sink).await?;
+
+            match next_message(&mut client).await?.msg {
+                server::Message::SpectatorStart(_) => assert!(allowed, "tick {}", tick),
+                server::Message::JoinError(JOIN_ERROR_ENDING) => assert!(!allowed, "tick {}", tick),
+                msg => panic!("expected SpectatorStart or JoinError, got {:?}", msg),
+            }
+        }
+        assert_eq!(game.spectators.len(), 2);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_inbound_past_the_cap_waits_for_the_next_tick() -> Result<()> {
+        let config = GameConfig {
+            max_messages_per_tick: 3,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), config, no_filter())?;
+        let (player, _client) = test_player(0, (40, 41)).await?;
+        seat(&mut game, player);
+
+        // five emotes queued up, in order
+        for emote_id in 0..5 {
+            let msg = ServerMessage::new(0, server::Message::Emote(server::Emote { from: 0, emote_id }));
+            game.tx.try_send(ConnectionMessage::Msg((first_key(0), Ok(msg))))?;
+        }
+
+        assert_eq!(game.process_inbound(), 3);
+        assert_eq!(game.tx.max_capacity() - game.tx.capacity(), 2);
+        assert_eq!(game.process_inbound(), 2);
+        assert_eq!(game.process_inbound(), 0);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_ready_threshold() {
+        for min_players in [1, 2, 5] {
+            let config = GameConfig {
+                min_players,
+                max_players: 8,
+                ..GameConfig::default()
+            };
+            let player_count = Arc::new(AtomicU8::new(0));
+            let game = Game::<8>::new(0, 0, player_count.clone(), config, no_filter()).expect("seed makes a playable map");
+
+            for count in 0..=8 {
+                player_count.store(count, std::sync::atomic::Ordering::Relaxed);
+                assert_eq!(game.is_ready(), count as usize >= min_players);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_lobby_rejects_connection() -> Result<()> {
+        let config = GameConfig {
+            min_players: 1,
+            max_players: 1,
+            allow_late_join: true,
+            ..GameConfig::default()
+        };
+
+        let (manager_tx, _manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let (first_server, mut first_client) = ws_pair().await?;
+        let (second_server, mut second_client) = ws_pair().await?;
+
+        for socket in [first_server, second_server] {
+            let (sink, stream) = socket.split();
+            sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        }
+
+        let key = GameKey { id: 0, epoch: 0 };
+        tokio::spawn(game_run(7, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        match complete_handshake(&mut first_client).await?.msg {
+            server::Message::PlayerStart(start) => assert_eq!(start.seed, 7),
+            msg => panic!("expected PlayerStart, got {:?}", msg),
+        }
+
+        assert_eq!(
+            next_message(&mut second_client).await?.msg,
+            server::Message::JoinError(JOIN_ERROR_FULL)
+        );
+
+        return Ok(());
+    }
+
+    async fn snapshot_sizes(config: GameConfig, positions: &[(u16, u16)]) -> Result<Vec<usize>> {
+        let mut game = Game::<8>::new(0, 0, Arc::new(AtomicU8::new(0)), config, no_filter())?;
+        let mut clients = vec![];
+        for (id, position) in positions.iter().enumerate() {
+            let (player, client) = test_player(id as u8, *position).await?;
+            game.insert_player(player);
+            clients.push(client);
+        }
+
+        game.broadcast_snapshots().await;
+
+        let mut sizes = vec![];
+        for client in clients.iter_mut() {
+            match next_message(client).await?.msg {
+                server::Message::Snapshot(snapshot) => sizes.push(snapshot.entities.len()),
+                msg => panic!("expected Snapshot, got {:?}", msg),
+            }
+        }
+
+        return Ok(sizes);
+    }
+
+    #[tokio::test]
+    async fn test_small_game_sends_full_snapshots() -> Result<()> {
+        let config = GameConfig {
+            full_snapshot_players: 2,
+            ..GameConfig::default()
+        };
+
+        let sizes = snapshot_sizes(config, &[(0, 0), (200, 200)]).await?;
+        assert_eq!(sizes, vec![2, 2]);
+
+        let sizes = snapshot_sizes(config, &[(0, 0), (200, 200), (5, 5)]).await?;
+        assert_eq!(sizes, vec![2, 1, 2]);
+
+        return Ok(());
+    }
+
+    // the frame a client got, in whatever encoding it asked for
+    async fn next_frame(client: &mut TestSocket, ser_type: SerializationType) -> Result<ServerMessage> {
+        loop {
+            if let Some(tungstenite::Message::Binary(bytes)) = client.next().await.transpose()? {
+                return match ser_type {
+                    SerializationType::JSON => ServerMessage::from_json(&bytes),
+                    SerializationType::Deku => ServerMessage::deserialize(&bytes),
+                };
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_broadcasts_reach_json_and_binary_clients() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        // two see each other, the third is out of range and gets its own snapshot
+        let mut clients = vec![];
+        for (id, (position, ser_type)) in [
+            ((1, 1), SerializationType::Deku),
+            ((2, 2), SerializationType::JSON),
+            ((200, 200), SerializationType::JSON),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let (mut player, client) = test_player(id as u8, position).await?;
+            player.sink.ser_type = ser_type;
+            // sinks don't start at the same seq_nu
+            player.sink.seq_nu = id as u16 * 10;
+            game.insert_player(player);
+            clients.push((client, ser_type));
+        }
+
+        game.tick = 4;
+        game.broadcast_snapshots().await;
+        game.broadcast(server::Message::Announcement(server::Announcement::new(ANNOUNCEMENT_WARNING, "hi"))).await;
+
+        let mut seen = vec![];
+        for (id, (client, ser_type)) in clients.iter_mut().enumerate() {
+            let snapshot = next_frame(client, *ser_type).await?;
+            assert_eq!(snapshot.seq_nu, id as u16 * 10 + 1);
+            match snapshot.msg {
+                server::Message::Snapshot(snapshot) => {
+                    assert_eq!(snapshot.server_tick, Some(4));
+                    seen.push(snapshot.entities.len());
+                }
+                msg => panic!("expected Snapshot, got {:?}", msg),
+            }
+
+            let announcement = next_frame(client, *ser_type).await?;
+            assert_eq!(announcement.seq_nu, id as u16 * 10 + 2);
+            assert!(matches!(announcement.msg, server::Message::Announcement(_)), "{:?}", announcement.msg);
+        }
+        assert_eq!(seen, vec![2, 2, 1]);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_warmup_counts_down_and_resets() -> Result<()> {
+        let config = GameConfig {
+            warmup_ticks: GameConfig::default().ticks(2),
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), config, no_filter())?;
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+
+        game.start_game().await?;
+        assert!(matches!(next_message(&mut client).await?.msg, server::Message::PlayerStart(_)));
+        assert!(matches!(next_message(&mut client).await?.msg, server::Message::ZoneUpdate(_)));
+        assert!(matches!(next_message(&mut client).await?.msg, server::Message::PlayerJoined(_)));
+        assert_eq!(game.state.state(), GameState::WarmUp);
+
+        for tick in 1..=config.ticks(2) {
+            game.update_state(tick);
+        }
+        game.flush_events().await;
+
+        // no flush in between, both go out in one batch in the order queued
+        let countdown = server::EventBatch::new(vec![server::GameEvent::Countdown(1), server::GameEvent::Countdown(0)]);
+        assert_eq!(next_message(&mut client).await?.msg, server::Message::EventBatch(countdown));
+        assert_eq!(game.state.state(), GameState::Live);
+        assert_eq!(game.players.get(0).map(|p| p.position), Some(super::SPAWN_POSITION));
+        assert_grid_matches(&game);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_admin_messages_reach_everyone_or_one_player() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), GameConfig::default(), no_filter())?;
+        let (player, mut first) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+        let (player, mut second) = test_player(1, (2, 2)).await?;
+        game.insert_player(player);
+
+        let everyone = server::AdminMessage::new(false, "restart in 5 minutes");
+        assert!(game.admin_say(None, everyone.clone()).await);
+        assert_eq!(next_message(&mut first).await?.msg, server::Message::AdminMessage(everyone.clone()));
+        assert_eq!(next_message(&mut second).await?.msg, server::Message::AdminMessage(everyone));
+
+        let private = server::AdminMessage::new(true, "stop that");
+        assert!(game.admin_say(Some(1), private.clone()).await);
+        assert_eq!(next_message(&mut second).await?.msg, server::Message::AdminMessage(private.clone()));
+
+        // nobody in slot 3, slot 200 doesn't exist
+        assert!(!game.admin_say(Some(3), private.clone()).await);
+        assert!(!game.admin_say(Some(200), private).await);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_late_connection_becomes_spectator() -> Result<()> {
+        let (manager_tx, _manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let (player_server, mut player_client) = ws_pair().await?;
+        let (sink, stream) = player_server.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+
+        let key = GameKey { id: 0, epoch: 0 };
+        tokio::spawn(game_run(9, Arc::new(AtomicU8::new(0)), key, comms, GameConfig::default()));
+        complete_handshake(&mut player_client).await?;
+
+        // well past tick 100
+        tokio::time::sleep(std::time::Duration::from_millis(1_800)).await;
+
+        let (late_server, mut late_client) = ws_pair().await?;
+        let (sink, stream) = late_server.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+
+        match next_message(&mut late_client).await?.msg {
+            server::Message::SpectatorStart(start) => assert_eq!(start.seed, 9),
+            msg => panic!("expected SpectatorStart, got {:?}", msg),
+        }
+        match next_message(&mut late_client).await?.msg {
+            server::Message::SpectatorSync(sync) => assert_eq!(sync.entities.len(), 1),
+            msg => panic!("expected SpectatorSync, got {:?}", msg),
+        }
+        for _ in 0..3 {
+            match next_message(&mut late_client).await?.msg {
+                server::Message::Snapshot(snapshot) => assert_eq!(snapshot.entities.len(), 1),
+                msg => panic!("expected Snapshot, got {:?}", msg),
+            }
+        }
+
+        drop(player_client);
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_player_start_includes_zone() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        game.zone = server::Zone {
+            center: (10, 20),
+            radius: 30,
+        };
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+
+        game.start_game().await?;
+
+        assert!(matches!(next_message(&mut client).await?.msg, server::Message::PlayerStart(_)));
+        assert_eq!(next_message(&mut client).await?.msg, server::Message::ZoneUpdate(game.zone.clone()));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_carry_server_tick() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+
+        game.start_game().await?;
+        match next_message(&mut client).await?.msg {
+            server::Message::PlayerStart(start) => assert_eq!(start.server_tick, Some(0)),
+            msg => panic!("expected PlayerStart, got {:?}", msg),
+        }
+        // zone and names
+        next_message(&mut client).await?;
+        next_message(&mut client).await?;
+
+        let mut ticks = vec![];
+        for tick in 1..=3 {
+            game.tick = tick;
+            game.broadcast_snapshots().await;
+            match next_message(&mut client).await?.msg {
+                server::Message::Snapshot(snapshot) => ticks.push(snapshot.server_tick),
+                msg => panic!("expected Snapshot, got {:?}", msg),
+            }
+        }
+
+        assert_eq!(ticks, vec![Some(1), Some(2), Some(3)]);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_ticks_past_u32_keep_their_timing() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+
+        // a bit over two years at 60hz
+        let tick = u32::MAX as u128 + 10;
+        game.tick = tick;
+        game.broadcast_snapshots().await;
+        match next_message(&mut client).await?.msg {
+            server::Message::Snapshot(snapshot) => {
+                assert_eq!(snapshot.server_tick, Some(9));
+                assert_eq!(snapshot.server_tick.map(|t| encoding::tick::widen_tick(t, tick - 30)), Some(tick));
+            }
+            msg => panic!("expected Snapshot, got {:?}", msg),
+        }
+
+        // the frame schedule still fits the sleep
+        let next_frame = tick * game.config.tick_micros();
+        assert_eq!(next_frame / game.config.tick_micros(), tick);
+        assert!(u64::try_from(next_frame).is_ok());
+        assert_eq!(game.drift.record(tick, next_frame, next_frame), None);
+        assert_eq!(game.drift.drift_us(), 0);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_fixed_positions_send_fine_snapshots() -> Result<()> {
+        let config = GameConfig {
+            positions: crate::game_config::PositionFormat::Fixed,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), config, no_filter())?;
+        let (player, mut client) = test_player(0, (12, 34)).await?;
+        game.insert_player(player);
+
+        game.tick = 5;
+        game.broadcast_snapshots().await;
+        match next_message(&mut client).await?.msg {
+            server::Message::FineSnapshot(snapshot) => {
+                assert_eq!(snapshot.entities[0].position, encoding::fixed::from_tiles((12, 34)));
+                assert_eq!(snapshot.to_tiles().entities[0].position, (12, 34));
+            }
+            msg => panic!("expected FineSnapshot, got {:?}", msg),
+        }
+
+        return Ok(());
+    }
+
+    async fn closed_player(id: u8) -> Result<super::Player> {
+        let (mut player, _client) = test_player(id, (1, 1)).await?;
+        player.sink.close().await;
+        return Ok(player);
+    }
+
+    // puts a player straight into their slot, like a finished join
+    // everyone is where the grid has them, and nobody else is on it
+    fn assert_grid_matches<const P: usize, T: crate::transport::Transport>(game: &Game<P, T>) {
+        for player in game.players.iter() {
+            assert_eq!(game.grid.position(player.id), Some(player.position), "player {}", player.id);
+        }
+        assert_eq!(game.grid.len(), game.players.len());
+    }
+
+    // what a test player's stream sends with, they are all their slot's
+    // first occupant
+    fn first_key(id: u8) -> PlayerKey {
+        return PlayerKey { id, generation: 1 };
+    }
+
+    fn seat<const P: usize>(game: &mut Game<P>, player: super::Player) {
+        assert!(game.slots.take(player.id), "slot {} is taken", player.id);
+        game.insert_player(player);
+    }
+
+    #[tokio::test]
+    async fn test_start_with_every_send_failing() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+        seat(&mut game, closed_player(0).await?);
+        seat(&mut game, closed_player(1).await?);
+
+        assert_eq!(game.start_game().await?, 0);
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert!(game.players.is_empty());
+
+        game.abort().await;
+        assert_eq!(game.state.state(), GameState::Ended);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_start_with_some_sends_failing() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        seat(&mut game, player);
+        seat(&mut game, closed_player(1).await?);
+        let dropped = game.players.key(1).expect("seated");
+
+        assert_eq!(game.start_game().await?, 1);
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        assert!(matches!(next_message(&mut client).await?.msg, server::Message::PlayerStart(_)));
+        assert!(matches!(next_message(&mut client).await?.msg, server::Message::ZoneUpdate(_)));
+        match next_message(&mut client).await?.msg {
+            server::Message::Roster(roster) => {
+                assert_eq!(roster.entities.len(), 1);
+                assert_eq!(roster.entities[0].entity_id, super::entity_id(0, 500));
+            }
+            msg => panic!("expected Roster, got {:?}", msg),
+        }
+
+        // the dropped player's stream closing later doesn't count them out twice
+        game.process_message(crate::connection::ConnectionMessage::Close(dropped));
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_late_close_counted_after_game_end() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+        let (mut player, _client) = test_player(0, (100, 100)).await?;
+        player.move_budget = 1000;
+        seat(&mut game, player);
+        seat(&mut game, test_player(1, (110, 110)).await?.0);
+
+        // both land in rx after the last tick read it
+        let press = ServerMessage::new(0, server::Message::KeyPressEvent(server::KeyPress { key: b'l', state: 0 }));
+        game.tx.send(ConnectionMessage::Msg((first_key(0), Ok(press)))).await?;
+        let key = game.players.key(1).expect("seated");
+        game.tx.send(ConnectionMessage::Close(key)).await?;
+
+        game.drain_late_messages();
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert!(!game.players.contains(1));
+        assert_eq!(game.players.get(0).map(|p| p.position), Some((100, 100)));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_stale_close_leaves_the_slots_next_player() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        seat(&mut game, test_player(1, (100, 100)).await?.0);
+        let first = game.players.key(1).expect("seated");
+
+        // a failed send drops them, their stream hasn't noticed yet
+        game.remove_player(1);
+        game.slots.leave(1);
+        let (mut next, _client) = test_player(1, (110, 110)).await?;
+        next.name = "next".to_string();
+        seat(&mut game, next);
+
+        game.process_message(ConnectionMessage::Close(first));
+        assert_eq!(game.players.get(1).map(|p| p.name.as_str()), Some("next"));
+        assert_grid_matches(&game);
+        assert!(game.events.all().iter().all(|event| event.kind != EventKind::Leave));
+
+        let second = game.players.key(1).expect("seated");
+        assert_ne!(first, second);
+        game.process_message(ConnectionMessage::Close(second));
+        assert!(!game.players.contains(1));
+        assert!(game.grid.is_empty());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_stale_input_doesnt_move_the_slots_next_player() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        seat(&mut game, test_player(1, (100, 100)).await?.0);
+        let first = game.players.key(1).expect("seated");
+
+        game.remove_player(1);
+        game.slots.leave(1);
+        let (mut next, _client) = test_player(1, (110, 110)).await?;
+        next.move_budget = 1000;
+        seat(&mut game, next);
+        game.map.set_terrain(109, 110, map::map::Terrain::Ground);
+
+        // the old stream is still reading keys
+        let press = || ServerMessage::new(0, server::Message::key_press(b'h', 0));
+        game.process_message(ConnectionMessage::Msg((first, Ok(press()))));
+        assert_eq!(game.players.get(1).map(|p| p.position), Some((110, 110)));
+
+        let second = game.players.key(1).expect("seated");
+        game.process_message(ConnectionMessage::Msg((second, Ok(press()))));
+        assert_eq!(game.players.get(1).map(|p| p.position), Some((109, 110)));
+        assert_grid_matches(&game);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_mid_game_disconnect_is_placed() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        let mut clients = vec![];
+        for id in 0..4 {
+            let (player, client) = test_player(id, (100 + id as u16, 100)).await?;
+            seat(&mut game, player);
+            clients.push(client);
+        }
+
+        // leaving the lobby doesn't place anyone
+        game.process_message(ConnectionMessage::Close(game.players.key(3).expect("seated")));
+        assert!(game.standings.is_empty());
+
+        game.start_game().await?;
+        game.tick = 40;
+        game.process_message(ConnectionMessage::Close(game.players.key(1).expect("seated")));
+
+        let leaderboard = game.result().leaderboard;
+        let places: Vec<(u8, usize, Option<OutReason>)> = leaderboard
+            .iter()
+            .map(|placement| (placement.player_id, placement.place, placement.reason))
+            .collect();
+        assert_eq!(places, vec![(0, 1, None), (2, 1, None), (1, 3, Some(OutReason::Disconnected))]);
+        assert_eq!((leaderboard[2].name.as_str(), leaderboard[2].tick), (default_name(1).as_str(), 40));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_tournament_player_that_drops_keeps_the_slot_for_a_while() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        let mut clients = vec![];
+        for id in 0..3 {
+            let (player, client) = test_player(id, (100 + id as u16, 100)).await?;
+            seat(&mut game, player);
+            clients.push(client);
+        }
+        game.tokens.insert(1, 77);
+
+        game.start_game().await?;
+        game.tick = 40;
+        game.process_message(ConnectionMessage::Close(game.players.key(1).expect("seated")));
+        assert_eq!(game.slots.state(1), SlotState::Grace(77));
+        assert!(game.players.get(1).is_some());
+        assert!(game.standings.is_empty());
+
+        // anyone else is out right away
+        game.process_message(ConnectionMessage::Close(game.players.key(2).expect("seated")));
+        assert_eq!(game.slots.state(2), SlotState::Free);
+
+        let until = game.tick + game.config.ticks(game.config.reconnect_grace_seconds);
+        game.expire_held_slots(until - 1);
+        assert_eq!(game.slots.state(1), SlotState::Grace(77));
+
+        game.expire_held_slots(until);
+        assert_eq!(game.slots.state(1), SlotState::Free);
+        assert!(game.players.get(1).is_none());
+        let out: Vec<(u8, Option<OutReason>)> =
+            game.result().leaderboard.iter().map(|placement| (placement.player_id, placement.reason)).collect();
+        assert!(out.contains(&(1, Some(OutReason::Disconnected))));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_tournament_player_reconnects_into_the_held_slot() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        let mut clients = vec![];
+        for id in 0..3 {
+            let (player, client) = test_player(id, (100 + id as u16, 100)).await?;
+            seat(&mut game, player);
+            clients.push(client);
+        }
+        game.tokens.insert(1, 77);
+
+        game.start_game().await?;
+        game.process_message(ConnectionMessage::Close(game.players.key(1).expect("seated")));
+        assert_eq!(game.slots.state(1), SlotState::Grace(77));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let conn = IncomingConnection {
+            token: Some(77),
+            ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+        };
+        game.handle_game_message(GameMessage::Connection(conn)).await;
+        match next_message(&mut client).await?.msg {
+            server::Message::PlayerStart(start) => assert_eq!(start.position, (101, 100)),
+            msg => panic!("expected PlayerStart, got {:?}", msg),
+        }
+        assert_eq!(game.slots.state(1), SlotState::Live);
+        assert!(game.held.is_empty());
+
+        // its stream is the current one, a close from it holds the slot again
+        game.process_message(ConnectionMessage::Close(game.players.key(1).expect("back in")));
+        assert_eq!(game.slots.state(1), SlotState::Grace(77));
+
+        // a token nothing is held for doesn't get in
+        let (server_socket, mut late) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let conn = IncomingConnection {
+            token: Some(88),
+            ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+        };
+        game.handle_game_message(GameMessage::Connection(conn)).await;
+        match next_message(&mut late).await?.msg {
+            server::Message::JoinError(reason) => assert_eq!(reason, JOIN_ERROR_STARTED),
+            msg => panic!("expected JoinError, got {:?}", msg),
+        }
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_failed_sends_show_in_inspection() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), GameConfig::default(), no_filter())?;
+        let (player, _client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+        game.insert_player(closed_player(1).await?);
+
+        game.broadcast_snapshots().await;
+        game.broadcast(server::Message::Countdown(3)).await;
+        game.broadcast(server::Message::Countdown(2)).await;
+
+        let inspection = game.inspect();
+        let (ok, closed) = (&inspection.roster[0].send, &inspection.roster[1].send);
+        assert_eq!((ok.sends, ok.dropped), (3, [0, 0]));
+        assert_eq!((closed.sends, closed.dropped), (3, [1, 2]));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_recovery_image_round_trips_into_restored_game() -> Result<()> {
+        let config = GameConfig {
+            warmup_ticks: 30,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<PLAYER_COUNT>::new(77, 21, Arc::new(AtomicU8::new(0)), config, no_filter())?;
+        let (mut player, _client) = test_player(0, (40, 41)).await?;
+        player.move_budget = 123;
+        player.last_emote = Some(90);
+        seat(&mut game, player);
+        game.add_bot();
+        game.state.handle(StateEvent::Started(80));
+        game.tick = 95;
+        game.zone = server::Zone {
+            center: (100, 120),
+            radius: 64,
+        };
+        game.short_handed = true;
+
+        let image = game.recovery_image();
+        let bytes = serde_json::to_vec(&image)?;
+        let restored_image: RecoveryImage = serde_json::from_slice(&bytes)?;
+        assert_eq!(restored_image, image);
+
+        let player_count = Arc::new(AtomicU8::new(0));
+        let restored = Game::<PLAYER_COUNT>::restore(&restored_image, player_count.clone(), config, no_filter())?;
+        let mut again = restored.recovery_image();
+        again.saved_at = image.saved_at;
+        assert_eq!(again, image);
+        assert_eq!(restored.state.state(), GameState::WarmUp);
+        assert_eq!(restored.state.warmup_remaining(restored.tick), Some(15));
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert!(restored.is_bot(1) && !restored.is_bot(0));
+        assert_grid_matches(&restored);
+
+        // another map under the same seed is refused
+        let mut tampered = image.clone();
+        tampered.map_checksum ^= 1;
+        assert!(Game::<PLAYER_COUNT>::restore(&tampered, Arc::new(AtomicU8::new(0)), config, no_filter()).is_err());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_admin_move_relocates_and_broadcasts() -> Result<()> {
+        let config = GameConfig {
+            admin_commands: true,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), config, no_filter())?;
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        let (other, mut other_client) = test_player(1, (2, 2)).await?;
+        game.insert_player(player);
+        game.insert_player(other);
+
+        let target = (40, 3);
+        game.map.set_terrain(40, 3, map::map::Terrain::Ground);
+        game.handle_game_message(GameMessage::AdminMove(0, target)).await;
+
+        assert_eq!(game.players.get(0).map(|p| p.position), Some(target));
+        let update = server::Message::PlayerPositionUpdate(server::PlayerPositionUpdate {
+            entity_id: super::entity_id(0, 500),
+            position: target,
+        });
+        assert_eq!(next_message(&mut client).await?.msg, update);
+        assert_eq!(next_message(&mut other_client).await?.msg, update);
+
+        // walls stay walls, even for admins
+        game.map.set_terrain(41, 3, map::map::Terrain::Wall);
+        game.handle_game_message(GameMessage::AdminMove(0, (41, 3))).await;
+        assert_eq!(game.players.get(0).map(|p| p.position), Some(target));
+        assert_grid_matches(&game);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_admin_move_needs_admin_commands() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        let (player, _client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+
+        game.map.set_terrain(40, 3, map::map::Terrain::Ground);
+        game.handle_game_message(GameMessage::AdminMove(0, (40, 3))).await;
+        assert_eq!(game.players.get(0).map(|p| p.position), Some((1, 1)));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_lobby_timer_lowers_requirement() {
+        let config = GameConfig {
+            min_players: 4,
+            max_lobby_wait: Some(std::time::Duration::from_secs(10)),
+            ..GameConfig::default()
+        };
+        let player_count = Arc::new(AtomicU8::new(1));
+        let mut game = Game::<8>::new(0, 0, player_count.clone(), config, no_filter()).expect("seed makes a playable map");
+        let start = std::time::Instant::now();
+        let waited = start + std::time::Duration::from_secs(10);
+
+        game.update_lobby_timer(start);
+        assert_eq!(game.required_players(start), 4);
+        assert_eq!(game.required_players(waited), 2);
+
+        // more players joining doesn't restart the timer
+        player_count.store(2, std::sync::atomic::Ordering::Relaxed);
+        game.update_lobby_timer(start + std::time::Duration::from_secs(5));
+        assert_eq!(game.required_players(waited), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_timer_resets_when_empty() {
+        let config = GameConfig {
+            min_players: 4,
+            max_lobby_wait: Some(std::time::Duration::from_secs(10)),
+            ..GameConfig::default()
+        };
+        let player_count = Arc::new(AtomicU8::new(1));
+        let mut game = Game::<8>::new(0, 0, player_count.clone(), config, no_filter()).expect("seed makes a playable map");
+        let start = std::time::Instant::now();
+
+        game.update_lobby_timer(start);
+        player_count.store(0, std::sync::atomic::Ordering::Relaxed);
+        game.update_lobby_timer(start + std::time::Duration::from_secs(5));
+        assert_eq!(game.lobby_since, None);
+
+        let rejoin = start + std::time::Duration::from_secs(8);
+        player_count.store(1, std::sync::atomic::Ordering::Relaxed);
+        game.update_lobby_timer(rejoin);
+        assert_eq!(game.required_players(start + std::time::Duration::from_secs(12)), 4);
+        assert_eq!(game.required_players(rejoin + std::time::Duration::from_secs(10)), 2);
+    }
+
+    #[tokio::test]
+    async fn test_on_deadline_policies() {
+        let config = GameConfig {
+            min_players: 4,
+            max_lobby_wait: Some(std::time::Duration::from_secs(10)),
+            on_deadline: OnDeadline::StartAnyway { floor: 3 },
+            ..GameConfig::default()
+        };
+        let player_count = Arc::new(AtomicU8::new(2));
+        let start = std::time::Instant::now();
+        let waited = start + std::time::Duration::from_secs(10);
+
+        let mut game = Game::<8>::new(0, 0, player_count.clone(), config, no_filter()).expect("seed makes a playable map");
+        game.update_lobby_timer(start);
+        assert_eq!(game.required_players(waited), 3);
+        assert!(!game.is_cancelled(waited));
+
+        // bots make up the rest, one player is enough
+        let mut game = Game::<8>::new(0, 0, player_count.clone(), GameConfig { bot_fill: true, ..config }, no_filter())
+            .expect("seed makes a playable map");
+        game.update_lobby_timer(start);
+        assert_eq!(game.required_players(waited), 1);
+
+        let config = GameConfig { on_deadline: OnDeadline::Cancel, ..config };
+        let mut game = Game::<8>::new(0, 0, player_count.clone(), config, no_filter()).expect("seed makes a playable map");
+        game.update_lobby_timer(start);
+        assert_eq!(game.required_players(waited), 4);
+        assert!(!game.is_cancelled(start));
+        assert!(game.is_cancelled(waited));
+        player_count.store(4, std::sync::atomic::Ordering::Relaxed);
+        assert!(!game.is_cancelled(waited));
+    }
+
+    #[tokio::test]
+    async fn test_on_deadline_cancel_closes_the_lobby() -> Result<()> {
+        let config = GameConfig {
+            min_players: 3,
+            max_lobby_wait: Some(std::time::Duration::from_millis(100)),
+            on_deadline: OnDeadline::Cancel,
+            ..GameConfig::default()
+        };
+        let (manager_tx, mut manager_rx) = mpsc::channel(10);
+        let (comms, sender): (GameComms, _) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 0, epoch: 0 };
+        let game = tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+
+        let notice = server::Announcement::new(ANNOUNCEMENT_WARNING, super::LOBBY_CANCELLED);
+        assert_eq!(complete_handshake(&mut client).await?.msg, server::Message::Announcement(notice));
+        assert!(next_message(&mut client).await.is_err());
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), game).await??;
+        // closed without ever starting
+        assert!(matches!(manager_rx.recv().await, Some(GameMessage::Close(closed)) if closed == key));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_max_lobby_wait_starts_short_handed() -> Result<()> {
+        let config = GameConfig {
+            min_players: 4,
+            max_lobby_wait: Some(std::time::Duration::from_millis(200)),
+            ..GameConfig::default()
+        };
+        let (manager_tx, _manager_rx) = mpsc::channel(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 0, epoch: 0 };
+        let started = std::time::Instant::now();
+        tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let mut handshakes = vec![];
+        for _ in 0..2 {
+            let (server_socket, mut client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+            handshakes.push(tokio::spawn(async move {
+                let msg = complete_handshake(&mut client).await;
+                return (client, msg);
+            }));
+        }
+
+        let status = query_status(&sender).await?;
+        assert_eq!((status.state, status.required_players), (GameState::Lobby, 4));
+
+        let mut clients = vec![];
+        for handshake in handshakes {
+            let (client, msg) = handshake.await?;
+            assert!(matches!(msg?.msg, server::Message::PlayerStart(_)));
+            clients.push(client);
+        }
+        assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+
+        let status = query_status(&sender).await?;
+        assert!(status.short_handed);
+        assert_eq!(status.player_count, 2);
+
+        return Ok(());
+    }
+
+    #[derive(Default)]
+    struct KeptOutcomes(std::sync::Mutex<Vec<(GameKey, super::GameResult)>>);
+
+    impl crate::outcome::OutcomeSink for KeptOutcomes {
+        fn persist(&self, key: GameKey, result: &super::GameResult) -> Result<()> {
+            self.0.lock().expect("outcomes lock").push((key, result.clone()));
+            return Ok(());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_game_outlives_the_manager_and_keeps_its_outcome() -> Result<()> {
+        let config = GameConfig {
+            max_ticks: Some(30),
+            ..GameConfig::default()
+        };
+        let (manager_tx, mut manager_rx) = mpsc::channel(10);
+        let (mut comms, sender): (GameComms, _) = GameComms::with_sender(manager_tx);
+        let outcomes = Arc::new(KeptOutcomes::default());
+        comms.outcomes = Some(outcomes.clone());
+        let key = GameKey { id: 3, epoch: 2 };
+        let game = tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        complete_handshake(&mut client).await?;
+
+        assert!(matches!(manager_rx.recv().await, Some(GameMessage::Start(started)) if started == key));
+        drop(manager_rx);
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), game).await??;
+        let outcomes = outcomes.0.lock().expect("outcomes lock");
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].0, key);
+        assert!(outcomes[0].1.events.iter().any(|event| event.detail == "manager gone"));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_finished_game_sends_its_result_before_close() -> Result<()> {
+        let config = GameConfig {
+            min_players: 2,
+            max_ticks: Some(30),
+            ..GameConfig::default()
+        };
+        let (manager_tx, mut manager_rx) = mpsc::channel(10);
+        let (comms, sender): (GameComms, _) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 4, epoch: 1 };
+        let game = tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        // tournament players, slots go out in the order they connect
+        let tokens = [11, 22];
+        let mut handshakes = vec![];
+        for token in tokens {
+            let (server_socket, mut client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            let conn = IncomingConnection {
+                token: Some(token),
+                ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+            };
+            sender.send(GameMessage::Connection(conn)).await?;
+            handshakes.push(tokio::spawn(async move {
+                let msg = complete_handshake(&mut client).await;
+                return (client, msg);
+            }));
+        }
+        let mut clients = vec![];
+        for handshake in handshakes {
+            let (client, msg) = handshake.await?;
+            assert!(matches!(msg?.msg, server::Message::PlayerStart(_)));
+            clients.push(client);
+        }
+
+        assert!(matches!(manager_rx.recv().await, Some(GameMessage::Start(started)) if started == key));
+        tokio::time::timeout(std::time::Duration::from_secs(5), game).await??;
+        let result = match manager_rx.recv().await {
+            Some(GameMessage::Result(finished, result)) if finished == key => result,
+            msg => panic!("expected the Result, got {:?}", msg),
+        };
+        assert!(matches!(manager_rx.recv().await, Some(GameMessage::Close(closed)) if closed == key));
+
+        assert_eq!(result.leaderboard.len(), 2);
+        let placed: Vec<u64> = result.leaderboard.iter().map(|p| tokens[p.player_id as usize]).collect();
+        assert_eq!(result.placements, placed);
+        assert_eq!(result.region, config.region);
+        assert!(result.events.iter().any(|event| event.detail == "ended, time up"));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_bots_only_game_runs_to_completion() -> Result<()> {
+        let config = GameConfig {
+            min_players: 3,
+            max_ticks: Some(30),
+            ..GameConfig::default()
+        };
+        let mut game = Game::<8>::new(21, 0, Arc::new(AtomicU8::new(0)), config, no_filter())?;
+        game.fill_with_bots();
+        assert_eq!((game.bots.len(), game.human_count()), (3, 0));
+
+        let (manager_tx, _manager_rx) = mpsc::channel(10);
+        let (mut comms, _sender) = GameComms::with_sender(manager_tx);
+        game.start_game().await?;
+        game.run(&mut comms).await?;
+
+        assert_eq!(game.state.state(), GameState::Ended);
+        assert_eq!(game.tick, 30);
+        assert!(game
+            .players
+            .iter()
+            .any(|player| player.position != super::SPAWN_POSITION));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_bot_fill_after_max_lobby_wait() -> Result<()> {
+        let config = GameConfig {
+            min_players: 3,
+            max_lobby_wait: Some(std::time::Duration::from_millis(100)),
+            bot_fill: true,
+            ..GameConfig::default()
+        };
+        let (manager_tx, _manager_rx) = mpsc::channel(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+
+        let key = GameKey { id: 0, epoch: 0 };
+        tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        // a single human is enough once bots can fill in
+        assert!(matches!(complete_handshake(&mut client).await?.msg, server::Message::PlayerStart(_)));
+
+        let status = query_status(&sender).await?;
+        assert!(status.short_handed);
+        assert_eq!((status.player_count, status.bot_count), (3, 2));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_joins_sync_in_parallel() -> Result<()> {
+        // 10 clock sync rounds at 20ms each, 5 players back to back would take 1s
+        let delay = std::time::Duration::from_millis(20);
+        let serial = delay * 10 * 5;
+        let config = GameConfig {
+            min_players: 5,
+            max_concurrent_handshakes: 5,
+            ..GameConfig::default()
+        };
+
+        let (manager_tx, _manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 0, epoch: 0 };
+        tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let started = std::time::Instant::now();
+        let mut handshakes = vec![];
+        for _ in 0..5 {
+            let (server_socket, mut client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+            handshakes.push(tokio::spawn(async move {
+                return complete_slow_handshake(&mut client, delay).await.map(|msg| (client, msg));
+            }));
+        }
+
+        let mut clients = vec![];
+        for handshake in handshakes {
+            let (client, msg) = handshake.await??;
+            assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+            clients.push(client);
+        }
+
+        assert!(started.elapsed() < serial / 2, "took {:?}", started.elapsed());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_clock_sync_drops_the_player() -> Result<()> {
+        for on_sync_timeout in [OnSyncTimeout::Drop, OnSyncTimeout::Admit] {
+            let player_count = Arc::new(AtomicU8::new(0));
+            let config = GameConfig {
+                handshake_timeout: std::time::Duration::from_millis(100),
+                on_sync_timeout,
+                max_concurrent_handshakes: 1,
+                ..GameConfig::default()
+            };
+            let mut game = Game::<4>::new(0, 0, player_count.clone(), config, no_filter())?;
+
+            // never answers, and holds the only handshake permit until it times out
+            let (server_socket, mut stalled) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            game.add_connection(stream, sink, WHO_AM_I_CLIENT, Some("ada".to_string()), None).await?;
+
+            let (server_socket, mut client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            game.add_connection(stream, sink, WHO_AM_I_CLIENT, Some("bob".to_string()), None).await?;
+            let answering = tokio::spawn(async move { _ = complete_handshake(&mut client).await; });
+            assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+            game.finish_handshakes().await;
+            answering.abort();
+            assert!(game.handshaking.is_empty());
+            assert!(game.players.contains(1), "{:?}", on_sync_timeout);
+
+            let admitted = on_sync_timeout == OnSyncTimeout::Admit;
+            assert_eq!(game.players.get(0).map(|p| p.clock_diff), admitted.then_some(0));
+            assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 1 + admitted as u8);
+            if admitted {
+                continue;
+            }
+
+            // the slot is free for the next one and the connection is gone
+            assert_eq!(game.slots.join(), Some(0));
+            let closed = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+                while let Some(Ok(_)) = stalled.next().await {}
+            });
+            assert!(closed.await.is_ok());
+        }
+
+        return Ok(());
+    }
+
+    async fn query_status(sender: &mpsc::Sender<GameMessage>) -> Result<GameStatus> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        sender.send(GameMessage::QueryStatus(tx)).await?;
+        return Ok(rx.await?);
+    }
+
+    #[tokio::test]
+    async fn test_status_query_behind_a_late_join_is_answered() -> Result<()> {
+        let config = GameConfig {
+            allow_late_join: true,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(5, 0, Arc::new(AtomicU8::new(0)), config, no_filter())?;
+        let (manager_tx, _manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (mut comms, sender) = GameComms::with_sender(manager_tx);
+
+        let (server_socket, _client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        sender.send(GameMessage::QueryStatus(tx)).await?;
+
+        game.drain_lobby_messages(&mut comms).await;
+        assert_eq!(game.handshaking.len(), 1);
+        let status = tokio::time::timeout(std::time::Duration::from_secs(1), rx).await??;
+        assert_eq!(status.state, GameState::Lobby);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_answered_from_lobby() -> Result<()> {
+        let (manager_tx, _manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 12, epoch: 0 };
+        tokio::spawn(game_run(11, Arc::new(AtomicU8::new(0)), key, comms, GameConfig::default()));
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        sender.send(GameMessage::HealthCheck(tx)).await?;
+        let report = rx.await?;
+        assert_eq!((report.game_id, report.tick, report.state), (12, 0, GameState::Lobby));
+        assert_eq!(report.interval, super::LOBBY_CHECK_INTERVAL);
+        assert!(report.last_tick <= std::time::Instant::now());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_panicking_game_closes_connections_and_reports() -> Result<()> {
+        let config = GameConfig {
+            min_players: 2,
+            ..GameConfig::default()
+        };
+        let (manager_tx, mut manager_rx) = mpsc::channel(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 13, epoch: 0 };
+        tokio::spawn(game_run(11, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        // syncs the clock, then waits in the lobby for how the connection ends
+        let closed = tokio::spawn(async move {
+            while let Some(Ok(msg)) = client.next().await {
+                match msg {
+                    tungstenite::Message::Binary(_) => {
+                        let resp = ServerMessage::new(0, server::Message::clock_response(0)).serialize()?;
+                        client.send(tungstenite::Message::Binary(resp)).await?;
+                    }
+                    tungstenite::Message::Close(frame) => return Ok(frame),
+                    _ => {}
+                }
+            }
+            return Err(anyhow::anyhow!("closed without a close frame"));
+        });
+
+        while query_status(&sender).await?.names.is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+
+        let crashes = || {
+            let scrape = metrics().render();
+            return scrape
+                .lines()
+                .find_map(|line| line.strip_prefix("vim_royale_game_crashes_total ")?.parse::<u64>().ok());
+        };
+        let crashes_before = crashes();
+        // only the manager sends Start, the lobby has no arm for it
+        sender.send(GameMessage::Start(key)).await?;
+
+        match manager_rx.recv().await {
+            Some(GameMessage::Crashed(crashed, report)) => {
+                assert_eq!(crashed, key);
+                assert!(report.message.contains("this should never happen"));
+                let kinds: Vec<EventKind> = report.events.iter().map(|event| event.kind).collect();
+                assert_eq!(kinds, vec![EventKind::Join, EventKind::Error]);
+                assert_eq!(report.events[0].player_id, Some(0));
+            }
+            msg => panic!("expected Crashed, got {:?}", msg),
+        }
+        assert!(matches!(manager_rx.recv().await, Some(GameMessage::Close(closed)) if closed == key));
+
+        let frame = closed.await??.expect("close frame has a code");
+        assert_eq!(frame.code, tungstenite::protocol::frame::coding::CloseCode::Error);
+        assert_eq!(frame.reason, "server error");
+        assert!(crashes() > crashes_before);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_status_through_lifecycle() -> Result<()> {
+        let config = GameConfig {
+            min_players: 2,
+            ..GameConfig::default()
+        };
+        let (manager_tx, _manager_rx) = mpsc::channel(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 3, epoch: 0 };
+        tokio::spawn(game_run(11, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let status = query_status(&sender).await?;
+        assert_eq!(status.state, GameState::Lobby);
+        assert_eq!((status.game_id, status.seed, status.player_count), (3, 11, 0));
+
+        let mut handshakes = vec![];
+        for _ in 0..2 {
+            let (server_socket, mut client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+            handshakes.push(tokio::spawn(async move {
+                let msg = complete_handshake(&mut client).await;
+                return (client, msg);
+            }));
+
+            // the game answers between connections, still in the lobby
+            let status = query_status(&sender).await?;
+            if status.player_count < 2 {
+                assert_eq!(status.state, GameState::Lobby);
+            }
+        }
+
+        let mut clients = vec![];
+        for handshake in handshakes {
+            let (client, msg) = handshake.await?;
+            assert!(matches!(msg?.msg, server::Message::PlayerStart(_)));
+            clients.push(client);
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let status = query_status(&sender).await?;
+        assert_eq!(status.state, GameState::Live);
+        assert_eq!(status.player_count, 2);
+        assert!(status.tick > 0);
+        assert!(status.timing.max_us >= status.timing.last_us);
+
+        return Ok(());
+    }
+
+    // answers clock syncs, returns the next lobby roster as (entity id, name, ready)
+    async fn next_lobby_state(client: &mut TestSocket) -> Result<Vec<(usize, String, bool)>> {
+        loop {
+            match next_message(client).await?.msg {
+                server::Message::ClockSyncRequest(_) => {
+                    let resp = ServerMessage::new(0, server::Message::clock_response(0)).serialize()?;
+                    client.send(tungstenite::Message::Binary(resp)).await?;
+                }
+                server::Message::LobbyState(lobby) => {
+                    return Ok(lobby
+                        .players
+                        .into_iter()
+                        .map(|p| (p.entity_id, String::from_utf8_lossy(&p.name.name).to_string(), p.ready))
+                        .collect());
+                }
+                msg => panic!("expected LobbyState, got {:?}", msg),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lobby_hears_about_new_players() -> Result<()> {
+        let config = GameConfig {
+            min_players: 2,
+            ..GameConfig::default()
+        };
+        let (manager_tx, _manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 0, epoch: 0 };
+        tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server_socket, mut first) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let conn = IncomingConnection {
+            name: Some("alice".to_string()),
+            ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+        };
+        sender.send(GameMessage::Connection(conn)).await?;
+        assert_eq!(next_lobby_state(&mut first).await?, vec![(0, "alice".to_string(), true)]);
+
+        let (server_socket, mut second) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let conn = IncomingConnection {
+            name: Some("bob".to_string()),
+            ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+        };
+        sender.send(GameMessage::Connection(conn)).await?;
+        let handshake = tokio::spawn(async move { complete_handshake(&mut second).await });
+
+        // in the lobby as soon as they connect, ready once their clock is synced
+        let joining = vec![(0, "alice".to_string(), true), (500, "bob".to_string(), false)];
+        assert_eq!(next_lobby_state(&mut first).await?, joining);
+        let joined = vec![(0, "alice".to_string(), true), (500, "bob".to_string(), true)];
+        assert_eq!(next_lobby_state(&mut first).await?, joined);
+
+        assert!(matches!(next_message(&mut first).await?.msg, server::Message::PlayerStart(_)));
+        assert!(matches!(handshake.await??.msg, server::Message::PlayerStart(_)));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_names_are_suffixed() -> Result<()> {
+        let config = GameConfig {
+            min_players: 3,
+            ..GameConfig::default()
+        };
+        let (manager_tx, _manager_rx) = mpsc::channel(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 0, epoch: 0 };
+        tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        // one at a time so slots and names line up
+        let mut clients = vec![];
+        for name in [Some("bob"), Some("bob"), None] {
+            let (server_socket, client) = ws_pair().await?;
+            let (sink, stream) = server_socket.split();
+            let name = name.map(|name| name.to_string());
+            let conn = IncomingConnection {
+                name,
+                ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+            };
+            sender.send(GameMessage::Connection(conn)).await?;
+
+            let mut client = client;
+            let handshake = tokio::spawn(async move {
+                let msg = complete_handshake(&mut client).await;
+                return (client, msg);
+            });
+            while query_status(&sender).await?.names.len() < clients.len() + 1 {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            clients.push(handshake);
+        }
+
+        let expected = ["bob", "bob-2", "player2"];
+        // still connected for the status at the end
+        let mut connected = vec![];
+        for handshake in clients {
+            let (mut client, msg) = handshake.await?;
+            assert!(matches!(msg?.msg, server::Message::PlayerStart(_)));
+            assert!(matches!(next_message(&mut client).await?.msg, server::Message::ZoneUpdate(_)));
+
+            for (id, name) in expected.iter().enumerate() {
+                match next_message(&mut client).await?.msg {
+                    server::Message::PlayerJoined(joined) => {
+                        assert_eq!(joined.entity_id, id * 500);
+                        assert_eq!(joined.name.name, name.as_bytes());
+                    }
+                    msg => panic!("expected PlayerJoined, got {:?}", msg),
+                }
+            }
+            connected.push(client);
+        }
+
+        assert_eq!(query_status(&sender).await?.names, expected);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_region_in_player_start_and_status() -> Result<()> {
+        let config = GameConfig {
+            region: server::region("eu-west"),
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), config, no_filter())?;
+        let (player, mut client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+
+        game.start_game().await?;
+        match next_message(&mut client).await?.msg {
+            server::Message::PlayerStart(start) => assert_eq!(start.region, Some(server::region("eu-west"))),
+            msg => panic!("expected PlayerStart, got {:?}", msg),
+        }
+        assert_eq!(server::region_label(&game.status().region), "eu-west");
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_emotes_reach_players_in_range() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(3)), GameConfig::default(), no_filter())?;
+        let (sender, mut sender_client) = test_player(0, (100, 100)).await?;
+        let (near, mut near_client) = test_player(1, (110, 120)).await?;
+        let (far, mut far_client) = test_player(2, (300, 300)).await?;
+        game.insert_player(sender);
+        game.insert_player(near);
+        game.insert_player(far);
+
+        let emote = |emote_id| {
+            let msg = ServerMessage::new(0, server::Message::Emote(server::Emote { from: 0, emote_id }));
+            return ConnectionMessage::Msg((first_key(0), Ok(msg)));
+        };
+
+        // not in the table
+        game.process_message(emote(EMOTES.len() as u8));
+        assert!(game.emotes.is_empty());
+
+        game.process_message(emote(1));
+        // rate limited, the cooldown hasn't passed
+        game.process_message(emote(2));
+        assert_eq!(game.emotes.len(), 1);
+        game.send_emotes();
+        game.flush_events().await;
+
+        let expected = emote_batch(0, 1);
+        assert_eq!(next_message(&mut sender_client).await?.msg, expected);
+        assert_eq!(next_message(&mut near_client).await?.msg, expected);
+
+        // the far player gets the next thing everyone gets, not the emote
+        game.broadcast(server::Message::Countdown(0)).await;
+        assert_eq!(next_message(&mut far_client).await?.msg, server::Message::Countdown(0));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_no_game_without_enough_spawns() {
+        // more players than any 256 tile map has spawn points for
+        let config = GameConfig {
+            max_players: 2000,
+            ..GameConfig::default()
+        };
+        assert!(Game::<2000>::new(0, 0, Arc::new(AtomicU8::new(0)), config, no_filter()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hot_logs_go_by_the_games_clock() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        let clock = Arc::new(crate::clock::MockClock::new());
+        game.clock = clock.clone();
+        game.insert_player(test_player(0, (100, 100)).await?.0);
+
+        // no move budget, every step is rejected
+        for _ in 0..10 {
+            game.move_player(0, b'h');
+        }
+        assert!(game.hot_logs.expired(game.clock.now()).is_empty());
+
+        clock.advance(crate::log_sampler::SAMPLE_WINDOW);
+        let suppressed = crate::log_sampler::HOT_LOGS_PER_WINDOW as u64;
+        assert_eq!(game.hot_logs.expired(game.clock.now()), vec![("move rejected", 10 - suppressed)]);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_emotes_follow_players_walking_into_range() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), GameConfig::default(), no_filter())?;
+        let (sender, mut sender_client) = test_player(0, (100, 100)).await?;
+        let (mut walker, mut walker_client) = test_player(1, (141, 100)).await?;
+        walker.move_budget = 1000;
+        game.insert_player(sender);
+        game.insert_player(walker);
+
+        game.process_message(emote_from(0, 1));
+        game.send_emotes();
+        game.flush_events().await;
+        assert_eq!(next_message(&mut sender_client).await?.msg, emote_batch(0, 1));
+
+        // one step left puts them exactly view distance away, that is in range
+        game.map.set_terrain(140, 100, map::map::Terrain::Ground);
+        let press = ServerMessage::new(0, server::Message::key_press(b'h', 0));
+        game.process_message(ConnectionMessage::Msg((first_key(1), Ok(press))));
+        assert_eq!(game.players.get(1).map(|p| p.position), Some((140, 100)));
+        assert_grid_matches(&game);
+
+        game.tick += game.config.emote_cooldown_ticks + 1;
+        game.process_message(emote_from(0, 2));
+        game.send_emotes();
+        game.flush_events().await;
+        assert_eq!(next_message(&mut walker_client).await?.msg, emote_batch(0, 2));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_a_ticks_events_arrive_in_one_batch_before_the_snapshot() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), GameConfig::default(), no_filter())?;
+        let (sender, mut sender_client) = test_player(0, (100, 100)).await?;
+        let (bot, _) = test_player(1, (100, 101)).await?;
+        game.insert_player(sender);
+        game.insert_player(Player {
+            sink: PlayerSink::detached(1),
+            ..bot
+        });
+
+        let announcement = server::Announcement::new(ANNOUNCEMENT_WARNING, "zone closing");
+        game.handle_game_message(GameMessage::Announce(announcement.clone())).await;
+        game.process_message(emote_from(0, 1));
+        game.step().await;
+
+        let batch = server::EventBatch::new(vec![
+            server::GameEvent::Announcement(announcement),
+            server::GameEvent::Emote(server::Emote { from: 0, emote_id: 1 }),
+        ]);
+        assert_eq!(next_message(&mut sender_client).await?.msg, server::Message::EventBatch(batch));
+        assert!(matches!(next_message(&mut sender_client).await?.msg, server::Message::Snapshot(_)));
+        // bots never hold on to events
+        assert!(game.players.get(1).is_some_and(|bot| bot.sink.events.is_empty()));
+
+        // a quiet tick sends no batch at all
+        game.step().await;
+        assert!(matches!(next_message(&mut sender_client).await?.msg, server::Message::Snapshot(_)));
+
+        return Ok(());
+    }
+
+    // what one emote in a tick arrives as
+    fn emote_batch(from: usize, emote_id: u8) -> server::Message {
+        let emote = server::GameEvent::Emote(server::Emote { from, emote_id });
+        return server::Message::EventBatch(server::EventBatch::new(vec![emote]));
+    }
+
+    fn emote_from(id: u8, emote_id: u8) -> ConnectionMessage {
+        let msg = ServerMessage::new(0, server::Message::Emote(server::Emote { from: 0, emote_id }));
+        return ConnectionMessage::Msg((first_key(id), Ok(msg)));
+    }
+
+    #[tokio::test]
+    async fn test_mute_sticks_through_a_rejoin() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), GameConfig::default(), no_filter())?;
+        let (muted, mut muted_client) = test_player(0, (100, 100)).await?;
+        let (other, mut other_client) = test_player(1, (100, 101)).await?;
+        let name = muted.name.clone();
+        game.insert_player(muted);
+        game.insert_player(other);
+
+        assert!(game.moderate(Moderation::Mute(0)));
+        assert!(!game.moderate(Moderation::Mute(3)));
+        let roster = game.inspect().roster;
+        assert_eq!(roster.iter().map(|p| p.muted).collect::<Vec<_>>(), vec![true, false]);
+
+        // acknowledged to them, nobody else hears it
+        let own = emote_batch(0, 1);
+        game.process_message(emote_from(0, 1));
+        game.send_emotes();
+        game.flush_events().await;
+        assert_eq!(next_message(&mut muted_client).await?.msg, own);
+        game.broadcast(server::Message::Countdown(0)).await;
+        assert_eq!(next_message(&mut other_client).await?.msg, server::Message::Countdown(0));
+        assert_eq!(next_message(&mut muted_client).await?.msg, server::Message::Countdown(0));
+
+        // same name, new slot
+        game.process_message(ConnectionMessage::Close(game.players.key(0).expect("seated")));
+        let (mut rejoined, mut rejoined_client) = test_player(2, (100, 100)).await?;
+        rejoined.name = name;
+        game.insert_player(rejoined);
+        assert!(game.inspect().roster.iter().find(|p| p.player_id == 2).is_some_and(|p| p.muted));
+
+        game.process_message(emote_from(2, 3));
+        game.send_emotes();
+        game.flush_events().await;
+        let rejoined_emote = emote_batch(1000, 3);
+        assert_eq!(next_message(&mut rejoined_client).await?.msg, rejoined_emote);
+
+        assert!(game.moderate(Moderation::Unmute(2)));
+        game.tick += game.config.emote_cooldown_ticks;
+        game.process_message(emote_from(2, 4));
+        game.send_emotes();
+        game.flush_events().await;
+        let heard = emote_batch(1000, 4);
+        assert_eq!(next_message(&mut other_client).await?.msg, heard);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_slow_mode_stretches_the_emote_cooldown() -> Result<()> {
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        let (player, _client) = test_player(0, (100, 100)).await?;
+        game.insert_player(player);
+        let cooldown = game.config.emote_cooldown_ticks;
+
+        assert!(game.moderate(Moderation::SlowMode(2)));
+        assert_eq!(game.inspect().slow_mode_ticks, game.config.ticks(2));
+
+        game.process_message(emote_from(0, 1));
+        // fine without slow mode
+        game.tick = cooldown;
+        game.process_message(emote_from(0, 1));
+        assert_eq!(game.emotes.len(), 1);
+
+        game.tick = cooldown + game.config.ticks(2);
+        game.process_message(emote_from(0, 1));
+        assert_eq!(game.emotes.len(), 2);
+
+        assert!(game.moderate(Moderation::SlowMode(0)));
+        game.tick += cooldown;
+        game.process_message(emote_from(0, 1));
+        assert_eq!(game.emotes.len(), 3);
+
+        return Ok(());
+    }
+
+    fn opt_in(id: u8, hz: u8) -> ConnectionMessage {
+        return ConnectionMessage::Msg((first_key(id), Ok(ServerMessage::new(0, server::Message::DebugOptIn(hz)))));
+    }
+
+    // runs a second worth of telemetry ticks, returns what the client got
+    async fn telemetry_for_a_second<const P: usize>(
+        game: &mut Game<P>,
+        client: &mut TestSocket,
+    ) -> Result<Vec<server::DebugTelemetry>> {
+        for _ in 0..game.config.tick_rate.hz() {
+            game.tick += 1;
+            game.send_telemetry().await;
+        }
+        game.broadcast(server::Message::Countdown(0)).await;
+
+        let mut telemetry = vec![];
+        loop {
+            match next_message(client).await?.msg {
+                server::Message::DebugTelemetry(t) => telemetry.push(t),
+                server::Message::Countdown(0) => return Ok(telemetry),
+                msg => panic!("expected DebugTelemetry, got {:?}", msg),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debug_telemetry_needs_the_server_flag() -> Result<()> {
+        let ranked = GameConfig {
+            debug_telemetry: true,
+            ranked: true,
+            ..GameConfig::default()
+        };
+        for config in [GameConfig::default(), ranked] {
+            let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), config, no_filter())?;
+            let (player, mut client) = test_player(0, (100, 100)).await?;
+            game.insert_player(player);
+
+            game.process_message(opt_in(0, 1));
+            assert!(telemetry_for_a_second(&mut game, &mut client).await?.is_empty());
+        }
+
+        let config = GameConfig {
+            debug_telemetry: true,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), config, no_filter())?;
+        let (player, mut client) = test_player(0, (100, 100)).await?;
+        let (other, mut other_client) = test_player(1, (100, 101)).await?;
+        game.insert_player(player);
+        game.insert_player(other);
+
+        game.process_message(opt_in(0, 1));
+        let press = ServerMessage::new(7, server::Message::key_press(b'j', 0));
+        game.process_message(ConnectionMessage::Msg((first_key(0), Ok(press))));
+
+        let telemetry = telemetry_for_a_second(&mut game, &mut client).await?;
+        assert_eq!(telemetry.len(), 1);
+        assert_eq!(telemetry[0].last_input_seq, 7);
+        assert_eq!(telemetry[0].position, game.players.get(0).map(|p| p.position).unwrap_or_default());
+        // only to whoever asked
+        assert!(telemetry_for_a_second(&mut game, &mut other_client).await?.is_empty());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_debug_telemetry_rate_is_capped() -> Result<()> {
+        let config = GameConfig {
+            debug_telemetry: true,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(1)), config, no_filter())?;
+        let (player, mut client) = test_player(0, (100, 100)).await?;
+        game.insert_player(player);
+
+        game.process_message(opt_in(0, u8::MAX));
+        let telemetry = telemetry_for_a_second(&mut game, &mut client).await?;
+        assert_eq!(telemetry.len(), MAX_TELEMETRY_HZ as usize);
+
+        game.process_message(opt_in(0, 0));
+        assert!(telemetry_for_a_second(&mut game, &mut client).await?.is_empty());
+
+        return Ok(());
+    }
+
+    // blocks "run", counts every call
+    #[derive(Debug)]
+    struct CountingFilter(std::sync::atomic::AtomicUsize);
+
+    impl EmoteFilter for CountingFilter {
+        fn allow(&self, _player_id: u8, text: &str) -> bool {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            return text != "run";
+        }
+    }
+
+    #[tokio::test]
+    async fn test_emote_filter_sees_every_emote_once() -> Result<()> {
+        let filter = Arc::new(CountingFilter(std::sync::atomic::AtomicUsize::new(0)));
+        let mut game = Game::<4>::new(0, 0, Arc::new(AtomicU8::new(2)), GameConfig::default(), filter.clone())?;
+        let (player, _client) = test_player(0, (100, 100)).await?;
+        game.insert_player(player);
+        let (player, _other) = test_player(1, (100, 100)).await?;
+        game.insert_player(player);
+        let calls = || filter.0.load(std::sync::atomic::Ordering::SeqCst);
+
+        game.process_message(emote_from(0, 1));
+        assert_eq!(calls(), 1);
+
+        // the cooldown turns it away first
+        game.process_message(emote_from(0, 1));
+        assert_eq!(calls(), 1);
+
+        let run = EMOTES.iter().position(|e| *e == "run").expect("run is an emote") as u8;
+        game.process_message(emote_from(1, run));
+        assert_eq!(calls(), 2);
+        assert_eq!(game.emotes.len(), 1);
+
+        // muted emotes are filtered the same
+        game.moderate(Moderation::Mute(1));
+        game.tick += game.config.emote_cooldown_ticks;
+        game.process_message(emote_from(1, 0));
+        assert_eq!(calls(), 3);
+        assert_eq!(game.emotes.len(), 2);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_serialize_time_recorded_after_broadcast() -> Result<()> {
+        let game_id = 9_002;
+        let mut game = Game::<4>::new(0, game_id, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        let (player, _client) = test_player(0, (1, 1)).await?;
+        game.insert_player(player);
+        game.series = metrics().game_started(game_id, GameConfig::default().tick_rate);
+
+        assert_eq!(game.record_serialize_time(), std::time::Duration::ZERO);
+        game.broadcast_snapshots().await;
+        assert!(game.record_serialize_time() > std::time::Duration::ZERO);
+
+        let rendered = metrics().render();
+        let total = rendered
+            .lines()
+            .find_map(|line| line.strip_prefix("vim_royale_serialize_ns_total{game_id=\"9002\"} "))
+            .and_then(|value| value.parse::<u128>().ok());
+        assert!(total.is_some_and(|total| total > 0));
+
+        metrics().game_ended(game_id);
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_scripted_game_logs_structured_events() -> Result<()> {
+        let lines = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let captured = lines.clone();
+        let logger = Logger::new(
+            Filter::parse("trace").expect("valid filter"),
+            true,
+            Box::new(move |line| captured.lock().expect("not poisoned").push(line.to_string())),
+        );
+        // current thread runtime, every spawned task runs with this subscriber
+        let _guard = tracing::subscriber::set_default(logger);
+
+        let config = GameConfig {
+            min_players: 1,
+            max_ticks: Some(5),
+            ..GameConfig::default()
+        };
+        let (manager_tx, mut manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 77, epoch: 3 };
+        tokio::spawn(game_run(5, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        assert!(matches!(complete_handshake(&mut client).await?.msg, server::Message::PlayerStart(_)));
+
+        while !matches!(manager_rx.recv().await, Some(GameMessage::Close(_)) | None) {}
+        drop(client);
+
+        let event = |message: &str| -> Option<serde_json::Value> {
+            return lines
+                .lock()
+                .expect("not poisoned")
+                .iter()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("json line"))
+                .find(|line| line["message"] == message);
+        };
+        let game_span = serde_json::json!({"name": "game", "game_id": 77, "epoch": 3, "seed": 5});
+
+        let started = event("new game started").expect("game started");
+        assert_eq!(started["spans"], serde_json::json!([game_span]));
+
+        let synced = event("player synced, creating player").expect("player synced");
+        assert_eq!(synced["fields"]["player_id"], 0);
+        assert_eq!(synced["spans"], serde_json::json!([game_span]));
+
+        let live = event("started with players").expect("game went live");
+        assert_eq!((live["level"].as_str(), &live["fields"]["started"]), (Some("WARN"), &serde_json::json!(1)));
+
+        let completed = event("game completed").expect("game completed");
+        assert_eq!(completed["fields"]["short_handed"], false);
+
+        // the connection span sits under the game that spawned it
+        let mut closed = None;
+        for _ in 0..100 {
+            closed = event("connection closed").filter(|line| line["spans"].as_array().is_some_and(|s| s.len() == 2));
+            if closed.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let closed = closed.expect("connection closed");
+        assert_eq!(closed["spans"], serde_json::json!([game_span, {"name": "connection", "player_id": 0}]));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_following_spectator_snapshots_center_on_target() -> Result<()> {
+        let config = GameConfig {
+            full_snapshot_players: 0,
+            ..GameConfig::default()
+        };
+        let mut game = Game::<8>::new(0, 0, Arc::new(AtomicU8::new(0)), config, no_filter())?;
+        let mut clients = vec![];
+        for (id, position) in [(0, 0), (200, 200), (210, 195)].into_iter().enumerate() {
+            let (player, client) = test_player(id as u8, position).await?;
+            game.insert_player(player);
+            clients.push(client);
+        }
+
+        let (server_socket, mut spectator) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        game.add_spectator(stream, sink).await?;
+        assert!(matches!(next_message(&mut spectator).await?.msg, server::Message::SpectatorStart(_)));
+
+        let range = game.config.entity_range as usize;
+        game.handle_game_message(GameMessage::Follow(0, range)).await;
+        match next_message(&mut spectator).await?.msg {
+            server::Message::Following(following) => assert_eq!(following.entity_id, range),
+            msg => panic!("expected Following, got {:?}", msg),
+        }
+
+        let entity_ids = |msg: server::Message| -> Vec<usize> {
+            return match msg {
+                server::Message::Snapshot(snapshot) => snapshot.entities.iter().map(|e| e.entity_id).collect(),
+                msg => panic!("expected Snapshot, got {:?}", msg),
+            };
+        };
+
+        game.broadcast_snapshots().await;
+        assert_eq!(entity_ids(next_message(&mut spectator).await?.msg), vec![range, 2 * range]);
+
+        // the target is gone, the spectator moves on to whoever was closest
+        game.remove_player(1);
+        game.broadcast_snapshots().await;
+        match next_message(&mut spectator).await?.msg {
+            server::Message::FollowChanged(changed) => assert_eq!(changed.new_target, Some(2 * range)),
+            msg => panic!("expected FollowChanged, got {:?}", msg),
+        }
+        assert_eq!(entity_ids(next_message(&mut spectator).await?.msg), vec![2 * range]);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_target_migrates_spectator() -> Result<()> {
+        let mut game = Game::<8>::new(0, 0, Arc::new(AtomicU8::new(3)), GameConfig::default(), no_filter())?;
+        let mut clients = vec![];
+        for (id, position) in [(50, 50), (300, 300), (320, 310)].into_iter().enumerate() {
+            let (player, client) = test_player(id as u8, position).await?;
+            game.insert_player(player);
+            clients.push(client);
+        }
+
+        let (server_socket, mut spectator) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        game.add_spectator(stream, sink).await?;
+        let range = game.config.entity_range as usize;
+        game.handle_game_message(GameMessage::Follow(0, range)).await;
+        assert!(matches!(next_message(&mut spectator).await?.msg, server::Message::SpectatorStart(_)));
+        assert!(matches!(next_message(&mut spectator).await?.msg, server::Message::Following(_)));
+
+        // out of the game, player 2 was right next to them
+        game.drop_player(1).await;
+        game.broadcast_snapshots().await;
+        match next_message(&mut spectator).await?.msg {
+            server::Message::FollowChanged(changed) => assert_eq!(changed.new_target, Some(2 * range)),
+            msg => panic!("expected FollowChanged, got {:?}", msg),
+        }
+        assert!(matches!(next_message(&mut spectator).await?.msg, server::Message::Snapshot(_)));
+        assert_eq!(game.spectators[0].following, Some(2));
+
+        // nobody left to follow, overhead it is
+        game.drop_player(0).await;
+        game.drop_player(2).await;
+        game.broadcast_snapshots().await;
+        match next_message(&mut spectator).await?.msg {
+            server::Message::FollowChanged(changed) => assert_eq!(changed.new_target, None),
+            msg => panic!("expected FollowChanged, got {:?}", msg),
+        }
+        assert_eq!(game.spectators[0].following, None);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_bots_target_the_nearest_player_in_view() -> Result<()> {
+        let mut game = Game::<8>::new(5, 0, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        let mut clients = vec![];
+        // the bot at 100,100, the others 3, 2 and 7 tiles away and one out of view
+        let positions = [(100, 100), (103, 101), (98, 98), (100, 107), (100, 100 + crate::interest::VIEW_DISTANCE + 1)];
+        for (id, position) in positions.into_iter().enumerate() {
+            let (player, client) = test_player(id as u8, position).await?;
+            game.insert_player(player);
+            clients.push(client);
+        }
+        game.bots.push(crate::bot::Bot::new(0, 0));
+
+        assert_eq!(game.nearest_threat(0), Some(2));
+        assert_eq!(game.nearest_threat(1), None, "only bots have threats");
+
+        game.remove_player(2);
+        assert_eq!(game.nearest_threat(0), Some(1));
+        game.remove_player(1);
+        game.remove_player(3);
+        assert_eq!(game.nearest_threat(0), None, "4 is out of view");
+
+        // and it is who the bot walks towards
+        game.insert_player(test_player(3, (100, 107)).await?.0);
+        let msgs = game.bot_inputs();
+        assert!(matches!(
+            &msgs[..],
+            [ConnectionMessage::Msg((key, Ok(msg)))] if key.id == 0 && msg.msg == server::Message::key_press(b'j', 0)
+        ));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_dump_matches_fixture() -> Result<()> {
+        let mut game = Game::<4>::new(1337, 6, Arc::new(AtomicU8::new(0)), GameConfig::default(), no_filter())?;
+        game.tick = 40;
+        let mut clients = vec![];
+        for (id, position) in [(10, 12), (20, 22)].into_iter().enumerate() {
+            let (mut player, client) = test_player(id as u8, position).await?;
+            player.clock_diff = -3;
+            game.insert_player(player);
+            clients.push(client);
+        }
+        game.queue_emote(1, 2);
+        game.bots.push(crate::bot::Bot::new(1, 0));
+
+        let (server_socket, _spectator) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        game.add_spectator(stream, sink).await?;
+        game.follow(0, 500).await;
+
+        // only what's listed here makes it into a dump, nothing else the game holds
+        let fixture = serde_json::json!({
+            "game_id": 6,
+            "seed": 1337,
+            "state": "Lobby",
+            "tick": 40,
+            "zone": {"center": [128, 128], "radius": 128},
+            "players": [
+                {
+                    "player_id": 0, "entity_id": 0, "name": "player0", "position": [10, 12], "bot": false,
+                    "clock_diff": -3, "clock_sync_pending": false, "move_budget": 0,
+                    "last_emote": null, "emote_ready_at": null,
+                },
+                {
+                    "player_id": 1, "entity_id": 500, "name": "player1", "position": [20, 22], "bot": true,
+                    "clock_diff": -3, "clock_sync_pending": false, "move_budget": 0,
+                    "last_emote": 40, "emote_ready_at": 100,
+                },
+            ],
+            "spectators": [{"id": 0, "following": 1}],
+            "pending_handshakes": 0,
+            "queued_messages": 0,
+            "short_handed": false,
+        });
+        let dumped: serde_json::Value = serde_json::from_slice(&serde_json::to_vec(&game.dump())?)?;
+        assert_eq!(dumped, fixture);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_traffic_counted_per_message_type() -> Result<()> {
+        // other tests run games too, this one keeps to its own id
+        let mut game = Game::<4>::new(0, 9_002, Arc::new(AtomicU8::new(1)), GameConfig::default(), no_filter())?;
+        game.series = metrics().game_started(9_002, GameConfig::default().tick_rate);
+        let (player, _client) = test_player(0, (100, 100)).await?;
+        let key = game.insert_player(player);
+
+        let (server_socket, mut input) = ws_pair().await?;
+        let (_sink, stream) = server_socket.split();
+        spawn_player_stream(&TokioExecutor, key, stream, SerializationType::Deku, game.tx.clone(), game.inbound.clone());
+
+        let emote = server::Message::Emote(server::Emote { from: 0, emote_id: 1 });
+        let script = [
+            server::Message::key_press(b'l', 0),
+            emote.clone(),
+            server::Message::key_press(b'l', 0),
+            server::Message::clock_response(12),
+            emote,
+            server::Message::key_press(b'h', 0),
+        ];
+        for msg in script {
+            input.send(tungstenite::Message::Binary(ServerMessage::new(0, msg).serialize()?)).await?;
+        }
+        // counted before they are forwarded
+        for _ in 0..6 {
+            game.rx.recv().await;
+        }
+
+        game.broadcast(server::Message::Countdown(3)).await;
+        game.broadcast(server::Message::Countdown(2)).await;
+        game.broadcast(server::Message::ZoneUpdate(game.zone.clone())).await;
+        game.record_traffic();
+        // nothing new since, nothing counted twice
+        game.record_traffic();
+
+        assert_eq!(
+            game.traffic.to_string(),
+            "in: clock_sync_response=1 key_press_event=3 emote=2 out: countdown=2 zone_update=1"
+        );
+        let scrape = metrics().render();
+        assert!(scrape.contains(
+            "vim_royale_game_messages_total{game_id=\"9002\",direction=\"in\",type=\"key_press_event\"} 3"
+        ));
+        assert!(scrape.contains(
+            "vim_royale_game_messages_total{game_id=\"9002\",direction=\"out\",type=\"countdown\"} 2"
+        ));
+        metrics().game_ended(9_002);
+
+        return Ok(());
+    }
+
+    // the client's side of the join handshake, answers every clock sync
+    async fn answer_clock_syncs(mut client: MemorySocket, samples: usize) -> Result<MemorySocket> {
+        for _ in 0..samples {
+            let msg = next_message(&mut client).await?;
+            assert!(matches!(msg.msg, server::Message::ClockSyncRequest(_)));
+            let resp = ServerMessage::new(0, server::Message::clock_response(0)).serialize()?;
+            client.send(tungstenite::Message::Binary(resp)).await?;
+        }
+
+        return Ok(client);
+    }
+
+    // connects a player over an in memory connection and waits until they
+    // have their slot
+    async fn join_in_memory<const P: usize>(game: &mut Game<P, Memory>, name: &str) -> Result<MemorySocket> {
+        let (server_socket, client) = memory_pair(64);
+        let (sink, stream) = server_socket.split();
+        game.add_connection(stream, sink, WHO_AM_I_CLIENT, Some(name.to_string()), None).await?;
+
+        let client = tokio::spawn(answer_clock_syncs(client, game.config.clock_sync_samples));
+        game.finish_handshakes().await;
+
+        return client.await?;
+    }
+
+    #[tokio::test]
+    async fn test_add_player_in_memory() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4, Memory>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+
+        let _ada = join_in_memory(&mut game, "ada").await?;
+        let _ada_too = join_in_memory(&mut game, "ada").await?;
+
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert!(game.handshaking.is_empty());
+        let names: Vec<&str> = game.players.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["ada", "ada-2"]);
+        assert_eq!(game.events.all().iter().filter(|e| e.kind == EventKind::Join).count(), 2);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_start_game_in_memory() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4, Memory>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+
+        let mut ada = join_in_memory(&mut game, "ada").await?;
+        let bob = join_in_memory(&mut game, "bob").await?;
+        // bob leaves before the start, their start can't be sent
+        drop(bob);
+
+        assert_eq!(game.start_game().await?, 1);
+        assert_ne!(game.state.state(), GameState::Lobby);
+        assert!(!game.players.contains(1));
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        match next_message(&mut ada).await?.msg {
+            server::Message::PlayerStart(start) => assert_eq!(start.seed, game.seed),
+            msg => panic!("expected PlayerStart, got {:?}", msg),
+        }
+        assert!(matches!(next_message(&mut ada).await?.msg, server::Message::ZoneUpdate(_)));
+        // bob missed the start, everyone gets the roster without them
+        assert!(matches!(next_message(&mut ada).await?.msg, server::Message::Roster(_)));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_join_after_a_leave_takes_the_free_slot() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4, Memory>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+
+        let ada = join_in_memory(&mut game, "ada").await?;
+        let _bob = join_in_memory(&mut game, "bob").await?;
+        drop(ada);
+        let msg = game.rx.recv().await.expect("game holds a sender");
+        game.process_message(msg);
+
+        // counting up from player_count would have put carol on bob
+        let _carol = join_in_memory(&mut game, "carol").await?;
+        let names: Vec<(u8, &str)> = game.players.iter().map(|p| (p.id, p.name.as_str())).collect();
+        assert_eq!(names, vec![(0, "carol"), (1, "bob")]);
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_in_memory_frees_the_slot() -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let mut game = Game::<4, Memory>::new(0, 0, player_count.clone(), GameConfig::default(), no_filter())?;
+
+        let ada = join_in_memory(&mut game, "ada").await?;
+        drop(ada);
+
+        // the stream task tells the game once it sees the connection end
+        let msg = game.rx.recv().await.expect("game holds a sender");
+        assert!(matches!(msg, ConnectionMessage::Close(key) if key.id == 0));
+        game.process_message(msg);
+
+        assert!(!game.players.contains(0));
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 0);
+        let last = game.events.all().pop().expect("events recorded");
+        assert_eq!((last.kind, last.player_id), (EventKind::Leave, Some(0)));
+
+        return Ok(());
+    }
+
+    // a client that plays along with whatever chaos does to its connection,
+    // answers clock syncs and presses a key for every other frame until it
+    // has read frames of them or the connection ends
+    async fn chaotic_client(mut client: ChaosSocket<MemorySocket>, frames: usize) {
+        for _ in 0..frames {
+            let reply = match client.next().await {
+                Some(Ok(tungstenite::Message::Binary(msg))) => match ServerMessage::deserialize(&msg).map(|msg| msg.msg) {
+                    Ok(server::Message::ClockSyncRequest(_)) => server::Message::clock_response(0),
+                    Ok(_) => server::Message::KeyPressEvent(server::KeyPress { key: b'j', state: 0 }),
+                    // like a real client, what it can't read it ignores
+                    Err(_) => continue,
+                },
+                Some(Ok(_)) => continue,
+                _ => return,
+            };
+
+            let reply = ServerMessage::new(0, reply).serialize().expect("serializes");
+            if client.send(tungstenite::Message::Binary(reply)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    // two players join, play and leave over connections with chaos. nobody
+    // may panic, every slot has to be given back and no connection may be
+    // closed twice
+    async fn survive(chaos: ChaosConfig) -> Result<()> {
+        let player_count = Arc::new(AtomicU8::new(0));
+        let config = GameConfig {
+            handshake_timeout: std::time::Duration::from_millis(200),
+            ..GameConfig::default()
+        };
+        let mut game = Game::<4, Chaos>::new(0, 0, player_count.clone(), config, no_filter())?;
+
+        let mut clients = vec![];
+        for (i, name) in ["ada", "bob"].into_iter().enumerate() {
+            let seed = chaos.seed.wrapping_add(2 * i as u32);
+            let (server_socket, client) = chaos_pair(64, ChaosConfig { seed, ..chaos.clone() });
+            let (sink, stream) = server_socket.split();
+            game.add_connection(stream, sink, WHO_AM_I_CLIENT, Some(name.to_string()), None).await?;
+            clients.push(tokio::spawn(chaotic_client(client, 40)));
+        }
+        game.finish_handshakes().await;
+        game.start_game().await?;
+
+        let mut closes = [0; 4];
+        let mut drain = |game: &mut Game<4, Chaos>| {
+            while let Ok(msg) = game.rx.try_recv() {
+                if let ConnectionMessage::Close(key) = msg {
+                    closes[key.id as usize] += 1;
+                }
+                game.process_message(msg);
+            }
+        };
+
+        for _ in 0..1000 {
+            drain(&mut game);
+            if game.players.is_empty() {
+                break;
+            }
+            game.broadcast_snapshots().await;
+            game.drop_stalled_players().await;
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        // a second close would come right after the first
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        drain(&mut game);
+
+        for client in clients {
+            client.abort();
+            if let Err(e) = client.await {
+                assert!(e.is_cancelled(), "client panicked {:?}", e);
+            }
+        }
+
+        assert!(game.players.is_empty(), "{:?}", chaos);
+        assert!(game.handshaking.is_empty());
+        assert_eq!(player_count.load(std::sync::atomic::Ordering::Relaxed), 0, "{:?}", chaos);
+        assert!(closes.iter().all(|&count| count <= 1), "{:?} closed {:?}", chaos, closes);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_game_survives_latency() -> Result<()> {
+        return survive(ChaosConfig {
+            latency: std::time::Duration::from_millis(2),
+            jitter: std::time::Duration::from_millis(3),
+            ..ChaosConfig::default()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_game_survives_dropped_frames() -> Result<()> {
+        return survive(ChaosConfig {
+            seed: 3,
+            drop: 0.5,
+            ..ChaosConfig::default()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_game_survives_duplicated_frames() -> Result<()> {
+        return survive(ChaosConfig {
+            seed: 5,
+            duplicate: 0.5,
+            ..ChaosConfig::default()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_game_survives_truncated_frames() -> Result<()> {
+        for seed in 0..8 {
+            survive(ChaosConfig {
+                seed,
+                truncate_within: Some(30),
+                ..ChaosConfig::default()
+            })
+            .await?;
+        }
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_game_survives_killed_connections() -> Result<()> {
+        // the first 40 frames cover the handshake as well as the game
+        for seed in 0..8 {
+            survive(ChaosConfig {
+                seed,
+                kill_within: Some(40),
+                ..ChaosConfig::default()
+            })
+            .await?;
+        }
+
+        return Ok(());
+    }
 }
@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{atomic::AtomicU8, Arc};
+use std::time::Duration;
+
+use anyhow::Result;
+use encoding::server::{self, ServerMessage, WHO_AM_I_CLIENT};
+use futures::{FutureExt, SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite;
+
+use super::{Game, PLAYER_COUNT};
+use crate::{
+    clock::{Clock, MockClock},
+    executor::{tokio_executor, Executor},
+    connection::{ConnectionMessage, SerializationType},
+    game_config::GameConfig,
+    moderation::WordList,
+    transport::{memory_pair, Memory, MemorySocket},
+};
+
+// frames each way per player, the harness drains them after every tick
+const SIM_BUFFER: usize = 1024;
+
+/// a Game on a MockClock and in memory connections, driven one tick at a
+/// time. inputs are scripted for the tick they go in on and everything the
+/// game sends is kept per player, so a run plays out the same every time
+/// however fast the machine is. outside of tests only with the bench
+/// feature, for game/benches.
+pub struct SimHarness {
+    pub(crate) game: Game<PLAYER_COUNT, Memory>,
+    pub clock: Arc<MockClock>,
+    clients: HashMap<u8, MemorySocket>,
+    // tick they go in on to (player id, input)
+    script: BTreeMap<u128, Vec<(u8, server::Message)>>,
+    outbound: HashMap<u8, Vec<server::Message>>,
+}
+
+impl SimHarness {
+    pub fn new(seed: u32, config: GameConfig) -> Self {
+        return SimHarness::with_executor(seed, config, tokio_executor());
+    }
+
+    /// a harness whose game and clients run on executor instead of tokio.
+    pub fn with_executor(seed: u32, config: GameConfig, executor: Arc<dyn Executor>) -> Self {
+        let clock = Arc::new(MockClock::new());
+        let mut game = Game::new(seed, 0, Arc::new(AtomicU8::new(0)), config, Arc::new(WordList::default())).expect("seed makes a playable map");
+        game.clock = clock.clone();
+        game.executor = executor;
+        game.created = clock.now();
+        game.last_tick = clock.now();
+
+        return Self {
+            game,
+            clock,
+            clients: HashMap::new(),
+            script: BTreeMap::new(),
+            outbound: HashMap::new(),
+        };
+    }
+
+    /// a player through the whole handshake, returns their id.
+    pub async fn join(&mut self, name: &str) -> Result<u8> {
+        let (server_socket, mut client) = memory_pair(SIM_BUFFER);
+        let (sink, stream) = server_socket.split();
+        let before: Vec<u8> = self.game.players.iter().map(|p| p.id).collect();
+        self.game.add_connection(stream, sink, WHO_AM_I_CLIENT, Some(name.to_string()), None).await?;
+
+        let samples = self.game.config.clock_sync_samples;
+        let (answering, answered) = async move {
+            for _ in 0..samples {
+                client.next().await.ok_or_else(|| anyhow::anyhow!("closed during the clock sync"))??;
+                let resp = ServerMessage::new(0, server::Message::clock_response(0)).serialize()?;
+                client.send(tungstenite::Message::Binary(resp)).await?;
+            }
+            return Ok::<MemorySocket, anyhow::Error>(client);
+        }
+        .remote_handle();
+        self.game.executor.spawn(Box::pin(answering));
+        self.game.finish_handshakes().await;
+        let client = answered.await?;
+
+        let id = self
+            .game
+            .players
+            .iter()
+            .map(|p| p.id)
+            .find(|id| !before.contains(id))
+            .ok_or_else(|| anyhow::anyhow!("{} didn't get a slot", name))?;
+        self.clients.insert(id, client);
+
+        return Ok(id);
+    }
+
+    /// msg from player goes in on tick.
+    pub fn at(&mut self, tick: u128, player: u8, msg: server::Message) {
+        self.script.entry(tick).or_default().push((player, msg));
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.clock.advance(by);
+    }
+
+    /// what run_game does every lobby check, true once the game would start.
+    pub fn lobby_check(&mut self) -> bool {
+        self.game.last_tick = self.clock.now();
+        self.game.process_inbound();
+        self.game.update_lobby_timer(self.clock.now());
+
+        return self.game.is_ready();
+    }
+
+    pub async fn start(&mut self) -> Result<()> {
+        self.game.start_game().await?;
+        self.collect_outbound();
+
+        return Ok(());
+    }
+
+    /// runs ticks game ticks, one tick length of mock time apart.
+    pub async fn run_ticks(&mut self, ticks: u128) {
+        let tick_length = Duration::from_micros(self.game.config.tick_micros() as u64);
+        for _ in 0..ticks {
+            self.clock.advance(tick_length);
+            let tick = self.game.tick + 1;
+            for (player, msg) in self.script.remove(&tick).unwrap_or_default() {
+                if let Some(msg) = self.input(player, msg) {
+                    _ = self.game.tx.try_send(msg);
+                }
+            }
+
+            self.game.last_tick = self.clock.now();
+            self.game.step().await;
+            self.collect_outbound();
+        }
+    }
+
+    // msg the way player's connection would send it, None once they are gone
+    fn input(&self, player: u8, msg: server::Message) -> Option<ConnectionMessage> {
+        let key = self.game.players.key(player)?;
+        return Some(ConnectionMessage::Msg((key, Ok(ServerMessage::new(0, msg)))));
+    }
+
+    /// msg from player into the game's inbound channel right away, the way a
+    /// connection sends it. false when the channel is full or player isn't in
+    /// the game.
+    pub fn queue(&mut self, player: u8, msg: server::Message) -> bool {
+        let Some(msg) = self.input(player, msg) else {
+            return false;
+        };
+        return self.game.tx.try_send(msg).is_ok();
+    }
+
+    /// what a tick does with the inbound channel and nothing else, returns
+    /// how many messages it took.
+    pub fn process_inbound(&mut self) -> usize {
+        return self.game.process_inbound();
+    }
+
+    /// one tick with inputs already queued up for it, more than the game's
+    /// channel would hold. what the game sends is thrown away unread, see
+    /// discard_outbound.
+    pub async fn tick_with(&mut self, inputs: &[(u8, server::Message)]) {
+        self.clock.advance(Duration::from_micros(self.game.config.tick_micros() as u64));
+        for (player, msg) in inputs {
+            if let Some(msg) = self.input(*player, msg.clone()) {
+                self.game.process_message(msg);
+            }
+        }
+
+        self.game.last_tick = self.clock.now();
+        self.game.step().await;
+        self.discard_outbound();
+    }
+
+    /// player's connection takes ser_type from now on. take_outbound only
+    /// reads binary, this is for benches that discard what's sent.
+    pub fn serialize_as(&mut self, player: u8, ser_type: SerializationType) {
+        if let Some(player) = self.game.players.get_mut(player) {
+            player.sink.ser_type = ser_type;
+        }
+    }
+
+    /// a tick's snapshots and nothing else of it, returns how long encoding
+    /// them took. what the game sends is left on the connections.
+    pub async fn broadcast_snapshots(&mut self) -> Duration {
+        self.game.broadcast_snapshots().await;
+        return self.game.record_serialize_time();
+    }
+
+    /// empties every client's connection without decoding what's in it.
+    pub fn discard_outbound(&mut self) {
+        for client in self.clients.values_mut() {
+            while let Some(Some(_)) = client.next().now_or_never() {}
+        }
+    }
+
+    /// everything the game sent player since the last call, event batches
+    /// taken apart in order the way a client handles them.
+    pub fn take_outbound(&mut self, player: u8) -> Vec<server::Message> {
+        return self.outbound.remove(&player).unwrap_or_default();
+    }
+
+    fn collect_outbound(&mut self) {
+        for (id, client) in self.clients.iter_mut() {
+            while let Some(Some(Ok(tungstenite::Message::Binary(bytes)))) = client.next().now_or_never() {
+                let outbound = self.outbound.entry(*id).or_default();
+                match ServerMessage::deserialize(&bytes).map(|msg| msg.msg) {
+                    Ok(server::Message::EventBatch(batch)) => {
+                        outbound.extend(batch.events.into_iter().map(|event| event.into_message()));
+                    }
+                    Ok(msg) => outbound.push(msg),
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use encoding::server;
+
+    use super::SimHarness;
+    use crate::{game_config::GameConfig, game_state::GameState, movement::{apply_step, key_to_step}};
+
+    #[tokio::test]
+    async fn test_lobby_wait_runs_out_on_mock_time() -> Result<()> {
+        let config = GameConfig {
+            min_players: 4,
+            max_lobby_wait: Some(Duration::from_secs(30)),
+            ..GameConfig::default()
+        };
+        let mut sim = SimHarness::new(5, config);
+        sim.join("ada").await?;
+        assert!(!sim.lobby_check());
+
+        sim.advance(Duration::from_secs(29));
+        assert!(!sim.lobby_check());
+        assert_eq!(sim.game.status().required_players, 4);
+
+        // short handed, but one player is still not enough without bot fill
+        sim.advance(Duration::from_secs(1));
+        assert!(!sim.lobby_check());
+        assert_eq!(sim.game.status().required_players, 2);
+
+        sim.join("bob").await?;
+        assert!(sim.lobby_check());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_warmup_phases_land_on_their_ticks() -> Result<()> {
+        let config = GameConfig {
+            warmup_ticks: 240,
+            ..GameConfig::default()
+        };
+        let mut sim = SimHarness::new(5, config);
+        let ada = sim.join("ada").await?;
+        sim.start().await?;
+        sim.take_outbound(ada);
+        assert_eq!(sim.game.state.state(), GameState::WarmUp);
+
+        let mut countdowns = vec![];
+        for _ in 0..240 {
+            sim.run_ticks(1).await;
+            for msg in sim.take_outbound(ada) {
+                if let server::Message::Countdown(seconds) = msg {
+                    countdowns.push((sim.game.tick, seconds));
+                }
+            }
+        }
+
+        assert_eq!(countdowns, vec![(60, 3), (120, 2), (180, 1), (240, 0)]);
+        assert_eq!(sim.game.state.state(), GameState::Live);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_scripted_move_lands_on_its_tick() -> Result<()> {
+        let mut sim = SimHarness::new(5, GameConfig::default());
+        let ada = sim.join("ada").await?;
+        sim.start().await?;
+
+        let start = sim.game.players.get(ada).map(|p| p.position).expect("ada has a slot");
+        let key = *b"hjkl"
+            .iter()
+            .find(|&&key| {
+                let to = key_to_step(key).and_then(|step| apply_step(start, step));
+                return to.is_some_and(|(x, y)| sim.game.map.is_walkable(x as usize, y as usize));
+            })
+            .expect("the spawn has a walkable neighbour");
+        sim.at(3, ada, server::Message::key_press(key, 0));
+
+        let mut positions = vec![];
+        for _ in 0..4 {
+            sim.run_ticks(1).await;
+            positions.push(sim.game.players.get(ada).map(|p| p.position));
+        }
+
+        let moved = key_to_step(key).and_then(|step| apply_step(start, step));
+        assert_eq!(positions, vec![Some(start), Some(start), moved, moved]);
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,179 @@
+// Regression tests for the game rules. Every tests/state/<feature>/<name>.script
+// runs on the sim harness and the game's dump after its last tick has to
+// match <name>.json next to it, so a failing snapshot names the system that
+// changed. When a change is on purpose the snapshots are written again with
+//
+// UPDATE_STATE_SNAPSHOTS=1 cargo test -p game state_snapshots
+//
+// and the diff goes in the same commit.
+//
+// script lines, # starts a comment:
+//   seed <n>              map seed, 26 by default
+//   warmup <ticks>        GameConfig::warmup_ticks, 0 by default
+//   players <name>...     joined in order before the game starts
+//   ticks <n>             how long the run is
+//   <tick> <name> press <keys>    one key press per key, in order
+//   <tick> <name> emote <id>
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use encoding::server;
+
+use super::sim::SimHarness;
+use crate::{dump::canonical_json, game_config::GameConfig};
+
+const STATE_DIR: &str = "tests/state";
+const UPDATE_ENV: &str = "UPDATE_STATE_SNAPSHOTS";
+
+#[derive(Debug, Default)]
+struct Script {
+    seed: u32,
+    warmup: u128,
+    players: Vec<String>,
+    ticks: u128,
+    // (tick, player name, input)
+    inputs: Vec<(u128, String, server::Message)>,
+}
+
+fn parse(text: &str) -> Result<Script> {
+    let mut script = Script {
+        seed: 26,
+        ..Script::default()
+    };
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let context = || format!("line {}: {}", number + 1, line);
+
+        match words.as_slice() {
+            [] => {}
+            ["seed", seed] => script.seed = seed.parse().with_context(context)?,
+            ["warmup", ticks] => script.warmup = ticks.parse().with_context(context)?,
+            ["ticks", ticks] => script.ticks = ticks.parse().with_context(context)?,
+            ["players", names @ ..] => script.players.extend(names.iter().map(|name| name.to_string())),
+            [tick, name, "press", keys] => {
+                let tick = tick.parse().with_context(context)?;
+                for key in keys.bytes() {
+                    script.inputs.push((tick, name.to_string(), server::Message::key_press(key, 0)));
+                }
+            }
+            [tick, name, "emote", id] => {
+                let emote = server::Emote {
+                    from: 0,
+                    emote_id: id.parse().with_context(context)?,
+                };
+                script.inputs.push((tick.parse().with_context(context)?, name.to_string(), server::Message::Emote(emote)));
+            }
+            _ => bail!("can't read {}", context()),
+        }
+    }
+
+    return Ok(script);
+}
+
+async fn run(script: &Script) -> Result<String> {
+    let config = GameConfig {
+        warmup_ticks: script.warmup,
+        ..GameConfig::default()
+    };
+    let mut sim = SimHarness::new(script.seed, config);
+
+    let mut ids = vec![];
+    for name in &script.players {
+        ids.push((name.as_str(), sim.join(name).await?));
+    }
+    for (tick, name, msg) in &script.inputs {
+        let (_, id) = ids
+            .iter()
+            .find(|(player, _)| player == name)
+            .ok_or_else(|| anyhow!("{} isn't in players", name))?;
+        sim.at(*tick, *id, msg.clone());
+    }
+
+    sim.start().await?;
+    sim.run_ticks(script.ticks).await;
+
+    // the clock sync offset is measured against the wall clock
+    let mut dump = sim.game.dump();
+    for player in &mut dump.players {
+        player.clock_diff = 0;
+    }
+
+    return canonical_json(&dump);
+}
+
+fn scripts(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut scripts = vec![];
+    for feature in std::fs::read_dir(dir)? {
+        for entry in std::fs::read_dir(feature?.path())? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "script") {
+                scripts.push(path);
+            }
+        }
+    }
+    scripts.sort();
+
+    return Ok(scripts);
+}
+
+#[tokio::test]
+async fn test_state_snapshots() -> Result<()> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(STATE_DIR);
+    let update = std::env::var_os(UPDATE_ENV).is_some();
+
+    let scripts = scripts(&dir)?;
+    assert!(!scripts.is_empty(), "no scripts under {}", dir.display());
+
+    let mut failed = vec![];
+    for path in scripts {
+        let name = path.strip_prefix(&dir)?.display().to_string();
+        let script = parse(&std::fs::read_to_string(&path)?).with_context(|| name.clone())?;
+        let state = run(&script).await.with_context(|| name.clone())?;
+
+        // the same script has to come out the same twice, or there is
+        // nothing to compare against
+        assert_eq!(state, run(&script).await?, "{} isn't deterministic", name);
+
+        let snapshot = path.with_extension("json");
+        if update {
+            std::fs::write(&snapshot, &state)?;
+            continue;
+        }
+
+        match std::fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == state => {}
+            Ok(expected) => failed.push(format!("{}\n--- expected\n{}\n--- got\n{}", name, expected, state)),
+            Err(_) => failed.push(format!("{} has no snapshot yet", name)),
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "state snapshots changed, rerun with {}=1 if that was the point:\n{}",
+        UPDATE_ENV,
+        failed.join("\n")
+    );
+
+    return Ok(());
+}
+
+#[test]
+fn test_script_parses() -> Result<()> {
+    let script = parse("seed 5 # the map\nplayers ada bob\nticks 20\n\n3 ada press hl\n4 bob emote 2\n")?;
+    assert_eq!((script.seed, script.warmup, script.ticks), (5, 0, 20));
+    assert_eq!(script.players, vec!["ada", "bob"]);
+    assert_eq!(
+        script.inputs,
+        vec![
+            (3, "ada".to_string(), server::Message::key_press(b'h', 0)),
+            (3, "ada".to_string(), server::Message::key_press(b'l', 0)),
+            (4, "bob".to_string(), server::Message::Emote(server::Emote { from: 0, emote_id: 2 })),
+        ]
+    );
+    assert!(parse("3 ada jump").is_err());
+
+    return Ok(());
+}
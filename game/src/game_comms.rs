@@ -1,34 +1,208 @@
-use tokio::sync::mpsc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::player::{PlayerWebSink, PlayerWebStream};
+use encoding::server;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    capture::CaptureDir,
+    drift::TickTiming,
+    events::{EventFilter, GameEvent},
+    game_state::GameState,
+    health::HealthReport,
+    moderation::{EmoteFilter, Moderation, WordList},
+    outcome::OutcomeSink,
+    send_stats::SendStats,
+    slots::Reservation,
+    standings::Placement,
+    transport::{Transport, WebSocket},
+};
+
+/// game ids can be handed out again once a game closes, the epoch tells the
+/// old and new game apart so late messages about the old one are ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GameKey {
+    pub id: u32,
+    pub epoch: u32,
+}
+
+// identifies a registered tournament player across games
+pub type PlayerToken = u64;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct GameResult {
+    // best first, players that left before the end can be missing
+    pub placements: Vec<PlayerToken>,
+    // every player that made it past the lobby by player id, best first
+    pub leaderboard: Vec<Placement>,
+    // GameConfig::region of the server the game ran on
+    pub region: server::Region,
+    // the game's event log as the game ended
+    pub events: Vec<GameEvent>,
+}
+
+/// what the manager hears about a game that panicked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrashReport {
+    pub message: String,
+    // the game's event log up to the panic
+    pub events: Vec<GameEvent>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameStatus {
+    pub game_id: u32,
+    pub state: GameState,
+    pub tick: u128,
+    // includes bots
+    pub player_count: usize,
+    pub bot_count: usize,
+    pub spectator_count: usize,
+    pub seed: u32,
+    pub uptime: Duration,
+    pub timing: TickTiming,
+    // players the lobby currently needs, drops once the max lobby wait is hit
+    pub required_players: usize,
+    // started with less than min_players
+    pub short_handed: bool,
+    // display names of everyone in a player slot, bots included
+    pub names: Vec<String>,
+    pub region: server::Region,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct InspectedPlayer {
+    pub player_id: u8,
+    pub entity_id: usize,
+    pub name: String,
+    pub position: (u16, u16),
+    pub bot: bool,
+    // from the last clock sync, the closest thing to a ping the server has
+    pub clock_diff: i64,
+    pub move_budget: u32,
+    // how sends to them have been going, see send_stats
+    pub send: SendStats,
+    // their emotes only go back to them
+    pub muted: bool,
+}
+
+/// everything an admin connection gets to see of a running game, sent as json.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct GameInspection {
+    pub game_id: u32,
+    pub state: GameState,
+    pub tick: u128,
+    pub zone: server::Zone,
+    pub roster: Vec<InspectedPlayer>,
+    pub spectators: usize,
+    // player messages waiting for the game loop, every player shares the queue
+    pub queued_messages: usize,
+    // ticks admins added to the emote cooldown
+    pub slow_mode_ticks: u128,
+}
+
+/// a connection the GameManager already read the handshake of.
+#[derive(Debug)]
+pub struct IncomingConnection<T: Transport = WebSocket> {
+    pub stream: T::Stream,
+    pub sink: T::Sink,
+    pub whoami: u8,
+    // already validated, None gets a default name
+    pub name: Option<String>,
+    // the game drops it once it took or turned away the connection
+    pub reservation: Option<Reservation>,
+    // the tournament player the connection joined as
+    pub token: Option<PlayerToken>,
+}
+
+impl<T: Transport> IncomingConnection<T> {
+    /// no name, reservation or token
+    pub fn new(stream: T::Stream, sink: T::Sink, whoami: u8) -> Self {
+        return IncomingConnection {
+            stream,
+            sink,
+            whoami,
+            name: None,
+            reservation: None,
+            token: None,
+        };
+    }
+}
 
 #[derive(Debug)]
-pub enum GameMessage {
-    Start,
-    Connection(PlayerWebStream, PlayerWebSink),
-    Close(usize),
+pub enum GameMessage<T: Transport = WebSocket> {
+    Start(GameKey),
+    Connection(IncomingConnection<T>),
+    Close(GameKey),
+    // sent before Close by games that finished properly, a Close without one is an abort
+    Result(GameKey, GameResult),
+    // the game panicked, sent before its Close
+    Crashed(GameKey, CrashReport),
+    // answered by the game from its own loop, at most a tick late
+    QueryStatus(oneshot::Sender<GameStatus>),
+    // same as QueryStatus, for admin connections
+    Inspect(oneshot::Sender<GameInspection>),
+    // the game's recent events that pass the filter, oldest first
+    Events(EventFilter, oneshot::Sender<Vec<GameEvent>>),
+    // writes a GameDump into the directory, the game never waits on the write
+    Dump(PathBuf),
+    // writes the game's RecoveryImage into the directory, replacing the last one
+    Snapshot(PathBuf),
+    // answered from the lobby and the running loop, a game that doesn't
+    // answer is judged by its last report
+    HealthCheck(oneshot::Sender<HealthReport>),
+    // debugging only, ignored unless GameConfig::admin_commands is set
+    AdminMove(u8, (u16, u16)),
+    // goes out to every player and spectator of the game
+    Announce(server::Announcement),
+    // from an admin session attached to the game, to one player or everyone.
+    // answers whether it reached them
+    AdminSay(Option<u8>, server::AdminMessage, oneshot::Sender<bool>),
+    // same as AdminSay, answers whether it applied
+    Moderate(Moderation, oneshot::Sender<bool>),
+    // (spectator id, entity id), the spectator only sees around that player from now on
+    Follow(u8, usize),
 }
 
-pub type GameSender = mpsc::Sender<GameMessage>;
-pub type GameReceiver = mpsc::Receiver<GameMessage>;
+pub type GameSender<T = WebSocket> = mpsc::Sender<GameMessage<T>>;
+pub type GameReceiver<T = WebSocket> = mpsc::Receiver<GameMessage<T>>;
 
-pub struct GameComms {
-    pub sender: GameSender,
-    pub receiver: GameReceiver,
+pub struct GameComms<T: Transport = WebSocket> {
+    pub sender: GameSender<T>,
+    pub receiver: GameReceiver<T>,
+    // where a game keeps its result when the manager is gone, see outcome
+    pub outcomes: Option<Arc<dyn OutcomeSink>>,
+    // players' connections are captured here, see capture
+    pub capture: Option<CaptureDir>,
+    pub emote_filter: Arc<dyn EmoteFilter>,
 }
 
-impl GameComms {
+impl<T: Transport> GameComms<T> {
     pub fn new() -> Self {
         let (sender, receiver) = mpsc::channel(10);
-        return Self { sender, receiver };
+        return Self {
+            sender,
+            receiver,
+            outcomes: None,
+            capture: None,
+            emote_filter: Arc::new(WordList::default()),
+        };
     }
 
-    pub fn with_sender(sender: GameSender) -> (Self, GameSender) {
+    pub fn with_sender(sender: GameSender<T>) -> (Self, GameSender<T>) {
         let (sender_receiver, receiver) = mpsc::channel(10);
-        return (Self { sender, receiver }, sender_receiver);
+        let comms = Self {
+            sender,
+            receiver,
+            outcomes: None,
+            capture: None,
+            emote_filter: Arc::new(WordList::default()),
+        };
+        return (comms, sender_receiver);
     }
 
-    pub fn link(&self, other: &mut GameComms) {
+    pub fn link(&self, other: &mut GameComms<T>) {
         other.sender = self.sender.clone();
     }
 }
@@ -0,0 +1,466 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use encoding::server::{region_label, Region, REGION_LENGTH};
+
+use crate::{
+    admin::AdminKeys,
+    connection::SerializationType,
+    game_thread::GameThread,
+    entity_ids::{EntityIdAllocator, ENTITY_ID_SPACE},
+    loot::LootTable,
+    moderation::{EmoteFilter, WordList},
+    movement::TILE_COST,
+    player::{check_sync_samples, MAX_CLOCK_SYNC_SAMPLES, MIN_CLOCK_SYNC_SAMPLES},
+    seed::SeedMode,
+    shaping::ShapeConfig,
+    tick_rate::TickRate,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    NoPlayers,
+    MaxPlayersOverCapacity { max_players: usize, capacity: usize },
+    MinPlayersOverMax { min_players: usize, max_players: usize },
+    ZeroEntityRange,
+    ZeroHandshakes,
+    ZeroMessagesPerTick,
+    NameLength(usize),
+    BadRegion,
+    ClockSyncSamples(usize),
+    EntityIdSpace,
+    ZeroDeadlineFloor,
+    Shaping,
+    Loot,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ConfigError::NoPlayers => write!(f, "max_players must be at least 1"),
+            ConfigError::MaxPlayersOverCapacity { max_players, capacity } => write!(
+                f,
+                "max_players {} is more than a game can hold ({})",
+                max_players, capacity
+            ),
+            ConfigError::MinPlayersOverMax { min_players, max_players } => write!(
+                f,
+                "min_players {} is more than max_players {}, the game could never start",
+                min_players, max_players
+            ),
+            ConfigError::ZeroEntityRange => write!(f, "entity_range must be at least 1"),
+            ConfigError::ZeroHandshakes => write!(f, "max_concurrent_handshakes must be at least 1"),
+            ConfigError::ZeroMessagesPerTick => write!(f, "max_messages_per_tick must be at least 1"),
+            ConfigError::NameLength(len) => write!(
+                f,
+                "max_name_length {} has to be between 1 and {}",
+                len,
+                u8::MAX
+            ),
+            ConfigError::BadRegion => write!(f, "region has to be printable ascii"),
+            ConfigError::ZeroDeadlineFloor => write!(f, "on_deadline floor must be at least 1"),
+            ConfigError::Shaping => write!(f, "network shaping is for debug builds only and never in ranked games"),
+            ConfigError::Loot => write!(f, "loot needs entries with some weight and no more items than item_ids"),
+            ConfigError::ClockSyncSamples(samples) => write!(
+                f,
+                "clock_sync_samples {} has to be between {} and {}",
+                samples, MIN_CLOCK_SYNC_SAMPLES, MAX_CLOCK_SYNC_SAMPLES
+            ),
+            ConfigError::EntityIdSpace => write!(
+                f,
+                "max_players * entity_range + projectile_ids + item_ids is more than {} entity ids",
+                ENTITY_ID_SPACE
+            ),
+        };
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GameConfig {
+    pub ser_type: SerializationType,
+    // game loop ticks per second
+    pub tick_rate: TickRate,
+    // entity ids handed to a player, player id * entity_range is where theirs start
+    pub entity_range: u16,
+    // entity ids for projectiles and items, after every player's range, see entity_ids
+    pub projectile_ids: usize,
+    pub item_ids: usize,
+    // how often connected clients get their clock re-synced
+    pub clock_resync_seconds: u128,
+    // how long a tournament player that dropped mid-game keeps their slot,
+    // 0 puts them out right away like everyone else
+    pub reconnect_grace_seconds: u128,
+    // players needed before the lobby starts the game
+    pub min_players: usize,
+    pub max_players: usize,
+    // connections already queued when min_players is reached still get in (up to max_players)
+    pub allow_late_join: bool,
+    // games with this many players or less get unfiltered snapshots
+    pub full_snapshot_players: usize,
+    // ticks between start_game and the live match, 0 skips warm up
+    pub warmup_ticks: u128,
+    // 1/100 tiles per tick on open ground, terrain scales it down
+    pub move_speed: u32,
+    // halve the snapshot rate while the loop can't keep up with real time
+    pub degrade_on_drift: bool,
+    // once the first player has waited this long on_deadline decides, None waits forever
+    pub max_lobby_wait: Option<Duration>,
+    pub on_deadline: OnDeadline,
+    // fill a short handed lobby up to min_players with bots
+    pub bot_fill: bool,
+    // hard cap on how long a match runs, None runs until the humans leave
+    pub max_ticks: Option<u128>,
+    // no new spectators once a match has less than this left of max_ticks,
+    // None takes them until the end
+    pub spectate_cutoff: Option<Duration>,
+    // clock syncs running at the same time while players join
+    pub max_concurrent_handshakes: usize,
+    // player messages a tick handles, the rest wait in the channel for the next one
+    pub max_messages_per_tick: usize,
+    // round trips in the join handshake's clock sync, see player::check_sync_samples
+    pub clock_sync_samples: usize,
+    // how long a join's clock sync may take, on_sync_timeout says what
+    // happens to a client that hasn't finished by then
+    pub handshake_timeout: Duration,
+    pub on_sync_timeout: OnSyncTimeout,
+    // accept debugging commands like GameMessage::AdminMove and admin connections
+    pub admin_commands: bool,
+    // longest display name in characters, see names::validate_name
+    pub max_name_length: usize,
+    // label of this server for multi region setups, see server::region
+    pub region: Region,
+    // ticks a player has to wait between two emotes
+    pub emote_cooldown_ticks: u128,
+    // recent events a game keeps for admins and crash reports, 0 keeps none
+    pub event_log_capacity: usize,
+    // a dedicated thread keeps the tick steady next to busy connections
+    pub thread: GameThread,
+    // players may opt into DebugTelemetry, debugging only
+    pub debug_telemetry: bool,
+    // tournament games, results count so nobody gets debug help
+    pub ranked: bool,
+    // how snapshots carry positions
+    pub positions: PositionFormat,
+    // accepted connections get artificial latency and loss, development only
+    pub shaping: Option<ShapeConfig>,
+    // the items a game starts with, drawn from the map seed, see loot::spawn_loot
+    pub loot: LootTable,
+}
+
+impl GameConfig {
+    pub fn new(ser_type: SerializationType, max_players: usize) -> Self {
+        return Self {
+            ser_type,
+            max_players,
+            ..Self::default()
+        };
+    }
+
+    /// length of one tick
+    pub fn tick_micros(&self) -> u128 {
+        return self.tick_rate.micros();
+    }
+
+    pub fn ticks(&self, seconds: u128) -> u128 {
+        return self.tick_rate.ticks(seconds);
+    }
+
+    /// catches settings that can't work together, capacity is how many
+    /// players a game can hold.
+    pub fn validate(&self, capacity: usize) -> Result<(), ConfigError> {
+        if self.max_players == 0 {
+            return Err(ConfigError::NoPlayers);
+        }
+
+        if self.max_players > capacity {
+            return Err(ConfigError::MaxPlayersOverCapacity {
+                max_players: self.max_players,
+                capacity,
+            });
+        }
+
+        if self.min_players > self.max_players {
+            return Err(ConfigError::MinPlayersOverMax {
+                min_players: self.min_players,
+                max_players: self.max_players,
+            });
+        }
+
+        if self.entity_range == 0 {
+            return Err(ConfigError::ZeroEntityRange);
+        }
+
+        if EntityIdAllocator::for_config(self).is_none() {
+            return Err(ConfigError::EntityIdSpace);
+        }
+
+        if self.on_deadline == (OnDeadline::StartAnyway { floor: 0 }) {
+            return Err(ConfigError::ZeroDeadlineFloor);
+        }
+
+        if self.max_concurrent_handshakes == 0 {
+            return Err(ConfigError::ZeroHandshakes);
+        }
+
+        if self.max_messages_per_tick == 0 {
+            return Err(ConfigError::ZeroMessagesPerTick);
+        }
+
+        if check_sync_samples(self.clock_sync_samples).is_err() {
+            return Err(ConfigError::ClockSyncSamples(self.clock_sync_samples));
+        }
+
+        // names go over the wire with a u8 length
+        if self.max_name_length == 0 || self.max_name_length > u8::MAX as usize {
+            return Err(ConfigError::NameLength(self.max_name_length));
+        }
+
+        // results that count can't come from a shaped server
+        if self.shaping.is_some() && (self.ranked || !cfg!(debug_assertions)) {
+            return Err(ConfigError::Shaping);
+        }
+
+        // every item takes an entity id
+        if !self.loot.is_valid() || self.loot.count > self.item_ids {
+            return Err(ConfigError::Loot);
+        }
+
+        // everything after the label has to be padding
+        let label = region_label(&self.region);
+        if !label.chars().all(|c| c.is_ascii_graphic()) || self.region[label.len()..].iter().any(|&b| b != 0) {
+            return Err(ConfigError::BadRegion);
+        }
+
+        return Ok(());
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        return Self {
+            ser_type: SerializationType::Deku,
+            tick_rate: TickRate::default(),
+            entity_range: 500,
+            projectile_ids: 4096,
+            item_ids: 1024,
+            clock_resync_seconds: 30,
+            reconnect_grace_seconds: 30,
+            min_players: 1,
+            max_players: 100,
+            allow_late_join: false,
+            full_snapshot_players: 2,
+            warmup_ticks: 0,
+            move_speed: TILE_COST,
+            degrade_on_drift: true,
+            max_lobby_wait: None,
+            on_deadline: OnDeadline::StartAnyway { floor: 2 },
+            bot_fill: false,
+            max_ticks: None,
+            spectate_cutoff: None,
+            max_concurrent_handshakes: 8,
+            max_messages_per_tick: 500,
+            clock_sync_samples: 10,
+            handshake_timeout: Duration::from_secs(10),
+            on_sync_timeout: OnSyncTimeout::Drop,
+            admin_commands: false,
+            max_name_length: 16,
+            region: [0; REGION_LENGTH],
+            emote_cooldown_ticks: 60,
+            event_log_capacity: 500,
+            thread: GameThread::Shared,
+            debug_telemetry: false,
+            ranked: false,
+            positions: PositionFormat::Tile,
+            shaping: None,
+            loot: LootTable::EMPTY,
+        };
+    }
+}
+
+/// what happens to a joining client whose clock sync didn't finish within
+/// handshake_timeout, or failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnSyncTimeout {
+    // let them in with a clock_diff of 0, the periodic resync fixes it later
+    Admit,
+    // close the connection and give the slot back
+    Drop,
+}
+
+/// what a lobby still short of min_players does once max_lobby_wait is up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnDeadline {
+    // start short handed with at least floor players, with bot_fill a single
+    // player is enough since bots make up the rest
+    StartAnyway { floor: usize },
+    // close the lobby and let everyone in it go
+    Cancel,
+}
+
+/// positions in snapshots. movement and collision stay on whole tiles either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionFormat {
+    // Snapshot, whole tiles
+    Tile,
+    // FineSnapshot, 16.16 fixed point for clients that move smoothly between tiles
+    Fixed,
+}
+
+/// how public connections are matched into lobbies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Balance {
+    // the current lobby until it fills, then a new one
+    Fill,
+    // the open lobby with the fewest players, skipping any whose last health
+    // report wasn't ok or was more than max_lag behind. with none left a new
+    // lobby opens
+    Auto { max_lag: Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct ManagerConfig {
+    pub game: GameConfig,
+    // lobbies, running and private games all count
+    pub max_games: usize,
+    // public connections that wait for a game to free up once max_games are
+    // running, past it they get ServerFull. 0 turns them away right away
+    pub max_queued: usize,
+    // keeps the game id high water mark so ids don't repeat across restarts
+    pub id_state_path: Option<PathBuf>,
+    // how long a browsing connection has to pick a game from the list
+    pub browse_timeout: Duration,
+    // sent to every client the manager accepts, before anything from the game
+    pub motd: Option<String>,
+    // least time between two announcements
+    pub announce_interval: Duration,
+    // where games write their state dumps, dumping is off without one
+    pub dump_dir: Option<PathBuf>,
+    // map seeds of new games
+    pub seeds: SeedMode,
+    // where running games keep their recovery images, off without one
+    pub recovery_dir: Option<PathBuf>,
+    // images older than this are deleted instead of reported at startup
+    pub recovery_ttl: Duration,
+    // every admin action is appended here, see audit
+    pub audit_log: Option<PathBuf>,
+    // who may log in to an admin session, none are allowed without any
+    pub admin_keys: AdminKeys,
+    // admin sessions for announcing and moderating, without
+    // GameConfig::admin_commands and the debugging moves that come with it
+    pub moderation: bool,
+    // every game's emotes go through it, see moderation
+    pub emote_filter: Arc<dyn EmoteFilter>,
+    pub balance: Balance,
+    // games that lose the manager mid-game write their result here, see outcome
+    pub outcome_dir: Option<PathBuf>,
+    // every player's connection is captured here, debugging only, see capture
+    pub capture_dir: Option<PathBuf>,
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        return Self {
+            game: GameConfig::default(),
+            max_games: 64,
+            max_queued: 0,
+            id_state_path: None,
+            browse_timeout: Duration::from_secs(30),
+            motd: None,
+            announce_interval: Duration::from_secs(10),
+            dump_dir: None,
+            seeds: SeedMode::Time,
+            recovery_dir: None,
+            recovery_ttl: Duration::from_secs(300),
+            audit_log: None,
+            admin_keys: AdminKeys::default(),
+            moderation: false,
+            emote_filter: Arc::new(WordList::default()),
+            balance: Balance::Fill,
+            outcome_dir: None,
+            capture_dir: None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use encoding::server::region;
+
+    use super::{ConfigError, GameConfig, OnDeadline};
+    use crate::{
+        loot::{LootEntry, LootTable},
+        shaping::ShapeConfig,
+    };
+
+    #[test]
+    fn test_default_matches_old_constants() {
+        let config = GameConfig::default();
+
+        assert_eq!(config.tick_micros(), 16_666);
+        assert_eq!(config.ticks(30), 1_800);
+        assert_eq!(config.entity_range, 500);
+        assert_eq!(config.validate(100), Ok(()));
+    }
+
+    #[test]
+    fn test_inconsistent_settings_are_rejected() {
+        let cases = [
+            (GameConfig { max_players: 0, min_players: 0, ..GameConfig::default() }, ConfigError::NoPlayers),
+            (
+                GameConfig { max_players: 101, ..GameConfig::default() },
+                ConfigError::MaxPlayersOverCapacity { max_players: 101, capacity: 100 },
+            ),
+            (
+                GameConfig { min_players: 5, max_players: 4, ..GameConfig::default() },
+                ConfigError::MinPlayersOverMax { min_players: 5, max_players: 4 },
+            ),
+            (GameConfig { entity_range: 0, ..GameConfig::default() }, ConfigError::ZeroEntityRange),
+            (
+                GameConfig { projectile_ids: 1 << 24, ..GameConfig::default() },
+                ConfigError::EntityIdSpace,
+            ),
+            (
+                GameConfig { max_concurrent_handshakes: 0, ..GameConfig::default() },
+                ConfigError::ZeroHandshakes,
+            ),
+            (
+                GameConfig { max_messages_per_tick: 0, ..GameConfig::default() },
+                ConfigError::ZeroMessagesPerTick,
+            ),
+            (GameConfig { clock_sync_samples: 0, ..GameConfig::default() }, ConfigError::ClockSyncSamples(0)),
+            (
+                GameConfig { clock_sync_samples: 10_000, ..GameConfig::default() },
+                ConfigError::ClockSyncSamples(10_000),
+            ),
+            (GameConfig { max_name_length: 256, ..GameConfig::default() }, ConfigError::NameLength(256)),
+            (GameConfig { region: region("eu west"), ..GameConfig::default() }, ConfigError::BadRegion),
+            (GameConfig { region: region("eu\twest"), ..GameConfig::default() }, ConfigError::BadRegion),
+            (
+                GameConfig { on_deadline: OnDeadline::StartAnyway { floor: 0 }, ..GameConfig::default() },
+                ConfigError::ZeroDeadlineFloor,
+            ),
+            (
+                GameConfig { shaping: Some(ShapeConfig::default()), ranked: true, ..GameConfig::default() },
+                ConfigError::Shaping,
+            ),
+            (
+                GameConfig { loot: LootTable { entries: &[], count: 1 }, ..GameConfig::default() },
+                ConfigError::Loot,
+            ),
+            (
+                GameConfig {
+                    loot: LootTable { entries: &[LootEntry { item: "ammo", weight: 1 }], count: 2048 },
+                    ..GameConfig::default()
+                },
+                ConfigError::Loot,
+            ),
+        ];
+
+        for (config, expected) in cases {
+            let err = config.validate(100).expect_err("config should be rejected");
+            assert!(!err.to_string().is_empty());
+            assert_eq!(err, expected);
+        }
+    }
+}
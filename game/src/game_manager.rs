@@ -1,72 +1,613 @@
-use std::collections::HashMap;
-use std::sync::{atomic::AtomicU8, Arc};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{atomic::AtomicU8, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use log::info;
-use tokio::task::JoinHandle;
+use encoding::server::{
+    self, Announcement, GameList, GameListing, PrivateGameCode, ServerMessage,
+    ANNOUNCEMENT_INFO, ANNOUNCEMENT_MAX_LENGTH, ANNOUNCEMENT_WARNING, GAME_LISTING_LOBBY,
+    GAME_LISTING_RUNNING, JOIN_ERROR_BAD_NAME, JOIN_ERROR_FULL, JOIN_ERROR_NOT_FOUND, JOIN_ERROR_NOT_REGISTERED,
+    JOIN_ERROR_STARTED, PRIVATE_CODE_LENGTH, WHO_AM_I_ADMIN, WHO_AM_I_CLIENT, WHO_AM_I_SPECTATOR,
+};
+use futures::StreamExt;
+use tracing::{error, info, warn};
+use map::rand::mulberry32;
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_tungstenite::tungstenite;
 
-use crate::connection::SerializationType;
-use crate::game_comms::GameMessage;
+use crate::admin::admin_login;
+use crate::allocator::{GameAllocation, GameIdAllocator};
+use crate::audit::AuditLog;
+use crate::capture::CaptureDir;
+use crate::connection::{handshake, Handshake};
+use crate::game_comms::{GameKey, GameMessage, GameStatus, IncomingConnection, PlayerToken};
+use crate::game_state::GameState;
+use crate::health::{game_health, Health, HealthReport, ProcessHealth, HEALTH_CHECK_TIMEOUT};
+use crate::metrics::metrics;
+use crate::names::validate_name;
+use crate::outcome::OutcomeDir;
+use crate::recovery::{load_images, now_millis, remove_image, RecoveryImage};
+use crate::shaping::ShapeConfig;
+use crate::slots::{Reservation, Slots};
+use crate::tournament::{Advance, Standings, Tournament, TournamentConfig, TournamentError};
 use crate::{
-    game::game_run,
+    game::{game_run, Game, PLAYER_COUNT},
+    game_thread::spawn_game,
     game_comms::{GameComms, GameSender},
-    player::{PlayerWebSink, PlayerWebStream},
+    game_config::{Balance, GameConfig, ManagerConfig},
+    player::{reject_connection, PlayerSink, PlayerWebSink, PlayerWebStream},
 };
 
+// no 0/O, 1/I/l so codes can be read out loud
+const PRIVATE_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+
+// games answer within a tick, anything slower is treated as unresponsive
+const STATUS_TIMEOUT: Duration = Duration::from_millis(250);
+
+// what we guess a game takes until one has actually finished
+const DEFAULT_GAME_DURATION: Duration = Duration::from_secs(300);
+
+/// what every player is told when the server is stopped.
+pub const SHUTDOWN_NOTICE: &str = "server shutting down";
+
+pub type PrivateCode = [u8; PRIVATE_CODE_LENGTH];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AnnounceError {
+    Empty,
+    TooLong,
+    // control characters would mess with the terminal on the other end
+    BadCharacter,
+    // the last one went out less than announce_interval ago
+    RateLimited(Duration),
+}
+
+/// what any operator text has to look like before it goes out to players.
+pub fn check_announcement_text(text: &str) -> Result<(), AnnounceError> {
+    if text.is_empty() {
+        return Err(AnnounceError::Empty);
+    }
+
+    if text.len() > ANNOUNCEMENT_MAX_LENGTH {
+        return Err(AnnounceError::TooLong);
+    }
+
+    if text.chars().any(|c| c.is_control()) {
+        return Err(AnnounceError::BadCharacter);
+    }
+
+    return Ok(());
+}
+
+// once min_players is reached the game starts, so unless late joins are
+// allowed there is no point routing anyone else to it.
+fn join_limit(config: &GameConfig) -> usize {
+    if config.allow_late_join {
+        return config.max_players;
+    }
+
+    return config.min_players.min(config.max_players);
+}
+
 pub struct GameStub {
     pub player_count: Arc<AtomicU8>,
+    // player_count plus the connections routed to it that it hasn't taken yet
+    pub slots: Slots,
     pub sender: GameSender,
     pub comms: Option<GameComms>,
     started: bool,
+    in_lobby: bool,
+    started_at: Option<Instant>,
+    private_code: Option<PrivateCode>,
+    tournament: bool,
     game_id: u32,
+    seed: u32,
+    epoch: u32,
     handle: Option<JoinHandle<()>>,
-    ser_type: SerializationType,
+    config: GameConfig,
 }
 
 impl GameStub {
-    fn new(sender: GameSender, id: u32, ser_type: SerializationType) -> Self {
+    fn new(sender: GameSender, key: GameKey, seed: u32, config: GameConfig) -> Self {
         let (comms, sender) = GameComms::with_sender(sender);
+        let player_count = Arc::new(AtomicU8::new(0));
 
         return Self {
-            player_count: Arc::new(AtomicU8::new(0)),
+            slots: Slots::new(player_count.clone(), join_limit(&config)),
+            player_count,
             sender,
-            ser_type,
-            game_id: id,
+            config,
+            game_id: key.id,
+            seed,
+            epoch: key.epoch,
             comms: Some(comms),
             handle: None,
             started: false,
+            in_lobby: true,
+            started_at: None,
+            private_code: None,
+            tournament: false,
+        };
+    }
+
+    fn is_full(&self) -> bool {
+        return self.slots.is_full();
+    }
+
+    pub fn key(&self) -> GameKey {
+        return GameKey {
+            id: self.game_id,
+            epoch: self.epoch,
         };
     }
 }
 
-pub struct GameConfig {
-    pub ser_type: SerializationType,
-    pub max_players: usize,
+// what a browsing connection needs to join a listed game on its own
+struct BrowseEntry {
+    sender: GameSender,
+    slots: Slots,
 }
 
-impl GameConfig {
-    pub fn new(ser_type: SerializationType, max_players: usize) -> Self {
-        return Self {
-            ser_type,
-            max_players,
-        };
+/// the rest of the handshake for a connection that asked for the game list.
+/// it runs on its own task so a slow browser doesn't hold up everyone else.
+async fn browse(
+    mut stream: PlayerWebStream,
+    sink: PlayerWebSink,
+    list: GameList,
+    entries: HashMap<u32, BrowseEntry>,
+    timeout: Duration,
+) {
+    let mut sink = PlayerSink::new(0, sink);
+    if sink.send(server::Message::GameList(list)).await.is_err() {
+        return;
     }
+
+    let game_id = match tokio::time::timeout(timeout, stream.next()).await {
+        Ok(Some(Ok(tungstenite::Message::Binary(msg)))) => match ServerMessage::deserialize(&msg) {
+            Ok(ServerMessage {
+                msg: server::Message::JoinGame(game_id),
+                ..
+            }) => game_id,
+            msg => {
+                info!(?msg, "browsing connection sent something other than a join");
+                sink.close().await;
+                return;
+            }
+        },
+        Err(_) => {
+            info!("browsing connection timed out");
+            sink.close().await;
+            return;
+        }
+        _ => return,
+    };
+
+    let reason = match entries.get(&game_id) {
+        None => JOIN_ERROR_NOT_FOUND,
+        Some(entry) => match entry.slots.reserve() {
+            // it may have filled up while they were looking
+            None => JOIN_ERROR_FULL,
+            Some(reservation) => {
+                if let Some(sink) = sink.sink.take() {
+                    let conn_message = GameMessage::Connection(IncomingConnection {
+                        reservation: Some(reservation),
+                        ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+                    });
+                    _ = entry.sender.send(conn_message).await;
+                }
+                return;
+            }
+        },
+    };
+
+    info!(game_id, reason, "rejecting browsed join");
+    _ = sink.send(server::Message::JoinError(reason)).await;
+    sink.close().await;
+}
+
+// a public connection waiting for a game, see ManagerConfig::max_queued
+struct QueuedConnection {
+    stream: PlayerWebStream,
+    sink: PlayerWebSink,
+    whoami: u8,
+    name: Option<String>,
 }
 
 pub struct GameManager {
+    // the public lobby new connections are matched into
     game_id: u32,
+    ids: GameIdAllocator,
     games: HashMap<u32, GameStub>,
+    // last epoch handed out for every game id that has been used
+    epochs: HashMap<u32, u32>,
+    private_games: HashMap<PrivateCode, GameKey>,
+    code_rand: Box<dyn FnMut() -> u32 + Send>,
     comms: GameComms,
-    ser_type: SerializationType,
+    config: ManagerConfig,
+    // rolling average of how long finished games ran
+    average_game_duration: Option<Duration>,
+    tournament: Option<Tournament>,
+    last_announcement: Option<Instant>,
+    // last report of every running game, what a game that stops answering is judged by
+    health_reports: HashMap<u32, HealthReport>,
+    // shared by every admin session, None without ManagerConfig::audit_log
+    audit: Option<Arc<Mutex<AuditLog>>>,
+    // first come first in, once a game frees up
+    queue: VecDeque<QueuedConnection>,
 }
 
 impl GameManager {
-    pub fn new(ser_type: SerializationType) -> GameManager {
-        return GameManager {
+    /// fails when the game id state can't be read or the audit log doesn't
+    /// verify.
+    pub fn new(config: ManagerConfig) -> anyhow::Result<GameManager> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let audit = config
+            .audit_log
+            .as_ref()
+            .map(|path| AuditLog::open(path))
+            .transpose()?;
+
+        let ids = GameIdAllocator::with_seeds(config.id_state_path.clone(), config.seeds.source())?;
+
+        return Ok(GameManager {
             games: HashMap::new(),
+            epochs: HashMap::new(),
+            private_games: HashMap::new(),
+            code_rand: Box::new(mulberry32(now)),
             game_id: 0,
+            ids,
             comms: GameComms::new(),
-            ser_type,
+            config,
+            average_game_duration: None,
+            tournament: None,
+            last_announcement: None,
+            health_reports: HashMap::new(),
+            audit: audit.map(|log| Arc::new(Mutex::new(log))),
+            queue: VecDeque::new(),
+        });
+    }
+
+    /// None once ids ran out or can't be persisted, no new games after that.
+    async fn allocate_game(&mut self) -> Option<GameAllocation> {
+        match self.ids.allocate().await {
+            Ok(allocation) => return Some(allocation),
+            Err(e) => {
+                error!(error = ?e, "can't allocate a game id");
+                return None;
+            }
+        }
+    }
+
+    /// the seed a game was started with, for results and replays.
+    pub fn game_seed(&self, game_id: u32) -> Option<u32> {
+        return self.ids.seed_for(game_id);
+    }
+
+    /// what the seed source drew for a game, its seed is derived from this
+    /// and the game id.
+    pub fn game_base_seed(&self, game_id: u32) -> Option<u32> {
+        return self.ids.allocation(game_id).map(|allocation| allocation.base_seed);
+    }
+
+    fn create_game(&mut self, allocation: GameAllocation) -> GameKey {
+        return self.create_game_with(allocation, self.config.game);
+    }
+
+    fn create_game_with(&mut self, allocation: GameAllocation, config: GameConfig) -> GameKey {
+        let game_id = allocation.game_id;
+        let epoch = *self
+            .epochs
+            .entry(game_id)
+            .and_modify(|epoch| *epoch += 1)
+            .or_insert(0);
+
+        let key = GameKey { id: game_id, epoch };
+        info!(?key, seed = allocation.seed, base_seed = allocation.base_seed, "creating new stub");
+
+        let mut stub = GameStub::new(self.comms.sender.clone(), key, allocation.seed, config);
+        if let Some(comms) = stub.comms.as_mut() {
+            comms.emote_filter = self.config.emote_filter.clone();
+        }
+        if let (Some(comms), Some(dir)) = (stub.comms.as_mut(), self.config.outcome_dir.as_ref()) {
+            comms.outcomes = Some(Arc::new(OutcomeDir::new(dir)));
+        }
+        if let (Some(comms), Some(dir)) = (stub.comms.as_mut(), self.config.capture_dir.as_ref()) {
+            comms.capture = Some(CaptureDir::new(dir));
+        }
+        GameManager::start_game_stub(&mut stub);
+        self.games.insert(game_id, stub);
+
+        return key;
+    }
+
+    fn at_capacity(&self) -> bool {
+        return self.games.len() >= self.config.max_games;
+    }
+
+    /// moves the public lobby to a fresh game, None when at max_games.
+    async fn open_lobby(&mut self) -> Option<GameKey> {
+        if self.at_capacity() {
+            warn!(max_games = self.config.max_games, "at max games, no new lobby");
+            return None;
+        }
+
+        let allocation = self.allocate_game().await?;
+        self.game_id = allocation.game_id;
+        return Some(self.create_game(allocation));
+    }
+
+    // a public connection could get in without opening a game past max_games
+    fn has_room(&self) -> bool {
+        return !self.at_capacity()
+            || self
+                .games
+                .values()
+                .any(|game| game.in_lobby && game.private_code.is_none() && !game.tournament && !game.is_full());
+    }
+
+    // tells the connection its place in the queue and holds on to it, or
+    // turns it away when the queue is full too
+    async fn queue_connection(&mut self, stream: PlayerWebStream, sink: PlayerWebSink, whoami: u8, name: Option<String>) {
+        if self.queue.len() >= self.config.max_queued {
+            self.reject_server_full(sink).await;
+            return;
+        }
+
+        let position = (self.queue.len() + 1).min(u8::MAX as usize) as u8;
+        info!(position, "at max games, queueing connection");
+
+        let mut player_sink = PlayerSink::new(0, sink);
+        if player_sink.send(server::Message::PlayerQueueCountResult(position)).await.is_err() {
+            return;
+        }
+        let Some(sink) = player_sink.sink.take() else {
+            return;
+        };
+
+        self.queue.push_back(QueuedConnection { stream, sink, whoami, name });
+    }
+
+    /// lets queued connections into the games that freed up since they came in.
+    pub async fn admit_queued(&mut self) {
+        self.process_game_messages().await;
+
+        // anyone that can't get in after all goes back in line
+        for _ in 0..self.queue.len() {
+            if !self.has_room() {
+                break;
+            }
+
+            let Some(queued) = self.queue.pop_front() else {
+                break;
+            };
+            info!(waiting = self.queue.len(), "admitting a queued connection");
+            self.add_public_connection(queued.stream, queued.sink, queued.whoami, queued.name).await;
+        }
+    }
+
+    /// how long until a running game should free up a slot
+    pub fn estimated_wait(&self) -> Duration {
+        let expected = self.average_game_duration.unwrap_or(DEFAULT_GAME_DURATION);
+        return self
+            .games
+            .values()
+            .filter_map(|game| game.started_at)
+            .map(|started_at| expected.saturating_sub(started_at.elapsed()))
+            .min()
+            .unwrap_or(expected);
+    }
+
+    async fn reject_server_full(&self, sink: PlayerWebSink) {
+        let wait = self.estimated_wait().as_secs().min(u16::MAX as u64) as u16;
+        info!(wait_secs = wait, "server full");
+
+        metrics().kick("server_full");
+        let mut sink = PlayerSink::new(0, sink);
+        _ = sink.send(server::Message::ServerFull(wait)).await;
+        sink.close().await;
+    }
+
+    fn record_game_duration(&mut self, duration: Duration) {
+        self.average_game_duration = Some(match self.average_game_duration {
+            Some(average) => (average * 3 + duration) / 4,
+            None => duration,
+        });
+    }
+
+    /// the game a key refers to, None if that game is gone or the id now
+    /// belongs to a newer game.
+    pub fn game(&self, key: GameKey) -> Option<&GameStub> {
+        return self.games.get(&key.id).filter(|game| game.epoch == key.epoch);
+    }
+
+    fn generate_code(&mut self) -> PrivateCode {
+        loop {
+            let mut code = [0; PRIVATE_CODE_LENGTH];
+            for c in code.iter_mut() {
+                let idx = (self.code_rand)() as usize % PRIVATE_CODE_ALPHABET.len();
+                *c = PRIVATE_CODE_ALPHABET[idx];
+            }
+
+            if !self.private_games.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    /// drains the notifications games send back (start / close) without waiting.
+    pub async fn process_game_messages(&mut self) {
+        while let Ok(msg) = self.comms.receiver.try_recv() {
+            match msg {
+                GameMessage::Start(key) => {
+                    match self.games.get_mut(&key.id).filter(|game| game.epoch == key.epoch) {
+                        Some(game) => {
+                            game.in_lobby = false;
+                            game.started_at = Some(Instant::now());
+                        }
+                        None => warn!(?key, "ignoring start for stale game"),
+                    }
+
+                    // always keep a joinable lobby around
+                    if key.id == self.game_id {
+                        self.open_lobby().await;
+                    }
+                }
+                GameMessage::Result(key, result) => {
+                    let Some(tournament) = self.tournament.as_mut() else {
+                        continue;
+                    };
+
+                    match tournament.record_result(key, result) {
+                        Ok(Advance::Waiting) => {}
+                        Ok(Advance::NextRound(round)) => {
+                            info!(round, "tournament advancing");
+                            self.start_tournament_games().await;
+                        }
+                        Ok(Advance::Finished(ranking)) => {
+                            info!(?ranking, "tournament finished");
+                        }
+                        Err(e) => warn!(?key, error = ?e, "ignoring result"),
+                    }
+                }
+                GameMessage::Close(key) => {
+                    if self.game(key).is_none() {
+                        warn!(?key, "ignoring close for stale game");
+                        continue;
+                    }
+
+                    // closed without a result, play it again with the same people
+                    let aborted = self
+                        .tournament
+                        .as_mut()
+                        .is_some_and(|tournament| tournament.abort(key).is_ok());
+
+                    if let Some(dir) = self.config.recovery_dir.as_ref() {
+                        remove_image(dir, key.id);
+                    }
+
+                    self.ids.release(key.id);
+                    if let Some(game) = self.games.remove(&key.id) {
+                        if let Some(code) = game.private_code {
+                            self.private_games.remove(&code);
+                        }
+
+                        if let Some(started_at) = game.started_at {
+                            self.record_game_duration(started_at.elapsed());
+                        }
+                    }
+
+                    if aborted {
+                        warn!(?key, "tournament game aborted, running it again");
+                        self.start_tournament_games().await;
+                    }
+                }
+                // the Close right behind it cleans the game up
+                GameMessage::Crashed(key, report) => error!(
+                    ?key,
+                    message = %report.message,
+                    events = ?report.events,
+                    "game crashed"
+                ),
+                msg => warn!(?msg, "unexpected game message"),
+            }
+        }
+    }
+
+    pub async fn create_private_game(&mut self) -> Option<(PrivateCode, GameKey)> {
+        let code = self.generate_code();
+        let allocation = self.allocate_game().await?;
+        let key = self.create_game(allocation);
+
+        if let Some(game) = self.games.get_mut(&key.id) {
+            game.private_code = Some(code);
+        }
+        self.private_games.insert(code, key);
+        info!(?key, "created private game");
+
+        return Some((code, key));
+    }
+
+    /// the game a private code routes to, or the JOIN_ERROR_* reason it can't.
+    pub fn find_private_game(&self, code: &PrivateCode) -> Result<GameKey, u8> {
+        let code = code.map(|c| c.to_ascii_uppercase());
+        let key = *self.private_games.get(&code).ok_or(JOIN_ERROR_NOT_FOUND)?;
+        let game = self.game(key).ok_or(JOIN_ERROR_NOT_FOUND)?;
+
+        if !game.in_lobby {
+            return Err(JOIN_ERROR_STARTED);
+        }
+
+        if game.is_full() {
+            return Err(JOIN_ERROR_FULL);
+        }
+
+        return Ok(key);
+    }
+
+    pub async fn start_tournament(&mut self, config: TournamentConfig) -> Result<(), TournamentError> {
+        self.tournament = Some(Tournament::new(config)?);
+        self.start_tournament_games().await;
+
+        return Ok(());
+    }
+
+    // one game per open bracket slot, each only takes its own participants
+    async fn start_tournament_games(&mut self) {
+        let Some(slots) = self.tournament.as_ref().map(|t| t.games_to_start()) else {
+            return;
         };
+
+        for slot in slots {
+            let participants = self
+                .tournament
+                .as_ref()
+                .map(|t| t.participants(slot).len())
+                .unwrap_or(0);
+
+            let config = GameConfig {
+                min_players: participants,
+                max_players: participants,
+                ranked: true,
+                ..self.config.game
+            };
+            if let Err(e) = config.validate(PLAYER_COUNT) {
+                error!(slot, error = %e, "can't run tournament slot");
+                continue;
+            }
+
+            let Some(allocation) = self.allocate_game().await else {
+                return;
+            };
+            let key = self.create_game_with(allocation, config);
+            if let Some(game) = self.games.get_mut(&key.id) {
+                game.tournament = true;
+            }
+            if let Some(tournament) = self.tournament.as_mut() {
+                tournament.assign(slot, key);
+            }
+            info!(slot, ?key, "tournament slot has its game");
+        }
+    }
+
+    /// the tournament game a player token routes to, or the JOIN_ERROR_* reason it can't.
+    /// a game that started only takes back players whose slot it is holding,
+    /// it turns everyone else away itself.
+    pub fn find_tournament_game(&self, token: PlayerToken) -> Result<GameKey, u8> {
+        let key = self
+            .tournament
+            .as_ref()
+            .and_then(|tournament| tournament.route(token))
+            .ok_or(JOIN_ERROR_NOT_REGISTERED)?;
+        self.game(key).ok_or(JOIN_ERROR_NOT_FOUND)?;
+
+        return Ok(key);
+    }
+
+    pub fn tournament_standings(&self) -> Option<Standings> {
+        return self.tournament.as_ref().map(|tournament| tournament.standings());
     }
 
     fn start_game_stub(game_stub: &mut GameStub) {
@@ -76,14 +617,14 @@ impl GameManager {
             .expect("comms always exist at this point");
 
         let run = game_run(
-            game_stub.game_id,
+            game_stub.seed,
             game_stub.player_count.clone(),
-            game_stub.game_id,
+            game_stub.key(),
             comms,
-            game_stub.ser_type.clone()
+            game_stub.config,
         );
 
-        game_stub.handle = Some(tokio::spawn(run));
+        game_stub.handle = Some(spawn_game(game_stub.config.thread, run));
         game_stub.started = true;
     }
 
@@ -92,44 +633,461 @@ impl GameManager {
     // I need to treat the Server, Game Manager, Game Lobby, Game Runner, and Subgame likely
     // as individual threads
     pub async fn add_connection(&mut self, stream: PlayerWebStream, sink: PlayerWebSink) {
-        let game_id = self.game_id;
-        info!("[GIM] add connection at {}", game_id);
-        let game = self.games.entry(game_id).or_insert_with(|| {
-            info!("[GIM] creating new stub for {}", game_id);
-            let mut stub = GameStub::new(self.comms.sender.clone(), game_id, self.ser_type.clone());
-            GameManager::start_game_stub(&mut stub);
-
-            return stub;
+        self.add_connection_from(stream, sink, None).await;
+    }
+
+    /// add_connection for a client at peer, admin sessions record it in the audit log
+    pub async fn add_connection_from(&mut self, mut stream: PlayerWebStream, sink: PlayerWebSink, peer: Option<SocketAddr>) {
+        // they were here first
+        self.admit_queued().await;
+
+        match handshake(stream.next().await) {
+            Ok(Handshake::Whoami(whoami))
+                if whoami == WHO_AM_I_CLIENT || whoami == WHO_AM_I_SPECTATOR =>
+            {
+                self.add_public_connection(stream, sink, whoami, None).await;
+            }
+
+            Ok(Handshake::Named(whoami, name))
+                if whoami == WHO_AM_I_CLIENT || whoami == WHO_AM_I_SPECTATOR =>
+            {
+                match validate_name(&name, self.config.game.max_name_length) {
+                    Ok(name) => self.add_public_connection(stream, sink, whoami, Some(name)).await,
+                    Err(e) => {
+                        info!(error = ?e, "rejecting connection with bad name");
+                        reject_connection(sink, JOIN_ERROR_BAD_NAME).await;
+                    }
+                }
+            }
+
+            Ok(Handshake::CreatePrivate) => {
+                if self.at_capacity() {
+                    self.reject_server_full(sink).await;
+                    return;
+                }
+
+                let Some((code, key)) = self.create_private_game().await else {
+                    self.reject_server_full(sink).await;
+                    return;
+                };
+                let mut player_sink = PlayerSink::new(0, sink);
+                let created = server::Message::PrivateGameCreated(PrivateGameCode { code });
+                if player_sink.send(created).await.is_err() {
+                    return;
+                }
+                let Some(sink) = player_sink.sink.take() else {
+                    return;
+                };
+
+                self.route_player(key, stream, sink, None).await;
+            }
+
+            Ok(Handshake::JoinPrivate(code)) => match self.find_private_game(&code) {
+                Ok(key) => {
+                    info!(?key, "routing private connection");
+                    self.route_player(key, stream, sink, None).await;
+                }
+                Err(reason) => {
+                    info!(reason, "rejecting private connection");
+                    reject_connection(sink, reason).await;
+                }
+            },
+
+            Ok(Handshake::Whoami(WHO_AM_I_ADMIN)) => {
+                let sessions_on = self.config.game.admin_commands || self.config.moderation;
+                if !sessions_on || self.config.admin_keys.is_empty() {
+                    info!(?peer, "admin connection refused, admin sessions are off or there are no admin keys");
+                    _ = sink.reunite(stream).map(|mut x| _ = x.close(None));
+                    return;
+                }
+
+                // logging in waits on the client, the manager doesn't
+                tokio::spawn(admin_login(
+                    stream,
+                    sink,
+                    self.config.admin_keys.clone(),
+                    peer,
+                    self.running_games(),
+                    self.config.dump_dir.clone(),
+                    self.audit.clone(),
+                ));
+            }
+
+            Ok(Handshake::ListGames) => {
+                let (list, entries) = self.game_list();
+                tokio::spawn(browse(stream, sink, list, entries, self.config.browse_timeout));
+            }
+
+            Ok(Handshake::JoinTournament(token)) => match self.find_tournament_game(token) {
+                Ok(key) => {
+                    info!(token, ?key, "routing tournament player");
+                    self.route_player(key, stream, sink, Some(token)).await;
+                }
+                Err(reason) => {
+                    info!(reason, "rejecting tournament connection");
+                    reject_connection(sink, reason).await;
+                }
+            },
+
+            Err(e) => {
+                info!(error = ?e, "bad handshake");
+                metrics().handshake_failed();
+                _ = sink.reunite(stream).map(|mut x| _ = x.close(None));
+            }
+
+            _ => {
+                _ = sink.reunite(stream).map(|mut x| _ = x.close(None));
+            }
+        }
+    }
+
+    async fn add_public_connection(
+        &mut self,
+        stream: PlayerWebStream,
+        sink: PlayerWebSink,
+        whoami: u8,
+        name: Option<String>,
+    ) {
+        let mut game_id = self.game_id;
+        info!(game_id, "add connection");
+
+        let mut reservation = match self.config.balance {
+            Balance::Fill => self
+                .games
+                .get(&game_id)
+                .filter(|game| game.in_lobby)
+                .and_then(|game| game.slots.reserve()),
+            Balance::Auto { max_lag } => match self.balanced_lobby(max_lag) {
+                Some((id, reservation)) => {
+                    game_id = id;
+                    Some(reservation)
+                }
+                None => None,
+            },
+        };
+
+        if reservation.is_none() {
+            info!(game_id, "game full, gone or struggling, opening a new lobby");
+            let Some(key) = self.open_lobby().await else {
+                self.queue_connection(stream, sink, whoami, name).await;
+                return;
+            };
+            game_id = key.id;
+            reservation = self.games[&key.id].slots.reserve();
+        }
+
+        let Some(sink) = self.greet(sink).await else {
+            return;
+        };
+
+        let conn_message = GameMessage::Connection(IncomingConnection {
+            name,
+            reservation,
+            ..IncomingConnection::new(stream, sink, whoami)
         });
+        info!(game_id, "sending connection message");
+        _ = self.games[&game_id].sender.send(conn_message).await;
+        info!(game_id, "sent connection message");
+    }
 
-        let conn_message = GameMessage::Connection(stream, sink);
-        let player_count = game.player_count.load(std::sync::atomic::Ordering::Relaxed);
-        // TODO: How to make this number configurable?
-        if player_count >= 1 {
-            self.game_id += 1;
-            info!(
-                "[GIM] game {} full, creating new stub for id={}",
-                game_id,
-                game_id + 1
-            );
-            let game_id = self.game_id;
-            let mut stub = GameStub::new(self.comms.sender.clone(), game_id, self.ser_type.clone());
-            GameManager::start_game_stub(&mut stub);
+    // the public lobby with room and the fewest players out of those keeping
+    // up, by what they last reported. a lobby that hasn't been checked yet
+    // is given the benefit of the doubt
+    fn balanced_lobby(&self, max_lag: Duration) -> Option<(u32, Reservation)> {
+        let now = Instant::now();
+        let mut lobbies: Vec<(u32, &GameStub)> = self
+            .games
+            .iter()
+            .filter(|(_, game)| game.in_lobby && game.private_code.is_none() && !game.tournament)
+            .filter(|(id, _)| {
+                return self
+                    .health_reports
+                    .get(id)
+                    .is_none_or(|report| game_health(report, now, true) == Health::Ok && report.lag <= max_lag);
+            })
+            .map(|(id, game)| (*id, game))
+            .collect();
+        lobbies.sort_by_key(|(id, game)| (game.player_count.load(std::sync::atomic::Ordering::Relaxed), *id));
+
+        return lobbies
+            .into_iter()
+            .find_map(|(id, game)| game.slots.reserve().map(|reservation| (id, reservation)));
+    }
+
+    // reserves a player slot in the game before handing it the connection,
+    // the game could have filled up since the caller looked
+    async fn route_player(&self, key: GameKey, stream: PlayerWebStream, sink: PlayerWebSink, token: Option<PlayerToken>) {
+        let Some(game) = self.game(key) else {
+            reject_connection(sink, JOIN_ERROR_NOT_FOUND).await;
+            return;
+        };
+
+        // a started game only takes back players whose slot it held, that
+        // slot is still counted so there is nothing to reserve
+        let reservation = match game.in_lobby {
+            true => match game.slots.reserve() {
+                Some(reservation) => Some(reservation),
+                None => {
+                    info!(?key, "game filled up, rejecting connection");
+                    reject_connection(sink, JOIN_ERROR_FULL).await;
+                    return;
+                }
+            },
+            false => None,
+        };
+
+        let Some(sink) = self.greet(sink).await else {
+            return;
+        };
+
+        let conn_message = GameMessage::Connection(IncomingConnection {
+            reservation,
+            token,
+            ..IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT)
+        });
+        _ = game.sender.send(conn_message).await;
+    }
+
+    /// sends an announcement to everyone in every game, lobbies included. each
+    /// game sends it from its own loop.
+    pub async fn announce(&mut self, severity: u8, text: &str) -> Result<(), AnnounceError> {
+        check_announcement_text(text)?;
+
+        if let Some(last) = self.last_announcement {
+            let wait = self.config.announce_interval.saturating_sub(last.elapsed());
+            if !wait.is_zero() {
+                return Err(AnnounceError::RateLimited(wait));
+            }
+        }
+        self.last_announcement = Some(Instant::now());
+
+        warn!(games = self.games.len(), %text, "announcing");
+        let announcement = Announcement::new(severity, text);
+        for game in self.games.values() {
+            _ = game.sender.send(GameMessage::Announce(announcement.clone())).await;
+        }
+
+        return Ok(());
+    }
+
+    /// tells everyone in every game the server is going away. it goes out
+    /// no matter when the last announcement did.
+    pub async fn announce_shutdown(&mut self) {
+        warn!(games = self.games.len(), "shutting down, telling the games");
+        let announcement = Announcement::new(ANNOUNCEMENT_WARNING, SHUTDOWN_NOTICE);
+        for game in self.games.values() {
+            _ = game.sender.send(GameMessage::Announce(announcement.clone())).await;
+        }
+    }
+
+    /// how every running game is doing. a game gets asked without waiting on
+    /// its channel and has HEALTH_CHECK_TIMEOUT to answer, so a game that
+    /// locked up can't hold the check up, it just shows up stalled.
+    pub async fn health(&mut self) -> ProcessHealth {
+        let health = self.check_health().await;
+        if health.status != Health::Ok {
+            warn!(?health, "game health");
+        }
+
+        return health;
+    }
+
+    /// what accepted connections go through before they get here, see shaping.
+    pub fn shaping(&self) -> Option<ShapeConfig> {
+        return self.config.game.shaping;
+    }
+
+    /// whether the server has to call heartbeat at all.
+    pub fn needs_heartbeat(&self) -> bool {
+        return matches!(self.config.balance, Balance::Auto { .. }) || self.config.max_queued > 0;
+    }
+
+    /// refreshes the health reports auto balancing routes by, quietly, and
+    /// lets queued connections in. the server calls it on an interval when
+    /// Balance::Auto is on or connections can be queued.
+    pub async fn heartbeat(&mut self) {
+        if let Balance::Auto { .. } = self.config.balance {
+            self.check_health().await;
+        }
+        self.admit_queued().await;
+    }
+
+    async fn check_health(&mut self) -> ProcessHealth {
+        let now = Instant::now();
+        let deadline = tokio::time::Instant::now() + HEALTH_CHECK_TIMEOUT;
+        self.health_reports.retain(|id, _| self.games.contains_key(id));
+
+        let mut checks = vec![];
+        for (id, game) in self.games.iter().filter(|(_, game)| game.started) {
+            let (tx, rx) = oneshot::channel();
+            let sent = game.sender.try_send(GameMessage::HealthCheck(tx)).is_ok();
+
+            // a game that never answered is aged from its first check
+            self.health_reports.entry(*id).or_insert(HealthReport {
+                game_id: *id,
+                tick: 0,
+                last_tick: now,
+                interval: Duration::from_micros(game.config.tick_micros() as u64),
+                state: GameState::Lobby,
+                lag: Duration::ZERO,
+            });
+            checks.push((*id, sent.then_some(rx)));
+        }
+
+        let mut games = vec![];
+        for (id, rx) in checks {
+            let answer = match rx {
+                Some(rx) => tokio::time::timeout_at(deadline, rx).await.ok().and_then(|r| r.ok()),
+                None => None,
+            };
+            if let Some(report) = answer {
+                self.health_reports.insert(id, report);
+            }
+
+            let report = self.health_reports.get(&id).expect("inserted above");
+            games.push((id, game_health(report, Instant::now(), answer.is_some())));
+        }
+
+        return ProcessHealth::new(games);
+    }
+
+    /// has every running game write its state into the dump dir, returns how
+    /// many were asked to.
+    pub async fn dump_games(&self) -> usize {
+        let Some(dir) = self.config.dump_dir.as_ref() else {
+            warn!("state dump asked for, no dump dir set");
+            return 0;
+        };
+
+        let games = self.running_games();
+        warn!(games = games.len(), ?dir, "dumping games");
+        for sender in games.values() {
+            _ = sender.send(GameMessage::Dump(dir.clone())).await;
+        }
+
+        return games.len();
+    }
+
+    /// has every running game write its recovery image, returns how many were asked.
+    pub async fn snapshot_games(&self) -> usize {
+        let Some(dir) = self.config.recovery_dir.as_ref() else {
+            return 0;
+        };
+
+        let games = self.running_games();
+        for sender in games.values() {
+            // a game that is behind skips this round, the next one comes soon enough
+            _ = sender.try_send(GameMessage::Snapshot(dir.clone()));
+        }
+
+        return games.len();
+    }
+
+    /// games that were running when the server went down, found from the
+    /// images they left behind. they are only reported, nothing resumes them.
+    /// stale images and ones that no longer restore are deleted.
+    pub fn unfinished_games(&self) -> Vec<RecoveryImage> {
+        let Some(dir) = self.config.recovery_dir.as_ref() else {
+            return vec![];
+        };
+
+        // an image from before a map generator change describes another map
+        let (images, broken): (Vec<_>, Vec<_>) = load_images(dir, self.config.recovery_ttl, now_millis())
+            .into_iter()
+            .partition(|image| {
+                let filter = self.config.emote_filter.clone();
+                Game::<PLAYER_COUNT>::restore(image, Arc::new(AtomicU8::new(0)), self.config.game, filter).is_ok()
+            });
+
+        for image in broken {
+            warn!(game_id = image.game_id, "recovery image doesn't restore, deleting");
+            remove_image(dir, image.game_id);
+        }
+
+        return images;
+    }
 
-            info!("[GIM] sending connection message id={}", game_id);
-            _ = stub.sender.send(conn_message).await;
-            info!("[GIM] sent connection message id={}", game_id);
-            self.games.insert(game_id, stub);
-        } else {
-            info!("[GIM] connection message sent id={}", game_id);
-            _ = game.sender.send(conn_message).await;
-            info!("[GIM] sent connection message id={}", game_id);
+    // the motd goes out before the game gets the connection, None if the client went away
+    async fn greet(&self, sink: PlayerWebSink) -> Option<PlayerWebSink> {
+        let Some(motd) = &self.config.motd else {
+            return Some(sink);
+        };
+
+        let mut player_sink = PlayerSink::new(0, sink);
+        let motd = server::Message::Announcement(Announcement::new(ANNOUNCEMENT_INFO, motd));
+        player_sink.send(motd).await.ok()?;
+
+        return player_sink.sink.take();
+    }
+
+    /// asks every game for its status, games that don't answer in time are left out.
+    pub async fn query_all_status(&self) -> Vec<GameStatus> {
+        let mut queries = vec![];
+        for game in self.games.values() {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            if game.sender.try_send(GameMessage::QueryStatus(tx)).is_ok() {
+                queries.push(tokio::time::timeout(STATUS_TIMEOUT, rx));
+            }
         }
+
+        let mut statuses: Vec<GameStatus> = futures::future::join_all(queries)
+            .await
+            .into_iter()
+            .filter_map(|status| status.ok()?.ok())
+            .collect();
+
+        statuses.sort_by_key(|status| status.game_id);
+        return statuses;
+    }
+
+    /// the public games a browsing client can pick from.
+    fn game_list(&self) -> (GameList, HashMap<u32, BrowseEntry>) {
+        let mut listings = vec![];
+        let mut entries = HashMap::new();
+
+        for (id, game) in self.games.iter() {
+            if game.private_code.is_some() || game.tournament {
+                continue;
+            }
+
+            listings.push(GameListing {
+                game_id: *id,
+                state: if game.in_lobby { GAME_LISTING_LOBBY } else { GAME_LISTING_RUNNING },
+                players: game.player_count.load(std::sync::atomic::Ordering::Relaxed),
+                capacity: game.config.max_players.min(u8::MAX as usize) as u8,
+            });
+            entries.insert(
+                *id,
+                BrowseEntry {
+                    sender: game.sender.clone(),
+                    slots: game.slots.clone(),
+                },
+            );
+        }
+
+        listings.sort_by_key(|listing| listing.game_id);
+        listings.truncate(u8::MAX as usize);
+
+        return (GameList::new(listings, self.config.game.region), entries);
+    }
+
+    // what an admin connection can inspect, games that aren't running can't answer
+    fn running_games(&self) -> HashMap<u32, GameSender> {
+        return self
+            .games
+            .iter()
+            .filter(|(_, game)| game.started)
+            .map(|(id, game)| (*id, game.sender.clone()))
+            .collect();
     }
 
     pub fn get_all_game_status(&self) -> HashMap<usize, usize> {
         let mut game_status = HashMap::new();
         for (id, game) in self.games.iter() {
+            if game.private_code.is_some() || game.tournament {
+                continue;
+            }
+
             game_status.insert(
                 *id as usize,
                 game.player_count.load(std::sync::atomic::Ordering::Relaxed) as usize,
@@ -140,3 +1098,757 @@ impl GameManager {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use encoding::server::{
+        self, ServerMessage, JOIN_ERROR_BAD_NAME, JOIN_ERROR_FULL, JOIN_ERROR_NOT_FOUND,
+        JOIN_ERROR_NOT_REGISTERED, JOIN_ERROR_STARTED, WHO_AM_I_ADMIN, WHO_AM_I_CLIENT, WHO_AM_I_SPECTATOR,
+        ADMIN_ERROR_UNAUTHORIZED, ANNOUNCEMENT_INFO, ANNOUNCEMENT_MAX_LENGTH, ANNOUNCEMENT_WARNING,
+    };
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_tungstenite::tungstenite;
+
+    use crate::{
+        admin::AdminKeys,
+        allocator::GameAllocation,
+        game_comms::{GameComms, GameKey, GameMessage, GameResult, IncomingConnection},
+        game_config::{Balance, GameConfig, ManagerConfig},
+        game_state::GameState,
+        game_thread::GameThread,
+        health::{Health, HealthReport},
+        seed::SeedMode,
+        tick_rate::TickRate,
+        tournament::TournamentConfig,
+        test_utils::{complete_handshake, next_message, ws_pair},
+    };
+
+    use super::{AnnounceError, GameManager, GameStub, PRIVATE_CODE_ALPHABET};
+
+    #[tokio::test]
+    async fn test_private_code_routes_to_its_game() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let (code, key) = manager.create_private_game().await.expect("ids left");
+        let (_, other) = manager.create_private_game().await.expect("ids left");
+
+        assert_ne!(key, other);
+        assert!(code.iter().all(|c| PRIVATE_CODE_ALPHABET.contains(c)));
+        assert_eq!(manager.find_private_game(&code), Ok(key));
+        assert_eq!(
+            manager.find_private_game(&code.map(|c| c.to_ascii_lowercase())),
+            Ok(key)
+        );
+        assert!(manager.get_all_game_status().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_code_is_rejected() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let (code, key) = manager.create_private_game().await.expect("ids left");
+
+        let mut wrong = code;
+        wrong[0] = if code[0] == b'A' { b'B' } else { b'A' };
+        assert_eq!(manager.find_private_game(&wrong), Err(JOIN_ERROR_NOT_FOUND));
+
+        manager.comms.sender.send(GameMessage::Start(key)).await.unwrap();
+        manager.process_game_messages().await;
+        assert_eq!(manager.find_private_game(&code), Err(JOIN_ERROR_STARTED));
+    }
+
+    #[tokio::test]
+    async fn test_code_expires_when_game_ends() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let (code, key) = manager.create_private_game().await.expect("ids left");
+        assert!(manager.game_seed(key.id).is_some());
+
+        manager.comms.sender.send(GameMessage::Close(key)).await.unwrap();
+        manager.process_game_messages().await;
+
+        assert_eq!(manager.find_private_game(&code), Err(JOIN_ERROR_NOT_FOUND));
+        assert!(manager.private_games.is_empty());
+        // the allocator lets go of it too
+        assert_eq!(manager.game_seed(key.id), None);
+    }
+
+    #[tokio::test]
+    async fn test_codes_expire_when_more_games_end_than_the_channel_holds() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let mut codes = vec![];
+        for _ in 0..12 {
+            codes.push(manager.create_private_game().await.expect("ids left").0);
+        }
+
+        // nobody can join anymore, every lobby closes
+        for game in manager.games.values_mut() {
+            game.sender = mpsc::channel(1).0;
+        }
+        while manager.comms.sender.capacity() > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let drained = tokio::time::timeout(Duration::from_secs(5), async {
+            while !manager.games.is_empty() {
+                manager.process_game_messages().await;
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+        assert!(drained.await.is_ok(), "closes got lost");
+        for code in codes {
+            assert_eq!(manager.find_private_game(&code), Err(JOIN_ERROR_NOT_FOUND));
+        }
+        assert!(manager.private_games.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_key_for_recycled_id_is_rejected() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let allocation = GameAllocation { game_id: 5, seed: 5, base_seed: 5 };
+        let old = manager.create_game(allocation);
+
+        manager.comms.sender.send(GameMessage::Close(old)).await.unwrap();
+        manager.process_game_messages().await;
+
+        let new = manager.create_game(allocation);
+        assert_eq!(new, GameKey { id: 5, epoch: old.epoch + 1 });
+        assert!(manager.game(old).is_none());
+
+        // a late close for the old game must not take down the new one
+        manager.comms.sender.send(GameMessage::Close(old)).await.unwrap();
+        manager.comms.sender.send(GameMessage::Start(old)).await.unwrap();
+        manager.process_game_messages().await;
+
+        let game = manager.game(new).expect("new game is still registered");
+        assert!(game.in_lobby);
+    }
+
+    #[tokio::test]
+    async fn test_tournament_reruns_aborts_and_advances() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let config = TournamentConfig {
+            players: (1..=8).collect(),
+            games_per_round: 2,
+            advance_per_game: 2,
+        };
+        manager.start_tournament(config).await.expect("valid tournament");
+
+        let first = manager.find_tournament_game(1).expect("registered");
+        let second = manager.find_tournament_game(2).expect("registered");
+        assert_ne!(first, second);
+        assert_eq!(manager.find_tournament_game(3), Ok(first));
+        assert_eq!(manager.find_tournament_game(99), Err(JOIN_ERROR_NOT_REGISTERED));
+        assert!(manager.get_all_game_status().is_empty());
+
+        // the second game dies, its players get a fresh game
+        manager.comms.sender.send(GameMessage::Close(second)).await.unwrap();
+        manager.process_game_messages().await;
+        let rerun = manager.find_tournament_game(2).expect("rerun game");
+        assert_ne!(rerun, second);
+        assert_eq!(manager.find_tournament_game(8), Ok(rerun));
+
+        let results = [(first, vec![1, 3, 5, 7]), (rerun, vec![8, 6, 4, 2])];
+        for (key, placements) in results {
+            let result = GameResult {
+                placements,
+                leaderboard: vec![],
+                region: [0; server::REGION_LENGTH],
+                events: vec![],
+            };
+            manager.comms.sender.send(GameMessage::Result(key, result)).await.unwrap();
+            manager.comms.sender.send(GameMessage::Close(key)).await.unwrap();
+        }
+        manager.process_game_messages().await;
+
+        let standings = manager.tournament_standings().expect("tournament running");
+        assert_eq!(standings.round, 1);
+        assert_eq!(standings.games[0].participants, vec![1, 3, 8, 6]);
+
+        let last = manager.find_tournament_game(6).expect("advanced");
+        assert!(manager.game(last).is_some());
+        assert_eq!(manager.find_tournament_game(5), Err(JOIN_ERROR_NOT_REGISTERED));
+    }
+
+    #[tokio::test]
+    async fn test_finished_tournament_games_advance_the_bracket() -> anyhow::Result<()> {
+        let config = ManagerConfig {
+            game: GameConfig {
+                max_ticks: Some(30),
+                ..GameConfig::default()
+            },
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+        let tournament = TournamentConfig {
+            players: (1..=8).collect(),
+            games_per_round: 2,
+            advance_per_game: 2,
+        };
+        manager.start_tournament(tournament).await.expect("valid tournament");
+
+        let mut handshakes = vec![];
+        for token in 1..=8 {
+            let (server_socket, mut client) = ws_pair().await?;
+            let join = ServerMessage::new(0, server::Message::JoinTournament(token)).serialize()?;
+            client.send(tungstenite::Message::Binary(join)).await?;
+            let (sink, stream) = server_socket.split();
+            manager.add_connection(stream, sink).await;
+            handshakes.push(tokio::spawn(async move {
+                let msg = complete_handshake(&mut client).await;
+                return (token, client, msg);
+            }));
+        }
+
+        // 1 and 2 lead their games' participants but leave once they start,
+        // they only go out if the results place them
+        let mut clients = vec![];
+        for handshake in handshakes {
+            let (token, client, msg) = handshake.await?;
+            assert!(matches!(msg?.msg, server::Message::PlayerStart(_)));
+            if token > 2 {
+                clients.push(client);
+            }
+        }
+
+        let advanced = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while manager.tournament_standings().is_some_and(|standings| standings.round == 0) {
+                manager.process_game_messages().await;
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        });
+        assert!(advanced.await.is_ok(), "the bracket never advanced");
+
+        let standings = manager.tournament_standings().expect("tournament running");
+        assert_eq!(standings.games.len(), 1);
+        let mut eliminated: Vec<u64> = standings.eliminated.iter().map(|(player, _)| *player).collect();
+        eliminated.sort();
+        assert_eq!(&eliminated[..2], &[1, 2]);
+        assert!(!standings.games[0].participants.contains(&1));
+        assert!(!standings.games[0].participants.contains(&2));
+
+        return Ok(());
+    }
+
+    // a client socket that asked for the game list, and the list it got
+    async fn browsing_client(
+        manager: &mut GameManager,
+    ) -> anyhow::Result<(crate::test_utils::TestSocket, server::GameList)> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let list_games = ServerMessage::new(0, server::Message::ListGames).serialize()?;
+        client.send(tungstenite::Message::Binary(list_games)).await?;
+
+        let (sink, stream) = server_socket.split();
+        manager.add_connection(stream, sink).await;
+
+        match next_message(&mut client).await?.msg {
+            server::Message::GameList(list) => return Ok((client, list)),
+            msg => panic!("expected GameList, got {:?}", msg),
+        }
+    }
+
+    async fn join_game(client: &mut crate::test_utils::TestSocket, game_id: u32) -> anyhow::Result<()> {
+        let join = ServerMessage::new(0, server::Message::JoinGame(game_id)).serialize()?;
+        client.send(tungstenite::Message::Binary(join)).await?;
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_list_then_join() -> anyhow::Result<()> {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let lobby = manager.open_lobby().await.expect("room for a lobby");
+        manager.create_private_game().await.expect("ids left");
+
+        let (mut client, list) = browsing_client(&mut manager).await?;
+        assert_eq!(list.games.len(), 1);
+        assert_eq!(list.games[0].game_id, lobby.id);
+        assert_eq!(list.games[0].state, server::GAME_LISTING_LOBBY);
+        assert_eq!(list.region, Some([0; server::REGION_LENGTH]));
+        assert_eq!((list.games[0].players, list.games[0].capacity), (0, 100));
+
+        join_game(&mut client, lobby.id).await?;
+        let msg = complete_handshake(&mut client).await?;
+        assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_game_on_dedicated_thread_takes_players() -> anyhow::Result<()> {
+        let config = ManagerConfig {
+            game: GameConfig {
+                thread: GameThread::Dedicated(None),
+                ..GameConfig::default()
+            },
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+        let lobby = manager.open_lobby().await.expect("room for a lobby");
+
+        let (mut client, _) = browsing_client(&mut manager).await?;
+        join_game(&mut client, lobby.id).await?;
+        let msg = complete_handshake(&mut client).await?;
+        assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_list_then_timeout() -> anyhow::Result<()> {
+        let config = ManagerConfig {
+            browse_timeout: std::time::Duration::from_millis(100),
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+        manager.open_lobby().await;
+
+        let (mut client, _) = browsing_client(&mut manager).await?;
+        assert!(next_message(&mut client).await.is_err());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_join_game_that_filled_up() -> anyhow::Result<()> {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        let lobby = manager.open_lobby().await.expect("room for a lobby");
+
+        let (mut client, _) = browsing_client(&mut manager).await?;
+        let game = manager.game(lobby).expect("lobby exists");
+        game.player_count.store(1, std::sync::atomic::Ordering::Relaxed);
+
+        join_game(&mut client, lobby.id).await?;
+        assert_eq!(next_message(&mut client).await?.msg, server::Message::JoinError(JOIN_ERROR_FULL));
+
+        let (mut client, _) = browsing_client(&mut manager).await?;
+        join_game(&mut client, 4242).await?;
+        assert_eq!(next_message(&mut client).await?.msg, server::Message::JoinError(JOIN_ERROR_NOT_FOUND));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_games_take_seeds_from_the_sequence() {
+        let config = ManagerConfig {
+            seeds: SeedMode::Sequence(100),
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+
+        let mut seeds: Vec<Option<u32>> = vec![];
+        for _ in 0..3 {
+            let lobby = manager.open_lobby().await;
+            seeds.push(lobby.and_then(|key| manager.game_seed(key.id)));
+        }
+        assert_eq!(seeds, vec![Some(100), Some(101), Some(102)]);
+    }
+
+    #[test]
+    fn test_unreadable_id_state_fails_startup() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("vim-royale-bad-ids-{}", std::process::id()));
+        std::fs::write(&path, "not a number")?;
+        let config = ManagerConfig {
+            id_state_path: Some(path.clone()),
+            ..ManagerConfig::default()
+        };
+
+        assert!(GameManager::new(config).is_err());
+
+        std::fs::remove_file(&path)?;
+        return Ok(());
+    }
+
+    #[test]
+    fn test_broken_audit_log_fails_startup() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("vim-royale-bad-audit-{}.jsonl", std::process::id()));
+        std::fs::write(&path, "{\"cut off\n")?;
+        let config = ManagerConfig {
+            audit_log: Some(path.clone()),
+            ..ManagerConfig::default()
+        };
+
+        assert!(GameManager::new(config).is_err());
+
+        std::fs::remove_file(&path)?;
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_lobby_turnover_until_max_games() -> anyhow::Result<()> {
+        let config = ManagerConfig {
+            max_games: 3,
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+        let mut clients = vec![];
+
+        for _ in 0..3 {
+            let (server_socket, mut client) = ws_pair().await?;
+            let whoami = ServerMessage::CLIENT_WHO_AM_I.serialize()?;
+            client.send(tungstenite::Message::Binary(whoami)).await?;
+
+            let (sink, stream) = server_socket.split();
+            manager.add_connection(stream, sink).await;
+
+            // every connection starts its own game
+            let msg = complete_handshake(&mut client).await?;
+            assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+            clients.push(client);
+        }
+
+        manager.process_game_messages().await;
+        assert_eq!(manager.games.len(), 3);
+        assert_eq!(manager.query_all_status().await.len(), 3);
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let whoami = ServerMessage::CLIENT_WHO_AM_I.serialize()?;
+        client.send(tungstenite::Message::Binary(whoami)).await?;
+        let (sink, stream) = server_socket.split();
+        manager.add_connection(stream, sink).await;
+
+        match next_message(&mut client).await?.msg {
+            server::Message::ServerFull(wait) => assert!(wait > 0),
+            msg => panic!("expected ServerFull, got {:?}", msg),
+        }
+
+        return Ok(());
+    }
+
+    async fn connect_admin(manager: &mut GameManager) -> anyhow::Result<crate::test_utils::TestSocket> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let whoami = ServerMessage::new(0, server::Message::Whoami(WHO_AM_I_ADMIN)).serialize()?;
+        client.send(tungstenite::Message::Binary(whoami)).await?;
+
+        let (sink, stream) = server_socket.split();
+        manager.add_connection(stream, sink).await;
+
+        return Ok(client);
+    }
+
+    #[tokio::test]
+    async fn test_admin_connections_need_a_key() -> anyhow::Result<()> {
+        // moderating doesn't need the debugging admin commands
+        let mut config = ManagerConfig {
+            moderation: true,
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config.clone()).expect("manager starts");
+
+        // no keys configured, nobody gets in
+        let mut client = connect_admin(&mut manager).await?;
+        assert!(!matches!(client.next().await, Some(Ok(tungstenite::Message::Binary(_)))));
+
+        config.admin_keys = AdminKeys::parse("alice hunter2")?;
+        let mut manager = GameManager::new(config.clone()).expect("manager starts");
+
+        let mut client = connect_admin(&mut manager).await?;
+        client.send(tungstenite::Message::Text(":login alice hunter3".to_string())).await?;
+        match next_message(&mut client).await?.msg {
+            server::Message::AdminError(code) => assert_eq!(code, ADMIN_ERROR_UNAUTHORIZED),
+            msg => panic!("expected AdminError, got {:?}", msg),
+        }
+
+        let mut client = connect_admin(&mut manager).await?;
+        client.send(tungstenite::Message::Text(":login alice hunter2".to_string())).await?;
+        let inspect = server::Message::InspectGame(server::InspectGame { game_id: 9, interval_secs: 0 });
+        client.send(tungstenite::Message::Binary(ServerMessage::new(0, inspect).serialize()?)).await?;
+        match next_message(&mut client).await?.msg {
+            server::Message::JoinError(reason) => assert_eq!(reason, JOIN_ERROR_NOT_FOUND),
+            msg => panic!("expected JoinError, got {:?}", msg),
+        }
+
+        // keys alone don't open admin sessions
+        config.moderation = false;
+        let mut manager = GameManager::new(config).expect("manager starts");
+        let mut client = connect_admin(&mut manager).await?;
+        assert!(!matches!(client.next().await, Some(Ok(tungstenite::Message::Binary(_)))));
+
+        return Ok(());
+    }
+
+    // a public client connection, handed to the manager
+    async fn connect_client(manager: &mut GameManager) -> anyhow::Result<crate::test_utils::TestSocket> {
+        let (server_socket, mut client) = ws_pair().await?;
+        let whoami = ServerMessage::CLIENT_WHO_AM_I.serialize()?;
+        client.send(tungstenite::Message::Binary(whoami)).await?;
+
+        let (sink, stream) = server_socket.split();
+        manager.add_connection(stream, sink).await;
+
+        return Ok(client);
+    }
+
+    #[tokio::test]
+    async fn test_players_past_max_games_wait_in_the_queue() -> anyhow::Result<()> {
+        let config = ManagerConfig {
+            max_games: 1,
+            max_queued: 1,
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+
+        let mut playing = connect_client(&mut manager).await?;
+        assert!(matches!(complete_handshake(&mut playing).await?.msg, server::Message::PlayerStart(_)));
+        manager.process_game_messages().await;
+
+        let mut queued = connect_client(&mut manager).await?;
+        assert_eq!(next_message(&mut queued).await?.msg, server::Message::PlayerQueueCountResult(1));
+        let mut turned_away = connect_client(&mut manager).await?;
+        assert!(matches!(next_message(&mut turned_away).await?.msg, server::Message::ServerFull(_)));
+        assert_eq!(manager.games.len(), 1);
+
+        // the only game ends once its player leaves, the queue moves up
+        drop(playing);
+        for _ in 0..100 {
+            manager.heartbeat().await;
+            if manager.queue.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        assert!(matches!(complete_handshake(&mut queued).await?.msg, server::Message::PlayerStart(_)));
+        assert!(manager.games.len() <= 1);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_named_connections() -> anyhow::Result<()> {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+
+        for (name, accepted) in [("vim enjoyer", true), ("way_too_long_for_a_name", false), ("tab\there", false)] {
+            let (server_socket, mut client) = ws_pair().await?;
+            let named = server::Message::NamedWhoami(server::NamedWhoami {
+                whoami: WHO_AM_I_CLIENT,
+                name: server::PlayerName::new(name),
+            });
+            client.send(tungstenite::Message::Binary(ServerMessage::new(0, named).serialize()?)).await?;
+
+            let (sink, stream) = server_socket.split();
+            manager.add_connection(stream, sink).await;
+
+            let msg = complete_handshake(&mut client).await?;
+            if accepted {
+                assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+            } else {
+                assert_eq!(msg.msg, server::Message::JoinError(JOIN_ERROR_BAD_NAME));
+            }
+        }
+
+        return Ok(());
+    }
+
+    // skips snapshots and whatever else the game sends in between, a running
+    // game sends it in the tick's event batch
+    async fn next_announcement(client: &mut crate::test_utils::TestSocket) -> anyhow::Result<server::Announcement> {
+        loop {
+            let events = match next_message(client).await?.msg {
+                server::Message::EventBatch(batch) => batch.events,
+                server::Message::Announcement(announcement) => return Ok(announcement),
+                _ => continue,
+            };
+            for event in events {
+                if let server::GameEvent::Announcement(announcement) = event {
+                    return Ok(announcement);
+                }
+            }
+        }
+    }
+
+    // waits until the game has the players and spectators in its slots
+    async fn wait_for(manager: &GameManager, key: GameKey, players: usize, spectators: usize) {
+        loop {
+            let status = manager.query_all_status().await;
+            if status.iter().any(|status| {
+                status.game_id == key.id
+                    && status.names.len() == players
+                    && status.spectator_count == spectators
+            }) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announcement_reaches_lobby_live_and_spectators() -> anyhow::Result<()> {
+        let mut config = ManagerConfig::default();
+        config.game.min_players = 2;
+        let mut manager = GameManager::new(config).expect("manager starts");
+
+        let lobby = manager.open_lobby().await.expect("room for a lobby");
+        let (server_socket, mut lobby_client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        let conn = GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT));
+        manager.game(lobby).expect("lobby exists").sender.send(conn).await?;
+        let lobby_handshake = tokio::spawn(async move {
+            let msg = complete_handshake(&mut lobby_client).await;
+            return (lobby_client, msg);
+        });
+        wait_for(&manager, lobby, 1, 0).await;
+
+        let allocation = manager.allocate_game().await.expect("ids left");
+        let live = manager.create_game_with(allocation, GameConfig::default());
+        let sender = manager.game(live).expect("game exists").sender.clone();
+
+        let (server_socket, mut live_client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        let msg = complete_handshake(&mut live_client).await?;
+        assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+
+        let (server_socket, mut spectator) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_SPECTATOR))).await?;
+        wait_for(&manager, live, 1, 1).await;
+
+        manager.announce(ANNOUNCEMENT_WARNING, "restart in 5 minutes").await.expect("first announcement");
+
+        let expected = server::Announcement::new(ANNOUNCEMENT_WARNING, "restart in 5 minutes");
+        let (_lobby_client, msg) = lobby_handshake.await?;
+        assert_eq!(msg?.msg, server::Message::Announcement(expected.clone()));
+        assert_eq!(next_announcement(&mut live_client).await?, expected);
+        assert_eq!(next_announcement(&mut spectator).await?, expected);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_announcements_are_limited() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+        manager.open_lobby().await;
+
+        let too_long = "a".repeat(ANNOUNCEMENT_MAX_LENGTH + 1);
+        assert_eq!(manager.announce(ANNOUNCEMENT_INFO, "").await, Err(AnnounceError::Empty));
+        assert_eq!(manager.announce(ANNOUNCEMENT_INFO, &too_long).await, Err(AnnounceError::TooLong));
+        assert_eq!(manager.announce(ANNOUNCEMENT_INFO, "\x1b[2J").await, Err(AnnounceError::BadCharacter));
+
+        // rejected ones don't count against the limit
+        assert_eq!(manager.announce(ANNOUNCEMENT_INFO, "hello").await, Ok(()));
+        assert!(matches!(
+            manager.announce(ANNOUNCEMENT_INFO, "hello again").await,
+            Err(AnnounceError::RateLimited(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_motd_comes_first() -> anyhow::Result<()> {
+        let config = ManagerConfig {
+            motd: Some("welcome to vim royale".to_string()),
+            ..ManagerConfig::default()
+        };
+        let mut manager = GameManager::new(config).expect("manager starts");
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let whoami = ServerMessage::CLIENT_WHO_AM_I.serialize()?;
+        client.send(tungstenite::Message::Binary(whoami)).await?;
+        let (sink, stream) = server_socket.split();
+        manager.add_connection(stream, sink).await;
+
+        let motd = server::Announcement::new(ANNOUNCEMENT_INFO, "welcome to vim royale");
+        assert_eq!(next_message(&mut client).await?.msg, server::Message::Announcement(motd));
+        let msg = complete_handshake(&mut client).await?;
+        assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+
+        return Ok(());
+    }
+
+    // a running game nobody answers for, the caller plays its loop with the comms
+    fn mock_game(manager: &mut GameManager, id: u32, tick_rate: u32) -> GameComms {
+        let key = GameKey { id, epoch: 0 };
+        let config = GameConfig {
+            tick_rate: TickRate::from_hz(tick_rate).expect("test tick rate"),
+            ..manager.config.game
+        };
+        let mut stub = GameStub::new(manager.comms.sender.clone(), key, 0, config);
+        stub.started = true;
+        let comms = stub.comms.take().expect("fresh stub has comms");
+        manager.games.insert(id, stub);
+
+        return comms;
+    }
+
+    // answers every health check as a game ticking once a second, that last
+    // ticked seconds_ago seconds ago and is lag_ms behind the wall clock
+    fn answer_health(mut comms: GameComms, game_id: u32, seconds_ago: u64, lag_ms: u64) {
+        let interval = std::time::Duration::from_secs(1);
+        tokio::spawn(async move {
+            while let Some(msg) = comms.receiver.recv().await {
+                if let GameMessage::HealthCheck(tx) = msg {
+                    _ = tx.send(HealthReport {
+                        game_id,
+                        tick: 5,
+                        last_tick: std::time::Instant::now() - std::time::Duration::from_secs(seconds_ago),
+                        interval,
+                        state: GameState::Live,
+                        lag: std::time::Duration::from_millis(lag_ms),
+                    });
+                }
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_health_of_healthy_slow_and_stuck_games() {
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+
+        let healthy = mock_game(&mut manager, 99, 1);
+        answer_health(healthy, 99, 0, 0);
+        let slow = mock_game(&mut manager, 100, 1);
+        answer_health(slow, 100, 5, 0);
+
+        // a loop that locked up, nothing ever reads its channel. 10 ticks a
+        // second so it's stalled after a second
+        let _stuck = mock_game(&mut manager, 101, 10);
+
+        let health = manager.health().await;
+        assert_eq!(health.status, Health::Degraded);
+        assert_eq!(
+            health.games,
+            vec![(99, Health::Ok), (100, Health::Degraded), (101, Health::Degraded)]
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let start = std::time::Instant::now();
+        let health = manager.health().await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(health.status, Health::Stalled);
+        assert_eq!(
+            health.games,
+            vec![(99, Health::Ok), (100, Health::Degraded), (101, Health::Stalled)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_balance_skips_a_lagging_lobby() {
+        let max_lag = std::time::Duration::from_millis(100);
+        let mut config = ManagerConfig {
+            balance: Balance::Auto { max_lag },
+            ..ManagerConfig::default()
+        };
+        config.game.min_players = 4;
+        let mut manager = GameManager::new(config).expect("manager starts");
+
+        // the emptier one, it would get the player if it kept up
+        let lagging = mock_game(&mut manager, 1, 1);
+        answer_health(lagging, 1, 0, 500);
+        let healthy = mock_game(&mut manager, 2, 1);
+        answer_health(healthy, 2, 0, 0);
+        manager.games[&2].player_count.store(2, std::sync::atomic::Ordering::Relaxed);
+
+        // nothing heard from either yet
+        let (id, reservation) = manager.balanced_lobby(max_lag).expect("both have room");
+        assert_eq!(id, 1);
+        drop(reservation);
+
+        manager.heartbeat().await;
+        let (id, _reservation) = manager.balanced_lobby(max_lag).expect("the healthy one has room");
+        assert_eq!(id, 2);
+
+        // with the healthy one full the player is better off in a new lobby
+        manager.games[&2].player_count.store(4, std::sync::atomic::Ordering::Relaxed);
+        assert!(manager.balanced_lobby(max_lag).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum GameState {
+    Lobby,
+    WarmUp,
+    Live,
+    Ended,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateEvent {
+    // start_game finished at the given tick
+    Started(u128),
+    Tick(u128),
+    AdminStart,
+    Empty,
+    // hit GameConfig::max_ticks
+    TimeUp,
+}
+
+pub struct GameStateMachine {
+    state: GameState,
+    warmup_ticks: u128,
+    warmup_end: u128,
+}
+
+impl GameStateMachine {
+    pub fn new(warmup_ticks: u128) -> Self {
+        return GameStateMachine {
+            state: GameState::Lobby,
+            warmup_ticks,
+            warmup_end: 0,
+        };
+    }
+
+    /// picks up where a recovery image left off.
+    pub fn restored(state: GameState, warmup_ticks: u128, warmup_end: u128) -> Self {
+        return GameStateMachine {
+            state,
+            warmup_ticks,
+            warmup_end,
+        };
+    }
+
+    pub fn state(&self) -> GameState {
+        return self.state;
+    }
+
+    pub fn warmup_end(&self) -> u128 {
+        return self.warmup_end;
+    }
+
+    /// applies the event, returns the new state if it changed.
+    pub fn handle(&mut self, event: StateEvent) -> Option<GameState> {
+        let next = match (self.state, event) {
+            (GameState::Ended, _) => None,
+            (_, StateEvent::Empty | StateEvent::TimeUp) => Some(GameState::Ended),
+
+            (GameState::Lobby, StateEvent::Started(tick)) => {
+                self.warmup_end = tick + self.warmup_ticks;
+                if self.warmup_ticks == 0 {
+                    Some(GameState::Live)
+                } else {
+                    Some(GameState::WarmUp)
+                }
+            }
+
+            (GameState::WarmUp, StateEvent::Tick(tick)) if tick >= self.warmup_end => {
+                Some(GameState::Live)
+            }
+            (GameState::WarmUp, StateEvent::AdminStart) => Some(GameState::Live),
+
+            _ => None,
+        };
+
+        if let Some(next) = next {
+            self.state = next;
+        }
+
+        return next;
+    }
+
+    /// ticks left in warm up, None outside of it.
+    pub fn warmup_remaining(&self, tick: u128) -> Option<u128> {
+        if self.state != GameState::WarmUp {
+            return None;
+        }
+
+        return Some(self.warmup_end.saturating_sub(tick));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GameState, GameStateMachine, StateEvent};
+
+    #[test]
+    fn test_warmup_then_live() {
+        let mut state = GameStateMachine::new(10);
+        assert_eq!(state.handle(StateEvent::Tick(1)), None);
+        assert_eq!(state.handle(StateEvent::Started(5)), Some(GameState::WarmUp));
+        assert_eq!(state.warmup_remaining(7), Some(8));
+        assert_eq!(state.handle(StateEvent::Tick(14)), None);
+        assert_eq!(state.handle(StateEvent::Tick(15)), Some(GameState::Live));
+        assert_eq!(state.warmup_remaining(16), None);
+        assert_eq!(state.handle(StateEvent::AdminStart), None);
+    }
+
+    #[test]
+    fn test_no_warmup_goes_live() {
+        let mut state = GameStateMachine::new(0);
+        assert_eq!(state.handle(StateEvent::Started(0)), Some(GameState::Live));
+    }
+
+    #[test]
+    fn test_admin_start_skips_warmup() {
+        let mut state = GameStateMachine::new(1_000);
+        state.handle(StateEvent::Started(0));
+        assert_eq!(state.handle(StateEvent::AdminStart), Some(GameState::Live));
+    }
+
+    #[test]
+    fn test_empty_ends_from_any_state() {
+        for started in [false, true] {
+            let mut state = GameStateMachine::new(10);
+            if started {
+                state.handle(StateEvent::Started(0));
+            }
+
+            assert_eq!(state.handle(StateEvent::Empty), Some(GameState::Ended));
+            assert_eq!(state.handle(StateEvent::Started(0)), None);
+            assert_eq!(state.state(), GameState::Ended);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::future::Future;
+
+use tracing::{error, warn};
+use tokio::task::JoinHandle;
+
+/// where a game's loop runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameThread {
+    // a task on the server's runtime, next to every connection and other game
+    Shared,
+    // its own OS thread with a single threaded runtime, pinned to the core
+    // when there is one. the game's connection tasks run there too
+    Dedicated(Option<usize>),
+}
+
+/// keeps the calling thread on that one core, linux only.
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(core: usize) -> std::io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "core past CPU_SETSIZE"));
+    }
+
+    // SAFETY: the set is plain data, zeroed is the empty set, and core is in range
+    let pinned = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+
+    if pinned != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(_core: usize) -> std::io::Result<()> {
+    return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "pinning is linux only"));
+}
+
+/// starts a game where its config says. a dedicated game takes a thread from
+/// the blocking pool for as long as it runs, a core it can't be pinned to
+/// only costs a warning.
+pub fn spawn_game<F>(thread: GameThread, run: F) -> JoinHandle<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let GameThread::Dedicated(core) = thread else {
+        return tokio::spawn(run);
+    };
+
+    return tokio::task::spawn_blocking(move || {
+        if let Some(core) = core {
+            if let Err(e) = pin_to_core(core) {
+                warn!(core, error = ?e, "game thread not pinned");
+            }
+        }
+
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!(error = ?e, "game runtime failed to start");
+                return;
+            }
+        };
+
+        runtime.block_on(run);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::oneshot;
+
+    use super::{spawn_game, GameThread};
+
+    const PERIOD: Duration = Duration::from_millis(5);
+    const HOG: Duration = Duration::from_millis(50);
+
+    // a tick loop like the game's, reports how late its worst tick was
+    async fn worst_lateness(ticks: u32, done: oneshot::Sender<Duration>) {
+        let start = tokio::time::Instant::now();
+        let mut worst = Duration::ZERO;
+        for tick in 1..=ticks {
+            let deadline = start + PERIOD * tick;
+            tokio::time::sleep_until(deadline).await;
+            worst = worst.max(tokio::time::Instant::now() - deadline);
+        }
+        _ = done.send(worst);
+    }
+
+    async fn jitter_next_to_a_hog(thread: GameThread) -> Duration {
+        let (tx, rx) = oneshot::channel();
+        spawn_game(thread, worst_lateness(40, tx));
+
+        // hogs the shared runtime, a tick loop on it has to wait its turn
+        let until = Instant::now() + PERIOD * 40;
+        let hog = tokio::spawn(async move {
+            while Instant::now() < until {
+                std::thread::sleep(HOG);
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let worst = rx.await.expect("tick loop finished");
+        _ = hog.await;
+        return worst;
+    }
+
+    #[tokio::test]
+    async fn test_dedicated_thread_has_less_jitter() {
+        let shared = jitter_next_to_a_hog(GameThread::Shared).await;
+        let dedicated = jitter_next_to_a_hog(GameThread::Dedicated(None)).await;
+
+        assert!(shared >= HOG * 3 / 4, "shared worst tick {:?}", shared);
+        assert!(dedicated < HOG / 2, "dedicated worst tick {:?}", dedicated);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_game_still_runs() {
+        let (tx, rx) = oneshot::channel();
+        spawn_game(GameThread::Dedicated(Some(0)), worst_lateness(2, tx)).await.expect("game thread");
+        assert!(rx.await.is_ok());
+    }
+}
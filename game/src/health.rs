@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::game_state::GameState;
+
+// ticks without progress before a game counts as degraded / stalled
+pub const SLOW_TICKS: u32 = 2;
+pub const STALL_TICKS: u32 = 10;
+
+// how long a health check waits on games before going by what it knows
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_millis(100);
+
+// worst last, the process is as healthy as its worst game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    Ok,
+    Degraded,
+    Stalled,
+}
+
+impl Health {
+    pub fn label(&self) -> &'static str {
+        return match self {
+            Health::Ok => "ok",
+            Health::Degraded => "degraded",
+            Health::Stalled => "stalled",
+        };
+    }
+}
+
+/// a game's answer to GameMessage::HealthCheck, from whichever loop it's in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HealthReport {
+    pub game_id: u32,
+    pub tick: u128,
+    // when the loop last went around, the lobby counts its checks as ticks
+    pub last_tick: Instant,
+    // how often the loop is supposed to go around in its current state
+    pub interval: Duration,
+    pub state: GameState,
+    // how far the loop is behind the wall clock, see DriftMonitor
+    pub lag: Duration,
+}
+
+/// answered tells if the game replied to this check, a game that didn't is
+/// judged by its last report and is never better than degraded.
+pub fn game_health(report: &HealthReport, now: Instant, answered: bool) -> Health {
+    let age = now.saturating_duration_since(report.last_tick);
+    if age > report.interval * STALL_TICKS {
+        return Health::Stalled;
+    }
+
+    if !answered || age > report.interval * SLOW_TICKS {
+        return Health::Degraded;
+    }
+
+    return Health::Ok;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessHealth {
+    pub status: Health,
+    // (game id, health) of every running game
+    pub games: Vec<(u32, Health)>,
+}
+
+impl ProcessHealth {
+    pub fn new(mut games: Vec<(u32, Health)>) -> Self {
+        games.sort_by_key(|(id, _)| *id);
+        let status = games.iter().map(|(_, health)| *health).max().unwrap_or(Health::Ok);
+        return ProcessHealth { status, games };
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!("{}\n", self.status.label());
+        for (id, health) in self.games.iter() {
+            out.push_str(&format!("game {} {}\n", id, health.label()));
+        }
+
+        return out;
+    }
+}
+
+/// answers GET /health, 503 once anything stalled so orchestration restarts us.
+pub async fn respond(mut stream: TcpStream, health: ProcessHealth) {
+    let mut request = [0; 1024];
+    let Ok(read) = stream.read(&mut request).await else {
+        return;
+    };
+
+    let response = if request[..read].starts_with(b"GET /health ") {
+        let status = match health.status {
+            Health::Stalled => "503 Service Unavailable",
+            _ => "200 OK",
+        };
+        let body = health.render();
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        warn!(error = ?e, "health response failed");
+    }
+    _ = stream.shutdown().await;
+}
+
+/// the next health check connection, never resolves without a listener.
+/// the GameManager has to answer them, so they are accepted where it lives.
+pub async fn accept(listener: Option<&TcpListener>) -> TcpStream {
+    let Some(listener) = listener else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => return stream,
+            Err(e) => info!(error = ?e, "health accept failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::game_state::GameState;
+
+    use super::{game_health, Health, HealthReport, ProcessHealth};
+
+    #[test]
+    fn test_health_by_last_tick() {
+        let now = Instant::now();
+        let report = |ago_ms: u64| HealthReport {
+            game_id: 1,
+            tick: 10,
+            last_tick: now - Duration::from_millis(ago_ms),
+            interval: Duration::from_millis(10),
+            state: GameState::Live,
+            lag: Duration::ZERO,
+        };
+
+        assert_eq!(game_health(&report(5), now, true), Health::Ok);
+        assert_eq!(game_health(&report(50), now, true), Health::Degraded);
+        assert_eq!(game_health(&report(5), now, false), Health::Degraded);
+        assert_eq!(game_health(&report(150), now, true), Health::Stalled);
+
+        let health = ProcessHealth::new(vec![(3, Health::Ok), (1, Health::Degraded)]);
+        assert_eq!(health.status, Health::Degraded);
+        assert_eq!(health.render(), "degraded\ngame 1 degraded\ngame 3 ok\n");
+        assert_eq!(ProcessHealth::new(vec![]).status, Health::Ok);
+    }
+}
@@ -0,0 +1,48 @@
+use encoding::{
+    fixed,
+    server::{self, FinePosition, FineSnapshot, PlayerPositionUpdate},
+};
+
+use crate::game_config::PositionFormat;
+
+// roughly half a terminal, anything further away can't be on screen
+pub const VIEW_DISTANCE: u16 = 40;
+
+pub fn in_range(a: (u16, u16), b: (u16, u16), range: u16) -> bool {
+    return a.0.abs_diff(b.0) <= range && a.1.abs_diff(b.1) <= range;
+}
+
+// in tiles, the same square in_range measures
+pub fn distance(a: (u16, u16), b: (u16, u16)) -> u16 {
+    return a.0.abs_diff(b.0).max(a.1.abs_diff(b.1));
+}
+
+/// entities within range of center, None means everything is relevant.
+pub fn entities_in_range(
+    entities: &[PlayerPositionUpdate],
+    center: (u16, u16),
+    range: Option<u16>,
+) -> Vec<PlayerPositionUpdate> {
+    return entities
+        .iter()
+        .filter(|e| range.is_none_or(|range| in_range(center, e.position, range)))
+        .cloned()
+        .collect();
+}
+
+/// what a snapshot of entities goes out as.
+pub fn snapshot_message(format: PositionFormat, tick: u32, entities: Vec<PlayerPositionUpdate>) -> server::Message {
+    return match format {
+        PositionFormat::Tile => server::Message::Snapshot(server::Snapshot::new(tick, entities)),
+        PositionFormat::Fixed => {
+            let entities = entities
+                .into_iter()
+                .map(|e| FinePosition {
+                    entity_id: e.entity_id,
+                    position: fixed::from_tiles(e.position),
+                })
+                .collect();
+            server::Message::FineSnapshot(FineSnapshot::new(tick, entities))
+        }
+    };
+}
@@ -1,7 +1,54 @@
+pub mod accept;
+pub mod admin;
+pub mod allocator;
+pub mod audit;
+pub mod bot;
+pub mod capture;
+pub mod chaos;
+pub mod clock;
 pub mod connection;
+pub mod drift;
+pub mod dump;
+pub mod emote;
+pub mod entity_ids;
+pub mod events;
+pub mod executor;
 pub mod game;
 pub mod sub_games;
 pub mod game_manager;
 pub mod game_comms;
+pub mod game_config;
+pub mod game_state;
+pub mod game_thread;
+pub mod health;
+pub mod interest;
+pub mod log_sampler;
+pub mod logging;
+pub mod loot;
+pub mod metrics;
+pub mod moderation;
+pub mod movement;
+pub mod names;
+pub mod outcome;
 pub mod player;
+pub mod player_slab;
+pub mod recovery;
+pub mod seed;
+pub mod send_stats;
+pub mod server;
+pub mod shaping;
+pub mod shared_message;
+pub mod slots;
+pub mod spatial_grid;
+pub mod spectator;
+pub mod standings;
+pub mod status;
+pub mod telemetry;
+pub mod tick_rate;
+pub mod tournament;
+pub mod traffic;
+pub mod transport;
+pub mod zone;
 
+#[cfg(test)]
+mod test_utils;
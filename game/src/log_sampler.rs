@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const SAMPLE_WINDOW: Duration = Duration::from_secs(1);
+// per key per window, enough to see what is going on without a 100 player
+// game at 60hz drowning the logger
+pub const HOT_LOGS_PER_WINDOW: u32 = 5;
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    logged: u32,
+    suppressed: u64,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        return Self {
+            started: now,
+            logged: 0,
+            suppressed: 0,
+        };
+    }
+}
+
+/// lets through at most `limit` occurrences of an event key per
+/// SAMPLE_WINDOW. what it drops is counted, the next line logged for the key
+/// carries the count and `expired` hands out the counts of keys that went
+/// quiet, so nothing disappears without a trace.
+#[derive(Debug)]
+pub struct LogSampler {
+    limit: u32,
+    windows: HashMap<&'static str, Window>,
+}
+
+impl LogSampler {
+    pub fn new(limit: u32) -> Self {
+        return Self {
+            limit,
+            windows: HashMap::new(),
+        };
+    }
+
+    /// Some when this occurrence should be logged, with how many of the key
+    /// were suppressed in the window before it. None to drop it.
+    pub fn sample(&mut self, key: &'static str, now: Instant) -> Option<u64> {
+        let window = self.windows.entry(key).or_insert_with(|| Window::new(now));
+        let mut suppressed = 0;
+        if now.duration_since(window.started) >= SAMPLE_WINDOW {
+            suppressed = window.suppressed;
+            *window = Window::new(now);
+        }
+
+        if window.logged < self.limit {
+            window.logged += 1;
+            return Some(suppressed);
+        }
+
+        window.suppressed += 1;
+        return None;
+    }
+
+    /// (key, suppressed) for every window that closed with events dropped
+    /// and nothing logged since. log these as summaries.
+    pub fn expired(&mut self, now: Instant) -> Vec<(&'static str, u64)> {
+        let mut summaries = vec![];
+        self.windows.retain(|key, window| {
+            if now.duration_since(window.started) < SAMPLE_WINDOW {
+                return true;
+            }
+            if window.suppressed > 0 {
+                summaries.push((*key, window.suppressed));
+            }
+            return false;
+        });
+
+        return summaries;
+    }
+
+    /// the suppressed counts of every window, closed or not, for when
+    /// whatever is logging is going away.
+    pub fn drain(&mut self) -> Vec<(&'static str, u64)> {
+        return self
+            .windows
+            .drain()
+            .filter(|(_, window)| window.suppressed > 0)
+            .map(|(key, window)| (key, window.suppressed))
+            .collect();
+    }
+}
+
+impl Default for LogSampler {
+    fn default() -> Self {
+        return Self::new(HOT_LOGS_PER_WINDOW);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{LogSampler, SAMPLE_WINDOW};
+
+    #[test]
+    fn test_window_limits_and_carries_the_suppressed_count() {
+        let start = Instant::now();
+        let mut sampler = LogSampler::new(3);
+
+        let logged: Vec<Option<u64>> = (0..10).map(|_| sampler.sample("move", start)).collect();
+        assert_eq!(logged[..3], [Some(0), Some(0), Some(0)]);
+        assert!(logged[3..].iter().all(|l| l.is_none()));
+
+        // keys don't share a window
+        assert_eq!(sampler.sample("emote", start), Some(0));
+
+        let almost = start + SAMPLE_WINDOW - Duration::from_millis(1);
+        assert_eq!(sampler.sample("move", almost), None);
+
+        // the first line of the next window reports what the last one dropped
+        let next = start + SAMPLE_WINDOW;
+        assert_eq!(sampler.sample("move", next), Some(8));
+        assert_eq!(sampler.sample("move", next), Some(0));
+        assert_eq!(sampler.sample("move", next), Some(0));
+        assert_eq!(sampler.sample("move", next), None);
+        assert_eq!(sampler.drain(), vec![("move", 1)]);
+        assert!(sampler.drain().is_empty());
+    }
+
+    #[test]
+    fn test_quiet_keys_get_a_summary() {
+        let start = Instant::now();
+        let mut sampler = LogSampler::new(1);
+
+        for _ in 0..4 {
+            sampler.sample("move", start);
+        }
+        sampler.sample("emote", start);
+
+        assert!(sampler.expired(start + Duration::from_millis(500)).is_empty());
+
+        // emote never dropped anything, it just goes
+        let later = start + SAMPLE_WINDOW;
+        assert_eq!(sampler.expired(later), vec![("move", 3)]);
+        assert!(sampler.expired(later).is_empty());
+
+        // already summarised, not carried again
+        assert_eq!(sampler.sample("move", later), Some(0));
+    }
+}
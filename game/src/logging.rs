@@ -0,0 +1,468 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+/// RUST_LOG style filter: a default level plus `target=level` directives,
+/// the longest matching target wins. `off` turns a target off.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    default: Option<Level>,
+    directives: Vec<(String, Option<Level>)>,
+}
+
+fn parse_level(level: &str) -> Result<Option<Level>, String> {
+    if level.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+
+    return level.parse::<Level>().map(Some).map_err(|_| format!("unknown level {}", level));
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Filter {
+            default: Some(Level::ERROR),
+            directives: vec![],
+        };
+
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => filter.directives.push((target.to_string(), parse_level(level)?)),
+                None => match parse_level(directive) {
+                    Ok(level) => filter.default = level,
+                    // a bare target means everything from it
+                    Err(_) => filter.directives.push((directive.to_string(), Some(Level::TRACE))),
+                },
+            }
+        }
+
+        return Ok(filter);
+    }
+
+    pub fn enabled(&self, target: &str, level: &Level) -> bool {
+        let max = self
+            .directives
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default);
+
+        // more verbose levels compare greater
+        return max.is_some_and(|max| *level <= max);
+    }
+}
+
+struct SpanData {
+    name: &'static str,
+    parent: Option<span::Id>,
+    fields: Map<String, Value>,
+    refs: usize,
+}
+
+#[derive(Default)]
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+thread_local! {
+    // spans entered on this thread, innermost last
+    static CURRENT: RefCell<Vec<span::Id>> = const { RefCell::new(vec![]) };
+}
+
+pub type Writer = Box<dyn Fn(&str) + Send + Sync>;
+
+/// the subscriber main installs. every event is one line, either
+/// `LEVEL game{game_id=1}:connection{player_id=2}: target: message key=value`
+/// or the same as a json object for log aggregation.
+pub struct Logger {
+    filter: Filter,
+    json: bool,
+    writer: Writer,
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+}
+
+fn text(value: &Value) -> String {
+    return match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+}
+
+impl Logger {
+    pub fn new(filter: Filter, json: bool, writer: Writer) -> Self {
+        return Logger {
+            filter,
+            json,
+            writer,
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        };
+    }
+
+    fn current(&self) -> Option<span::Id> {
+        return CURRENT.with(|current| current.borrow().last().cloned());
+    }
+
+    // outermost first
+    fn scope(&self, mut id: Option<span::Id>) -> Vec<(&'static str, Map<String, Value>)> {
+        let spans = self.spans.lock().expect("logger lock poisoned");
+        let mut scope = vec![];
+        while let Some(span) = id.and_then(|id| spans.get(&id.into_u64())) {
+            scope.push((span.name, span.fields.clone()));
+            id = span.parent.clone();
+        }
+        scope.reverse();
+
+        return scope;
+    }
+
+    fn format(&self, level: &Level, target: &str, mut fields: Map<String, Value>, scope: Vec<(&'static str, Map<String, Value>)>) -> String {
+        let message = fields.remove("message").map(|m| text(&m)).unwrap_or_default();
+
+        if self.json {
+            let spans: Vec<Value> = scope
+                .into_iter()
+                .map(|(name, mut fields)| {
+                    fields.insert("name".to_string(), Value::from(name));
+                    return Value::Object(fields);
+                })
+                .collect();
+
+            let mut line = Map::new();
+            line.insert("level".to_string(), Value::from(level.as_str()));
+            line.insert("target".to_string(), Value::from(target));
+            line.insert("message".to_string(), Value::from(message));
+            line.insert("fields".to_string(), Value::Object(fields));
+            line.insert("spans".to_string(), Value::Array(spans));
+            return Value::Object(line).to_string();
+        }
+
+        let mut line = format!("{:>5} ", level.as_str());
+        for (name, fields) in scope {
+            let fields: Vec<String> = fields.iter().map(|(k, v)| format!("{}={}", k, text(v))).collect();
+            line.push_str(&format!("{}{{{}}}:", name, fields.join(" ")));
+        }
+        line.push_str(&format!(" {}: {}", target, message));
+        for (k, v) in fields.iter() {
+            line.push_str(&format!(" {}={}", k, text(v)));
+        }
+
+        return line;
+    }
+
+    fn write_log(&self, record: &log::Record) {
+        let Ok(level) = record.level().as_str().parse::<Level>() else {
+            return;
+        };
+        if !self.filter.enabled(record.target(), &level) {
+            return;
+        }
+
+        let mut fields = Map::new();
+        fields.insert("message".to_string(), Value::from(record.args().to_string()));
+        let line = self.format(&level, record.target(), fields, self.scope(self.current()));
+        (self.writer)(&line);
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // spans are cheap and carry the context for events below them
+        return metadata.is_span() || self.filter.enabled(metadata.target(), metadata.level());
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+
+        let parent = if attrs.is_root() {
+            None
+        } else {
+            attrs.parent().cloned().or_else(|| self.current())
+        };
+        if let Some(parent) = parent.as_ref() {
+            self.clone_span(parent);
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.spans.lock().expect("logger lock poisoned").insert(
+            id,
+            SpanData {
+                name: attrs.metadata().name(),
+                parent,
+                fields: fields.0,
+                refs: 1,
+            },
+        );
+
+        return span::Id::from_u64(id);
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+
+        if let Some(span) = self.spans.lock().expect("logger lock poisoned").get_mut(&span.into_u64()) {
+            span.fields.extend(fields.0);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let parent = if event.is_root() {
+            None
+        } else {
+            event.parent().cloned().or_else(|| self.current())
+        };
+
+        let metadata = event.metadata();
+        let line = self.format(metadata.level(), metadata.target(), fields.0, self.scope(parent));
+        (self.writer)(&line);
+    }
+
+    fn enter(&self, span: &span::Id) {
+        CURRENT.with(|current| current.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            if let Some(idx) = current.iter().rposition(|id| id == span) {
+                current.remove(idx);
+            }
+        });
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        if let Some(span) = self.spans.lock().expect("logger lock poisoned").get_mut(&id.into_u64()) {
+            span.refs += 1;
+        }
+
+        return id.clone();
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        let parent = {
+            let mut spans = self.spans.lock().expect("logger lock poisoned");
+            let Some(span) = spans.get_mut(&id.into_u64()) else {
+                return false;
+            };
+
+            span.refs -= 1;
+            if span.refs > 0 {
+                return false;
+            }
+
+            spans.remove(&id.into_u64()).and_then(|span| span.parent)
+        };
+
+        if let Some(parent) = parent {
+            self.try_close(parent);
+        }
+
+        return true;
+    }
+}
+
+// library deps log through the log facade, this hands their records to the
+// same Logger so they show up with everything else
+struct LogBridge(Arc<Logger>);
+
+impl log::Log for LogBridge {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        return metadata
+            .level()
+            .as_str()
+            .parse::<Level>()
+            .is_ok_and(|level| self.0.filter.enabled(metadata.target(), &level));
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.write_log(record);
+    }
+
+    fn flush(&self) {}
+}
+
+/// installs the Logger as the global tracing subscriber and log logger.
+/// RUST_LOG picks what gets through, json switches the output format.
+pub fn init(json: bool) -> anyhow::Result<()> {
+    let filter = Filter::parse(&std::env::var("RUST_LOG").unwrap_or_default()).map_err(anyhow::Error::msg)?;
+    let logger = Arc::new(Logger::new(filter, json, Box::new(|line| eprintln!("{}", line))));
+
+    tracing::subscriber::set_global_default(SharedLogger(logger.clone()))?;
+    log::set_boxed_logger(Box::new(LogBridge(logger)))?;
+    log::set_max_level(log::LevelFilter::Trace);
+    install_panic_hook();
+
+    return Ok(());
+}
+
+/// the text a panic was raised with, for panics caught with catch_unwind.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+
+    return "unknown panic".to_string();
+}
+
+// panics go through the logger like everything else, inside whatever span
+// the panicking task was in, so a game's panic carries its game span
+fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        tracing::error!(target: "panic", panic = %panic_message(info.payload()), location, "panicked");
+    }));
+}
+
+// lets the tracing side and the log bridge share one Logger
+pub struct SharedLogger(pub Arc<Logger>);
+
+impl Subscriber for SharedLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        return self.0.enabled(metadata);
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        return self.0.new_span(attrs);
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.0.record(span, values);
+    }
+
+    fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+        self.0.record_follows_from(span, follows);
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        self.0.event(event);
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.0.enter(span);
+    }
+
+    fn exit(&self, span: &span::Id) {
+        self.0.exit(span);
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        return self.0.clone_span(id);
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        return self.0.try_close(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::Level;
+
+    use super::{Filter, LogBridge, Logger};
+
+    fn capture(filter: &str) -> (Arc<Logger>, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let captured = lines.clone();
+        let logger = Logger::new(
+            Filter::parse(filter).expect("valid filter"),
+            false,
+            Box::new(move |line| captured.lock().expect("not poisoned").push(line.to_string())),
+        );
+
+        return (Arc::new(logger), lines);
+    }
+
+    #[test]
+    fn test_filter_longest_target_wins() {
+        let filter = Filter::parse("warn,game=info,game::game=off,tokio").expect("valid filter");
+
+        assert!(filter.enabled("vim_royale", &Level::WARN));
+        assert!(!filter.enabled("vim_royale", &Level::INFO));
+        assert!(filter.enabled("game::player", &Level::INFO));
+        assert!(!filter.enabled("game::game", &Level::ERROR));
+        assert!(filter.enabled("tokio::net", &Level::TRACE));
+
+        assert!(Filter::parse("game=loud").is_err());
+        assert!(!Filter::parse("").expect("valid filter").enabled("game", &Level::WARN));
+    }
+
+    #[test]
+    fn test_text_lines_carry_spans_and_log_records() {
+        let (logger, lines) = capture("info");
+
+        tracing::subscriber::with_default(super::SharedLogger(logger.clone()), || {
+            let game = tracing::info_span!("game", game_id = 4);
+            let _game = game.enter();
+            let connection = tracing::info_span!("connection", player_id = 2);
+            let _connection = connection.enter();
+
+            tracing::warn!(error = "timeout", "clock sync failed");
+            tracing::debug!("filtered out");
+
+            // a dependency logging through log shows up inside the same spans
+            log::Log::log(
+                &LogBridge(logger.clone()),
+                &log::Record::builder()
+                    .level(log::Level::Info)
+                    .target("tungstenite")
+                    .args(format_args!("handshake done"))
+                    .build(),
+            );
+        });
+
+        assert_eq!(
+            *lines.lock().expect("not poisoned"),
+            vec![
+                " WARN game{game_id=4}:connection{player_id=2}: game::logging::test: clock sync failed error=timeout",
+                " INFO game{game_id=4}:connection{player_id=2}: tungstenite: handshake done",
+            ]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use map::{
+    map::{Map, MAP_SIZE_SIDE},
+    rand::mulberry32,
+};
+
+// tiles tried per item before it's left out, a map is mostly open ground
+const PLACE_ATTEMPTS: usize = 64;
+
+/// one kind of item a table can hand out and how often next to the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LootEntry {
+    pub item: &'static str,
+    pub weight: u32,
+}
+
+/// what a game mode's items are made of: count items, each drawn from
+/// entries by weight. tables are static so GameConfig stays Copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LootTable {
+    pub entries: &'static [LootEntry],
+    pub count: usize,
+}
+
+impl LootTable {
+    /// no loot at all.
+    pub const EMPTY: LootTable = LootTable { entries: &[], count: 0 };
+
+    fn total_weight(&self) -> Option<u32> {
+        return self.entries.iter().try_fold(0u32, |total, entry| total.checked_add(entry.weight));
+    }
+
+    /// a table that spawns items needs entries with some weight, and the
+    /// weights have to add up without overflowing.
+    pub fn is_valid(&self) -> bool {
+        return self.count == 0 || self.total_weight().is_some_and(|total| total > 0);
+    }
+}
+
+impl Default for LootTable {
+    fn default() -> Self {
+        return LootTable::EMPTY;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LootSpawn {
+    pub item: &'static str,
+    pub position: (u16, u16),
+}
+
+// loot has its own rand so changing a table never moves the map around
+fn loot_seed(seed: u32) -> u32 {
+    return seed ^ 0x1007_7AB1;
+}
+
+/// the items a game on map starts with, the same map and table always give
+/// the same items in the same places. items only land on walkable tiles,
+/// one that can't find a tile is left out.
+pub fn spawn_loot(map: &Map, table: &LootTable) -> Vec<LootSpawn> {
+    let Some(total) = table.total_weight().filter(|total| *total > 0) else {
+        return vec![];
+    };
+
+    let mut rand = mulberry32(loot_seed(map.seed));
+    let mut spawns = vec![];
+    for _ in 0..table.count {
+        let mut roll = rand() % total;
+        let entry = table
+            .entries
+            .iter()
+            .find(|entry| {
+                if roll < entry.weight {
+                    return true;
+                }
+                roll -= entry.weight;
+                return false;
+            })
+            .expect("roll is below the total weight");
+
+        let position = (0..PLACE_ATTEMPTS)
+            .map(|_| {
+                let x = rand() as usize % MAP_SIZE_SIDE;
+                let y = rand() as usize % MAP_SIZE_SIDE;
+                return (x, y);
+            })
+            .find(|(x, y)| map.is_walkable(*x, *y));
+
+        if let Some((x, y)) = position {
+            spawns.push(LootSpawn {
+                item: entry.item,
+                position: (x as u16, y as u16),
+            });
+        }
+    }
+
+    return spawns;
+}
+
+#[cfg(test)]
+mod test {
+    use map::map::Map;
+
+    use super::{spawn_loot, LootEntry, LootTable};
+
+    const STANDARD: &[LootEntry] = &[
+        LootEntry { item: "medkit", weight: 1 },
+        LootEntry { item: "ammo", weight: 3 },
+    ];
+    const AMMO_ONLY: &[LootEntry] = &[LootEntry { item: "ammo", weight: 1 }];
+
+    #[test]
+    fn test_same_seed_and_table_spawn_the_same_loot() {
+        let map = Map::new(26);
+        let table = LootTable { entries: STANDARD, count: 40 };
+
+        let loot = spawn_loot(&map, &table);
+        assert_eq!(loot, spawn_loot(&Map::new(26), &table));
+        assert_eq!(loot.len(), 40);
+        assert!(loot.iter().all(|spawn| map.is_walkable(spawn.position.0 as usize, spawn.position.1 as usize)));
+
+        // both kinds come up, ammo more often
+        let medkits = loot.iter().filter(|spawn| spawn.item == "medkit").count();
+        assert!(medkits > 0 && medkits < 20, "{} medkits", medkits);
+
+        assert_ne!(loot, spawn_loot(&Map::new(27), &table));
+    }
+
+    #[test]
+    fn test_changing_the_table_changes_the_loot() {
+        let map = Map::new(26);
+        let standard = spawn_loot(&map, &LootTable { entries: STANDARD, count: 40 });
+
+        let ammo = spawn_loot(&map, &LootTable { entries: AMMO_ONLY, count: 40 });
+        assert_ne!(standard, ammo);
+        assert!(ammo.iter().all(|spawn| spawn.item == "ammo"));
+
+        assert!(spawn_loot(&map, &LootTable::EMPTY).is_empty());
+        assert!(!LootTable { entries: &[LootEntry { item: "ammo", weight: 0 }], count: 1 }.is_valid());
+        assert!(LootTable::EMPTY.is_valid());
+    }
+}
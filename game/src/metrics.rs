@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, LazyLock, Mutex,
+};
+
+use encoding::server::{
+    JOIN_ERROR_BAD_NAME, JOIN_ERROR_ENDING, JOIN_ERROR_FULL, JOIN_ERROR_NOT_FOUND, JOIN_ERROR_NOT_REGISTERED,
+    JOIN_ERROR_STARTED, MESSAGE_TAGS,
+};
+use tracing::{info, warn};
+
+use crate::send_stats::{SendClass, SEND_CLASSES};
+use crate::tick_rate::TickRate;
+use crate::traffic::Traffic;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+// bucket upper bounds as a share of one tick, from a 16th of a tick to 4 ticks
+const TICK_BUCKETS: usize = 7;
+
+fn tick_buckets_us(tick_rate: TickRate) -> [u128; TICK_BUCKETS] {
+    let tick = tick_rate.micros();
+    return [tick / 16, tick / 8, tick / 4, tick / 2, tick, tick * 2, tick * 4];
+}
+
+/// one game's series. the game holds on to it and writes every tick without
+/// going through the registry, see Metrics::game_started.
+pub struct GameMetrics {
+    players: AtomicUsize,
+    spectators: AtomicUsize,
+    // upper bounds in micros for the game's tick rate
+    tick_buckets_us: [u128; TICK_BUCKETS],
+    // per bucket, the last one is everything slower than tick_buckets_us
+    tick_buckets: [AtomicU64; TICK_BUCKETS + 1],
+    tick_sum_us: AtomicU64,
+    ticks: AtomicU64,
+    // encoding time of the last tick and of the whole game
+    serialize_ns: AtomicU64,
+    serialize_ns_total: AtomicU64,
+    // the game's running totals per message type
+    inbound: [AtomicU64; MESSAGE_TAGS],
+    outbound: [AtomicU64; MESSAGE_TAGS],
+}
+
+impl GameMetrics {
+    /// a series nobody scrapes until it is registered
+    pub fn new(tick_rate: TickRate) -> Self {
+        return GameMetrics {
+            players: AtomicUsize::new(0),
+            spectators: AtomicUsize::new(0),
+            tick_buckets_us: tick_buckets_us(tick_rate),
+            tick_buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            tick_sum_us: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            serialize_ns: AtomicU64::new(0),
+            serialize_ns_total: AtomicU64::new(0),
+            inbound: std::array::from_fn(|_| AtomicU64::new(0)),
+            outbound: std::array::from_fn(|_| AtomicU64::new(0)),
+        };
+    }
+
+    pub fn population(&self, players: usize, spectators: usize) {
+        self.players.store(players, Ordering::Relaxed);
+        self.spectators.store(spectators, Ordering::Relaxed);
+    }
+
+    pub fn tick(&self, tick_us: u128) {
+        let bucket = self
+            .tick_buckets_us
+            .iter()
+            .position(|&le| tick_us <= le)
+            .unwrap_or(TICK_BUCKETS);
+        self.tick_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.tick_sum_us.fetch_add(tick_us as u64, Ordering::Relaxed);
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn serialize_time(&self, time: std::time::Duration) {
+        self.serialize_ns.store(time.as_nanos() as u64, Ordering::Relaxed);
+        self.serialize_ns_total.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// see Game::record_traffic
+    pub fn traffic(&self, traffic: &Traffic) {
+        for (counter, count) in self.inbound.iter().zip(traffic.inbound.iter()) {
+            counter.store(*count, Ordering::Relaxed);
+        }
+        for (counter, count) in self.outbound.iter().zip(traffic.outbound.iter()) {
+            counter.store(*count, Ordering::Relaxed);
+        }
+    }
+
+    fn load(counters: &[AtomicU64; MESSAGE_TAGS]) -> [u64; MESSAGE_TAGS] {
+        return std::array::from_fn(|tag| counters[tag].load(Ordering::Relaxed));
+    }
+}
+
+/// counters and gauges for the /metrics endpoint. per game series only live
+/// as long as the game so ids that come and go don't pile up.
+#[derive(Default)]
+pub struct Metrics {
+    connections_accepted: AtomicU64,
+    messages_in: AtomicU64,
+    messages_out: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    handshake_failures: AtomicU64,
+    game_crashes: AtomicU64,
+    slow_sends: AtomicU64,
+    send_saturated_us: AtomicU64,
+    // failed sends per SendClass
+    sends_dropped: [AtomicU64; SEND_CLASSES],
+    kicks: Mutex<HashMap<&'static str, u64>>,
+    games: Mutex<HashMap<u32, Arc<GameMetrics>>>,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+pub fn metrics() -> &'static Metrics {
+    return &METRICS;
+}
+
+pub fn join_error_reason(reason: u8) -> &'static str {
+    return match reason {
+        JOIN_ERROR_FULL => "full",
+        JOIN_ERROR_STARTED => "started",
+        JOIN_ERROR_NOT_FOUND => "not_found",
+        JOIN_ERROR_NOT_REGISTERED => "not_registered",
+        JOIN_ERROR_BAD_NAME => "bad_name",
+        JOIN_ERROR_ENDING => "ending",
+        _ => "unknown",
+    };
+}
+
+impl Metrics {
+    pub fn connection_accepted(&self) {
+        self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn message_in(&self, bytes: usize) {
+        self.messages_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn message_out(&self, bytes: usize) {
+        self.messages_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn handshake_failed(&self) {
+        self.handshake_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn game_crashed(&self) {
+        self.game_crashes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn slow_send(&self, took: std::time::Duration) {
+        self.slow_sends.fetch_add(1, Ordering::Relaxed);
+        self.send_saturated_us.fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn send_dropped(&self, class: SendClass) {
+        self.sends_dropped[class as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// a connection that was turned away or dropped, reason is a fixed label.
+    pub fn kick(&self, reason: &'static str) {
+        // counters are still good after a panic elsewhere
+        *self.kicks.lock().unwrap_or_else(|e| e.into_inner()).entry(reason).or_insert(0) += 1;
+    }
+
+    /// registers the game's series for scraping, the game writes to the
+    /// handle from then on.
+    pub fn game_started(&self, game_id: u32, tick_rate: TickRate) -> Arc<GameMetrics> {
+        let series = Arc::new(GameMetrics::new(tick_rate));
+        self.games.lock().unwrap_or_else(|e| e.into_inner()).insert(game_id, series.clone());
+        return series;
+    }
+
+    pub fn game_ended(&self, game_id: u32) {
+        self.games.lock().unwrap_or_else(|e| e.into_inner()).remove(&game_id);
+    }
+
+    /// prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("vim_royale_connections_accepted_total", &self.connections_accepted),
+            ("vim_royale_messages_in_total", &self.messages_in),
+            ("vim_royale_messages_out_total", &self.messages_out),
+            ("vim_royale_bytes_in_total", &self.bytes_in),
+            ("vim_royale_bytes_out_total", &self.bytes_out),
+            ("vim_royale_handshake_failures_total", &self.handshake_failures),
+            ("vim_royale_game_crashes_total", &self.game_crashes),
+            ("vim_royale_slow_sends_total", &self.slow_sends),
+            ("vim_royale_send_saturated_us_total", &self.send_saturated_us),
+        ];
+        for (name, counter) in counters {
+            _ = writeln!(out, "# TYPE {} counter", name);
+            _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+
+        _ = writeln!(out, "# TYPE vim_royale_sends_dropped_total counter");
+        for class in [SendClass::State, SendClass::Control] {
+            let dropped = self.sends_dropped[class as usize].load(Ordering::Relaxed);
+            _ = writeln!(out, "vim_royale_sends_dropped_total{{class=\"{}\"}} {}", class.label(), dropped);
+        }
+
+        let kicks = self.kicks.lock().unwrap_or_else(|e| e.into_inner());
+        let mut reasons: Vec<_> = kicks.iter().collect();
+        reasons.sort();
+        _ = writeln!(out, "# TYPE vim_royale_kicks_total counter");
+        for (reason, count) in reasons {
+            _ = writeln!(out, "vim_royale_kicks_total{{reason=\"{}\"}} {}", reason, count);
+        }
+        drop(kicks);
+
+        // a snapshot of the registry, the series are read without the lock
+        let games: HashMap<u32, Arc<GameMetrics>> = self.games.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut ids: Vec<_> = games.keys().cloned().collect();
+        ids.sort();
+
+        _ = writeln!(out, "# TYPE vim_royale_active_games gauge");
+        _ = writeln!(out, "vim_royale_active_games {}", games.len());
+        _ = writeln!(out, "# TYPE vim_royale_players_connected gauge");
+        _ = writeln!(out, "vim_royale_players_connected {}", games.values().map(|g| g.players.load(Ordering::Relaxed)).sum::<usize>());
+
+        _ = writeln!(out, "# TYPE vim_royale_game_players gauge");
+        for id in ids.iter() {
+            _ = writeln!(out, "vim_royale_game_players{{game_id=\"{}\"}} {}", id, games[id].players.load(Ordering::Relaxed));
+        }
+
+        _ = writeln!(out, "# TYPE vim_royale_game_spectators gauge");
+        for id in ids.iter() {
+            _ = writeln!(out, "vim_royale_game_spectators{{game_id=\"{}\"}} {}", id, games[id].spectators.load(Ordering::Relaxed));
+        }
+
+        _ = writeln!(out, "# TYPE vim_royale_tick_serialize_ns gauge");
+        for id in ids.iter() {
+            _ = writeln!(out, "vim_royale_tick_serialize_ns{{game_id=\"{}\"}} {}", id, games[id].serialize_ns.load(Ordering::Relaxed));
+        }
+
+        _ = writeln!(out, "# TYPE vim_royale_serialize_ns_total counter");
+        for id in ids.iter() {
+            _ = writeln!(out, "vim_royale_serialize_ns_total{{game_id=\"{}\"}} {}", id, games[id].serialize_ns_total.load(Ordering::Relaxed));
+        }
+
+        _ = writeln!(out, "# TYPE vim_royale_game_messages_total counter");
+        for id in ids.iter() {
+            let inbound = GameMetrics::load(&games[id].inbound);
+            let outbound = GameMetrics::load(&games[id].outbound);
+            for (direction, counts) in [("in", &inbound), ("out", &outbound)] {
+                for (kind, count) in Traffic::nonzero(counts) {
+                    _ = writeln!(
+                        out,
+                        "vim_royale_game_messages_total{{game_id=\"{}\",direction=\"{}\",type=\"{}\"}} {}",
+                        id, direction, kind, count
+                    );
+                }
+            }
+        }
+
+        _ = writeln!(out, "# TYPE vim_royale_tick_duration_us histogram");
+        for id in ids.iter() {
+            let game = &games[id];
+            let ticks = game.ticks.load(Ordering::Relaxed);
+            let mut cumulative = 0;
+            for (le, count) in game.tick_buckets_us.iter().zip(game.tick_buckets.iter()) {
+                cumulative += count.load(Ordering::Relaxed);
+                _ = writeln!(
+                    out,
+                    "vim_royale_tick_duration_us_bucket{{game_id=\"{}\",le=\"{}\"}} {}",
+                    id, le, cumulative
+                );
+            }
+            _ = writeln!(out, "vim_royale_tick_duration_us_bucket{{game_id=\"{}\",le=\"+Inf\"}} {}", id, ticks);
+            _ = writeln!(out, "vim_royale_tick_duration_us_sum{{game_id=\"{}\"}} {}", id, game.tick_sum_us.load(Ordering::Relaxed));
+            _ = writeln!(out, "vim_royale_tick_duration_us_count{{game_id=\"{}\"}} {}", id, ticks);
+        }
+
+        return out;
+    }
+}
+
+async fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request = [0; 1024];
+    let read = stream.read(&mut request).await?;
+
+    let response = if request[..read].starts_with(b"GET /metrics ") {
+        let body = metrics().render();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    return stream.shutdown().await;
+}
+
+/// serves GET /metrics on its own listener, away from the game websockets.
+pub async fn serve(listener: TcpListener) {
+    info!(addr = ?listener.local_addr(), "serving metrics");
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream).await {
+                        warn!(error = ?e, "metrics scrape failed");
+                    }
+                });
+            }
+            Err(e) => warn!(error = ?e, "metrics accept failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+    use std::sync::{atomic::AtomicU8, Arc};
+
+    use anyhow::Result;
+    use encoding::server::{self, WHO_AM_I_CLIENT};
+    use futures::StreamExt;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    use crate::{
+        game::game_run,
+        game_comms::{GameComms, GameKey, GameMessage, IncomingConnection},
+        game_config::GameConfig,
+        test_utils::{complete_handshake, ws_pair},
+        tick_rate::TickRate,
+    };
+
+    use super::{serve, Metrics};
+
+    async fn get(addr: SocketAddr, path: &str) -> Result<String> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        return Ok(response);
+    }
+
+    fn value(scrape: &str, series: &str) -> Option<u64> {
+        return scrape
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok());
+    }
+
+    #[tokio::test]
+    async fn test_scrape_after_scripted_game() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(serve(listener));
+
+        assert!(get(addr, "/").await?.starts_with("HTTP/1.1 404"));
+        let before = get(addr, "/metrics").await?;
+        assert!(before.starts_with("HTTP/1.1 200"));
+        let sent_before = value(&before, "vim_royale_messages_out_total").expect("counter exists");
+
+        // other tests run games too, this one keeps to its own id
+        let config = GameConfig {
+            max_ticks: Some(30),
+            ..GameConfig::default()
+        };
+        let (manager_tx, mut manager_rx) = mpsc::channel::<GameMessage>(10);
+        let (comms, sender) = GameComms::with_sender(manager_tx);
+        let key = GameKey { id: 9_001, epoch: 0 };
+        tokio::spawn(game_run(1, Arc::new(AtomicU8::new(0)), key, comms, config));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        let (sink, stream) = server_socket.split();
+        sender.send(GameMessage::Connection(IncomingConnection::new(stream, sink, WHO_AM_I_CLIENT))).await?;
+        let msg = complete_handshake(&mut client).await?;
+        assert!(matches!(msg.msg, server::Message::PlayerStart(_)));
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let during = get(addr, "/metrics").await?;
+        assert_eq!(value(&during, "vim_royale_game_players{game_id=\"9001\"}"), Some(1));
+        assert!(value(&during, "vim_royale_tick_duration_us_count{game_id=\"9001\"}").is_some());
+
+        loop {
+            if let Some(GameMessage::Close(_)) = manager_rx.recv().await {
+                break;
+            }
+        }
+
+        let after = get(addr, "/metrics").await?;
+        let sent_after = value(&after, "vim_royale_messages_out_total").expect("counter exists");
+        assert!(sent_after > sent_before);
+        assert!(value(&after, "vim_royale_bytes_out_total") > value(&before, "vim_royale_bytes_out_total"));
+        // the series went away with the game
+        assert!(!after.contains("game_id=\"9001\""));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_tick_buckets_follow_the_tick_rate() {
+        let metrics = Metrics::default();
+        // a tick and a bit at 20 ticks a second, over 3 ticks at 60
+        metrics.game_started(1, TickRate::from_hz(20).unwrap()).tick(60_000);
+        metrics.game_started(2, TickRate::default()).tick(60_000);
+
+        let scrape = metrics.render();
+        assert_eq!(value(&scrape, "vim_royale_tick_duration_us_bucket{game_id=\"1\",le=\"50000\"}"), Some(0));
+        assert_eq!(value(&scrape, "vim_royale_tick_duration_us_bucket{game_id=\"1\",le=\"100000\"}"), Some(1));
+        assert_eq!(value(&scrape, "vim_royale_tick_duration_us_bucket{game_id=\"2\",le=\"33332\"}"), Some(0));
+        assert_eq!(value(&scrape, "vim_royale_tick_duration_us_bucket{game_id=\"2\",le=\"66664\"}"), Some(1));
+    }
+}
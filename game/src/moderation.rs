@@ -0,0 +1,68 @@
+/// decides whether an emote goes out, called once for every emote that made
+/// it past the cooldown. text is the emote's entry in EMOTES. every game gets
+/// ManagerConfig::emote_filter.
+pub trait EmoteFilter: Send + Sync + std::fmt::Debug {
+    fn allow(&self, player_id: u8, text: &str) -> bool;
+}
+
+/// the default filter, drops emotes with a blocked word in them. emotes are
+/// fixed ids, there is no free text to mask, so a blocked emote is dropped
+/// as a whole.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WordList {
+    // lowercase
+    words: Vec<String>,
+}
+
+impl WordList {
+    pub fn new(words: &[&str]) -> Self {
+        return Self {
+            words: words.iter().map(|word| word.trim().to_lowercase()).filter(|word| !word.is_empty()).collect(),
+        };
+    }
+
+    /// every blocked word in text replaced by as many *, whole words only.
+    pub fn mask(&self, text: &str) -> String {
+        return text
+            .split(' ')
+            .map(|word| match self.words.contains(&word.to_lowercase()) {
+                true => "*".repeat(word.chars().count()),
+                false => word.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+    }
+}
+
+impl EmoteFilter for WordList {
+    fn allow(&self, _player_id: u8, text: &str) -> bool {
+        return self.mask(text) == text;
+    }
+}
+
+/// admin commands that change what a game lets through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Moderation {
+    // by player id, the game remembers the name so it sticks through a rejoin
+    Mute(u8),
+    Unmute(u8),
+    // seconds between two emotes on top of emote_cooldown_ticks, 0 turns it off
+    SlowMode(u16),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EmoteFilter, WordList};
+
+    #[test]
+    fn test_word_list_masks_whole_words() {
+        let words = WordList::new(&["Run", " ", "oops"]);
+        assert_eq!(words.mask("run"), "***");
+        assert_eq!(words.mask("RUN away"), "*** away");
+        assert_eq!(words.mask("runner"), "runner");
+
+        assert!(!words.allow(0, "oops"));
+        assert!(words.allow(0, "follow me"));
+        assert!(WordList::default().allow(0, "run"));
+    }
+}
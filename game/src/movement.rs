@@ -0,0 +1,89 @@
+use map::map::{Map, Terrain};
+
+// move budgets are in 1/100 of a tile
+pub const TILE_COST: u32 = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MoveError {
+    TooFast,
+    Blocked,
+}
+
+/// most a player can move in one tick on this terrain, base_speed is in 1/100
+/// tiles per tick on open ground.
+pub fn max_move_per_tick(base_speed: u32, terrain: Terrain) -> u32 {
+    return base_speed * terrain.speed_percent() / 100;
+}
+
+/// vim keys to a one tile step
+pub fn key_to_step(key: u8) -> Option<(i32, i32)> {
+    return match key.to_ascii_lowercase() {
+        b'h' => Some((-1, 0)),
+        b'j' => Some((0, 1)),
+        b'k' => Some((0, -1)),
+        b'l' => Some((1, 0)),
+        _ => None,
+    };
+}
+
+pub fn apply_step(from: (u16, u16), step: (i32, i32)) -> Option<(u16, u16)> {
+    let x = u16::try_from(from.0 as i32 + step.0).ok()?;
+    let y = u16::try_from(from.1 as i32 + step.1).ok()?;
+    return Some((x, y));
+}
+
+/// checks a move against the map and the player's budget, returns what it costs.
+pub fn validate_move(
+    map: &Map,
+    from: (u16, u16),
+    to: (u16, u16),
+    budget: u32,
+) -> Result<u32, MoveError> {
+    if !map.is_walkable(to.0 as usize, to.1 as usize) {
+        return Err(MoveError::Blocked);
+    }
+
+    let distance = from.0.abs_diff(to.0) as u32 + from.1.abs_diff(to.1) as u32;
+    let cost = distance * TILE_COST;
+    if cost > budget {
+        return Err(MoveError::TooFast);
+    }
+
+    return Ok(cost);
+}
+
+#[cfg(test)]
+mod test {
+    use map::map::{Map, Terrain};
+
+    use super::{max_move_per_tick, validate_move, MoveError, TILE_COST};
+
+    #[test]
+    fn test_mud_is_slower_than_ground() {
+        let mut map = Map::new(1);
+        for x in 10..=11 {
+            map.set_terrain(x, 10, Terrain::Ground);
+            map.set_terrain(x, 20, Terrain::Mud);
+        }
+
+        let ground = max_move_per_tick(TILE_COST, Terrain::Ground);
+        let mud = max_move_per_tick(TILE_COST, Terrain::Mud);
+
+        assert_eq!(validate_move(&map, (10, 10), (11, 10), ground), Ok(TILE_COST));
+        assert_eq!(validate_move(&map, (10, 20), (11, 20), mud), Err(MoveError::TooFast));
+
+        // two ticks in the mud is enough for a step
+        assert_eq!(validate_move(&map, (10, 20), (11, 20), mud * 2), Ok(TILE_COST));
+    }
+
+    #[test]
+    fn test_teleport_and_walls_rejected() {
+        let mut map = Map::new(1);
+        map.set_terrain(30, 30, Terrain::Ground);
+        map.set_terrain(31, 30, Terrain::Wall);
+        map.set_terrain(40, 30, Terrain::Ground);
+
+        assert_eq!(validate_move(&map, (30, 30), (31, 30), TILE_COST), Err(MoveError::Blocked));
+        assert_eq!(validate_move(&map, (30, 30), (40, 30), TILE_COST), Err(MoveError::TooFast));
+    }
+}
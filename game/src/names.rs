@@ -0,0 +1,85 @@
+#[derive(Debug, PartialEq, Eq)]
+pub enum NameError {
+    Empty,
+    TooLong,
+    // control characters and anything outside printable ascii
+    BadCharacter,
+}
+
+pub fn default_name(id: u8) -> String {
+    return format!("player{}", id);
+}
+
+pub fn bot_name(id: u8) -> String {
+    return format!("bot{}", id);
+}
+
+/// checks a name as sent in the handshake. surrounding whitespace is
+/// trimmed, what is left has to be printable ascii and at most max_len long.
+pub fn validate_name(raw: &[u8], max_len: usize) -> Result<String, NameError> {
+    let Ok(name) = std::str::from_utf8(raw) else {
+        return Err(NameError::BadCharacter);
+    };
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+
+    if !name.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Err(NameError::BadCharacter);
+    }
+
+    if name.len() > max_len {
+        return Err(NameError::TooLong);
+    }
+
+    return Ok(name.to_string());
+}
+
+/// name with a -N suffix when someone in the game already has it. the name
+/// gets cut short to keep the suffix inside max_len.
+pub fn unique_name(name: &str, taken: &[&str], max_len: usize) -> String {
+    if !taken.contains(&name) {
+        return name.to_string();
+    }
+
+    let mut suffix = 2;
+    loop {
+        let tail = format!("-{}", suffix);
+        let keep = max_len.saturating_sub(tail.len()).min(name.len());
+        let candidate = format!("{}{}", &name[..keep], tail);
+        if !taken.contains(&candidate.as_str()) {
+            return candidate;
+        }
+        suffix += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{unique_name, validate_name, NameError};
+
+    #[test]
+    fn test_validate_name() {
+        assert_eq!(validate_name(b"  vim enjoyer ", 16), Ok("vim enjoyer".to_string()));
+        assert_eq!(validate_name(b"sixteen_chars_xx", 16), Ok("sixteen_chars_xx".to_string()));
+
+        assert_eq!(validate_name(b"seventeen_chars_x", 16), Err(NameError::TooLong));
+        assert_eq!(validate_name(b"   ", 16), Err(NameError::Empty));
+        assert_eq!(validate_name(b"bell\x07", 16), Err(NameError::BadCharacter));
+        assert_eq!(validate_name(b"new\nline", 16), Err(NameError::BadCharacter));
+        assert_eq!(validate_name("n\u{e4}me".as_bytes(), 16), Err(NameError::BadCharacter));
+        assert_eq!(validate_name(&[0xff, 0xfe], 16), Err(NameError::BadCharacter));
+    }
+
+    #[test]
+    fn test_duplicate_names_get_a_suffix() {
+        assert_eq!(unique_name("bob", &["alice"], 16), "bob");
+        assert_eq!(unique_name("bob", &["bob"], 16), "bob-2");
+        assert_eq!(unique_name("bob", &["bob", "bob-2"], 16), "bob-3");
+
+        // the suffix never pushes it over the limit
+        assert_eq!(unique_name("abcdef", &["abcdef"], 6), "abcd-2");
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::game_comms::{GameKey, GameResult};
+
+/// where a game keeps its result when the manager can't take it anymore.
+pub trait OutcomeSink: Send + Sync {
+    fn persist(&self, key: GameKey, result: &GameResult) -> Result<()>;
+}
+
+#[derive(serde::Serialize)]
+struct Outcome<'a> {
+    game_id: u32,
+    epoch: u32,
+    #[serde(flatten)]
+    result: &'a GameResult,
+}
+
+/// one json file per game in a directory, game ids repeat so the epoch is
+/// part of the name.
+#[derive(Debug, Clone)]
+pub struct OutcomeDir {
+    dir: PathBuf,
+}
+
+impl OutcomeDir {
+    pub fn new(dir: &Path) -> Self {
+        return OutcomeDir { dir: dir.to_path_buf() };
+    }
+
+    pub fn path(&self, key: GameKey) -> PathBuf {
+        return self.dir.join(format!("game-{}-{}.outcome.json", key.id, key.epoch));
+    }
+}
+
+impl OutcomeSink for OutcomeDir {
+    // blocking, it only runs once the game is over
+    fn persist(&self, key: GameKey, result: &GameResult) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context("creating outcome dir")?;
+
+        let outcome = Outcome {
+            game_id: key.id,
+            epoch: key.epoch,
+            result,
+        };
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&outcome)?).context("writing outcome")?;
+        std::fs::rename(&tmp, &path).context("moving outcome into place")?;
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use encoding::server::region;
+
+    use super::{OutcomeDir, OutcomeSink};
+    use crate::game_comms::{GameKey, GameResult};
+
+    #[test]
+    fn test_outcomes_land_in_their_own_files() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("vim-royale-outcomes-{}", std::process::id()));
+        let sink = OutcomeDir::new(&dir);
+        let result = GameResult {
+            placements: vec![7, 3],
+            leaderboard: vec![],
+            region: region("eu-west"),
+            events: vec![],
+        };
+
+        let first = GameKey { id: 4, epoch: 0 };
+        let second = GameKey { id: 4, epoch: 1 };
+        sink.persist(first, &result)?;
+        sink.persist(second, &result)?;
+
+        let written: serde_json::Value = serde_json::from_slice(&std::fs::read(sink.path(second))?)?;
+        assert_eq!(written["game_id"], 4);
+        assert_eq!(written["epoch"], 1);
+        assert_eq!(written["placements"], serde_json::json!([7, 3]));
+        assert!(sink.path(first).exists());
+
+        std::fs::remove_dir_all(dir)?;
+        return Ok(());
+    }
+}
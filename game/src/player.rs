@@ -1,169 +1,749 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use encoding::server::{self, Message, ServerMessage};
+use encoding::server::{self, EncodeBuffer, Message, ServerMessage, EVENT_BATCH_MAX, MESSAGE_TAGS};
 use futures::{
     stream::{SplitSink, SplitStream},
-    SinkExt, StreamExt,
+    FutureExt, SinkExt, StreamExt,
 };
 
-use tokio::{net::TcpStream, sync::mpsc::Sender};
-use tokio_tungstenite::{tungstenite, WebSocketStream};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
 
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc::Sender, Semaphore},
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        self,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+    },
+    WebSocketStream,
+};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::capture::{CaptureWriter, Direction};
 use crate::connection::{ConnectionError, ConnectionMessage, SerializationType};
+use crate::executor::{timeout, tokio_executor, Executor};
+use crate::game_config::{GameConfig, OnSyncTimeout};
+use crate::log_sampler::LogSampler;
+use crate::metrics::{join_error_reason, metrics};
+use crate::player_slab::PlayerKey;
+use crate::send_stats::{SendClass, SendStats, CONTROL_SEND_TIMEOUT, SLOW_SEND};
+use crate::shared_message::SharedMessage;
+use crate::traffic::InboundTraffic;
+use crate::transport::{FrameSink, FrameStream, Transport, WebSocket};
 
 pub type PlayerWebStream = SplitStream<WebSocketStream<TcpStream>>;
 pub type PlayerWebSink = SplitSink<WebSocketStream<TcpStream>, tungstenite::Message>;
 
-pub struct PlayerSink {
+pub struct PlayerSink<S: FrameSink = PlayerWebSink> {
     pub id: u8,
     pub seq_nu: u16,
-    pub sink: PlayerWebSink,
+    // None for bots, sends just go nowhere
+    pub sink: Option<S>,
     pub ser_type: SerializationType,
+    // spent encoding since the game last took it, see Game::record_serialize_time
+    pub serialize_time: std::time::Duration,
+    // sent per message tag since the game last took them, see Game::record_traffic
+    pub sent: [u64; MESSAGE_TAGS],
+    pub stats: SendStats,
+    // a control message timed out, every send fails from now on and the
+    // game disconnects them
+    pub stalled: bool,
+    // every frame sent goes in here too, see capture
+    pub capture: Option<Arc<CaptureWriter>>,
+    // this tick's events, they go out together in flush_events
+    pub events: Vec<server::GameEvent>,
+    // times the sends, the game's executor once it's in a game
+    pub executor: Arc<dyn Executor>,
+    // every message is encoded in here, it grows to the biggest one sent
+    buffer: EncodeBuffer,
 }
 
-fn deserialize(vec: Vec<u8>, ser: &SerializationType) -> Result<ServerMessage> {
+pub(crate) fn deserialize(vec: Vec<u8>, ser: &SerializationType) -> Result<ServerMessage> {
     if let SerializationType::JSON = ser {
-        return serde_json::from_slice(&vec).context("error while decoding json");
+        return ServerMessage::from_json(&vec).context("error while decoding json");
     }
 
     return ServerMessage::deserialize(&vec);
 }
 
-pub fn spawn_player_stream(
-    id: u8,
-    mut stream: PlayerWebStream,
+pub fn spawn_player_stream<S: FrameStream>(
+    executor: &dyn Executor,
+    key: PlayerKey,
+    mut stream: S,
     ser_type: SerializationType,
     tx: Sender<ConnectionMessage>,
+    traffic: Arc<InboundTraffic>,
 ) {
     // TODO: Sorry benny, i am positive you are sad by this.
-    tokio::spawn(async move {
-        loop {
-            match stream.next().await {
-                Some(Ok(tungstenite::Message::Binary(msg))) => {
-                    let msg =
-                        deserialize(msg, &ser_type).context("error while deserializing message");
+    // a panic reading the stream still frees the slot
+    let id = key.id;
+    let closed = tx.clone();
+    executor.spawn(Box::pin(async move {
+        let read = async move {
+            // a client spamming garbage would otherwise get a line per frame
+            let mut logs = LogSampler::default();
+            loop {
+                match stream.next().await {
+                    Some(Ok(tungstenite::Message::Binary(msg))) => {
+                        metrics().message_in(msg.len());
+                        let msg =
+                            deserialize(msg, &ser_type).context("error while deserializing message");
+                        match msg.as_ref() {
+                            Ok(msg) => traffic.record(msg.msg.tag()),
+                            Err(e) => {
+                                if let Some(suppressed) = logs.sample("bad message", std::time::Instant::now()) {
+                                    warn!(error = ?e, suppressed, "bad message");
+                                }
+                            }
+                        }
 
-                    _ = tx.send(ConnectionMessage::Msg((id, msg))).await;
-                }
+                        _ = tx.send(ConnectionMessage::Msg((key, msg))).await;
+                    }
 
-                Some(Ok(tungstenite::Message::Text(_))) => {
-                    _ = tx
-                        .send(ConnectionMessage::Error((id, ConnectionError::Text)))
-                        .await;
-                    break;
-                }
+                    Some(Ok(tungstenite::Message::Text(_))) => {
+                        _ = tx
+                            .send(ConnectionMessage::Error((key, ConnectionError::Text)))
+                            .await;
+                        break;
+                    }
 
-                // control frames
-                Some(Ok(_)) => {}
+                    // control frames
+                    Some(Ok(_)) => {}
 
-                Some(Err(e)) => {
-                    _ = tx
-                        .send(ConnectionMessage::Error((
-                            id,
-                            ConnectionError::WebSocketError(e),
-                        )))
-                        .await;
-                }
+                    // past accept::MAX_MESSAGE_SIZE, nothing legit is that big
+                    Some(Err(tungstenite::Error::Capacity(e))) => {
+                        warn!(error = ?e, "message too large, disconnecting");
+                        metrics().kick("oversized_message");
+                        _ = tx.send(ConnectionMessage::Close(key)).await;
+                        break;
+                    }
 
-                None => {
-                    _ = tx.send(ConnectionMessage::Close(id)).await;
-                    break;
-                }
-            };
+                    Some(Err(e)) => {
+                        if let Some(suppressed) = logs.sample("websocket error", std::time::Instant::now()) {
+                            warn!(error = ?e, suppressed, "websocket error");
+                        }
+                        _ = tx
+                            .send(ConnectionMessage::Error((
+                                key,
+                                ConnectionError::WebSocketError(e),
+                            )))
+                            .await;
+                    }
+
+                    None => {
+                        info!("connection closed");
+                        _ = tx.send(ConnectionMessage::Close(key)).await;
+                        break;
+                    }
+                };
+            }
+
+            for (event, suppressed) in logs.drain() {
+                warn!(event, suppressed, "log events suppressed");
+            }
+        };
+
+        if AssertUnwindSafe(read).catch_unwind().await.is_err() {
+            _ = closed.send(ConnectionMessage::Close(key)).await;
         }
-    });
+    }.instrument(info_span!("connection", player_id = id))));
 }
 
-impl PlayerSink {
-    pub fn new(id: u8, sink: PlayerWebSink) -> PlayerSink {
+/// how a join handshake ended, see spawn_handshake.
+pub enum Handshake<T: Transport = WebSocket> {
+    Synced(SyncedPlayer<T>),
+    // the clock sync never finished and the connection is closed already,
+    // the slot is free to go
+    Abandoned(u8),
+}
+
+// a connection that finished its clock sync and can take its slot
+pub struct SyncedPlayer<T: Transport = WebSocket> {
+    pub id: u8,
+    // as asked for, the game still has to make it unique
+    pub name: Option<String>,
+    pub stream: T::Stream,
+    pub sink: T::Sink,
+    pub clock_diff: i64,
+}
+
+/// runs the clock sync off the game loop, at most one per permit at a time.
+/// config has the samples it takes, how long it may take and what happens
+/// when it doesn't finish, see OnSyncTimeout.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_handshake<T: Transport>(
+    executor: &Arc<dyn Executor>,
+    id: u8,
+    name: Option<String>,
+    config: &GameConfig,
+    mut stream: T::Stream,
+    mut sink: T::Sink,
+    permits: Arc<Semaphore>,
+    tx: Sender<Handshake<T>>,
+) {
+    let (samples, limit, on_timeout) = (config.clock_sync_samples, config.handshake_timeout, config.on_sync_timeout);
+    let timer = executor.clone();
+    executor.spawn(Box::pin(async move {
+        let Ok(_permit) = permits.acquire_owned().await else {
+            return;
+        };
+
+        // a request or response lost on the way would hold the permit and
+        // the slot forever
+        let sync = timeout(&*timer, limit, sync_clock(samples, &mut stream, &mut sink));
+        let clock_diff = match sync.await {
+            Some(Ok(clock_diff)) => Some(clock_diff),
+            Some(Err(e)) => {
+                warn!(error = ?e, "clock sync failed");
+                None
+            }
+            None => {
+                warn!(timeout = ?limit, "clock sync timed out");
+                None
+            }
+        };
+
+        if clock_diff.is_none() {
+            metrics().handshake_failed();
+        }
+
+        let handshake = match (clock_diff, on_timeout) {
+            (None, OnSyncTimeout::Drop) => {
+                T::close(stream, sink);
+                Handshake::Abandoned(id)
+            }
+            (clock_diff, _) => Handshake::Synced(SyncedPlayer {
+                id,
+                name,
+                stream,
+                sink,
+                clock_diff: clock_diff.unwrap_or(0),
+            }),
+        };
+        _ = tx.send(handshake).await;
+    }.instrument(info_span!("connection", player_id = id))));
+}
+
+/// tells the connection why it couldn't join (JOIN_ERROR_*) and closes it.
+pub async fn reject_connection<S: FrameSink>(sink: S, reason: u8) {
+    reject_connection_on(tokio_executor(), sink, reason).await;
+}
+
+/// reject_connection from a game, on the game's executor.
+pub async fn reject_connection_on<S: FrameSink>(executor: Arc<dyn Executor>, sink: S, reason: u8) {
+    metrics().kick(join_error_reason(reason));
+    let mut sink = PlayerSink::with_executor(0, sink, executor);
+    _ = sink.send(Message::JoinError(reason)).await;
+    sink.close().await;
+}
+
+impl<S: FrameSink> PlayerSink<S> {
+    pub fn new(id: u8, sink: S) -> Self {
+        return PlayerSink::with_executor(id, sink, tokio_executor());
+    }
+
+    pub fn with_executor(id: u8, sink: S, executor: Arc<dyn Executor>) -> Self {
         return PlayerSink {
             id,
-            sink,
+            sink: Some(sink),
             seq_nu: 0,
             ser_type: SerializationType::Deku,
+            serialize_time: std::time::Duration::ZERO,
+            sent: [0; MESSAGE_TAGS],
+            stats: SendStats::default(),
+            stalled: false,
+            capture: None,
+            events: vec![],
+            executor,
+            buffer: EncodeBuffer::default(),
         };
     }
 
+    pub fn detached(id: u8) -> Self {
+        return PlayerSink {
+            id,
+            sink: None,
+            seq_nu: 0,
+            ser_type: SerializationType::Deku,
+            serialize_time: std::time::Duration::ZERO,
+            sent: [0; MESSAGE_TAGS],
+            stats: SendStats::default(),
+            stalled: false,
+            capture: None,
+            events: vec![],
+            executor: tokio_executor(),
+            buffer: EncodeBuffer::default(),
+        };
+    }
+
+    // a stalled client won't take the close frame either, don't wait on it
+    pub async fn close(&mut self) {
+        if let Some(sink) = self.sink.as_mut() {
+            _ = timeout(&*self.executor, CONTROL_SEND_TIMEOUT, sink.close()).await;
+        }
+    }
+
+    /// closes with 1011, the client shows a server error instead of a
+    /// normal disconnect. a crashed game closes everyone with it, one stalled
+    /// client can't hold up the rest.
+    pub async fn close_with_error(&mut self) {
+        if let Some(sink) = self.sink.as_mut() {
+            let frame = CloseFrame {
+                code: CloseCode::Error,
+                reason: "server error".into(),
+            };
+            let close = sink.send(tungstenite::Message::Close(Some(frame)));
+            if timeout(&*self.executor, CONTROL_SEND_TIMEOUT, close).await.is_some() {
+                _ = timeout(&*self.executor, CONTROL_SEND_TIMEOUT, sink.close()).await;
+            }
+        }
+    }
+
+    /// held until flush_events, bots have nowhere to send it and drop it.
+    pub fn queue_event(&mut self, event: server::GameEvent) {
+        if self.sink.is_some() {
+            self.events.push(event);
+        }
+    }
+
+    /// everything queued since the last flush as one EventBatch, in the order
+    /// it was queued. nothing is sent when nothing was queued.
+    pub async fn flush_events(&mut self) -> Result<()> {
+        let mut events = std::mem::take(&mut self.events);
+        while !events.is_empty() {
+            let rest = events.split_off(events.len().min(EVENT_BATCH_MAX));
+            self.send(Message::EventBatch(server::EventBatch::new(events))).await?;
+            events = rest;
+        }
+
+        return Ok(());
+    }
+
     pub async fn send(&mut self, msg: server::Message) -> Result<()> {
         self.seq_nu += 1;
+        if self.sink.is_none() {
+            return Ok(());
+        }
 
+        if self.stalled {
+            return Err(anyhow::anyhow!("player {} is stalled", self.id));
+        }
+
+        let tag = msg.tag();
+        let class = SendClass::of(&msg);
         let msg = ServerMessage::new(self.seq_nu, msg);
 
-        let msg = if let SerializationType::JSON = self.ser_type {
-            serde_json::to_vec(&msg).context("error while encoding json")?
+        let started = std::time::Instant::now();
+        let encoded = if let SerializationType::JSON = self.ser_type {
+            msg.json_into(&mut self.buffer).context("error while encoding json")?
         } else {
-            msg.serialize().context("error while encoding deku")?
+            msg.serialize_into(&mut self.buffer).context("error while encoding deku")?
+        };
+        // tungstenite takes a frame as a Vec, this copy is the one allocation
+        let msg = encoded.to_vec();
+        self.serialize_time += started.elapsed();
+
+        return self.write(tag, class, msg).await;
+    }
+
+    /// a broadcast encoded once for every sink, only the seq_nu is this
+    /// sink's own. the same as send with the message shared was encoded from.
+    pub async fn send_raw(&mut self, shared: &SharedMessage) -> Result<()> {
+        self.seq_nu += 1;
+        if self.sink.is_none() {
+            return Ok(());
+        }
+
+        if self.stalled {
+            return Err(anyhow::anyhow!("player {} is stalled", self.id));
+        }
+
+        let msg = shared.frame(self.ser_type, self.seq_nu)?;
+        return self.write(shared.tag, shared.class, msg).await;
+    }
+
+    // an encoded message onto the socket, stalls and slow sends are handled
+    // here for send and send_raw alike
+    async fn write(&mut self, tag: usize, class: SendClass, msg: Vec<u8>) -> Result<()> {
+        let Some(sink) = self.sink.as_mut() else {
+            return Ok(());
+        };
+
+        self.sent[tag] += 1;
+        metrics().message_out(msg.len());
+        if let Some(capture) = self.capture.as_ref() {
+            capture.record(Direction::Out, &msg);
+        }
+        let started = std::time::Instant::now();
+        let frame = tungstenite::Message::Binary(msg);
+        // None when the socket couldn't take it in time
+        let sent = match class {
+            // only dropped when there is no room for it, once the frame is
+            // queued a flush that has to wait carries on with the next send
+            SendClass::State => match std::future::poll_fn(|cx| sink.poll_ready_unpin(cx)).now_or_never() {
+                Some(Ok(())) => match sink.start_send_unpin(frame) {
+                    Ok(()) => Some(sink.flush().now_or_never().unwrap_or(Ok(()))),
+                    Err(e) => Some(Err(e)),
+                },
+                not_ready => not_ready,
+            },
+            SendClass::Control => timeout(&*self.executor, CONTROL_SEND_TIMEOUT, sink.send(frame)).await,
         };
+        let took = started.elapsed();
+        let delivered = matches!(sent, Some(Ok(())));
 
-        // TODO: Somehow i got into this situation where i don't know how to
-        // write.  i really hate this trait thing.
-        // PlayerSink.write(&mut self.sink, &msg).await;
+        if !delivered {
+            metrics().send_dropped(class);
+        }
+        if took > SLOW_SEND {
+            metrics().slow_send(took);
+        }
+        if self.stats.record(took, delivered, class, std::time::Instant::now()) {
+            warn!(player_id = self.id, send_us = took.as_micros() as u64, "slow consumer");
+        }
 
-        // self.sink.write_all(tungstenite::Message::Binary(msg)).await?;
-        self.sink.send(tungstenite::Message::Binary(msg)).await?;
+        match sent {
+            Some(sent) => sent?,
+            // the next snapshot replaces it, the client just missed one
+            None if class == SendClass::State => {}
+            None => {
+                self.stalled = true;
+                warn!(player_id = self.id, "stalled on a control message");
+                return Err(anyhow::anyhow!("player {} stalled", self.id));
+            }
+        }
 
         return Ok(());
     }
 }
 
-pub struct Player {
+// bounds on the round trips of one clock sync. every round waits on the
+// client, a connection asking for thousands would hold a handshake permit
+// for as long as it liked
+pub const MIN_CLOCK_SYNC_SAMPLES: usize = 1;
+pub const MAX_CLOCK_SYNC_SAMPLES: usize = 32;
+
+/// a clock sync sample count the server is willing to run, counts out of
+/// range are refused rather than quietly changed.
+pub fn check_sync_samples(requested: usize) -> Result<usize> {
+    if !(MIN_CLOCK_SYNC_SAMPLES..=MAX_CLOCK_SYNC_SAMPLES).contains(&requested) {
+        return Err(anyhow::anyhow!(
+            "clock sync of {} samples, has to be between {} and {}",
+            requested,
+            MIN_CLOCK_SYNC_SAMPLES,
+            MAX_CLOCK_SYNC_SAMPLES
+        ));
+    }
+
+    return Ok(requested);
+}
+
+// each resync only moves clock_diff 1/N of the way towards the new sample so a
+// single noisy round trip can't make inputs jump around.
+const CLOCK_RESYNC_SMOOTHING: i64 = 8;
+
+pub fn now_micros() -> i64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as i64;
+}
+
+pub fn clock_sample(then: i64, rtt: i64, client_time: i64) -> i64 {
+    return (then + rtt / 2) - client_time * 1000;
+}
+
+pub fn smooth_clock_diff(current: i64, sample: i64) -> i64 {
+    return current + (sample - current) / CLOCK_RESYNC_SMOOTHING;
+}
+
+pub struct Player<S: FrameSink = PlayerWebSink> {
     pub id: u8,
+    // unique within the game
+    pub name: String,
     pub position: (u16, u16),
-    pub sink: PlayerSink,
+    pub sink: PlayerSink<S>,
     pub clock_diff: i64,
+    // (when, server micros) of the resync request still waiting on a response
+    pub pending_clock_sync: Option<(std::time::Instant, i64)>,
+    // see movement::validate_move
+    pub move_budget: u32,
+    // tick of the last emote that went out, see emote::check_emote
+    pub last_emote: Option<u128>,
+    // seq_nu of the last key press the game processed, moves it rejected too
+    pub last_input_seq: u16,
+    // micros, round trip of the last clock resync
+    pub rtt: i64,
+    // ticks between two DebugTelemetry, None unless they opted in
+    pub telemetry: Option<u128>,
 }
 
-impl Player {
-    pub async fn sync_clock(
-        count: usize,
-        stream: &mut PlayerWebStream,
-        sink: &mut PlayerWebSink,
-    ) -> Result<i64> {
-        let mut clock_diffs: Vec<i64> = vec![];
+impl<S: FrameSink> Player<S> {
+    pub async fn request_clock_resync(&mut self) -> Result<()> {
+        self.pending_clock_sync = Some((std::time::Instant::now(), now_micros()));
+        return self.sink.send(Message::clock_request()).await;
+    }
 
-        for _ in 0..count {
-            let rtt = std::time::Instant::now();
-            let then = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_micros() as i64;
+    pub fn on_clock_sync_response(&mut self, client_time: i64) {
+        if let Some((rtt, then)) = self.pending_clock_sync.take() {
+            let rtt = rtt.elapsed().as_micros() as i64;
+            self.rtt = rtt;
+            self.clock_diff = smooth_clock_diff(self.clock_diff, clock_sample(then, rtt, client_time));
+        }
+    }
+}
 
-            let msg = Message::clock_request();
-            let msg = ServerMessage::new(0, msg).serialize()?;
+/// averages count clock sync round trips with the client, the handshake
+/// every new player goes through.
+pub async fn sync_clock<St: FrameStream, Si: FrameSink>(count: usize, stream: &mut St, sink: &mut Si) -> Result<i64> {
+    let count = check_sync_samples(count)?;
+    let mut clock_diffs: Vec<i64> = vec![];
 
-            sink.send(tungstenite::Message::Binary(msg)).await?;
-            let msg = loop {
-                match stream.next().await {
-                    Some(Ok(tungstenite::Message::Binary(msg))) => {
-                        break msg;
-                    }
-                    Some(Ok(tungstenite::Message::Ping(_))) => {}
-                    Some(Ok(tungstenite::Message::Pong(_))) => {}
+    for _ in 0..count {
+        let rtt = std::time::Instant::now();
+        let then = now_micros();
 
-                    Some(Err(e)) => {
-                        return Err(anyhow::anyhow!("error while syncing clock: {:?}", e));
-                    }
+        let msg = Message::clock_request();
+        let msg = ServerMessage::new(0, msg).serialize()?;
 
-                    _ => {
-                        return Err(anyhow::anyhow!("error while syncing clock"));
-                    }
+        sink.send(tungstenite::Message::Binary(msg)).await?;
+        let msg = loop {
+            match stream.next().await {
+                Some(Ok(tungstenite::Message::Binary(msg))) => {
+                    break msg;
+                }
+                Some(Ok(tungstenite::Message::Ping(_))) => {}
+                Some(Ok(tungstenite::Message::Pong(_))) => {}
+
+                Some(Err(e)) => {
+                    return Err(anyhow::anyhow!("error while syncing clock: {:?}", e));
                 }
-            };
 
-            let msg = ServerMessage::deserialize(&msg)?;
-            let msg = match msg.msg {
-                Message::ClockSyncResponse(resp) => resp,
                 _ => {
                     return Err(anyhow::anyhow!("error while syncing clock"));
                 }
+            }
+        };
+
+        let msg = ServerMessage::deserialize(&msg)?;
+        let msg = match msg.msg {
+            Message::ClockSyncResponse(resp) => resp,
+            _ => {
+                return Err(anyhow::anyhow!("error while syncing clock"));
+            }
+        };
+
+        let rtt = rtt.elapsed().as_micros() as i64;
+        clock_diffs.push(clock_sample(then, rtt, msg.client_time));
+    }
+
+    return Ok(clock_diffs.iter().sum::<i64>() / clock_diffs.len() as i64);
+}
+
+#[cfg(test)]
+mod test {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use anyhow::Result;
+    use futures::StreamExt;
+
+    use encoding::server::{self, Message, ServerMessage};
+    use tokio_tungstenite::tungstenite;
+
+    use crate::{
+        send_stats::{SendClass, CONTROL_SEND_TIMEOUT},
+        test_utils::{small_buffer_ws_pair, ws_pair, TestSocket},
+    };
+
+    use super::{check_sync_samples, smooth_clock_diff, sync_clock, PlayerSink, MAX_CLOCK_SYNC_SAMPLES};
+
+    // a client that never takes another frame, not even the close
+    #[derive(Debug)]
+    struct Stuck;
+
+    impl futures::Sink<tungstenite::Message> for Stuck {
+        type Error = tungstenite::Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            return Poll::Pending;
+        }
+
+        fn start_send(self: Pin<&mut Self>, _: tungstenite::Message) -> Result<(), Self::Error> {
+            return Ok(());
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            return Poll::Pending;
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            return Poll::Pending;
+        }
+    }
+
+    // takes every frame but never gets one out to the client
+    #[derive(Debug)]
+    struct Unflushed;
+
+    impl futures::Sink<tungstenite::Message> for Unflushed {
+        type Error = tungstenite::Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            return Poll::Ready(Ok(()));
+        }
+
+        fn start_send(self: Pin<&mut Self>, _: tungstenite::Message) -> Result<(), Self::Error> {
+            return Ok(());
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            return Poll::Pending;
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            return Poll::Pending;
+        }
+    }
+
+    // sends snapshots nobody reads until in_a_row of them in a row got
+    // dropped, none of them may wait on the client. sleeps in between like
+    // the tick loop does, so tokio gets to see the socket drain
+    async fn saturate(sink: &mut PlayerSink, in_a_row: u32) -> Result<()> {
+        let entities: Vec<server::PlayerPositionUpdate> = (0..2000)
+            .map(|entity_id| server::PlayerPositionUpdate {
+                entity_id,
+                position: (1, 1),
+            })
+            .collect();
+
+        let started = std::time::Instant::now();
+        let mut dropped = 0;
+        while dropped < in_a_row {
+            let before = sink.stats.dropped[SendClass::State as usize];
+            sink.send(Message::Snapshot(server::Snapshot::new(1, entities.clone()))).await?;
+            dropped = match sink.stats.dropped[SendClass::State as usize] > before {
+                true => dropped + 1,
+                false => 0,
             };
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            assert!(started.elapsed() < std::time::Duration::from_secs(10), "socket never filled up");
+        }
 
-            let rtt = rtt.elapsed().as_micros() as i64;
-            let clock_diff = (then + rtt / 2) - msg.client_time * 1000;
-            clock_diffs.push(clock_diff);
+        return Ok(());
+    }
+
+    // skips over the snapshots without decoding them
+    async fn read_until_countdown(mut client: TestSocket) -> Result<()> {
+        while let Some(msg) = client.next().await {
+            if let tungstenite::Message::Binary(bytes) = msg? {
+                if bytes.len() < 64 && ServerMessage::deserialize(&bytes)?.msg == Message::Countdown(0) {
+                    return Ok(());
+                }
+            }
+        }
+
+        return Err(anyhow::anyhow!("closed before the countdown"));
+    }
+
+    #[tokio::test]
+    async fn test_saturated_client_still_gets_control_messages() -> Result<()> {
+        let (server_socket, client) = small_buffer_ws_pair(1 << 16).await?;
+        let (sink, _stream) = server_socket.split();
+        let mut sink = PlayerSink::new(0, sink);
+        saturate(&mut sink, 1).await?;
+
+        // stands in for the end of the game, the client catches up in time
+        let reader = tokio::spawn(read_until_countdown(client));
+        sink.send(Message::Countdown(0)).await?;
+        reader.await??;
+        assert!(!sink.stalled);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_client_that_never_reads_is_stalled() -> Result<()> {
+        let (server_socket, _client) = small_buffer_ws_pair(1 << 16).await?;
+        let (sink, _stream) = server_socket.split();
+        let mut sink = PlayerSink::new(0, sink);
+        saturate(&mut sink, 20).await?;
+
+        assert!(sink.send(Message::Countdown(0)).await.is_err());
+        assert!(sink.stalled);
+        assert_eq!(sink.stats.dropped[SendClass::Control as usize], 1);
+
+        // no more waiting on them
+        let started = std::time::Instant::now();
+        assert!(sink.send(Message::Countdown(1)).await.is_err());
+        assert!(started.elapsed() < std::time::Duration::from_millis(50));
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_is_only_dropped_without_room_for_it() -> Result<()> {
+        let snapshot = Message::Snapshot(server::Snapshot::new(1, vec![]));
+
+        // queued, the flush goes on with the next send
+        let mut sink = PlayerSink::new(0, Unflushed);
+        sink.send(snapshot.clone()).await?;
+        assert_eq!(sink.stats.dropped[SendClass::State as usize], 0);
+
+        let mut sink = PlayerSink::new(0, Stuck);
+        sink.send(snapshot).await?;
+        assert_eq!(sink.stats.dropped[SendClass::State as usize], 1);
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn test_close_with_error_gives_up_on_a_stuck_client() {
+        let mut sink = PlayerSink::new(0, Stuck);
+        let closed = tokio::time::timeout(CONTROL_SEND_TIMEOUT * 3, sink.close_with_error()).await;
+        assert!(closed.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_excessive_sync_samples_rejected() -> Result<()> {
+        assert_eq!(check_sync_samples(10)?, 10);
+        assert!(check_sync_samples(0).is_err());
+        assert!(check_sync_samples(MAX_CLOCK_SYNC_SAMPLES + 1).is_err());
+
+        let (server_socket, client) = ws_pair().await?;
+        let (mut sink, mut stream) = server_socket.split();
+        drop(client);
+
+        // refused before a single request goes out, a closed client would
+        // fail the first round otherwise
+        let err = sync_clock(1_000_000, &mut stream, &mut sink).await.expect_err("too many samples");
+        assert!(err.to_string().contains("1000000 samples"));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_clock_drift_is_smoothed_towards_offset() {
+        let true_offset = 5_000;
+        let mut clock_diff = 0;
+        let mut last_error = true_offset;
+
+        for _ in 0..30 {
+            let next = smooth_clock_diff(clock_diff, true_offset);
+
+            // never jumps more than a fraction of the error in one resync
+            assert!(next - clock_diff <= true_offset / 8);
+
+            clock_diff = next;
+            let error = (true_offset - clock_diff).abs();
+            assert!(error <= last_error);
+            last_error = error;
         }
 
-        return Ok(clock_diffs.iter().sum::<i64>() / clock_diffs.len() as i64);
+        assert!((true_offset - clock_diff).abs() < 100);
     }
 }
@@ -0,0 +1,156 @@
+// A game's players by id. Ids are slots and slots get reused, so anything
+// that outlives a player (its stream task, a Close on its way) holds a
+// PlayerKey: the id plus which occupant of the slot it was. A key from a
+// previous occupant doesn't reach the new one. The live players sit in a
+// list of their own, in id order, walking them doesn't touch empty slots.
+
+/// one occupant of a player slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PlayerKey {
+    pub id: u8,
+    pub generation: u32,
+}
+
+#[derive(Debug)]
+pub struct PlayerSlab<V> {
+    // per slot, moves on every time someone takes it
+    generations: Vec<u32>,
+    // the live players, sorted by id
+    live: Vec<(u8, V)>,
+}
+
+impl<V> PlayerSlab<V> {
+    pub fn new(capacity: usize) -> Self {
+        return PlayerSlab {
+            generations: vec![0; capacity.min(u8::MAX as usize + 1)],
+            live: Vec::with_capacity(capacity),
+        };
+    }
+
+    fn position(&self, id: u8) -> Result<usize, usize> {
+        return self.live.binary_search_by_key(&id, |(live, _)| *live);
+    }
+
+    /// value takes slot id, whoever had it before is gone. the key is what
+    /// reaches this occupant and no other.
+    pub fn insert(&mut self, id: u8, value: V) -> PlayerKey {
+        let generation = &mut self.generations[id as usize];
+        *generation = generation.wrapping_add(1);
+        let key = PlayerKey {
+            id,
+            generation: *generation,
+        };
+
+        match self.position(id) {
+            Ok(at) => self.live[at].1 = value,
+            Err(at) => self.live.insert(at, (id, value)),
+        }
+
+        return key;
+    }
+
+    pub fn remove(&mut self, id: u8) -> Option<V> {
+        let at = self.position(id).ok()?;
+        return Some(self.live.remove(at).1);
+    }
+
+    /// remove, unless the slot has moved on to someone else since key.
+    pub fn remove_key(&mut self, key: PlayerKey) -> Option<V> {
+        if !self.is_current(key) {
+            return None;
+        }
+
+        return self.remove(key.id);
+    }
+
+    pub fn is_current(&self, key: PlayerKey) -> bool {
+        return self.generations.get(key.id as usize) == Some(&key.generation) && self.contains(key.id);
+    }
+
+    /// the key of whoever is in slot id.
+    pub fn key(&self, id: u8) -> Option<PlayerKey> {
+        if !self.contains(id) {
+            return None;
+        }
+
+        return Some(PlayerKey {
+            id,
+            generation: self.generations[id as usize],
+        });
+    }
+
+    pub fn contains(&self, id: u8) -> bool {
+        return self.position(id).is_ok();
+    }
+
+    pub fn get(&self, id: u8) -> Option<&V> {
+        let at = self.position(id).ok()?;
+        return Some(&self.live[at].1);
+    }
+
+    pub fn get_mut(&mut self, id: u8) -> Option<&mut V> {
+        let at = self.position(id).ok()?;
+        return Some(&mut self.live[at].1);
+    }
+
+    /// how many slots there are, ids go up to one below.
+    pub fn capacity(&self) -> usize {
+        return self.generations.len();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.live.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.live.is_empty();
+    }
+
+    /// the live players in id order.
+    pub fn iter(&self) -> impl Iterator<Item = &V> {
+        return self.live.iter().map(|(_, value)| value);
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        return self.live.iter_mut().map(|(_, value)| value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PlayerKey, PlayerSlab};
+
+    #[test]
+    fn test_stale_key_does_not_reach_the_next_occupant() {
+        let mut slab = PlayerSlab::new(4);
+        let first = slab.insert(2, "ada");
+        assert_eq!(slab.remove_key(first), Some("ada"));
+
+        let second = slab.insert(2, "bob");
+        assert_ne!(first, second);
+        assert!(!slab.is_current(first));
+        assert_eq!(slab.remove_key(first), None);
+        assert_eq!(slab.get(2), Some(&"bob"));
+
+        assert_eq!(slab.key(2), Some(second));
+        assert_eq!(slab.remove_key(second), Some("bob"));
+        assert_eq!(slab.key(2), None);
+        assert!(!slab.is_current(PlayerKey { id: 9, generation: 0 }));
+    }
+
+    #[test]
+    fn test_iterates_live_players_in_id_order() {
+        let mut slab = PlayerSlab::new(8);
+        for id in [5, 1, 7, 3] {
+            slab.insert(id, id);
+        }
+        slab.remove(7);
+
+        assert_eq!(slab.iter().copied().collect::<Vec<_>>(), vec![1, 3, 5]);
+        for value in slab.iter_mut() {
+            *value *= 10;
+        }
+        assert_eq!(slab.get(3), Some(&30));
+        assert_eq!(slab.len(), 3);
+    }
+}
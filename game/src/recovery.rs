@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use encoding::server;
+use tracing::{info, warn};
+
+use crate::game_state::GameState;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RecoveredPlayer {
+    pub player_id: u8,
+    pub name: String,
+    pub position: (u16, u16),
+    pub bot: bool,
+    pub move_budget: u32,
+    pub last_emote: Option<u128>,
+}
+
+/// what it takes to put a game back together after a restart. the map
+/// isn't in it, it comes back from the seed and map_checksum makes sure it
+/// is the same one.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RecoveryImage {
+    pub game_id: u32,
+    pub seed: u32,
+    pub map_checksum: u32,
+    pub state: GameState,
+    pub warmup_end: u128,
+    pub tick: u128,
+    pub zone: server::Zone,
+    pub players: Vec<RecoveredPlayer>,
+    pub short_handed: bool,
+    // unix millis, images older than the ttl are thrown away
+    pub saved_at: u64,
+}
+
+fn unix_millis(at: SystemTime) -> u64 {
+    return at.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+}
+
+pub fn now_millis() -> u64 {
+    return unix_millis(SystemTime::now());
+}
+
+/// one file per game, every snapshot replaces the last one.
+pub fn image_path(dir: &Path, game_id: u32) -> PathBuf {
+    return dir.join(format!("game-{}.recovery.json", game_id));
+}
+
+/// blocking, keep it off the game loop.
+pub fn write_image(dir: &Path, image: &RecoveryImage) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).context("creating recovery dir")?;
+
+    let path = image_path(dir, image.game_id);
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(image)?).context("writing recovery image")?;
+    std::fs::rename(&tmp, &path).context("moving recovery image into place")?;
+
+    return Ok(path);
+}
+
+/// the game ended on its own, nothing to recover.
+pub fn remove_image(dir: &Path, game_id: u32) {
+    let path = image_path(dir, game_id);
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!(?path, error = ?e, "removing recovery image failed");
+        }
+    }
+}
+
+/// every image in dir younger than ttl. stale and unreadable ones are
+/// deleted on the way.
+pub fn load_images(dir: &Path, ttl: Duration, now: u64) -> Vec<RecoveryImage> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut images = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_image = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".recovery.json"));
+        if !is_image {
+            continue;
+        }
+
+        let image = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<RecoveryImage>(&bytes).ok());
+
+        match image {
+            Some(image) if now.saturating_sub(image.saved_at) <= ttl.as_millis() as u64 => images.push(image),
+            Some(image) => {
+                info!(game_id = image.game_id, "recovery image past the ttl, deleting");
+                _ = std::fs::remove_file(&path);
+            }
+            None => {
+                warn!(?path, "unreadable recovery image, deleting");
+                _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+
+    images.sort_by_key(|image| image.game_id);
+    return images;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use encoding::server::Zone;
+
+    use crate::game_state::GameState;
+
+    use super::{image_path, load_images, remove_image, write_image, RecoveryImage};
+
+    fn image(game_id: u32, saved_at: u64) -> RecoveryImage {
+        return RecoveryImage {
+            game_id,
+            seed: 3,
+            map_checksum: 4,
+            state: GameState::Live,
+            warmup_end: 0,
+            tick: 500,
+            zone: Zone {
+                center: (1, 1),
+                radius: 1,
+            },
+            players: vec![],
+            short_handed: false,
+            saved_at,
+        };
+    }
+
+    #[test]
+    fn test_stale_images_are_deleted() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("vim-royale-recovery-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+
+        write_image(&dir, &image(1, 100_000))?;
+        write_image(&dir, &image(2, 10_000))?;
+        write_image(&dir, &image(3, 100_000))?;
+        std::fs::write(dir.join("game-4.recovery.json"), b"not json")?;
+        remove_image(&dir, 3);
+        remove_image(&dir, 5);
+
+        let images = load_images(&dir, Duration::from_secs(60), 120_000);
+        assert_eq!(images, vec![image(1, 100_000)]);
+        assert!(!image_path(&dir, 2).exists());
+        assert!(!image_path(&dir, 4).exists());
+        assert!(image_path(&dir, 1).exists());
+
+        std::fs::remove_dir_all(&dir)?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use map::rand::mulberry32;
+
+/// where new games get their map seed from.
+pub trait SeedSource: Send {
+    fn next_seed(&mut self) -> u32;
+
+    /// the seed game_id plays with when it drew base. two games that drew
+    /// the same base still get different maps and outcome keys, sources that
+    /// pick their seeds on purpose keep them as they are.
+    fn game_seed(&self, base: u32, game_id: u32) -> u32 {
+        return per_game_seed(base, game_id);
+    }
+}
+
+// murmur3's finalizer. it is a bijection on u32, so two ids never land on the
+// same value and the same base can't give two games the same seed
+fn mix(game_id: u32) -> u32 {
+    let mut h = game_id;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    return h;
+}
+
+/// base combined with game_id, unique per game for any one base.
+pub fn per_game_seed(base: u32, game_id: u32) -> u32 {
+    return base ^ mix(game_id);
+}
+
+/// a different seed every game, the rand is seeded from the clock.
+pub struct TimeSeeds {
+    rand: Box<dyn FnMut() -> u32 + Send>,
+}
+
+impl TimeSeeds {
+    pub fn new() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+
+        return TimeSeeds {
+            rand: Box::new(mulberry32(now)),
+        };
+    }
+}
+
+impl Default for TimeSeeds {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl SeedSource for TimeSeeds {
+    fn next_seed(&mut self) -> u32 {
+        return (self.rand)();
+    }
+}
+
+/// every game plays the same map.
+pub struct FixedSeed(pub u32);
+
+impl SeedSource for FixedSeed {
+    fn next_seed(&mut self) -> u32 {
+        return self.0;
+    }
+
+    fn game_seed(&self, base: u32, _game_id: u32) -> u32 {
+        return base;
+    }
+}
+
+/// start, start + 1, ... so tests know up front which seed every game gets.
+pub struct SeedSequence {
+    next: u32,
+}
+
+impl SeedSequence {
+    pub fn new(start: u32) -> Self {
+        return SeedSequence { next: start };
+    }
+}
+
+impl SeedSource for SeedSequence {
+    fn next_seed(&mut self) -> u32 {
+        let seed = self.next;
+        self.next = self.next.wrapping_add(1);
+        return seed;
+    }
+
+    fn game_seed(&self, base: u32, _game_id: u32) -> u32 {
+        return base;
+    }
+}
+
+/// the config side of SeedSource, ManagerConfig has to stay Clone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SeedMode {
+    #[default]
+    Time,
+    Fixed(u32),
+    Sequence(u32),
+}
+
+impl SeedMode {
+    pub fn source(&self) -> Box<dyn SeedSource> {
+        return match self {
+            SeedMode::Time => Box::new(TimeSeeds::new()),
+            SeedMode::Fixed(seed) => Box::new(FixedSeed(*seed)),
+            SeedMode::Sequence(start) => Box::new(SeedSequence::new(*start)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{per_game_seed, SeedMode, SeedSequence, SeedSource, TimeSeeds};
+
+    #[test]
+    fn test_sequence_yields_seeds_in_order() {
+        let mut sequence = SeedSequence::new(u32::MAX - 1);
+        let seeds: Vec<u32> = (0..4).map(|_| sequence.next_seed()).collect();
+        assert_eq!(seeds, vec![u32::MAX - 1, u32::MAX, 0, 1]);
+
+        let mut fixed = SeedMode::Fixed(42).source();
+        assert_eq!((fixed.next_seed(), fixed.next_seed()), (42, 42));
+        assert_eq!((fixed.game_seed(42, 1), fixed.game_seed(42, 2)), (42, 42));
+    }
+
+    #[test]
+    fn test_same_base_seed_differs_per_game() {
+        let time = TimeSeeds::new();
+        for base in [0, 7, u32::MAX] {
+            let seeds: HashSet<u32> = (1..=1000).map(|game_id| time.game_seed(base, game_id)).collect();
+            assert_eq!(seeds.len(), 1000, "base {} repeated a seed", base);
+        }
+
+        // and it stays reproducible from the base and the id
+        assert_eq!(per_game_seed(7, 3), time.game_seed(7, 3));
+        assert_ne!(per_game_seed(7, 3), 7);
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use encoding::server::Message;
+
+// a send that takes longer than this means the client isn't keeping up, the
+// socket buffer is full and the flush waits on them
+pub const SLOW_SEND: Duration = Duration::from_millis(5);
+// how long a control message may wait on a backed up client before the
+// player counts as stalled and is disconnected
+pub const CONTROL_SEND_TIMEOUT: Duration = Duration::from_millis(250);
+// a slow consumer gets at most one warning per interval
+pub const SLOW_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+pub const SEND_CLASSES: usize = 2;
+
+/// what a lost message costs. state is replaced by the next snapshot anyways,
+/// so it is dropped when the socket can't take it right away. control
+/// messages (starts, zone updates, ...) are gone for good, they wait up to
+/// CONTROL_SEND_TIMEOUT and the player is disconnected if that isn't enough.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendClass {
+    State = 0,
+    Control = 1,
+}
+
+impl SendClass {
+    pub fn of(msg: &Message) -> SendClass {
+        return match msg {
+            Message::Snapshot(_)
+            | Message::FineSnapshot(_)
+            | Message::SpectatorSync(_)
+            | Message::PlayerPositionUpdate(_)
+            | Message::DebugTelemetry(_) => SendClass::State,
+            _ => SendClass::Control,
+        };
+    }
+
+    pub fn label(&self) -> &'static str {
+        return match self {
+            SendClass::State => "state",
+            SendClass::Control => "control",
+        };
+    }
+}
+
+/// how a sink's sends went. PlayerSink::send owns and updates it, the game
+/// copies it out when asked so the send path never waits on a lock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize)]
+pub struct SendStats {
+    pub sends: u64,
+    pub slow_sends: u64,
+    pub last_send_us: u64,
+    // high water mark
+    pub max_send_us: u64,
+    // time spent in sends slower than SLOW_SEND
+    pub saturated_us: u64,
+    // failed sends, indexed by SendClass
+    pub dropped: [u64; SEND_CLASSES],
+    #[serde(skip)]
+    last_warned: Option<Instant>,
+}
+
+impl SendStats {
+    /// true when this send should be warned about, the first slow one every
+    /// SLOW_WARN_INTERVAL.
+    pub fn record(&mut self, took: Duration, sent: bool, class: SendClass, now: Instant) -> bool {
+        let took_us = took.as_micros() as u64;
+        self.sends += 1;
+        self.last_send_us = took_us;
+        self.max_send_us = self.max_send_us.max(took_us);
+
+        if !sent {
+            self.dropped[class as usize] += 1;
+        }
+
+        if took <= SLOW_SEND {
+            return false;
+        }
+
+        self.slow_sends += 1;
+        self.saturated_us += took_us;
+
+        let warn = self
+            .last_warned
+            .is_none_or(|warned| now.saturating_duration_since(warned) >= SLOW_WARN_INTERVAL);
+        if warn {
+            self.last_warned = Some(now);
+        }
+
+        return warn;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use encoding::server::Message;
+
+    use super::{SendClass, SendStats, SLOW_WARN_INTERVAL};
+
+    #[test]
+    fn test_slow_sends_warn_once_a_minute() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut stats = SendStats::default();
+
+        assert!(!stats.record(ms(1), true, SendClass::State, start));
+        assert!(stats.record(ms(20), true, SendClass::State, start + ms(10)));
+        assert!(!stats.record(ms(30), false, SendClass::State, start + ms(40)));
+        assert!(!stats.record(ms(2), false, SendClass::Control, start + ms(80)));
+        assert!(stats.record(ms(8), true, SendClass::Control, start + ms(10) + SLOW_WARN_INTERVAL));
+
+        assert_eq!(stats.sends, 5);
+        assert_eq!(stats.slow_sends, 3);
+        assert_eq!(stats.last_send_us, 8_000);
+        assert_eq!(stats.max_send_us, 30_000);
+        assert_eq!(stats.saturated_us, 58_000);
+        assert_eq!(stats.dropped, [1, 1]);
+
+        assert_eq!(SendClass::of(&Message::Countdown(3)), SendClass::Control);
+    }
+}
@@ -0,0 +1,198 @@
+// The server's main loop: connections from the listener go to the
+// GameManager, and the manager's periodic work (health and status requests,
+// heartbeats, recovery images, dumps) runs in between. main runs it until
+// ctrl-c, LocalServer runs it on a port of its own for tests that want the
+// whole server.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, warn};
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::oneshot;
+
+use crate::{
+    accept::{accept_queue, DEFAULT_ACCEPT_BACKLOG},
+    game_config::{GameConfig, ManagerConfig},
+    game_manager::GameManager,
+};
+
+// how long games get to send the shutdown notice before the process goes
+pub const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+// how fresh the health auto balancing goes by is, and how soon queued
+// players get into a game that freed up
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// what runs next to the game listener.
+pub struct ServeOptions {
+    pub accept_backlog: usize,
+    // /health and /status, each on their own listener
+    pub health: Option<TcpListener>,
+    pub status: Option<TcpListener>,
+    pub recovery_interval: Duration,
+    // SIGUSR1 dumps every game
+    pub dump_signal: bool,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        return ServeOptions {
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            health: None,
+            status: None,
+            recovery_interval: Duration::from_secs(10),
+            dump_signal: false,
+        };
+    }
+}
+
+async fn dump_requested(signal: Option<&mut Signal>) {
+    match signal {
+        Some(signal) => _ = signal.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// serves listener until shutdown resolves or the listener fails. on
+/// shutdown every game is told, see GameManager::announce_shutdown, and gets
+/// SHUTDOWN_GRACE to pass it on.
+pub async fn serve(
+    listener: TcpListener,
+    mut game_manager: GameManager,
+    options: ServeOptions,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let mut accepted = accept_queue(listener, options.accept_backlog, game_manager.shaping());
+    let mut recovery = tokio::time::interval(options.recovery_interval.max(Duration::from_secs(1)));
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let heartbeat_on = game_manager.needs_heartbeat();
+    let mut dump_signal = match options.dump_signal {
+        true => Some(signal(SignalKind::user_defined1())?),
+        false => None,
+    };
+
+    tokio::pin!(shutdown);
+    let mut connection_count = 0;
+    loop {
+        tokio::select! {
+            connection = accepted.recv() => match connection {
+                Some(connection) => {
+                    connection_count += 1;
+                    info!(connection_count, peer = %connection.peer, "handing the game manager a new connection");
+                    game_manager.add_connection_from(connection.stream, connection.sink, Some(connection.peer)).await;
+                }
+
+                // the listener failed
+                None => return Ok(()),
+            },
+
+            stream = crate::health::accept(options.health.as_ref()) => {
+                let report = game_manager.health().await;
+                tokio::spawn(crate::health::respond(stream, report));
+            }
+
+            stream = crate::status::accept(options.status.as_ref()) => {
+                let statuses = game_manager.query_all_status().await;
+                tokio::spawn(crate::status::respond(stream, statuses));
+            }
+
+            _ = heartbeat.tick(), if heartbeat_on => {
+                game_manager.heartbeat().await;
+            }
+
+            _ = recovery.tick() => {
+                game_manager.snapshot_games().await;
+            }
+
+            _ = dump_requested(dump_signal.as_mut()) => {
+                game_manager.dump_games().await;
+            }
+
+            _ = &mut shutdown => break,
+        }
+    }
+
+    warn!("shutting down");
+    game_manager.announce_shutdown().await;
+    tokio::time::sleep(SHUTDOWN_GRACE).await;
+
+    return Ok(());
+}
+
+/// a whole server on a free localhost port, in a thread and runtime of its
+/// own so tests can run side by side. dropping it stops the server too.
+pub struct LocalServer {
+    pub addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    stopped: Option<oneshot::Receiver<()>>,
+}
+
+impl LocalServer {
+    pub fn start(game: GameConfig) -> Result<LocalServer> {
+        return LocalServer::start_with(ManagerConfig {
+            game,
+            ..ManagerConfig::default()
+        });
+    }
+
+    pub fn start_with(config: ManagerConfig) -> Result<LocalServer> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let (stopped_tx, stopped) = oneshot::channel();
+
+        // the manager isn't Send, it gets a runtime of its own like in main
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        std::thread::spawn(move || {
+            let served = runtime.block_on(async move {
+                let listener = TcpListener::from_std(listener)?;
+                let game_manager = GameManager::new(config)?;
+                // a dropped LocalServer shuts down the same way
+                let shutdown = async move { _ = shutdown_rx.await; };
+
+                return serve(listener, game_manager, ServeOptions::default(), shutdown).await;
+            });
+
+            if let Err(e) = served {
+                warn!(error = ?e, "local server failed");
+            }
+            // the games go with the runtime
+            drop(runtime);
+            _ = stopped_tx.send(());
+        });
+
+        return Ok(LocalServer {
+            addr,
+            shutdown: Some(shutdown),
+            stopped: Some(stopped),
+        });
+    }
+
+    /// host:port, what BotClient::connect takes.
+    pub fn address(&self) -> String {
+        return self.addr.to_string();
+    }
+
+    /// shuts the server down like ctrl-c does and waits until it is gone.
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            _ = shutdown.send(());
+        }
+        if let Some(stopped) = self.stopped.take() {
+            _ = stopped.await;
+        }
+    }
+}
+
+impl Drop for LocalServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            _ = shutdown.send(());
+        }
+    }
+}
@@ -0,0 +1,285 @@
+// Network shaping for development servers. Against localhost every frame
+// arrives instantly and netcode problems stay hidden, a shaped connection
+// holds every frame back by a steady latency plus jitter and loses some
+// unreliable ones, both ways. Unlike ChaosSocket frames are delayed side by
+// side instead of one after the other, so throughput stays what it was.
+// Never in release builds or ranked games, see GameConfig::validate.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use tracing::{info, warn};
+use map::rand::mulberry32;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::Instant,
+};
+use tokio_tungstenite::{
+    tungstenite::{self, Message},
+    WebSocketStream,
+};
+
+use crate::{
+    chaos::unreliable,
+    transport::{memory_pair, MemorySocket},
+};
+
+/// what a shaped connection does to the frames going through it, in both
+/// directions. parses from `latency=80ms,jitter=20ms,loss=2%`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShapeConfig {
+    pub seed: u32,
+    // every frame arrives latency plus up to jitter after it was sent
+    pub latency: Duration,
+    pub jitter: Duration,
+    // chance an unreliable frame never arrives, 0 to 1, see chaos::unreliable
+    pub loss: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeError {
+    // not key=value
+    Malformed(String),
+    UnknownKey(String),
+    BadDuration(String),
+    BadLoss(String),
+}
+
+impl std::fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ShapeError::Malformed(part) => write!(f, "expected key=value, got {:?}", part),
+            ShapeError::UnknownKey(key) => write!(f, "unknown shaping key {:?}, expected latency, jitter, loss or seed", key),
+            ShapeError::BadDuration(value) => write!(f, "expected a duration like 80ms or 1s, got {:?}", value),
+            ShapeError::BadLoss(value) => write!(f, "expected a loss between 0% and 100%, got {:?}", value),
+        };
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+fn parse_duration(value: &str) -> Result<Duration, ShapeError> {
+    let bad = || ShapeError::BadDuration(value.to_string());
+    if let Some(ms) = value.strip_suffix("ms") {
+        return ms.parse().map(Duration::from_millis).map_err(|_| bad());
+    }
+
+    return match value.strip_suffix('s') {
+        Some(s) => s.parse().map(Duration::from_secs).map_err(|_| bad()),
+        None => Err(bad()),
+    };
+}
+
+fn parse_loss(value: &str) -> Result<f32, ShapeError> {
+    let bad = || ShapeError::BadLoss(value.to_string());
+    let percent: f32 = value.strip_suffix('%').unwrap_or(value).parse().map_err(|_| bad())?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(bad());
+    }
+
+    return Ok(percent / 100.0);
+}
+
+impl FromStr for ShapeConfig {
+    type Err = ShapeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = ShapeConfig::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(ShapeError::Malformed(part.to_string()));
+            };
+
+            match key.trim() {
+                "latency" => config.latency = parse_duration(value.trim())?,
+                "jitter" => config.jitter = parse_duration(value.trim())?,
+                "loss" => config.loss = parse_loss(value.trim())?,
+                "seed" => {
+                    config.seed = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| ShapeError::Malformed(part.to_string()))?
+                }
+                key => return Err(ShapeError::UnknownKey(key.to_string())),
+            }
+        }
+
+        return Ok(config);
+    }
+}
+
+// reads from until it ends and sends everything that isn't lost into to,
+// each frame once its time has come. frames keep their order, one held back
+// by jitter holds back the ones after it too, like tcp would
+async fn pump<R, W>(mut from: R, mut to: W, config: ShapeConfig, seed: u32)
+where
+    R: Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+    W: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<(Instant, Message)>();
+
+    let read = async move {
+        let mut rand = mulberry32(seed);
+        let jitter = config.jitter.as_micros() as u32;
+        let mut last_due = Instant::now();
+
+        while let Some(Ok(frame)) = from.next().await {
+            if config.loss > 0.0 && (rand() as f64 / u32::MAX as f64) < config.loss as f64 && unreliable(&frame) {
+                continue;
+            }
+
+            let jitter = match jitter {
+                0 => 0,
+                _ => rand() % jitter,
+            };
+            let due = (Instant::now() + config.latency + Duration::from_micros(jitter as u64)).max(last_due);
+            last_due = due;
+
+            // the writing side is gone
+            if tx.send((due, frame)).is_err() {
+                break;
+            }
+        }
+    };
+
+    let write = async move {
+        while let Some((due, frame)) = rx.recv().await {
+            tokio::time::sleep_until(due).await;
+            if to.send(frame).await.is_err() {
+                break;
+            }
+        }
+        _ = to.close().await;
+    };
+
+    tokio::join!(read, write);
+}
+
+/// passes frames between a and b with config applied both ways, until
+/// either side closes. the b to a direction draws from the next seed.
+pub fn relay<A, B>(a: A, b: B, config: ShapeConfig)
+where
+    A: Stream<Item = Result<Message, tungstenite::Error>> + Sink<Message, Error = tungstenite::Error> + Send + 'static,
+    B: Stream<Item = Result<Message, tungstenite::Error>> + Sink<Message, Error = tungstenite::Error> + Send + 'static,
+{
+    let (a_sink, a_stream) = a.split();
+    let (b_sink, b_stream) = b.split();
+
+    tokio::spawn(pump(a_stream, b_sink, config, config.seed));
+    tokio::spawn(pump(b_stream, a_sink, config, config.seed.wrapping_add(1)));
+}
+
+/// (server end, client end) of an in memory connection shaped by config.
+pub fn shaped_pair(buffer: usize, config: ShapeConfig) -> (MemorySocket, MemorySocket) {
+    let (server, server_inner) = memory_pair(buffer);
+    let (client_inner, client) = memory_pair(buffer);
+    relay(server_inner, client_inner, config);
+
+    return (server, client);
+}
+
+/// the game's end of an accepted websocket with config applied. the frames
+/// go through a second websocket over loopback, so the game still gets a
+/// plain WebSocketStream<TcpStream>.
+pub async fn shape_websocket(
+    socket: WebSocketStream<TcpStream>,
+    config: ShapeConfig,
+) -> anyhow::Result<WebSocketStream<TcpStream>> {
+    // one listener per connection, fine for a development server
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (near, far) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    let (near, (far, _)) = (near?, far?);
+
+    let (near, far) = tokio::join!(
+        tokio_tungstenite::client_async(format!("ws://{}", addr), near),
+        tokio_tungstenite::accept_async(far)
+    );
+    let ((near, _), far) = (near?, far?);
+
+    info!(?config, "shaping a connection");
+    relay(socket, near, config);
+
+    return Ok(far);
+}
+
+/// shape_websocket, a connection that can't be shaped is let go.
+pub async fn shape_or_drop(
+    socket: WebSocketStream<TcpStream>,
+    config: ShapeConfig,
+) -> Option<WebSocketStream<TcpStream>> {
+    return match shape_websocket(socket, config).await {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            warn!(error = ?e, "couldn't shape a connection");
+            None
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use encoding::server::{self, ServerMessage};
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{shaped_pair, ShapeConfig, ShapeError};
+
+    #[test]
+    fn test_shape_parses_from_the_command_line() {
+        let config: ShapeConfig = "latency=80ms, jitter=20ms,loss=2%".parse().expect("parses");
+        assert_eq!(
+            config,
+            ShapeConfig {
+                seed: 0,
+                latency: Duration::from_millis(80),
+                jitter: Duration::from_millis(20),
+                loss: 0.02,
+            }
+        );
+        assert_eq!("latency=1s".parse::<ShapeConfig>().map(|c| c.latency), Ok(Duration::from_secs(1)));
+
+        assert_eq!("latency".parse::<ShapeConfig>(), Err(ShapeError::Malformed("latency".into())));
+        assert_eq!("lag=80ms".parse::<ShapeConfig>(), Err(ShapeError::UnknownKey("lag".into())));
+        assert_eq!("latency=80".parse::<ShapeConfig>(), Err(ShapeError::BadDuration("80".into())));
+        assert_eq!("loss=120%".parse::<ShapeConfig>(), Err(ShapeError::BadLoss("120%".into())));
+    }
+
+    #[tokio::test]
+    async fn test_ping_round_trip_takes_the_configured_latency() -> Result<()> {
+        let config = ShapeConfig {
+            latency: Duration::from_millis(80),
+            jitter: Duration::from_millis(20),
+            ..ShapeConfig::default()
+        };
+        let (mut server, mut client) = shaped_pair(8, config);
+
+        // the server end answers every ping right away
+        tokio::spawn(async move {
+            while let Some(Ok(frame)) = server.next().await {
+                if server.send(frame).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        let ping = Message::Binary(ServerMessage::new(0, server::Message::Countdown(1)).serialize()?);
+        for _ in 0..3 {
+            let start = std::time::Instant::now();
+            client.send(ping.clone()).await?;
+            assert_eq!(client.next().await.transpose()?, Some(ping.clone()));
+
+            // both ways take 80ms plus up to 20ms
+            let rtt = start.elapsed();
+            assert!(rtt >= Duration::from_millis(160), "round trip took {:?}", rtt);
+            assert!(rtt < Duration::from_millis(200 + 50), "round trip took {:?}", rtt);
+        }
+
+        return Ok(());
+    }
+}
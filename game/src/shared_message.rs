@@ -0,0 +1,120 @@
+// A broadcast is the same message to every sink, only the seq_nu in front of
+// it differs. Encoding it per sink made serialization grow with the player
+// count, a SharedMessage is encoded once per serialization type in the game
+// and every sink writes its own seq_nu into a copy of the shared bytes, see
+// PlayerSink::send_raw.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use encoding::{
+    server::{self, ServerMessage},
+    version::VERSION,
+};
+
+use crate::connection::SerializationType;
+use crate::send_stats::SendClass;
+
+// the big endian u16 every binary message starts with
+const SEQ_NU_BYTES: usize = 2;
+
+/// a message encoded once for everyone it goes to.
+#[derive(Clone, Debug)]
+pub struct SharedMessage {
+    pub tag: usize,
+    pub class: SendClass,
+    // the whole message with seq_nu 0, a sink puts its own in the first bytes
+    deku: Option<Arc<[u8]>>,
+    // just the message, what goes after "msg": in a sink's json
+    json: Option<Arc<[u8]>>,
+    /// spent encoding, the game counts it once with its sinks' time.
+    pub serialize_time: std::time::Duration,
+}
+
+impl SharedMessage {
+    /// msg encoded for every type in types, sending it to a sink of any
+    /// other type fails.
+    pub fn encode(msg: server::Message, types: &[SerializationType]) -> Result<SharedMessage> {
+        let started = std::time::Instant::now();
+        let tag = msg.tag();
+        let class = SendClass::of(&msg);
+
+        let json = if types.contains(&SerializationType::JSON) {
+            Some(serde_json::to_vec(&msg).context("error while encoding json")?.into())
+        } else {
+            None
+        };
+        let deku = if types.contains(&SerializationType::Deku) {
+            Some(ServerMessage::new(0, msg).serialize().context("error while encoding deku")?.into())
+        } else {
+            None
+        };
+
+        return Ok(SharedMessage {
+            tag,
+            class,
+            deku,
+            json,
+            serialize_time: started.elapsed(),
+        });
+    }
+
+    /// the bytes a sink sends, the same as encoding ServerMessage::new(seq_nu, msg)
+    /// for it.
+    pub fn frame(&self, ser_type: SerializationType, seq_nu: u16) -> Result<Vec<u8>> {
+        return match ser_type {
+            SerializationType::Deku => {
+                let encoded = self.deku.as_ref().ok_or_else(|| anyhow!("broadcast wasn't encoded for deku"))?;
+                let mut frame = encoded.to_vec();
+                frame[..SEQ_NU_BYTES].copy_from_slice(&seq_nu.to_be_bytes());
+                Ok(frame)
+            }
+            SerializationType::JSON => {
+                let encoded = self.json.as_ref().ok_or_else(|| anyhow!("broadcast wasn't encoded for json"))?;
+                // the fields in the order serde writes ServerMessage
+                let header = format!("{{\"seq_nu\":{},\"version\":{},\"msg\":", seq_nu, VERSION);
+                let mut frame = Vec::with_capacity(header.len() + encoded.len() + 1);
+                frame.extend_from_slice(header.as_bytes());
+                frame.extend_from_slice(encoded);
+                frame.push(b'}');
+                Ok(frame)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use encoding::server::{self, PlayerPositionUpdate, ServerMessage};
+
+    use super::SharedMessage;
+    use crate::connection::SerializationType;
+
+    fn snapshot() -> server::Message {
+        let entities = (0..5)
+            .map(|i| PlayerPositionUpdate {
+                entity_id: i * 1000,
+                position: (i as u16, 300 + i as u16),
+            })
+            .collect();
+        return server::Message::Snapshot(server::Snapshot::new(77, entities));
+    }
+
+    #[test]
+    fn test_frames_match_encoding_per_sink() {
+        let types = [SerializationType::Deku, SerializationType::JSON];
+        for msg in [snapshot(), server::Message::key_press(b'j', 3)] {
+            let shared = SharedMessage::encode(msg.clone(), &types).expect("encodes");
+            for seq_nu in [0, 1, 258, u16::MAX] {
+                let deku = ServerMessage::new(seq_nu, msg.clone()).serialize().expect("serializes");
+                assert_eq!(shared.frame(SerializationType::Deku, seq_nu).expect("deku frame"), deku);
+
+                let json = serde_json::to_vec(&ServerMessage::new(seq_nu, msg.clone())).expect("json");
+                assert_eq!(shared.frame(SerializationType::JSON, seq_nu).expect("json frame"), json);
+            }
+        }
+
+        let deku_only = SharedMessage::encode(snapshot(), &[SerializationType::Deku]).expect("encodes");
+        assert!(deku_only.frame(SerializationType::JSON, 1).is_err());
+    }
+}
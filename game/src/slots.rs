@@ -0,0 +1,376 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use crate::game_comms::PlayerToken;
+
+/// the player slots of one game as the manager sees them. taken belongs to the
+/// game (player ids come out of it), the manager reserves a slot on top of it
+/// before routing a connection so two routes can't both get the last slot.
+#[derive(Clone, Debug)]
+pub struct Slots {
+    taken: Arc<AtomicU8>,
+    reserved: Arc<AtomicU8>,
+    limit: usize,
+}
+
+/// a slot held for a connection on its way to the game. the game lets go of
+/// it once the player shows up in taken or was turned away, dropping it
+/// anywhere else releases it too.
+#[derive(Debug)]
+pub struct Reservation {
+    reserved: Arc<AtomicU8>,
+}
+
+impl Slots {
+    pub fn new(taken: Arc<AtomicU8>, limit: usize) -> Self {
+        return Slots {
+            taken,
+            reserved: Arc::new(AtomicU8::new(0)),
+            limit,
+        };
+    }
+
+    fn used(&self) -> usize {
+        return self.taken.load(Ordering::SeqCst) as usize + self.reserved.load(Ordering::SeqCst) as usize;
+    }
+
+    pub fn is_full(&self) -> bool {
+        return self.used() >= self.limit;
+    }
+
+    pub fn reserve(&self) -> Option<Reservation> {
+        loop {
+            let reserved = self.reserved.load(Ordering::SeqCst);
+            if self.taken.load(Ordering::SeqCst) as usize + reserved as usize >= self.limit {
+                return None;
+            }
+
+            // someone else reserved in between, look again
+            if self
+                .reserved
+                .compare_exchange(reserved, reserved + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Some(Reservation {
+                    reserved: self.reserved.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.reserved.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// what one of a game's player slots holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotState {
+    Free,
+    Live,
+    // kept for a player that dropped until they reconnect with the token
+    // or their grace period expires
+    Grace(PlayerToken),
+}
+
+/// the same slots as the game sees them, a slot is a player id. taken is
+/// the count Slots reads, every slot that isn't free is in it. ids used to
+/// come from counting up taken, which gave a new player the id of someone
+/// still playing once anyone before them had left.
+#[derive(Debug)]
+pub struct PlayerSlots {
+    states: Vec<SlotState>,
+    taken: Arc<AtomicU8>,
+}
+
+impl PlayerSlots {
+    pub fn new(taken: Arc<AtomicU8>, capacity: usize) -> Self {
+        return PlayerSlots {
+            states: vec![SlotState::Free; capacity.min(u8::MAX as usize + 1)],
+            taken,
+        };
+    }
+
+    pub fn state(&self, id: u8) -> SlotState {
+        return self.states.get(id as usize).copied().unwrap_or(SlotState::Free);
+    }
+
+    // every state change goes through here to keep taken in step
+    fn set(&mut self, id: u8, state: SlotState) {
+        let was_free = self.states[id as usize] == SlotState::Free;
+        let is_free = state == SlotState::Free;
+        self.states[id as usize] = state;
+
+        match (was_free, is_free) {
+            (true, false) => _ = self.taken.fetch_add(1, Ordering::SeqCst),
+            (false, true) => _ = self.taken.fetch_sub(1, Ordering::SeqCst),
+            _ => {}
+        }
+    }
+
+    /// the lowest free slot for a new player, None when every slot is in use.
+    pub fn join(&mut self) -> Option<u8> {
+        let id = self.states.iter().position(|state| *state == SlotState::Free)? as u8;
+        self.set(id, SlotState::Live);
+        return Some(id);
+    }
+
+    /// takes a given slot, for games restored from an image. false if it
+    /// doesn't exist or isn't free.
+    pub fn take(&mut self, id: u8) -> bool {
+        if (id as usize) >= self.states.len() || self.state(id) != SlotState::Free {
+            return false;
+        }
+
+        self.set(id, SlotState::Live);
+        return true;
+    }
+
+    /// frees a live slot, false if it wasn't live. a close that comes after
+    /// the player was already kicked changes nothing.
+    pub fn leave(&mut self, id: u8) -> bool {
+        if self.state(id) != SlotState::Live {
+            return false;
+        }
+
+        self.set(id, SlotState::Free);
+        return true;
+    }
+
+    /// frees a slot whether its player is live or in their grace period.
+    pub fn kick(&mut self, id: u8) -> bool {
+        if self.state(id) == SlotState::Free {
+            return false;
+        }
+
+        self.set(id, SlotState::Free);
+        return true;
+    }
+
+    /// keeps a live slot for token. false if the slot isn't live or token
+    /// already holds one.
+    pub fn disconnect(&mut self, id: u8, token: PlayerToken) -> bool {
+        if self.state(id) != SlotState::Live || self.holder(token).is_some() {
+            return false;
+        }
+
+        self.set(id, SlotState::Grace(token));
+        return true;
+    }
+
+    fn holder(&self, token: PlayerToken) -> Option<u8> {
+        return self
+            .states
+            .iter()
+            .position(|state| *state == SlotState::Grace(token))
+            .map(|id| id as u8);
+    }
+
+    /// the slot token was kept in, live again.
+    pub fn reconnect(&mut self, token: PlayerToken) -> Option<u8> {
+        let id = self.holder(token)?;
+        self.set(id, SlotState::Live);
+        return Some(id);
+    }
+
+    /// frees the slot token was kept in once its grace period is over.
+    pub fn expire(&mut self, token: PlayerToken) -> Option<u8> {
+        let id = self.holder(token)?;
+        self.set(id, SlotState::Free);
+        return Some(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    };
+
+    use proptest::prelude::*;
+
+    use super::{PlayerSlots, SlotState, Slots};
+    use crate::game_comms::PlayerToken;
+
+    #[test]
+    fn test_reservations_release_on_drop() {
+        let taken = Arc::new(AtomicU8::new(1));
+        let slots = Slots::new(taken.clone(), 2);
+
+        let reservation = slots.reserve().expect("one slot left");
+        assert!(slots.is_full());
+        assert!(slots.reserve().is_none());
+
+        // the game took the player, then let go of the reservation
+        taken.fetch_add(1, Ordering::SeqCst);
+        drop(reservation);
+        assert!(slots.is_full());
+
+        taken.fetch_sub(1, Ordering::SeqCst);
+        assert!(slots.reserve().is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_routing_never_exceeds_capacity() -> anyhow::Result<()> {
+        let taken = Arc::new(AtomicU8::new(0));
+        let slots = Slots::new(taken.clone(), 10);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+
+        // stands in for the game, takes every routed player
+        let game = tokio::spawn(async move {
+            while let Some(reservation) = rx.recv().await {
+                taken.fetch_add(1, Ordering::SeqCst);
+                drop(reservation);
+            }
+            return taken.load(Ordering::SeqCst);
+        });
+
+        let mut routers = vec![];
+        for _ in 0..8 {
+            let slots = slots.clone();
+            let tx = tx.clone();
+            routers.push(tokio::spawn(async move {
+                let mut routed = 0;
+                for _ in 0..10 {
+                    if let Some(reservation) = slots.reserve() {
+                        _ = tx.send(reservation).await;
+                        routed += 1;
+                    }
+                    tokio::task::yield_now().await;
+                }
+                return routed;
+            }));
+        }
+        drop(tx);
+
+        let mut routed = 0;
+        for router in routers {
+            routed += router.await?;
+        }
+
+        assert_eq!(routed, 10);
+        assert_eq!(game.await?, 10);
+
+        return Ok(());
+    }
+
+    const CAPACITY: usize = 4;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Join,
+        Leave(u8),
+        Kick(u8),
+        Disconnect(u8, PlayerToken),
+        Reconnect(PlayerToken),
+        Expire(PlayerToken),
+    }
+
+    // ids one past capacity and a handful of tokens, so ops collide often
+    fn op() -> impl Strategy<Value = Op> {
+        let id = 0..=CAPACITY as u8;
+        let token = 0..4u64;
+        return prop_oneof![
+            3 => Just(Op::Join),
+            1 => id.clone().prop_map(Op::Leave),
+            1 => id.clone().prop_map(Op::Kick),
+            1 => (id, token.clone()).prop_map(|(id, token)| Op::Disconnect(id, token)),
+            1 => token.clone().prop_map(Op::Reconnect),
+            1 => token.prop_map(Op::Expire),
+        ];
+    }
+
+    // what the slots should hold, by id, absent is free
+    #[derive(Default)]
+    struct Model(HashMap<u8, SlotState>);
+
+    impl Model {
+        fn holder(&self, token: PlayerToken) -> Option<u8> {
+            return self.0.iter().find(|(_, state)| **state == SlotState::Grace(token)).map(|(id, _)| *id);
+        }
+
+        // what the op returns, as an Option<u8> of the slot it touched
+        fn apply(&mut self, op: &Op) -> Option<u8> {
+            match *op {
+                Op::Join => {
+                    let id = (0..CAPACITY as u8).find(|id| !self.0.contains_key(id))?;
+                    self.0.insert(id, SlotState::Live);
+                    return Some(id);
+                }
+                Op::Leave(id) => {
+                    if self.0.get(&id) != Some(&SlotState::Live) {
+                        return None;
+                    }
+                    self.0.remove(&id);
+                    return Some(id);
+                }
+                Op::Kick(id) => return self.0.remove(&id).map(|_| id),
+                Op::Disconnect(id, token) => {
+                    if self.0.get(&id) != Some(&SlotState::Live) || self.holder(token).is_some() {
+                        return None;
+                    }
+                    self.0.insert(id, SlotState::Grace(token));
+                    return Some(id);
+                }
+                Op::Reconnect(token) => {
+                    let id = self.holder(token)?;
+                    self.0.insert(id, SlotState::Live);
+                    return Some(id);
+                }
+                Op::Expire(token) => {
+                    let id = self.holder(token)?;
+                    self.0.remove(&id);
+                    return Some(id);
+                }
+            }
+        }
+    }
+
+    fn apply(slots: &mut PlayerSlots, op: &Op) -> Option<u8> {
+        return match *op {
+            Op::Join => slots.join(),
+            Op::Leave(id) => slots.leave(id).then_some(id),
+            Op::Kick(id) => slots.kick(id).then_some(id),
+            Op::Disconnect(id, token) => slots.disconnect(id, token).then_some(id),
+            Op::Reconnect(token) => slots.reconnect(token),
+            Op::Expire(token) => slots.expire(token),
+        };
+    }
+
+    proptest! {
+        #[test]
+        fn test_player_slots_match_the_model(ops in prop::collection::vec(op(), 0..200)) {
+            let taken = Arc::new(AtomicU8::new(0));
+            let mut slots = PlayerSlots::new(taken.clone(), CAPACITY);
+            let mut model = Model::default();
+
+            for op in ops.iter() {
+                let live: Vec<u8> = (0..CAPACITY as u8).filter(|&id| slots.state(id) == SlotState::Live).collect();
+                // joins get the model's free slot, so a freed slot is joinable again
+                let got = apply(&mut slots, op);
+                prop_assert_eq!(got, model.apply(op), "{:?}", op);
+
+                // a new player never lands on someone still playing
+                if let (Op::Join, Some(id)) = (op, got) {
+                    prop_assert!(!live.contains(&id));
+                }
+
+                for id in 0..=CAPACITY as u8 {
+                    prop_assert_eq!(slots.state(id), model.0.get(&id).copied().unwrap_or(SlotState::Free));
+                }
+                prop_assert_eq!(taken.load(Ordering::SeqCst) as usize, model.0.len());
+
+                for token in 0..4 {
+                    let holders = (0..CAPACITY as u8).filter(|&id| slots.state(id) == SlotState::Grace(token)).count();
+                    prop_assert!(holders <= 1);
+                }
+            }
+        }
+    }
+}
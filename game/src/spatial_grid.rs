@@ -0,0 +1,222 @@
+// Where the players are, bucketed into square cells one view distance wide.
+// Who is in range of a point is then the 3x3 cells around it instead of
+// every player in the game: snapshots, emotes and bots ask here. The game
+// keeps it in step with every position it changes, a move is O(1): the cell
+// lists are unordered and every player knows its index in its cell.
+
+use map::map::MAP_SIZE_SIDE;
+
+use crate::interest::{in_range, VIEW_DISTANCE};
+
+/// a range query of VIEW_DISTANCE touches at most 3x3 cells.
+pub const CELL_SIZE: u16 = VIEW_DISTANCE;
+const CELLS_SIDE: usize = MAP_SIZE_SIDE.div_ceil(CELL_SIZE as usize);
+const PLAYER_IDS: usize = u8::MAX as usize + 1;
+
+#[derive(Clone, Copy, Debug)]
+struct Placed {
+    position: (u16, u16),
+    cell: usize,
+    // where in cells[cell] the id is
+    index: usize,
+}
+
+#[derive(Debug)]
+pub struct SpatialGrid {
+    // (id, position) per cell, row major. positions are kept here too so a
+    // query doesn't look every id up
+    cells: Vec<Vec<(u8, (u16, u16))>>,
+    // per player id
+    placed: Vec<Option<Placed>>,
+}
+
+// anything off the map lands in the edge cells, queries clamp the same way
+fn cell_of(position: (u16, u16)) -> (usize, usize) {
+    let x = (position.0 / CELL_SIZE) as usize;
+    let y = (position.1 / CELL_SIZE) as usize;
+    return (x.min(CELLS_SIDE - 1), y.min(CELLS_SIDE - 1));
+}
+
+fn cell_index((x, y): (usize, usize)) -> usize {
+    return y * CELLS_SIDE + x;
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl SpatialGrid {
+    pub fn new() -> Self {
+        return SpatialGrid {
+            cells: vec![vec![]; CELLS_SIDE * CELLS_SIDE],
+            placed: vec![None; PLAYER_IDS],
+        };
+    }
+
+    /// id is at position now, whether it was on the grid before or not.
+    pub fn place(&mut self, id: u8, position: (u16, u16)) {
+        let cell = cell_index(cell_of(position));
+        if let Some(placed) = self.placed[id as usize].as_mut() {
+            if placed.cell == cell {
+                placed.position = position;
+                self.cells[cell][placed.index].1 = position;
+                return;
+            }
+            self.remove(id);
+        }
+
+        self.placed[id as usize] = Some(Placed {
+            position,
+            cell,
+            index: self.cells[cell].len(),
+        });
+        self.cells[cell].push((id, position));
+    }
+
+    pub fn remove(&mut self, id: u8) {
+        let Some(placed) = self.placed[id as usize].take() else {
+            return;
+        };
+
+        let cell = &mut self.cells[placed.cell];
+        cell.swap_remove(placed.index);
+        // whoever was last took its index
+        if let Some(&(moved, _)) = cell.get(placed.index) {
+            if let Some(moved) = self.placed[moved as usize].as_mut() {
+                moved.index = placed.index;
+            }
+        }
+    }
+
+    pub fn position(&self, id: u8) -> Option<(u16, u16)> {
+        return self.placed[id as usize].map(|placed| placed.position);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.cells.iter().map(|cell| cell.len()).sum();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.cells.iter().all(|cell| cell.is_empty());
+    }
+
+    /// (id, position) of everyone within radius of center, the square
+    /// in_range measures, the edge included. in no particular order.
+    pub fn players_within(&self, center: (u16, u16), radius: u16) -> impl Iterator<Item = (u8, (u16, u16))> + '_ {
+        let (min_x, min_y) = cell_of((center.0.saturating_sub(radius), center.1.saturating_sub(radius)));
+        let (max_x, max_y) = cell_of((center.0.saturating_add(radius), center.1.saturating_add(radius)));
+
+        return (min_y..=max_y)
+            .flat_map(move |y| (min_x..=max_x).map(move |x| cell_index((x, y))))
+            .flat_map(move |cell| self.cells[cell].iter().copied())
+            .filter(move |&(_, position)| in_range(center, position, radius));
+    }
+
+    /// players_within in id order, what snapshots list entities in.
+    pub fn players_within_sorted(&self, center: (u16, u16), radius: u16) -> Vec<(u8, (u16, u16))> {
+        // a bit per id, reading them out in order is the sort
+        let mut seen = [0u64; PLAYER_IDS / 64];
+        let mut count = 0;
+        for (id, _) in self.players_within(center, radius) {
+            seen[id as usize / 64] |= 1 << (id % 64);
+            count += 1;
+        }
+
+        let mut players = Vec::with_capacity(count);
+        for (at, mut bits) in seen.into_iter().enumerate() {
+            while bits != 0 {
+                let id = at * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                if let Some(placed) = self.placed[id] {
+                    players.push((id as u8, placed.position));
+                }
+            }
+        }
+        return players;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SpatialGrid, CELL_SIZE};
+    use crate::interest::in_range;
+
+    fn within(grid: &SpatialGrid, center: (u16, u16), radius: u16) -> Vec<u8> {
+        return grid.players_within_sorted(center, radius).into_iter().map(|(id, _)| id).collect();
+    }
+
+    #[test]
+    fn test_radius_edge_and_cell_borders() {
+        let mut grid = SpatialGrid::new();
+        let c = CELL_SIZE;
+        // on either side of a cell border, and exactly radius away across it
+        grid.place(0, (c - 1, 10));
+        grid.place(1, (c, 10));
+        grid.place(2, (c - 1 + 5, 10));
+        grid.place(3, (c - 1 + 6, 10));
+        // a corner, radius away on both axes
+        grid.place(4, (c - 1 + 5, 10 + 5));
+
+        assert_eq!(within(&grid, (c - 1, 10), 5), vec![0, 1, 2, 4]);
+        assert_eq!(within(&grid, (c, 10), 0), vec![1]);
+        assert_eq!(within(&grid, (c - 1, 10), 0), vec![0]);
+
+        // the map's corners, nothing wraps or underflows
+        grid.place(5, (0, 0));
+        grid.place(6, (u16::MAX, u16::MAX));
+        assert_eq!(within(&grid, (0, 0), 3), vec![5]);
+        assert_eq!(within(&grid, (u16::MAX, u16::MAX), 1), vec![6]);
+        assert_eq!(within(&grid, (u16::MAX - 1, u16::MAX), 0), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_moves_and_removes_keep_cells_consistent() {
+        let mut grid = SpatialGrid::new();
+        for id in 0..6 {
+            grid.place(id, (id as u16, 0));
+        }
+
+        // out of the crowded cell and back, a teleport across the map
+        grid.place(1, (200, 200));
+        grid.place(4, (201, 200));
+        grid.remove(0);
+        grid.remove(0);
+        grid.place(4, (4, 0));
+
+        assert_eq!(within(&grid, (0, 0), 10), vec![2, 3, 4, 5]);
+        assert_eq!(within(&grid, (200, 200), 1), vec![1]);
+        assert_eq!(grid.position(0), None);
+        assert_eq!(grid.len(), 5);
+
+        grid.remove(1);
+        grid.remove(5);
+        assert_eq!(within(&grid, (100, 100), u16::MAX), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_matches_scanning_everyone() {
+        let mut rand = map::rand::mulberry32(7);
+        let mut grid = SpatialGrid::new();
+        let mut positions = vec![None; 100];
+        for _ in 0..2000 {
+            let id = (rand() % 100) as u8;
+            if rand().is_multiple_of(5) {
+                grid.remove(id);
+                positions[id as usize] = None;
+            } else {
+                let position = ((rand() % 300) as u16, (rand() % 300) as u16);
+                grid.place(id, position);
+                positions[id as usize] = Some(position);
+            }
+
+            let center = ((rand() % 300) as u16, (rand() % 300) as u16);
+            let radius = (rand() % 90) as u16;
+            let scanned: Vec<u8> = (0..100u8)
+                .filter(|&id| positions[id as usize].is_some_and(|p| in_range(center, p, radius)))
+                .collect();
+            assert_eq!(within(&grid, center, radius), scanned);
+        }
+    }
+}
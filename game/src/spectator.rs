@@ -0,0 +1,35 @@
+use futures::StreamExt;
+use tokio::sync::mpsc::Sender;
+use tracing::{info, info_span, Instrument};
+
+use crate::connection::ConnectionMessage;
+use crate::executor::Executor;
+use crate::player::{PlayerSink, PlayerWebSink};
+use crate::transport::{FrameSink, FrameStream};
+
+pub struct Spectator<S: FrameSink = PlayerWebSink> {
+    pub id: u8,
+    pub sink: PlayerSink<S>,
+    // player id whose view this spectator gets, None sees the whole game
+    pub following: Option<u8>,
+    // where their view was centered last, a spectator whose target leaves
+    // moves on to the player closest to it
+    pub center: (u16, u16),
+}
+
+/// reads a spectator's stream until it closes, then tells the game to drop
+/// them. they have nothing to say, reading is how the close shows up.
+pub fn spawn_spectator_stream<S: FrameStream>(
+    executor: &dyn Executor,
+    id: u8,
+    mut stream: S,
+    tx: Sender<ConnectionMessage>,
+) {
+    executor.spawn(Box::pin(async move {
+        // an error is as good as a close, the sink can't be trusted either
+        while let Some(Ok(_)) = stream.next().await {}
+
+        info!("spectator connection closed");
+        _ = tx.send(ConnectionMessage::SpectatorClose(id)).await;
+    }.instrument(info_span!("spectator", spectator_id = id))));
+}
@@ -0,0 +1,95 @@
+/// why a player stopped being in the running. both place the same way, the
+/// reason only tells them apart on the leaderboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum OutReason {
+    Eliminated,
+    // left mid-game, closed the connection or got dropped by the server
+    Disconnected,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Placement {
+    pub player_id: u8,
+    pub name: String,
+    // 1 is the winner, everyone still in the game at the end shares it
+    pub place: usize,
+    pub tick: u128,
+    // None for the players that made it to the end
+    pub reason: Option<OutReason>,
+}
+
+/// who went out of a game, in the order they went.
+#[derive(Clone, Debug, Default)]
+pub struct Standings {
+    out: Vec<Placement>,
+}
+
+impl Standings {
+    /// alive counts the player going out too: the first of 10 to go places
+    /// 10th. returns the place.
+    pub fn out(&mut self, player_id: u8, name: &str, alive: usize, tick: u128, reason: OutReason) -> usize {
+        let place = alive.max(1);
+        self.out.push(Placement {
+            player_id,
+            name: name.to_string(),
+            place,
+            tick,
+            reason: Some(reason),
+        });
+
+        return place;
+    }
+
+    /// the full leaderboard best first, survivors are (player id, name) of
+    /// everyone still in the game.
+    pub fn leaderboard(&self, survivors: &[(u8, String)], tick: u128) -> Vec<Placement> {
+        let mut leaderboard: Vec<Placement> = survivors
+            .iter()
+            .map(|(player_id, name)| Placement {
+                player_id: *player_id,
+                name: name.clone(),
+                place: 1,
+                tick,
+                reason: None,
+            })
+            .collect();
+        leaderboard.extend(self.out.iter().rev().cloned());
+
+        return leaderboard;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.out.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.out.is_empty();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OutReason, Standings};
+
+    #[test]
+    fn test_disconnects_and_eliminations_place_the_same_way() {
+        let mut standings = Standings::default();
+        assert_eq!(standings.out(4, "quitter", 4, 10, OutReason::Disconnected), 4);
+        assert_eq!(standings.out(2, "shot", 3, 12, OutReason::Eliminated), 3);
+
+        let leaderboard = standings.leaderboard(&[(0, "winner".to_string()), (1, "also".to_string())], 20);
+        let places: Vec<(u8, usize, Option<OutReason>)> = leaderboard
+            .iter()
+            .map(|placement| (placement.player_id, placement.place, placement.reason))
+            .collect();
+        assert_eq!(
+            places,
+            vec![
+                (0, 1, None),
+                (1, 1, None),
+                (2, 3, Some(OutReason::Eliminated)),
+                (4, 4, Some(OutReason::Disconnected)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use encoding::server::region_label;
+use tracing::{info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{game_comms::GameStatus, game_state::GameState};
+
+/// one game in the /status document, GameStatus flattened into plain numbers.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct StatusEntry {
+    pub game_id: u32,
+    pub state: GameState,
+    pub tick: u64,
+    pub players: usize,
+    pub bots: usize,
+    pub spectators: usize,
+    pub required_players: usize,
+    pub short_handed: bool,
+    pub seed: u32,
+    pub region: String,
+    pub uptime_ms: u64,
+    pub tick_last_us: u64,
+    pub tick_max_us: u64,
+    pub tick_average_us: u64,
+}
+
+impl From<&GameStatus> for StatusEntry {
+    fn from(status: &GameStatus) -> Self {
+        return StatusEntry {
+            game_id: status.game_id,
+            state: status.state,
+            tick: u64::try_from(status.tick).unwrap_or(u64::MAX),
+            players: status.player_count,
+            bots: status.bot_count,
+            spectators: status.spectator_count,
+            required_players: status.required_players,
+            short_handed: status.short_handed,
+            seed: status.seed,
+            region: region_label(&status.region).to_string(),
+            uptime_ms: status.uptime.as_millis() as u64,
+            tick_last_us: status.timing.last_us as u64,
+            tick_max_us: status.timing.max_us as u64,
+            tick_average_us: status.timing.average_us as u64,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct StatusDocument {
+    pub games: Vec<StatusEntry>,
+}
+
+/// answers GET /status with every game that answered GameManager::query_all_status.
+pub async fn respond(mut stream: TcpStream, statuses: Vec<GameStatus>) {
+    let mut request = [0; 1024];
+    let Ok(read) = stream.read(&mut request).await else {
+        return;
+    };
+
+    let response = if request[..read].starts_with(b"GET /status ") {
+        let doc = StatusDocument {
+            games: statuses.iter().map(StatusEntry::from).collect(),
+        };
+        let body = serde_json::to_string(&doc).unwrap_or_else(|_| "{}".to_string());
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        warn!(error = ?e, "status response failed");
+    }
+    _ = stream.shutdown().await;
+}
+
+/// the next status connection, never resolves without a listener. answered
+/// where the GameManager lives, like health checks.
+pub async fn accept(listener: Option<&TcpListener>) -> TcpStream {
+    let Some(listener) = listener else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => return stream,
+            Err(e) => info!(error = ?e, "status accept failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use encoding::server::{self, ServerMessage};
+    use futures::{SinkExt, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_tungstenite::tungstenite;
+
+    use crate::{
+        game_config::ManagerConfig,
+        game_manager::GameManager,
+        test_utils::{complete_handshake, ws_pair},
+    };
+
+    use super::{accept, respond};
+
+    async fn get(listener: &TcpListener, manager: &GameManager, path: &str) -> Result<String> {
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        client.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await?;
+
+        let stream = accept(Some(listener)).await;
+        respond(stream, manager.query_all_status().await).await;
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await?;
+        return Ok(response);
+    }
+
+    #[tokio::test]
+    async fn test_status_lists_running_games() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut manager = GameManager::new(ManagerConfig::default()).expect("manager starts");
+
+        let response = get(&listener, &manager, "/status").await?;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("{\"games\":[]}"));
+        assert!(get(&listener, &manager, "/").await?.starts_with("HTTP/1.1 404"));
+
+        let (server_socket, mut client) = ws_pair().await?;
+        client.send(tungstenite::Message::Binary(ServerMessage::CLIENT_WHO_AM_I.serialize()?)).await?;
+        let (sink, stream) = server_socket.split();
+        manager.add_connection(stream, sink).await;
+        assert!(matches!(complete_handshake(&mut client).await?.msg, server::Message::PlayerStart(_)));
+
+        let response = get(&listener, &manager, "/status").await?;
+        let (_, body) = response.split_once("\r\n\r\n").expect("response has a body");
+        let doc: serde_json::Value = serde_json::from_str(body)?;
+        let games = doc["games"].as_array().expect("games is a list");
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0]["players"], 1);
+        assert_eq!(games[0]["bots"], 0);
+        assert!(games[0]["tick"].as_u64().is_some());
+
+        return Ok(());
+    }
+}
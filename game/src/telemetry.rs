@@ -0,0 +1,27 @@
+// the most DebugTelemetry a player gets a second, whatever they asked for
+pub const MAX_TELEMETRY_HZ: u8 = 2;
+
+/// ticks between two DebugTelemetry for a player that asked for hz of them
+/// a second, None when they asked for none.
+pub fn telemetry_interval(hz: u8, tick_rate: u128) -> Option<u128> {
+    if hz == 0 {
+        return None;
+    }
+
+    let hz = hz.min(MAX_TELEMETRY_HZ) as u128;
+    return Some((tick_rate / hz).max(1));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{telemetry_interval, MAX_TELEMETRY_HZ};
+
+    #[test]
+    fn test_rate_is_capped() {
+        assert_eq!(telemetry_interval(0, 60), None);
+        assert_eq!(telemetry_interval(1, 60), Some(60));
+        assert_eq!(telemetry_interval(MAX_TELEMETRY_HZ, 60), Some(30));
+        assert_eq!(telemetry_interval(u8::MAX, 60), Some(30));
+        assert_eq!(telemetry_interval(u8::MAX, 1), Some(1));
+    }
+}
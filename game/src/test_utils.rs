@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Result};
+use encoding::server::{self, ServerMessage};
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio_tungstenite::{tungstenite, WebSocketStream};
+
+use crate::{
+    names::default_name,
+    player::{Player, PlayerSink},
+    transport::{FrameSink, FrameStream},
+};
+
+pub type TestSocket = WebSocketStream<TcpStream>;
+
+// returns (server side, client side) of a websocket over localhost
+pub async fn ws_pair() -> Result<(TestSocket, TestSocket)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let client = TcpSocket::new_v4()?;
+    return connect_pair(listener, client).await;
+}
+
+// same as ws_pair with the server's send and the client's receive buffer
+// fixed at about size bytes, so a client that doesn't read backs up quickly
+pub async fn small_buffer_ws_pair(size: u32) -> Result<(TestSocket, TestSocket)> {
+    let server = TcpSocket::new_v4()?;
+    server.set_send_buffer_size(size)?;
+    server.bind("127.0.0.1:0".parse()?)?;
+    let listener = server.listen(1)?;
+
+    let client = TcpSocket::new_v4()?;
+    client.set_recv_buffer_size(size)?;
+    return connect_pair(listener, client).await;
+}
+
+async fn connect_pair(listener: TcpListener, client: TcpSocket) -> Result<(TestSocket, TestSocket)> {
+    let addr = listener.local_addr()?;
+
+    let client = tokio::spawn(async move {
+        let stream = client.connect(addr).await?;
+        let (client, _) = tokio_tungstenite::client_async(format!("ws://{}", addr), stream).await?;
+        return Ok::<TestSocket, anyhow::Error>(client);
+    });
+
+    let (stream, _) = listener.accept().await?;
+    let server = tokio_tungstenite::accept_async(stream).await?;
+    let client = client.await??;
+
+    return Ok((server, client));
+}
+
+pub async fn next_message<C: FrameStream>(client: &mut C) -> Result<ServerMessage> {
+    loop {
+        match client.next().await {
+            Some(Ok(tungstenite::Message::Binary(msg))) => {
+                return ServerMessage::deserialize(&msg);
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(anyhow!("socket error {:?}", e)),
+            None => return Err(anyhow!("socket closed")),
+        }
+    }
+}
+
+// answers clock sync requests like the real client does and skips the lobby
+// rosters, returns the first other message
+pub async fn complete_handshake<C: FrameStream + FrameSink>(client: &mut C) -> Result<ServerMessage> {
+    return complete_slow_handshake(client, std::time::Duration::ZERO).await;
+}
+
+// same as complete_handshake but every clock sync answer takes delay
+pub async fn complete_slow_handshake<C: FrameStream + FrameSink>(
+    client: &mut C,
+    delay: std::time::Duration,
+) -> Result<ServerMessage> {
+    loop {
+        let msg = next_message(client).await?;
+        match msg.msg {
+            server::Message::ClockSyncRequest(_) => {
+                tokio::time::sleep(delay).await;
+                let resp = ServerMessage::new(0, server::Message::clock_response(0)).serialize()?;
+                client.send(tungstenite::Message::Binary(resp)).await?;
+            }
+            server::Message::LobbyState(_) => {}
+            _ => return Ok(msg),
+        }
+    }
+}
+
+// a player whose sink writes to the returned client socket
+pub async fn test_player(id: u8, position: (u16, u16)) -> Result<(Player, TestSocket)> {
+    let (server, client) = ws_pair().await?;
+    let (sink, _stream) = server.split();
+    let player = Player {
+        id,
+        name: default_name(id),
+        position,
+        sink: PlayerSink::new(id, sink),
+        clock_diff: 0,
+        pending_clock_sync: None,
+        move_budget: 0,
+        last_emote: None,
+        last_input_seq: 0,
+        rtt: 0,
+        telemetry: None,
+    };
+
+    return Ok((player, client));
+}
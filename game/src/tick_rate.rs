@@ -0,0 +1,79 @@
+// past this a tick is shorter than tokio's timer can sleep reliably
+pub const MAX_TICK_RATE_HZ: u32 = 1_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TickRateError {
+    Zero,
+    TooHigh(u32),
+}
+
+impl std::fmt::Display for TickRateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            TickRateError::Zero => write!(f, "tick rate must be at least 1 tick a second"),
+            TickRateError::TooHigh(hz) => write!(
+                f,
+                "tick rate of {} ticks a second is more than {}",
+                hz, MAX_TICK_RATE_HZ
+            ),
+        };
+    }
+}
+
+impl std::error::Error for TickRateError {}
+
+/// game loop ticks per second, only ever between 1 and MAX_TICK_RATE_HZ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickRate {
+    hz: u32,
+}
+
+impl TickRate {
+    pub fn from_hz(hz: u32) -> Result<Self, TickRateError> {
+        if hz == 0 {
+            return Err(TickRateError::Zero);
+        }
+
+        if hz > MAX_TICK_RATE_HZ {
+            return Err(TickRateError::TooHigh(hz));
+        }
+
+        return Ok(Self { hz });
+    }
+
+    pub fn hz(&self) -> u128 {
+        return self.hz as u128;
+    }
+
+    /// length of one tick, rounded down
+    pub fn micros(&self) -> u128 {
+        return 1_000_000 / self.hz();
+    }
+
+    pub fn ticks(&self, seconds: u128) -> u128 {
+        return seconds * self.hz();
+    }
+}
+
+impl Default for TickRate {
+    fn default() -> Self {
+        return Self { hz: 60 };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TickRate, TickRateError, MAX_TICK_RATE_HZ};
+
+    #[test]
+    fn test_from_hz() {
+        let rate = TickRate::from_hz(60).expect("60 is fine");
+        assert_eq!(rate.micros(), 16_666);
+        assert_eq!(rate.ticks(2), 120);
+        assert_eq!(rate, TickRate::default());
+        assert_eq!(TickRate::from_hz(MAX_TICK_RATE_HZ).map(|rate| rate.micros()), Ok(1_000));
+
+        assert_eq!(TickRate::from_hz(0), Err(TickRateError::Zero));
+        assert_eq!(TickRate::from_hz(MAX_TICK_RATE_HZ + 1), Err(TickRateError::TooHigh(1_001)));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::game_comms::{GameKey, GameResult, PlayerToken};
+
+#[derive(Clone, Debug)]
+pub struct TournamentConfig {
+    pub players: Vec<PlayerToken>,
+    // games in the first round, every round after has half as many
+    pub games_per_round: usize,
+    // the top N of every game move on
+    pub advance_per_game: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TournamentError {
+    // every first round game needs more players than advance
+    NotEnoughPlayers,
+    UnknownGame,
+    Finished,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BracketGame {
+    pub participants: Vec<PlayerToken>,
+    // None until the manager created a game for it, and again after an abort
+    pub key: Option<GameKey>,
+    pub result: Option<GameResult>,
+    pub reruns: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Advance {
+    // other games of the round are still running
+    Waiting,
+    // round number that now needs its games started
+    NextRound(usize),
+    // final ranking, best first
+    Finished(Vec<PlayerToken>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standings {
+    pub round: usize,
+    pub games: Vec<BracketGame>,
+    // (player, round they went out in)
+    pub eliminated: Vec<(PlayerToken, usize)>,
+    pub ranking: Option<Vec<PlayerToken>>,
+}
+
+/// the bracket above individual games. the GameManager creates a game for
+/// every slot that needs one, routes participants by token and reports back
+/// results and aborts.
+pub struct Tournament {
+    config: TournamentConfig,
+    round: usize,
+    games: Vec<BracketGame>,
+    eliminated: Vec<(PlayerToken, usize)>,
+    ranking: Option<Vec<PlayerToken>>,
+}
+
+// deals players out round robin so seeds spread across games
+fn split(players: &[PlayerToken], count: usize) -> Vec<BracketGame> {
+    let mut games: Vec<BracketGame> = (0..count)
+        .map(|_| BracketGame {
+            participants: vec![],
+            key: None,
+            result: None,
+            reruns: 0,
+        })
+        .collect();
+
+    for (idx, player) in players.iter().enumerate() {
+        games[idx % count].participants.push(*player);
+    }
+
+    return games;
+}
+
+// participants that didn't get placed (left early) rank behind everyone else
+fn ranking(game: &BracketGame, result: &GameResult) -> Vec<PlayerToken> {
+    let mut ranking: Vec<PlayerToken> = result
+        .placements
+        .iter()
+        .filter(|player| game.participants.contains(player))
+        .cloned()
+        .collect();
+
+    for player in game.participants.iter() {
+        if !ranking.contains(player) {
+            ranking.push(*player);
+        }
+    }
+
+    return ranking;
+}
+
+impl Tournament {
+    pub fn new(config: TournamentConfig) -> Result<Self, TournamentError> {
+        let games = config.games_per_round.max(1);
+        if config.advance_per_game == 0 || config.players.len() < games * (config.advance_per_game + 1) {
+            return Err(TournamentError::NotEnoughPlayers);
+        }
+
+        return Ok(Tournament {
+            games: split(&config.players, games),
+            config,
+            round: 0,
+            eliminated: vec![],
+            ranking: None,
+        });
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.ranking.is_some();
+    }
+
+    /// slots of the current round without a running game.
+    pub fn games_to_start(&self) -> Vec<usize> {
+        if self.is_finished() {
+            return vec![];
+        }
+
+        return self
+            .games
+            .iter()
+            .enumerate()
+            .filter(|(_, game)| game.key.is_none() && game.result.is_none())
+            .map(|(slot, _)| slot)
+            .collect();
+    }
+
+    pub fn participants(&self, slot: usize) -> &[PlayerToken] {
+        return &self.games[slot].participants;
+    }
+
+    pub fn assign(&mut self, slot: usize, key: GameKey) {
+        self.games[slot].key = Some(key);
+    }
+
+    pub fn is_tournament_game(&self, key: GameKey) -> bool {
+        return self.games.iter().any(|game| game.key == Some(key));
+    }
+
+    /// the game a registered player belongs in right now.
+    pub fn route(&self, player: PlayerToken) -> Option<GameKey> {
+        return self
+            .games
+            .iter()
+            .find(|game| game.result.is_none() && game.participants.contains(&player))?
+            .key;
+    }
+
+    /// the game went away without a result, it has to be played again with
+    /// the same participants. returns the slot that needs a new game.
+    pub fn abort(&mut self, key: GameKey) -> Result<usize, TournamentError> {
+        let slot = self.slot(key)?;
+        let game = &mut self.games[slot];
+        game.key = None;
+        game.reruns += 1;
+
+        return Ok(slot);
+    }
+
+    pub fn record_result(&mut self, key: GameKey, result: GameResult) -> Result<Advance, TournamentError> {
+        let slot = self.slot(key)?;
+        self.games[slot].result = Some(result);
+
+        if self.games.iter().any(|game| game.result.is_none()) {
+            return Ok(Advance::Waiting);
+        }
+
+        let rankings: Vec<Vec<PlayerToken>> = self
+            .games
+            .iter()
+            .map(|game| ranking(game, game.result.as_ref().expect("checked above")))
+            .collect();
+
+        if self.games.len() == 1 {
+            let ranking = rankings.into_iter().next().unwrap_or_default();
+            self.ranking = Some(ranking.clone());
+            return Ok(Advance::Finished(ranking));
+        }
+
+        let advance = self.config.advance_per_game;
+        let mut advancing = vec![];
+        for ranking in rankings {
+            advancing.extend(ranking.iter().take(advance));
+            for player in ranking.iter().skip(advance) {
+                self.eliminated.push((*player, self.round));
+            }
+        }
+
+        self.round += 1;
+        self.games = split(&advancing, self.games.len().div_ceil(2));
+
+        return Ok(Advance::NextRound(self.round));
+    }
+
+    pub fn standings(&self) -> Standings {
+        return Standings {
+            round: self.round,
+            games: self.games.clone(),
+            eliminated: self.eliminated.clone(),
+            ranking: self.ranking.clone(),
+        };
+    }
+
+    fn slot(&self, key: GameKey) -> Result<usize, TournamentError> {
+        if self.is_finished() {
+            return Err(TournamentError::Finished);
+        }
+
+        return self
+            .games
+            .iter()
+            .position(|game| game.key == Some(key) && game.result.is_none())
+            .ok_or(TournamentError::UnknownGame);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game_comms::{GameKey, GameResult};
+
+    use super::{Advance, Tournament, TournamentConfig, TournamentError};
+
+    fn key(id: u32) -> GameKey {
+        return GameKey { id, epoch: 0 };
+    }
+
+    fn result(placements: &[u64]) -> GameResult {
+        return GameResult {
+            placements: placements.to_vec(),
+            leaderboard: vec![],
+            region: [0; encoding::server::REGION_LENGTH],
+            events: vec![],
+        };
+    }
+
+    // starts every open slot with game ids counting up from next_id
+    fn start_all(tournament: &mut Tournament, next_id: &mut u32) {
+        for slot in tournament.games_to_start() {
+            tournament.assign(slot, key(*next_id));
+            *next_id += 1;
+        }
+    }
+
+    fn eight_players() -> Tournament {
+        return Tournament::new(TournamentConfig {
+            players: (1..=8).collect(),
+            games_per_round: 2,
+            advance_per_game: 2,
+        })
+        .expect("valid tournament");
+    }
+
+    #[test]
+    fn test_rejects_too_few_players() {
+        let config = TournamentConfig {
+            players: vec![1, 2, 3, 4],
+            games_per_round: 2,
+            advance_per_game: 2,
+        };
+
+        assert_eq!(Tournament::new(config).err(), Some(TournamentError::NotEnoughPlayers));
+    }
+
+    #[test]
+    fn test_bracket_to_final() {
+        let mut tournament = eight_players();
+        let mut next_id = 0;
+        start_all(&mut tournament, &mut next_id);
+
+        assert_eq!(tournament.participants(0), &[1, 3, 5, 7]);
+        assert_eq!(tournament.participants(1), &[2, 4, 6, 8]);
+        assert_eq!(tournament.route(5), Some(key(0)));
+        assert_eq!(tournament.route(6), Some(key(1)));
+        assert_eq!(tournament.route(42), None);
+
+        assert_eq!(tournament.record_result(key(0), result(&[7, 1, 3, 5])), Ok(Advance::Waiting));
+        // finished players wait for the next round instead of rejoining
+        assert_eq!(tournament.route(7), None);
+
+        // 6 left early and wasn't placed
+        assert_eq!(tournament.record_result(key(1), result(&[8, 2, 4])), Ok(Advance::NextRound(1)));
+        assert_eq!(tournament.games_to_start(), vec![0]);
+        assert_eq!(tournament.participants(0), &[7, 1, 8, 2]);
+
+        let standings = tournament.standings();
+        assert_eq!(standings.eliminated, vec![(3, 0), (5, 0), (4, 0), (6, 0)]);
+
+        start_all(&mut tournament, &mut next_id);
+        assert_eq!(tournament.route(8), Some(key(2)));
+        assert_eq!(tournament.route(3), None);
+
+        assert_eq!(
+            tournament.record_result(key(2), result(&[2, 7, 8, 1])),
+            Ok(Advance::Finished(vec![2, 7, 8, 1]))
+        );
+        assert!(tournament.is_finished());
+        assert_eq!(tournament.standings().ranking, Some(vec![2, 7, 8, 1]));
+        assert_eq!(tournament.games_to_start(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_aborted_game_reruns_with_same_players() {
+        let mut tournament = eight_players();
+        let mut next_id = 0;
+        start_all(&mut tournament, &mut next_id);
+        tournament.record_result(key(0), result(&[1, 3, 5, 7])).expect("known game");
+
+        assert_eq!(tournament.abort(key(1)), Ok(1));
+        assert_eq!(tournament.games_to_start(), vec![1]);
+        assert_eq!(tournament.route(2), None);
+
+        start_all(&mut tournament, &mut next_id);
+        assert_eq!(tournament.participants(1), &[2, 4, 6, 8]);
+        assert_eq!(tournament.route(2), Some(key(2)));
+        assert_eq!(tournament.standings().games[1].reruns, 1);
+
+        // the old game is gone for good
+        assert_eq!(tournament.record_result(key(1), result(&[2])), Err(TournamentError::UnknownGame));
+        assert_eq!(tournament.record_result(key(2), result(&[2, 4, 6, 8])), Ok(Advance::NextRound(1)));
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use encoding::server::{MESSAGE_TAGS, MESSAGE_TAG_NAMES};
+
+/// inbound counts per message tag, shared by every stream task of a game.
+/// the game takes them each tick.
+pub struct InboundTraffic {
+    counts: [AtomicU64; MESSAGE_TAGS],
+}
+
+impl InboundTraffic {
+    pub fn new() -> Self {
+        return InboundTraffic {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+        };
+    }
+
+    pub fn record(&self, tag: usize) {
+        self.counts[tag].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // everything since the last take
+    pub fn take(&self) -> [u64; MESSAGE_TAGS] {
+        return std::array::from_fn(|tag| self.counts[tag].swap(0, Ordering::Relaxed));
+    }
+}
+
+impl Default for InboundTraffic {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// messages per tag over a whole game, index with Message::tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Traffic {
+    pub inbound: [u64; MESSAGE_TAGS],
+    pub outbound: [u64; MESSAGE_TAGS],
+}
+
+impl Default for Traffic {
+    fn default() -> Self {
+        return Traffic {
+            inbound: [0; MESSAGE_TAGS],
+            outbound: [0; MESSAGE_TAGS],
+        };
+    }
+}
+
+impl Traffic {
+    pub fn add_inbound(&mut self, counts: &[u64; MESSAGE_TAGS]) {
+        for (total, count) in self.inbound.iter_mut().zip(counts.iter()) {
+            *total += count;
+        }
+    }
+
+    pub fn add_outbound(&mut self, counts: &[u64; MESSAGE_TAGS]) {
+        for (total, count) in self.outbound.iter_mut().zip(counts.iter()) {
+            *total += count;
+        }
+    }
+
+    // (tag name, count) of every tag that saw traffic
+    pub fn nonzero(counts: &[u64; MESSAGE_TAGS]) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        return counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(tag, count)| (MESSAGE_TAG_NAMES[tag], *count));
+    }
+}
+
+// in: key_press_event=3 emote=2 out: snapshot=40, for the end of game summary
+impl Display for Traffic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "in:")?;
+        for (name, count) in Traffic::nonzero(&self.inbound) {
+            write!(f, " {}={}", name, count)?;
+        }
+        write!(f, " out:")?;
+        for (name, count) in Traffic::nonzero(&self.outbound) {
+            write!(f, " {}={}", name, count)?;
+        }
+
+        return Ok(());
+    }
+}
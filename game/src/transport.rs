@@ -0,0 +1,143 @@
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{
+    channel::mpsc,
+    stream::{SplitSink, SplitStream},
+    Sink, Stream, StreamExt,
+};
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use crate::player::{PlayerWebSink, PlayerWebStream};
+
+/// the frames coming in from one connection.
+pub trait FrameStream: Stream<Item = Result<Message, tungstenite::Error>> + Debug + Send + Unpin + 'static {}
+
+impl<S> FrameStream for S where S: Stream<Item = Result<Message, tungstenite::Error>> + Debug + Send + Unpin + 'static {}
+
+/// the frames going out to one connection.
+pub trait FrameSink: Sink<Message, Error = tungstenite::Error> + Debug + Send + Unpin + 'static {}
+
+impl<S> FrameSink for S where S: Sink<Message, Error = tungstenite::Error> + Debug + Send + Unpin + 'static {}
+
+/// what connections are made of. games, their players and the handshake are
+/// generic over it, the server only ever runs on WebSocket and tests can
+/// run a game on Memory without any sockets.
+pub trait Transport: Debug + Send + 'static {
+    type Stream: FrameStream;
+    type Sink: FrameSink;
+
+    /// lets go of a connection that never became a player or spectator.
+    fn close(stream: Self::Stream, sink: Self::Sink);
+}
+
+/// tungstenite over tcp.
+#[derive(Debug)]
+pub struct WebSocket;
+
+impl Transport for WebSocket {
+    type Stream = PlayerWebStream;
+    type Sink = PlayerWebSink;
+
+    fn close(stream: Self::Stream, sink: Self::Sink) {
+        _ = sink.reunite(stream).map(|mut x| _ = x.close(None));
+    }
+}
+
+/// one end of an in memory connection. what is sent into one end comes out
+/// of the other, closing or dropping an end ends the other's stream.
+#[derive(Debug)]
+pub struct MemorySocket {
+    tx: mpsc::Sender<Message>,
+    rx: mpsc::Receiver<Message>,
+}
+
+/// (server end, client end). each direction holds about buffer frames
+/// before sends have to wait on the other end reading.
+pub fn memory_pair(buffer: usize) -> (MemorySocket, MemorySocket) {
+    let (server_tx, client_rx) = mpsc::channel(buffer);
+    let (client_tx, server_rx) = mpsc::channel(buffer);
+
+    let server = MemorySocket {
+        tx: server_tx,
+        rx: server_rx,
+    };
+    let client = MemorySocket {
+        tx: client_tx,
+        rx: client_rx,
+    };
+
+    return (server, client);
+}
+
+// the other end is gone, same as a socket the peer closed
+fn closed(_: mpsc::SendError) -> tungstenite::Error {
+    return tungstenite::Error::ConnectionClosed;
+}
+
+impl Stream for MemorySocket {
+    type Item = Result<Message, tungstenite::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        return self.rx.poll_next_unpin(cx).map(|msg| msg.map(Ok));
+    }
+}
+
+impl Sink<Message> for MemorySocket {
+    type Error = tungstenite::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.tx.poll_ready(cx).map_err(closed);
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, msg: Message) -> Result<(), Self::Error> {
+        return self.tx.start_send(msg).map_err(closed);
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return Pin::new(&mut self.tx).poll_flush(cx).map_err(closed);
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return Pin::new(&mut self.tx).poll_close(cx).map_err(closed);
+    }
+}
+
+/// MemorySocket split like a websocket is.
+#[derive(Debug)]
+pub struct Memory;
+
+impl Transport for Memory {
+    type Stream = SplitStream<MemorySocket>;
+    type Sink = SplitSink<MemorySocket, Message>;
+
+    fn close(stream: Self::Stream, sink: Self::Sink) {
+        drop(stream);
+        drop(sink);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::memory_pair;
+
+    #[tokio::test]
+    async fn test_memory_pair_is_a_duplex() -> anyhow::Result<()> {
+        let (mut server, mut client) = memory_pair(4);
+
+        client.send(Message::Binary(vec![1])).await?;
+        server.send(Message::Binary(vec![2])).await?;
+        assert_eq!(server.next().await.transpose()?, Some(Message::Binary(vec![1])));
+        assert_eq!(client.next().await.transpose()?, Some(Message::Binary(vec![2])));
+
+        drop(client);
+        assert!(server.next().await.is_none());
+        assert!(server.send(Message::Binary(vec![3])).await.is_err());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,144 @@
+use tracing::warn;
+
+use crate::movement::TILE_COST;
+
+/// one step of the zone closing in. it holds for hold_ticks, then shrinks to
+/// radius over shrink_ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZonePhase {
+    pub hold_ticks: u128,
+    pub shrink_ticks: u128,
+    pub radius: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ZoneScheduleError {
+    ZeroMoveSpeed,
+    // the zone has to close in, a phase can't be bigger than the one before
+    Grows { phase: usize },
+    // someone standing at the old edge can't make it into the new one in time
+    TooFast { phase: usize, needed_ticks: u128, ticks: u128 },
+}
+
+impl std::fmt::Display for ZoneScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ZoneScheduleError::ZeroMoveSpeed => write!(f, "move_speed must be at least 1"),
+            ZoneScheduleError::Grows { phase } => write!(f, "zone phase {} is bigger than the one before", phase),
+            ZoneScheduleError::TooFast { phase, needed_ticks, ticks } => write!(
+                f,
+                "zone phase {} closes in {} ticks, running in from its old edge takes {}",
+                phase, ticks, needed_ticks
+            ),
+        };
+    }
+}
+
+/// ticks it takes at full speed on open ground to get from the corner of a
+/// zone of radius from into one of radius to. moves are one axis at a time,
+/// so the corner is the worst spot, it's off on both axes.
+pub fn ticks_to_cross(from: u16, to: u16, move_speed: u32) -> u128 {
+    let tiles = 2 * from.saturating_sub(to) as u128;
+    return (tiles * TILE_COST as u128).div_ceil(move_speed.max(1) as u128);
+}
+
+/// the zone starts out covering the whole map, every phase has to leave the
+/// players at its old edge enough time to run into the new one. a player that
+/// starts running when the phase begins is never outrun by the edge halfway
+/// through a shrink, so the whole phase is what counts.
+pub fn check_zone_schedule(schedule: &[ZonePhase], map_side: u16, move_speed: u32) -> Result<(), ZoneScheduleError> {
+    if move_speed == 0 {
+        return Err(ZoneScheduleError::ZeroMoveSpeed);
+    }
+
+    let mut radius = map_side / 2;
+    for (phase, step) in schedule.iter().enumerate() {
+        if step.radius > radius {
+            return Err(ZoneScheduleError::Grows { phase });
+        }
+
+        let needed_ticks = ticks_to_cross(radius, step.radius, move_speed);
+        let ticks = step.hold_ticks + step.shrink_ticks;
+        if ticks < needed_ticks {
+            return Err(ZoneScheduleError::TooFast { phase, needed_ticks, ticks });
+        }
+
+        radius = step.radius;
+    }
+
+    return Ok(());
+}
+
+/// the schedule with every phase slowed down until it's survivable, phases
+/// that would grow keep the radius before them instead.
+pub fn clamp_zone_schedule(schedule: &[ZonePhase], map_side: u16, move_speed: u32) -> Vec<ZonePhase> {
+    let mut radius = map_side / 2;
+    let mut clamped = vec![];
+
+    for (phase, step) in schedule.iter().enumerate() {
+        let mut step = *step;
+        if step.radius > radius {
+            warn!(phase, grows_to = step.radius, keeping = radius, "zone phase grows, keeping its radius");
+            step.radius = radius;
+        }
+
+        let needed_ticks = ticks_to_cross(radius, step.radius, move_speed);
+        if step.hold_ticks + step.shrink_ticks < needed_ticks {
+            warn!(phase, ticks = needed_ticks, "zone phase shrinks too fast, stretching it");
+            step.shrink_ticks = needed_ticks - step.hold_ticks;
+        }
+
+        radius = step.radius;
+        clamped.push(step);
+    }
+
+    return clamped;
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_zone_schedule, clamp_zone_schedule, ZonePhase, ZoneScheduleError};
+
+    fn phase(hold_ticks: u128, shrink_ticks: u128, radius: u16) -> ZonePhase {
+        return ZonePhase {
+            hold_ticks,
+            shrink_ticks,
+            radius,
+        };
+    }
+
+    #[test]
+    fn test_impossible_schedule_is_flagged() {
+        // 1 tile a tick, 256 wide map so the zone starts at 128
+        let survivable = [phase(100, 100, 64), phase(50, 30, 24)];
+        assert_eq!(check_zone_schedule(&survivable, 256, 100), Ok(()));
+
+        // 128 tiles out from the corner in 20 ticks
+        let schedule = [phase(10, 10, 64), phase(50, 30, 24)];
+        assert_eq!(
+            check_zone_schedule(&schedule, 256, 100),
+            Err(ZoneScheduleError::TooFast {
+                phase: 0,
+                needed_ticks: 128,
+                ticks: 20
+            })
+        );
+
+        // the same schedule is fine for players twice as fast
+        assert_eq!(check_zone_schedule(&survivable, 256, 200), Ok(()));
+        assert_eq!(
+            check_zone_schedule(&[phase(100, 100, 64), phase(0, 0, 80)], 256, 100),
+            Err(ZoneScheduleError::Grows { phase: 1 })
+        );
+        assert_eq!(check_zone_schedule(&survivable, 256, 0), Err(ZoneScheduleError::ZeroMoveSpeed));
+    }
+
+    #[test]
+    fn test_clamp_stretches_fast_phases() {
+        let schedule = [phase(10, 10, 64), phase(50, 30, 24), phase(0, 0, 30)];
+        let clamped = clamp_zone_schedule(&schedule, 256, 100);
+
+        assert_eq!(clamped, vec![phase(10, 118, 64), phase(50, 30, 24), phase(0, 0, 24)]);
+        assert_eq!(check_zone_schedule(&clamped, 256, 100), Ok(()));
+    }
+}
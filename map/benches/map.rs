@@ -0,0 +1,52 @@
+// The map lookups movement and visibility make every tick, on a default map.
+//
+// cargo bench -p map --bench map
+//
+// baseline, 1 core xeon vm, release:
+//   map/is_walkable_64k    ~134 µs
+//   map/line_of_sight_1k   ~162 µs
+
+
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use map::map::{Map, MAP_SIZE_SIDE};
+
+fn lookups(c: &mut Criterion) {
+    let map = Map::new(5);
+
+    let mut group = c.benchmark_group("map");
+    // every tile once
+    group.bench_function("is_walkable_64k", |b| {
+        b.iter(|| {
+            let mut walkable = 0;
+            for y in 0..MAP_SIZE_SIDE {
+                for x in 0..MAP_SIZE_SIDE {
+                    walkable += map.is_walkable(black_box(x), black_box(y)) as usize;
+                }
+            }
+            return walkable;
+        })
+    });
+
+    // sight lines from the middle out to a ring of points, up to 100 tiles long
+    let center = (MAP_SIZE_SIDE as u16 / 2, MAP_SIZE_SIDE as u16 / 2);
+    let targets: Vec<(u16, u16)> = (0..1000)
+        .map(|i| {
+            let angle = i as f64 / 1000.0 * std::f64::consts::TAU;
+            let length = 10.0 + (i % 10) as f64 * 10.0;
+            return ((center.0 as f64 + angle.cos() * length) as u16, (center.1 as f64 + angle.sin() * length) as u16);
+        })
+        .collect();
+    group.bench_function("line_of_sight_1k", |b| {
+        b.iter(|| targets.iter().filter(|&&to| map.has_line_of_sight(center, black_box(to))).count())
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().warm_up_time(Duration::from_millis(500)).measurement_time(Duration::from_secs(2));
+    targets = lookups
+}
+criterion_main!(benches);
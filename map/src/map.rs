@@ -4,10 +4,82 @@ pub const MAP_SIZE_SIDE: usize = 256;
 pub const MAP_SIZE: usize = MAP_SIZE_SIDE * MAP_SIZE_SIDE;
 pub const BUILDING_SIZE: usize = 10;
 pub const BUILDING_COUNT: usize = 25;
+pub const MUD_SIZE: usize = 8;
+pub const MUD_COUNT: usize = 15;
+// spawns are picked from a grid this far apart
+pub const SPAWN_SPACING: usize = 8;
+// open ground needed around a spawn in every direction
+pub const SPAWN_CLEARANCE: usize = 2;
+// seeds tried by Map::with_spawns before giving up
+pub const MAP_ATTEMPTS: u32 = 8;
+pub const HILL_COUNT: usize = 12;
+pub const HILL_RADIUS: usize = 20;
+pub const MAX_ELEVATION: u8 = 9;
+// eyes and muzzles are this far above the ground you stand on
+pub const EYE_HEIGHT: u8 = 1;
+// extra tiles of projectile range per level of high ground
+pub const RANGE_PER_LEVEL: u16 = 2;
+
+// values stored in the board
+pub const TERRAIN_GROUND: usize = 0;
+pub const TERRAIN_WALL: usize = 1;
+pub const TERRAIN_MUD: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terrain {
+    Ground,
+    Wall,
+    Mud,
+}
+
+impl Terrain {
+    fn from_board(value: usize) -> Terrain {
+        return match value {
+            TERRAIN_WALL => Terrain::Wall,
+            TERRAIN_MUD => Terrain::Mud,
+            _ => Terrain::Ground,
+        };
+    }
+
+    fn to_board(self) -> usize {
+        return match self {
+            Terrain::Ground => TERRAIN_GROUND,
+            Terrain::Wall => TERRAIN_WALL,
+            Terrain::Mud => TERRAIN_MUD,
+        };
+    }
+
+    /// how fast you move on this terrain compared to open ground
+    pub fn speed_percent(&self) -> u32 {
+        return match self {
+            Terrain::Ground => 100,
+            Terrain::Mud => 50,
+            Terrain::Wall => 0,
+        };
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MapError {
+    // the last seed tried and what it had to offer
+    TooFewSpawns { seed: u32, found: usize, needed: usize },
+}
+
+// spreads attempts out, attempt 0 is the seed itself
+fn perturb_seed(seed: u32, attempt: u32) -> u32 {
+    return seed ^ attempt.wrapping_mul(0x9E37_79B9);
+}
+
+// elevation has its own rand so hills never move buildings or mud around
+fn elevation_seed(seed: u32) -> u32 {
+    return seed ^ 0x5A17_E1E7;
+}
 
 pub struct Map {
     pub seed: u32,
     board: Window<MAP_SIZE_SIDE, MAP_SIZE_SIDE>,
+    // row major, MAP_SIZE_SIDE * y + x
+    elevation: Vec<u8>,
 }
 
 impl Map {
@@ -15,13 +87,60 @@ impl Map {
         let mut map = Map {
             seed,
             board: Window::new(),
+            elevation: vec![0; MAP_SIZE],
         };
 
         map.generate();
+        map.generate_elevation();
 
         return map;
     }
 
+    /// a map with at least needed spawn candidates. the seed gets perturbed
+    /// until one works, so use the returned map's seed from here on.
+    pub fn with_spawns(seed: u32, needed: usize) -> Result<Map, MapError> {
+        let mut found = 0;
+        let mut last_seed = seed;
+
+        for attempt in 0..MAP_ATTEMPTS {
+            last_seed = perturb_seed(seed, attempt);
+            let map = Map::new(last_seed);
+            found = map.spawn_candidates().len();
+
+            if found >= needed {
+                return Ok(map);
+            }
+        }
+
+        return Err(MapError::TooFewSpawns {
+            seed: last_seed,
+            found,
+            needed,
+        });
+    }
+
+    /// grid points with nothing but open ground around them.
+    pub fn spawn_candidates(&self) -> Vec<(usize, usize)> {
+        let mut candidates = vec![];
+        let start = SPAWN_CLEARANCE;
+        let end = MAP_SIZE_SIDE - SPAWN_CLEARANCE;
+
+        for y in (start..end).step_by(SPAWN_SPACING) {
+            for x in (start..end).step_by(SPAWN_SPACING) {
+                let clear = (y - SPAWN_CLEARANCE..=y + SPAWN_CLEARANCE).all(|row| {
+                    (x - SPAWN_CLEARANCE..=x + SPAWN_CLEARANCE)
+                        .all(|col| self.board.data[row][col] == TERRAIN_GROUND)
+                });
+
+                if clear {
+                    candidates.push((x, y));
+                }
+            }
+        }
+
+        return candidates;
+    }
+
     pub fn generate(&mut self) -> Vec<(usize, usize)> {
         let mut m32 = mulberry32(self.seed);
         let random_points: Vec<(usize, usize)> = (0..BUILDING_COUNT)
@@ -42,7 +161,334 @@ impl Map {
             self.board.write(&b, Some(Offset::new(*x, *y)));
         }
 
+        // mud goes down after the buildings so it never changes where they are
+        for _ in 0..MUD_COUNT {
+            let x = (m32() % (MAP_SIZE_SIDE - MUD_SIZE) as u32) as usize;
+            let y = (m32() % (MAP_SIZE_SIDE - MUD_SIZE) as u32) as usize;
+
+            for row in y..y + MUD_SIZE {
+                for col in x..x + MUD_SIZE {
+                    if self.board.data[row][col] == TERRAIN_GROUND {
+                        self.board.data[row][col] = TERRAIN_MUD;
+                    }
+                }
+            }
+        }
+
         return random_points;
     }
+
+    /// cone shaped hills, where two overlap the higher one wins.
+    pub fn generate_elevation(&mut self) {
+        let mut m32 = mulberry32(elevation_seed(self.seed));
+        for _ in 0..HILL_COUNT {
+            let cx = (m32() % MAP_SIZE_SIDE as u32) as i32;
+            let cy = (m32() % MAP_SIZE_SIDE as u32) as i32;
+            let peak = (m32() % MAX_ELEVATION as u32) as i32 + 1;
+            let radius = HILL_RADIUS as i32;
+
+            for y in (cy - radius).max(0)..(cy + radius).min(MAP_SIZE_SIDE as i32) {
+                for x in (cx - radius).max(0)..(cx + radius).min(MAP_SIZE_SIDE as i32) {
+                    let distance = (x - cx).abs().max((y - cy).abs());
+                    let height = (peak * (radius - distance) / radius) as u8;
+                    let cell = &mut self.elevation[y as usize * MAP_SIZE_SIDE + x as usize];
+                    *cell = (*cell).max(height);
+                }
+            }
+        }
+    }
+
+    /// anything off the map is at sea level
+    pub fn elevation_at(&self, x: usize, y: usize) -> u8 {
+        if x >= MAP_SIZE_SIDE || y >= MAP_SIZE_SIDE {
+            return 0;
+        }
+
+        return self.elevation[y * MAP_SIZE_SIDE + x];
+    }
+
+    pub fn set_elevation(&mut self, x: usize, y: usize, elevation: u8) {
+        if x < MAP_SIZE_SIDE && y < MAP_SIZE_SIDE {
+            self.elevation[y * MAP_SIZE_SIDE + x] = elevation.min(MAX_ELEVATION);
+        }
+    }
+
+    /// how far a shot from here carries, high ground adds to it.
+    pub fn projectile_range(&self, from: (u16, u16), base: u16) -> u16 {
+        let level = self.elevation_at(from.0 as usize, from.1 as usize) as u16;
+        return base + level * RANGE_PER_LEVEL;
+    }
+
+    /// fnv-1a over terrain and elevation, two servers with the same checksum
+    /// play the same map.
+    pub fn checksum(&self) -> u32 {
+        let mut hash: u32 = 0x811C_9DC5;
+        let terrain = self.board.data.iter().flatten().map(|&value| value as u8);
+        for byte in terrain.chain(self.elevation.iter().cloned()) {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+
+        return hash;
+    }
+
+    /// anything off the map is a wall
+    pub fn terrain_at(&self, x: usize, y: usize) -> Terrain {
+        if x >= MAP_SIZE_SIDE || y >= MAP_SIZE_SIDE {
+            return Terrain::Wall;
+        }
+
+        return Terrain::from_board(self.board.data[y][x]);
+    }
+
+    pub fn set_terrain(&mut self, x: usize, y: usize, terrain: Terrain) {
+        if x < MAP_SIZE_SIDE && y < MAP_SIZE_SIDE {
+            self.board.data[y][x] = terrain.to_board();
+        }
+    }
+
+    pub fn is_walkable(&self, x: usize, y: usize) -> bool {
+        return self.terrain_at(x, y) != Terrain::Wall;
+    }
+
+    /// one line per row, '.' ground, '~' mud, '#' wall. for seeing what
+    /// a seed generated, e.g. when someone spawned in a wall.
+    pub fn to_ascii(&self) -> String {
+        return self.to_ascii_with(&[]);
+    }
+
+    /// to_ascii with an '@' on every position in players.
+    pub fn to_ascii_with(&self, players: &[(u16, u16)]) -> String {
+        let mut ascii = String::with_capacity((MAP_SIZE_SIDE + 1) * MAP_SIZE_SIDE);
+        for y in 0..MAP_SIZE_SIDE {
+            for x in 0..MAP_SIZE_SIDE {
+                let tile = match self.terrain_at(x, y) {
+                    Terrain::Ground => '.',
+                    Terrain::Mud => '~',
+                    Terrain::Wall => '#',
+                };
+                ascii.push(tile);
+            }
+            ascii.push('\n');
+        }
+
+        // a row is MAP_SIZE_SIDE tiles and its newline
+        for &(x, y) in players {
+            let (x, y) = (x as usize, y as usize);
+            if x < MAP_SIZE_SIDE && y < MAP_SIZE_SIDE {
+                let at = y * (MAP_SIZE_SIDE + 1) + x;
+                ascii.replace_range(at..at + 1, "@");
+            }
+        }
+
+        return ascii;
+    }
+
+    /// walks a bresenham line from a to b, any wall strictly between the two
+    /// blocks it. the ends themselves don't count so a wall can be seen.
+    /// the sight line runs EYE_HEIGHT above the ground at both ends, ground
+    /// that rises above it in between blocks it too.
+    pub fn has_line_of_sight(&self, a: (u16, u16), b: (u16, u16)) -> bool {
+        if a == b {
+            return true;
+        }
+
+        // heights along the line are from + (to - from) * step / steps, kept
+        // multiplied by steps to stay in integers
+        let from = (self.elevation_at(a.0 as usize, a.1 as usize) + EYE_HEIGHT) as i32;
+        let to = (self.elevation_at(b.0 as usize, b.1 as usize) + EYE_HEIGHT) as i32;
+        let steps = (a.0 as i32 - b.0 as i32).abs().max((a.1 as i32 - b.1 as i32).abs());
+        let mut step = 0;
+
+        let (mut x, mut y) = (a.0 as i32, a.1 as i32);
+        let (x1, y1) = (b.0 as i32, b.1 as i32);
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+
+            if (x, y) == (x1, y1) {
+                return true;
+            }
+
+            if !self.is_walkable(x as usize, y as usize) {
+                return false;
+            }
+
+            step += 1;
+            let ground = self.elevation_at(x as usize, y as usize) as i32;
+            if ground * steps > from * (steps - step) + to * step {
+                return false;
+            }
+        }
+    }
 }
 
+#[cfg(test)]
+mod test {
+    use super::{
+        Map, MapError, Terrain, MAP_SIZE_SIDE, MAX_ELEVATION, RANGE_PER_LEVEL, SPAWN_CLEARANCE, SPAWN_SPACING,
+    };
+
+    // a flat map with nothing on it around the area the tests use
+    fn open_map() -> Map {
+        let mut map = Map::new(1);
+        for y in 0..40 {
+            for x in 0..40 {
+                map.set_terrain(x, y, Terrain::Ground);
+                map.set_elevation(x, y, 0);
+            }
+        }
+
+        return map;
+    }
+
+    #[test]
+    fn test_clear_line_of_sight() {
+        let map = open_map();
+
+        assert!(map.has_line_of_sight((5, 5), (5, 5)));
+        assert!(map.has_line_of_sight((5, 5), (6, 5)));
+        assert!(map.has_line_of_sight((5, 5), (30, 5)));
+        assert!(map.has_line_of_sight((5, 5), (30, 17)));
+        assert!(map.has_line_of_sight((30, 17), (5, 5)));
+    }
+
+    #[test]
+    fn test_walls_block_line_of_sight() {
+        let mut map = open_map();
+        for y in 0..20 {
+            map.set_terrain(10, y, Terrain::Wall);
+        }
+
+        assert!(!map.has_line_of_sight((5, 5), (15, 5)));
+        assert!(!map.has_line_of_sight((15, 8), (5, 2)));
+        // around the end of the wall
+        assert!(map.has_line_of_sight((5, 25), (15, 25)));
+        // the wall itself can be seen
+        assert!(map.has_line_of_sight((5, 5), (10, 5)));
+
+        // mud slows you down but doesn't block anything
+        map.set_terrain(20, 25, Terrain::Mud);
+        assert!(map.has_line_of_sight((15, 25), (25, 25)));
+    }
+
+    #[test]
+    fn test_ridge_blocks_line_of_sight() {
+        let mut map = open_map();
+        for y in 0..40 {
+            map.set_elevation(10, y, 3);
+        }
+
+        assert!(!map.has_line_of_sight((5, 5), (15, 5)));
+        assert!(!map.has_line_of_sight((15, 8), (5, 2)));
+        // the ridge itself can be seen, and seen from
+        assert!(map.has_line_of_sight((5, 5), (10, 5)));
+        assert!(map.has_line_of_sight((10, 5), (20, 5)));
+
+        // high ground on both sides looks over it
+        map.set_elevation(5, 5, 3);
+        map.set_elevation(15, 5, 3);
+        assert!(map.has_line_of_sight((5, 5), (15, 5)));
+
+        // a low ridge is below eye height
+        for y in 0..40 {
+            map.set_elevation(10, y, 1);
+        }
+        assert!(map.has_line_of_sight((5, 20), (15, 20)));
+
+        assert_eq!(map.projectile_range((5, 5), 10), 10 + 3 * RANGE_PER_LEVEL);
+        assert_eq!(map.projectile_range((5, 20), 10), 10);
+    }
+
+    #[test]
+    fn test_elevation_is_deterministic_and_checksummed() {
+        let map = Map::new(42);
+        let again = Map::new(42);
+        assert_eq!(map.checksum(), again.checksum());
+        assert_ne!(map.checksum(), Map::new(43).checksum());
+
+        let peak = (0..MAP_SIZE_SIDE)
+            .flat_map(|y| (0..MAP_SIZE_SIDE).map(move |x| (x, y)))
+            .map(|(x, y)| map.elevation_at(x, y))
+            .max();
+        assert!(peak.is_some_and(|peak| peak > 0 && peak <= MAX_ELEVATION));
+        assert_eq!(map.elevation_at(MAP_SIZE_SIDE, 0), 0);
+
+        let mut changed = Map::new(42);
+        let level = changed.elevation_at(7, 7);
+        changed.set_elevation(7, 7, if level == 0 { 1 } else { 0 });
+        assert_ne!(changed.checksum(), map.checksum());
+    }
+
+    #[test]
+    fn test_seeded_map_renders_as_ascii() {
+        let map = Map::new(69);
+        let ascii = map.to_ascii_with(&[(200, 14)]);
+        let rows: Vec<&str> = ascii.lines().collect();
+        assert_eq!(rows.len(), MAP_SIZE_SIDE);
+        assert!(rows.iter().all(|row| row.len() == MAP_SIZE_SIDE));
+        assert!(ascii.contains('~'));
+
+        // a building of this seed with a player stuck in it
+        let area: Vec<&str> = rows[8..22].iter().map(|row| &row[186..214]).collect();
+        assert_eq!(
+            area,
+            vec![
+                "............................",
+                "............................",
+                "............................",
+                "..........##########........",
+                "..........#........#........",
+                "..........#........#........",
+                "..........#...@....#........",
+                "..........#........#........",
+                "..........#........#........",
+                "..........#........#........",
+                "..........#........#........",
+                "..........#........#........",
+                "..........##########........",
+                "............................",
+            ]
+        );
+
+        // the same every time, players only in the one that asked for them
+        assert_eq!(map.to_ascii(), Map::new(69).to_ascii());
+        assert_eq!(&map.to_ascii()[(14 * (MAP_SIZE_SIDE + 1) + 200)..][..1], ".");
+    }
+
+    #[test]
+    fn test_too_few_spawns_falls_back_to_another_seed() {
+        let seed = 69;
+        let found = Map::new(seed).spawn_candidates().len();
+
+        let map = Map::with_spawns(seed, found + 1).expect("a perturbed seed has more room");
+        assert_ne!(map.seed, seed);
+        assert!(map.spawn_candidates().len() > found);
+
+        // the same seed always falls back to the same map
+        assert_eq!(Map::with_spawns(seed, found + 1).map(|m| m.seed), Ok(map.seed));
+        assert_eq!(Map::with_spawns(seed, found).map(|m| m.seed), Ok(seed));
+    }
+
+    #[test]
+    fn test_impossible_spawn_count_errors() {
+        let grid = (MAP_SIZE_SIDE - 2 * SPAWN_CLEARANCE).div_ceil(SPAWN_SPACING);
+        match Map::with_spawns(1, grid * grid + 1) {
+            Err(MapError::TooFewSpawns { needed, found, .. }) => assert!(found < needed),
+            Ok(_) => panic!("more spawns than grid points"),
+        }
+    }
+}
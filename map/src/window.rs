@@ -25,14 +25,19 @@ impl Offset {
 }
 
 pub struct Window<const ROWS: usize, const COLS: usize> {
-    pub data: [[usize; COLS]; ROWS],
+    pub data: Box<[[usize; COLS]; ROWS]>,
 }
 
 impl<const R: usize, const C: usize> Window<R, C> {
     pub fn new() -> Self {
-        return Window {
-            data: [[usize::default(); C]; R],
-        };
+        // the map board is 256x256 usizes, build it directly on the heap so
+        // it never lands on the (much smaller) task / test thread stack.
+        let data = vec![[usize::default(); C]; R]
+            .into_boxed_slice()
+            .try_into()
+            .expect("vec was allocated with exactly R rows");
+
+        return Window { data };
     }
 
     pub fn write<const ROW: usize, const COL: usize>(
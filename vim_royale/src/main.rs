@@ -1,9 +1,20 @@
 use anyhow::Result;
 use clap::Parser;
-use futures_util::StreamExt;
-use game::connection::SerializationType;
-use log::{error, warn, info};
+use encoding::server::{region, REGION_LENGTH};
+use game::{
+    admin::AdminKeys,
+    connection::SerializationType,
+    game_config::{Balance, GameConfig, ManagerConfig, OnDeadline, OnSyncTimeout, PositionFormat},
+    game_thread::GameThread,
+    moderation::WordList,
+    seed::SeedMode,
+    server::ServeOptions,
+    shaping::ShapeConfig,
+    tick_rate::TickRate,
+};
+use tracing::{error, warn};
 use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[derive(Parser, Debug)]
 #[clap()]
@@ -13,40 +24,284 @@ struct Args {
 
     #[clap(short = 's', long = "serialization", value_enum, default_value_t = SerializationType::Deku)]
     serialization: SerializationType,
+
+    #[clap(long = "min-players", default_value_t = 1)]
+    min_players: usize,
+
+    #[clap(long = "max-players", default_value_t = 100)]
+    max_players: usize,
+
+    // game loop ticks per second
+    #[clap(long = "tick-rate", default_value_t = 60)]
+    tick_rate: u32,
+
+    #[clap(long = "late-join")]
+    allow_late_join: bool,
+
+    #[clap(long = "max-games", default_value_t = 64)]
+    max_games: usize,
+
+    // players waiting for a game once max-games are running, past it they get ServerFull
+    #[clap(long = "max-queued", default_value_t = 0)]
+    max_queued: usize,
+
+    // seconds, after this the lobby starts with whoever is there
+    #[clap(long = "max-lobby-wait")]
+    max_lobby_wait: Option<u64>,
+
+    // fewest players a lobby starts with once max-lobby-wait is up
+    #[clap(long = "lobby-floor", default_value_t = 2)]
+    lobby_floor: usize,
+
+    // close a lobby still short of min-players at max-lobby-wait instead
+    #[clap(long = "cancel-lobby-on-wait", conflicts_with = "lobby_floor")]
+    cancel_lobby_on_wait: bool,
+
+    #[clap(long = "bot-fill")]
+    bot_fill: bool,
+
+    #[clap(long = "max-handshakes", default_value_t = 8)]
+    max_concurrent_handshakes: usize,
+
+    // seconds a joining client gets to finish its clock sync
+    #[clap(long = "handshake-timeout", default_value_t = 10)]
+    handshake_timeout: u64,
+
+    // let clients that didn't finish their clock sync in unsynced instead of
+    // dropping them
+    #[clap(long = "admit-unsynced")]
+    admit_unsynced: bool,
+
+    // connections waiting for the game manager, past it new ones get ServerBusy
+    #[clap(long = "accept-backlog", default_value_t = game::accept::DEFAULT_ACCEPT_BACKLOG)]
+    accept_backlog: usize,
+
+    #[clap(long = "id-state")]
+    id_state_path: Option<std::path::PathBuf>,
+
+    // message of the day, every client gets it right after their whoami
+    #[clap(long = "motd")]
+    motd: Option<String>,
+
+    // shown to clients and kept with results, e.g. eu-west
+    #[clap(long = "region", default_value = "")]
+    region: String,
+
+    // serves /health on its own port when set, ok/degraded/stalled
+    #[clap(long = "health-port")]
+    health_port: Option<u16>,
+
+    // serves /status on this address when set, json of every game, e.g. 127.0.0.1:42003
+    #[clap(long = "status-addr")]
+    status_addr: Option<std::net::SocketAddr>,
+
+    // serves /metrics on its own port when set
+    #[clap(long = "metrics-port")]
+    metrics_port: Option<u16>,
+
+    // admin moves and WHO_AM_I_ADMIN inspection connections, debugging only
+    #[clap(long = "admin-commands")]
+    admin_commands: bool,
+
+    // admin connections for announcements and moderation, without the debugging admin moves
+    #[clap(long = "moderation")]
+    moderation: bool,
+
+    // one `<name> <secret>` per line, admin connections have to :login with one
+    #[clap(long = "admin-keys")]
+    admin_keys: Option<std::path::PathBuf>,
+
+    // players can ask for DebugTelemetry about themselves, never in ranked games
+    #[clap(long = "debug-telemetry")]
+    debug_telemetry: bool,
+
+    // SIGUSR1 or an admin DumpGame writes game state dumps here
+    #[clap(long = "dump-dir")]
+    dump_dir: Option<std::path::PathBuf>,
+
+    // every admin action goes in here, the server won't start if it doesn't verify
+    #[clap(long = "audit-log")]
+    audit_log: Option<std::path::PathBuf>,
+
+    // checks an audit log's hash chain, prints the head and exits
+    #[clap(long = "verify-audit-log")]
+    verify_audit_log: Option<std::path::PathBuf>,
+
+    // running games keep a recovery image here, rewritten every recovery-interval seconds
+    #[clap(long = "recovery-dir")]
+    recovery_dir: Option<std::path::PathBuf>,
+
+    // games that lose the game manager mid-game write their result here
+    #[clap(long = "outcome-dir")]
+    outcome_dir: Option<std::path::PathBuf>,
+
+    // every player's frames go in a capture file here, for chasing desyncs
+    // with replaytool. auth tokens are redacted
+    #[clap(long = "capture-dir")]
+    capture_dir: Option<std::path::PathBuf>,
+
+    #[clap(long = "recovery-interval", default_value_t = 10)]
+    recovery_interval: u64,
+
+    // every game plays this map, handy for reproducing one
+    #[clap(long = "seed", conflicts_with = "seed_sequence")]
+    seed: Option<u32>,
+
+    // seeds count up from here, one per game
+    #[clap(long = "seed-sequence")]
+    seed_sequence: Option<u32>,
+
+    // every game loop gets its own thread instead of sharing the runtime
+    #[clap(long = "dedicated-game-threads")]
+    dedicated_game_threads: bool,
+
+    // pins the dedicated game threads to this core, linux only
+    #[clap(long = "pin-game-core", requires = "dedicated_game_threads")]
+    pin_game_core: Option<usize>,
+
+    // comma separated, emotes with any of these words are dropped
+    #[clap(long = "blocked-words", value_delimiter = ',')]
+    blocked_words: Vec<String>,
+
+    // one json object per line instead of text, for log aggregation
+    #[clap(long = "log-json")]
+    log_json: bool,
+
+    // new players go to the emptiest lobby that is healthy and less than this
+    // many ms behind, instead of filling one lobby at a time
+    #[clap(long = "auto-balance-lag")]
+    auto_balance_lag: Option<u64>,
+
+    // snapshots carry 16.16 fixed point positions, for smooth movement clients
+    #[clap(long = "fixed-positions")]
+    fixed_positions: bool,
+
+    // every connection gets latency and loss, e.g. latency=80ms,jitter=20ms,loss=2%.
+    // debug builds only
+    #[clap(long = "shape")]
+    shape: Option<ShapeConfig>,
 }
 
 // #[tokio::main(flavor = "current_thread")]
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
-
     let args: &'static Args = Box::leak(Box::new(Args::parse()));
+    // RUST_LOG filters it, e.g. RUST_LOG=info,game::game=warn
+    game::logging::init(args.log_json)?;
 
-    error!("args {:?}", args);
-    let server = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
+    if let Some(path) = args.verify_audit_log.as_ref() {
+        let (records, head) = game::audit::verify(path)?;
+        println!("{} records, head {}", records, head);
+        return Ok(());
+    }
 
+    error!(?args, "starting");
+    let server = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
 
-    warn!("starting the server on {}", args.port);
+    if let Some(port) = args.metrics_port {
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+        tokio::spawn(game::metrics::serve(listener));
+    }
 
-    let mut game_manager = game::game_manager::GameManager::new(args.serialization.clone());
+    let blocked: Vec<&str> = args.blocked_words.iter().map(String::as_str).collect();
+    let config = ManagerConfig {
+        game: GameConfig {
+            ser_type: args.serialization,
+            tick_rate: TickRate::from_hz(args.tick_rate)?,
+            min_players: args.min_players,
+            max_players: args.max_players,
+            allow_late_join: args.allow_late_join,
+            max_lobby_wait: args.max_lobby_wait.map(std::time::Duration::from_secs),
+            on_deadline: match args.cancel_lobby_on_wait {
+                true => OnDeadline::Cancel,
+                false => OnDeadline::StartAnyway { floor: args.lobby_floor },
+            },
+            bot_fill: args.bot_fill,
+            max_concurrent_handshakes: args.max_concurrent_handshakes,
+            handshake_timeout: std::time::Duration::from_secs(args.handshake_timeout),
+            on_sync_timeout: match args.admit_unsynced {
+                true => OnSyncTimeout::Admit,
+                false => OnSyncTimeout::Drop,
+            },
+            region: region(&args.region),
+            admin_commands: args.admin_commands,
+            debug_telemetry: args.debug_telemetry,
+            positions: match args.fixed_positions {
+                true => PositionFormat::Fixed,
+                false => PositionFormat::Tile,
+            },
+            thread: match args.dedicated_game_threads {
+                true => GameThread::Dedicated(args.pin_game_core),
+                false => GameThread::Shared,
+            },
+            shaping: args.shape,
+            ..GameConfig::default()
+        },
+        max_games: args.max_games,
+        max_queued: args.max_queued,
+        id_state_path: args.id_state_path.clone(),
+        motd: args.motd.clone(),
+        dump_dir: args.dump_dir.clone(),
+        audit_log: args.audit_log.clone(),
+        moderation: args.moderation,
+        emote_filter: std::sync::Arc::new(WordList::new(&blocked)),
+        admin_keys: match args.admin_keys.as_ref() {
+            Some(path) => AdminKeys::load(path)?,
+            None => AdminKeys::default(),
+        },
+        recovery_dir: args.recovery_dir.clone(),
+        outcome_dir: args.outcome_dir.clone(),
+        capture_dir: args.capture_dir.clone(),
+        balance: match args.auto_balance_lag {
+            Some(ms) => Balance::Auto { max_lag: std::time::Duration::from_millis(ms) },
+            None => Balance::Fill,
+        },
+        seeds: match (args.seed, args.seed_sequence) {
+            (Some(seed), _) => SeedMode::Fixed(seed),
+            (None, Some(start)) => SeedMode::Sequence(start),
+            (None, None) => SeedMode::Time,
+        },
+        ..ManagerConfig::default()
+    };
+    if args.region.len() > REGION_LENGTH {
+        anyhow::bail!("region can be at most {} bytes", REGION_LENGTH);
+    }
+    config.game.validate(game::game::PLAYER_COUNT)?;
+    let game_manager = game::game_manager::GameManager::new(config)?;
+    for image in game_manager.unfinished_games() {
+        warn!(
+            game_id = image.game_id,
+            tick = image.tick as u64,
+            players = image.players.len(),
+            "game was cut off by a restart"
+        );
+    }
 
-    let mut connection_count = 0;
-    loop {
-        match server.accept().await {
-            Ok((stream, _)) => {
-                let stream = tokio_tungstenite::accept_async(stream).await?;
-                let (write, read) = stream.split();
-                connection_count += 1;
-                info!("[SERVER]: sending game manage new connection {}", connection_count);
-                game_manager.add_connection(read, write).await;
-            }
+    let options = ServeOptions {
+        accept_backlog: args.accept_backlog,
+        health: match args.health_port {
+            Some(port) => Some(TcpListener::bind(format!("0.0.0.0:{}", port)).await?),
+            None => None,
+        },
+        status: match args.status_addr {
+            Some(addr) => Some(TcpListener::bind(addr).await?),
+            None => None,
+        },
+        recovery_interval: std::time::Duration::from_secs(args.recovery_interval),
+        dump_signal: true,
+    };
 
-            Err(e) => {
-                println!("error {}", e);
-                break;
-            }
+    // ctrl-c or SIGTERM, players are told before the server goes
+    let mut terminate = signal(SignalKind::terminate())?;
+    let shutdown = async move {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
         }
-    }
+    };
+
+    warn!(port = args.port, "starting the server");
+    game::server::serve(server, game_manager, options, shutdown).await?;
 
     return Ok(());
 }